@@ -0,0 +1,520 @@
+//! # 用户管理
+//!
+//! 供 `admin.user.*` JSON-RPC 方法使用的管理操作：列出、查看、创建、停用/启用、删除用户，
+//! 重置密码和设置角色。调用者的管理权限由 JSON-RPC 层检查，审计记录也由 JSON-RPC 层写入。
+//!
+//! 用户存储只支持按 ID 分页，[`list_users`] 会扫描全部用户后再筛选和排序，适合管理后台的规模。
+
+use serde::{ Deserialize, Serialize };
+
+use super::{ apikey, create_reserved_user, create_user, lockout, password, reset, validate_password, AccountError };
+use crate::config::config;
+use crate::rbac;
+use crate::token;
+use crate::userstore::canonical::canonical_username;
+use crate::userstore::{ self, UserRecord, UserStoreError };
+
+/// 每页默认的用户数。
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+/// 每页最多的用户数。
+pub const MAX_PAGE_SIZE: usize = 200;
+/// 每个用户最多的角色数。
+pub const MAX_ROLES: usize = 16;
+/// 配置的管理员账户初始密码的环境变量名，见 [`seed_admins`]。
+pub static ADMIN_PASSWORD_ENV_VAR: &str = "BTCMWEB_ADMIN_PASSWORD";
+/// 扫描用户存储时每批读取的用户数。
+const SCAN_BATCH: usize = 500;
+
+/// 用户的状态。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UserStatus {
+    /// 正常。
+    Active,
+    /// 邮箱尚未验证。
+    Unverified,
+    /// 等待管理员批准注册，见 [`super::invite`]。
+    Pending,
+    /// 已被管理员停用。
+    Disabled,
+}
+
+impl UserStatus {
+    /// 用户记录当前的状态，依次按停用、等待批准、未验证判断。
+    pub fn of(user: &UserRecord) -> Self {
+        if user.disabled {
+            UserStatus::Disabled
+        } else if user.pending_approval {
+            UserStatus::Pending
+        } else if super::verification::is_unverified(user) {
+            UserStatus::Unverified
+        } else {
+            UserStatus::Active
+        }
+    }
+}
+
+/// 管理员看到的用户信息，不包含密码哈希和两步验证密钥。
+#[derive(Debug, Clone, Serialize)]
+pub struct AdminUserView {
+    /// 用户 ID。
+    pub id: u64,
+    /// 用户名。
+    pub username: String,
+    /// 邮箱。
+    pub email: Option<String>,
+    /// 邮箱是否已验证。
+    pub email_verified: bool,
+    /// 状态。
+    pub status: UserStatus,
+    /// 分配的角色，不含隐含的 `user` 角色。
+    pub roles: Vec<String>,
+    /// 实际拥有的全部权限，见 [`rbac::permissions_of`]。
+    pub permissions: Vec<String>,
+    /// 是否为服务账户。
+    pub service: bool,
+    /// 注册时使用的邀请码的创建者 ID。
+    pub invited_by: Option<u64>,
+    /// 是否启用了两步验证。
+    pub mfa_enabled: bool,
+    /// 显示名称。
+    pub display_name: Option<String>,
+    /// 创建时间（Unix 时间戳，秒）。
+    pub created_at: i64,
+    /// 最后修改时间（Unix 时间戳，秒）。
+    pub updated_at: i64,
+}
+
+impl From<&UserRecord> for AdminUserView {
+    fn from(user: &UserRecord) -> Self {
+        AdminUserView {
+            id: user.id,
+            username: user.username.clone(),
+            email: user.email.clone(),
+            email_verified: user.email_verified,
+            status: UserStatus::of(user),
+            roles: user.roles.clone(),
+            permissions: rbac::permissions_of(&rbac::effective_roles(user)),
+            service: user.service,
+            invited_by: user.invited_by,
+            mfa_enabled: user.totp.is_some(),
+            display_name: user.profile.display_name.clone(),
+            created_at: user.created_at,
+            updated_at: user.updated_at,
+        }
+    }
+}
+
+/// 排序字段。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortKey {
+    /// 用户 ID。
+    #[default]
+    Id,
+    /// 用户名，不区分大小写。
+    Username,
+    /// 创建时间。
+    CreatedAt,
+    /// 最后修改时间。
+    UpdatedAt,
+}
+
+/// 排序方向。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    /// 升序。
+    #[default]
+    Asc,
+    /// 降序。
+    Desc,
+}
+
+/// [`list_users`] 的筛选、排序和分页参数，所有字段都可省略。
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ListQuery {
+    /// 只列出该状态的用户。
+    pub status: Option<UserStatus>,
+    /// 只列出拥有该角色的用户，包括隐含的 `user` 角色和配置的管理员的 `superadmin` 角色。
+    pub role: Option<String>,
+    /// 只列出在该时间（含）之后创建的用户（Unix 时间戳，秒）。
+    pub created_after: Option<i64>,
+    /// 只列出在该时间（不含）之前创建的用户（Unix 时间戳，秒）。
+    pub created_before: Option<i64>,
+    /// 排序字段，默认按 ID。
+    pub sort: SortKey,
+    /// 排序方向，默认升序。
+    pub order: SortOrder,
+    /// 跳过的用户数。
+    pub offset: usize,
+    /// 每页用户数，默认 [`DEFAULT_PAGE_SIZE`]，最多 [`MAX_PAGE_SIZE`]。
+    pub limit: Option<usize>,
+}
+
+impl ListQuery {
+    fn matches(&self, user: &UserRecord) -> bool {
+        self.status.is_none_or(|status| UserStatus::of(user) == status)
+            && self.role.as_ref().is_none_or(|role| rbac::effective_roles(user).contains(role))
+            && self.created_after.is_none_or(|after| user.created_at >= after)
+            && self.created_before.is_none_or(|before| user.created_at < before)
+    }
+}
+
+/// 一页用户。
+#[derive(Debug, Clone, Serialize)]
+pub struct UserPage {
+    /// 符合筛选条件的用户总数。
+    pub total: usize,
+    /// 本页的用户。
+    pub users: Vec<AdminUserView>,
+}
+
+/// 管理员创建用户的参数。
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewUser {
+    /// 用户名。
+    pub username: String,
+    /// 初始密码。
+    pub password: String,
+    /// 邮箱。
+    #[serde(default)]
+    pub email: Option<String>,
+    /// 是否直接将邮箱标记为已验证，不发送验证邮件。
+    #[serde(default)]
+    pub email_verified: bool,
+    /// 角色。
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+/// 按条件列出用户。
+pub async fn list_users(query: &ListQuery) -> Result<UserPage, AccountError> {
+    let store = userstore::user_store();
+    let mut matched = Vec::new();
+    let mut offset = 0;
+    loop {
+        let batch = store.list(offset, SCAN_BATCH).await?;
+        offset += batch.len();
+        let done = batch.len() < SCAN_BATCH;
+        matched.extend(batch.into_iter().filter(|user| query.matches(user)));
+        if done {
+            break;
+        }
+    }
+    Ok(page_of(matched, query))
+}
+
+/// 将已筛选的用户按 `query` 排序并取出一页。
+fn page_of(mut matched: Vec<UserRecord>, query: &ListQuery) -> UserPage {
+    match query.sort {
+        SortKey::Id => matched.sort_by_key(|user| user.id),
+        SortKey::Username => matched.sort_by_cached_key(|user| (canonical_username(&user.username), user.id)),
+        SortKey::CreatedAt => matched.sort_by_key(|user| (user.created_at, user.id)),
+        SortKey::UpdatedAt => matched.sort_by_key(|user| (user.updated_at, user.id)),
+    }
+    if query.order == SortOrder::Desc {
+        matched.reverse();
+    }
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+    UserPage {
+        total: matched.len(),
+        users: matched.iter().skip(query.offset).take(limit).map(AdminUserView::from).collect(),
+    }
+}
+
+/// 按 ID 读取用户。
+pub async fn get_user(user_id: u64) -> Result<UserRecord, AccountError> {
+    userstore::user_store().get_by_id(user_id).await?.ok_or(AccountError::UserNotFound)
+}
+
+/// 创建用户。用户名、邮箱和密码按注册时的规则校验；未标记为已验证且填写了邮箱时发送验证邮件。
+pub async fn create(new_user: &NewUser) -> Result<UserRecord, AccountError> {
+    let roles = normalize_roles(&new_user.roles)?;
+    let email = new_user.email.as_deref().filter(|email| !email.trim().is_empty());
+    let user = create_user(&new_user.username, Some(&new_user.password), email, |user| {
+        user.email_verified = new_user.email_verified && user.email.is_some();
+        user.roles = roles;
+    }).await?;
+    if !user.email_verified {
+        if let Err(err) = super::verification::send_verification_email(&user).await {
+            tracing::error!("failed to send verification email to user {}: {}", user.id, err);
+        }
+    }
+    Ok(user)
+}
+
+/// 创建服务账户：没有密码和邮箱，只能使用 API 密钥（见 [`apikey`]）调用 JSON-RPC。
+pub async fn create_service_account(username: &str, roles: &[String]) -> Result<UserRecord, AccountError> {
+    let roles = normalize_roles(roles)?;
+    create_user(username, None, None, |user| {
+        user.service = true;
+        user.roles = roles;
+    }).await
+}
+
+/// 为配置 `admin.usernames` 中还不存在的用户名创建账户，存储的角色为 `superadmin`，
+/// 并为配置 `admin.user_ids` 中的用户分配 `superadmin` 角色，返回新建的账户。
+///
+/// 这些用户名可以是保留名（如 `root`），保留名不能通过注册获得，因此只能由这里创建。
+/// 初始密码取自环境变量 `BTCMWEB_ADMIN_PASSWORD`，按注册时的规则校验；未设置时不创建并记录警告。
+/// 已经存在的同名账户不会被修改，也不会因此获得任何权限。服务器启动时调用。
+pub async fn seed_admins() -> Result<Vec<UserRecord>, AccountError> {
+    let store = userstore::user_store();
+    let password = std::env::var(ADMIN_PASSWORD_ENV_VAR).ok().filter(|password| !password.is_empty());
+    let mut created = Vec::new();
+    for username in &config().admin.usernames {
+        if let Some(existing) = store.get_by_username(username).await? {
+            if !existing.roles.iter().any(|role| role == rbac::SUPERADMIN_ROLE) {
+                tracing::warn!("configured admin {} already exists as user {} without the superadmin role", username, existing.id);
+            }
+            continue;
+        }
+        let Some(password) = password.as_deref() else {
+            tracing::warn!("configured admin {} does not exist, set {} to create it", username, ADMIN_PASSWORD_ENV_VAR);
+            continue;
+        };
+        let user = match create_reserved_user(username, Some(password), None, |user| {
+            user.roles = vec![rbac::SUPERADMIN_ROLE.to_string()];
+        }).await {
+            Ok(user) => user,
+            // 另一个实例同时创建了该账户。
+            Err(AccountError::UsernameTaken) => continue,
+            Err(err) => return Err(err),
+        };
+        tracing::info!("created configured admin {} as user {}", user.username, user.id);
+        created.push(user);
+    }
+    for &id in &config().admin.user_ids {
+        let promoted = userstore::modify(store.as_ref(), id, |user| {
+            if user.service || user.roles.iter().any(|role| role == rbac::SUPERADMIN_ROLE) {
+                return Ok(false);
+            }
+            user.roles.push(rbac::SUPERADMIN_ROLE.to_string());
+            user.roles.sort();
+            Ok::<_, AccountError>(true)
+        }).await;
+        match promoted {
+            Ok(_) => {}
+            Err(AccountError::UserNotFound) => tracing::warn!("configured admin user id {} does not exist", id),
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(created)
+}
+
+/// 停用或启用用户。停用时吊销该用户所有的令牌和会话。
+pub async fn set_disabled(user_id: u64, disabled: bool) -> Result<UserRecord, AccountError> {
+    let user = userstore::modify(userstore::user_store().as_ref(), user_id, |user| {
+        let changed = user.disabled != disabled;
+        user.disabled = disabled;
+        Ok::<_, AccountError>(changed)
+    }).await?;
+    if disabled {
+        token::revoke::revoke_all_tokens(user_id).await?;
+    }
+    Ok(user)
+}
+
+/// 批准等待批准的注册，之后用户可以登录。用户不在等待批准时返回 [`AccountError::UserNotFound`]。
+pub async fn approve(user_id: u64) -> Result<UserRecord, AccountError> {
+    userstore::modify(userstore::user_store().as_ref(), user_id, |user| {
+        if !user.pending_approval {
+            return Err(AccountError::UserNotFound);
+        }
+        user.pending_approval = false;
+        Ok(true)
+    }).await
+}
+
+/// 拒绝等待批准的注册，删除该用户。用户不在等待批准时返回 [`AccountError::UserNotFound`]。
+///
+/// 按读取到的版本比较并删除：检查之后注册被批准或记录被修改时重新读取并检查，
+/// 不会删除已经批准的用户。
+pub async fn reject(user_id: u64) -> Result<UserRecord, AccountError> {
+    let store = userstore::user_store();
+    for _ in 0..userstore::MAX_MODIFY_RETRIES {
+        let user = get_user(user_id).await?;
+        if !user.pending_approval {
+            return Err(AccountError::UserNotFound);
+        }
+        match store.delete_if(user_id, user.version).await {
+            Ok(()) => {
+                revoke_access(&user).await?;
+                return Ok(user);
+            }
+            Err(UserStoreError::Conflict) => continue,
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Err(UserStoreError::Conflict.into())
+}
+
+/// 删除用户，并吊销该用户所有的令牌、会话和 API 密钥。
+pub async fn delete(user_id: u64) -> Result<UserRecord, AccountError> {
+    let user = get_user(user_id).await?;
+    userstore::user_store().delete(user_id).await?;
+    revoke_access(&user).await?;
+    Ok(user)
+}
+
+/// 吊销已删除用户所有的令牌、会话和 API 密钥，并清除账户的登录锁定。
+async fn revoke_access(user: &UserRecord) -> Result<(), AccountError> {
+    token::revoke::revoke_all_tokens(user.id).await?;
+    apikey::revoke_all(user.id).await?;
+    lockout::clear(&lockout::Subject::account(&user.username)).await?;
+    Ok(())
+}
+
+/// 重置用户的密码。
+///
+/// 给出 `new_password` 时直接设置为新密码（按密码规则校验），并吊销该用户所有的令牌；
+/// 否则向用户的邮箱发送密码重置码，用户没有邮箱时返回 [`AccountError::InvalidEmail`]，
+/// 一分钟内重复发送返回 [`AccountError::TooManyRequests`]。服务账户没有密码，返回
+/// [`AccountError::ServiceAccount`]。返回是否直接设置了密码。
+pub async fn reset_password(user_id: u64, new_password: Option<&str>) -> Result<bool, AccountError> {
+    let user = get_user(user_id).await?;
+    if user.service {
+        return Err(AccountError::ServiceAccount);
+    }
+    let Some(new_password) = new_password else {
+        if user.email.is_none() {
+            return Err(AccountError::InvalidEmail);
+        }
+        if !reset::send_reset_email(&user).await? {
+            return Err(AccountError::TooManyRequests);
+        }
+        return Ok(false);
+    };
+    validate_password(new_password, &user.username, user.email.as_deref()).await?;
+    let password_hash = password::hash_password(new_password).await?;
+    userstore::modify(userstore::user_store().as_ref(), user_id, |user| {
+        if user.service {
+            return Err(AccountError::ServiceAccount);
+        }
+        user.password_hash = password_hash.clone();
+        Ok(true)
+    }).await?;
+    token::revoke::revoke_all_tokens(user_id).await?;
+    lockout::clear(&lockout::Subject::account(&user.username)).await?;
+    Ok(true)
+}
+
+/// 设置用户的角色，替换原有的全部角色。
+///
+/// 角色有变化时吊销该用户所有的令牌和会话：令牌中的角色和权限声明在签发时确定，
+/// 不吊销的话被撤销的权限在令牌过期前仍然有效。
+pub async fn set_roles(user_id: u64, roles: &[String]) -> Result<UserRecord, AccountError> {
+    let roles = normalize_roles(roles)?;
+    let mut changed = false;
+    let user = userstore::modify(userstore::user_store().as_ref(), user_id, |user| {
+        changed = user.roles != roles;
+        user.roles = roles.clone();
+        Ok::<_, AccountError>(changed)
+    }).await?;
+    if changed {
+        token::revoke::revoke_all_tokens(user_id).await?;
+    }
+    Ok(user)
+}
+
+/// 校验角色都存在（见 [`crate::rbac`]），去重并排序。隐含的 `user` 角色不需要分配，会被去掉。
+fn normalize_roles(roles: &[String]) -> Result<Vec<String>, AccountError> {
+    let mut normalized: Vec<String> = roles
+        .iter()
+        .map(|role| role.trim().to_string())
+        .filter(|role| role != rbac::USER_ROLE)
+        .collect();
+    normalized.sort();
+    normalized.dedup();
+    if normalized.len() > MAX_ROLES {
+        return Err(AccountError::InvalidRole(format!("at most {} roles are allowed", MAX_ROLES)));
+    }
+    if let Some(unknown) = normalized.iter().find(|role| rbac::role(role).is_none()) {
+        return Err(AccountError::InvalidRole(format!("unknown role {}", unknown)));
+    }
+    Ok(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(id: u64, username: &str, created_at: i64) -> UserRecord {
+        UserRecord { id, username: username.to_string(), created_at, updated_at: created_at, ..Default::default() }
+    }
+
+    fn users() -> Vec<UserRecord> {
+        vec![
+            user(1, "carol", 300),
+            UserRecord { roles: vec!["admin".to_string()], ..user(2, "Alice", 100) },
+            UserRecord { disabled: true, ..user(3, "bob", 200) },
+            UserRecord { pending_approval: true, ..user(4, "dave", 400) },
+            UserRecord { email: Some("erin@example.com".to_string()), ..user(5, "erin", 500) },
+        ]
+    }
+
+    fn select(query: &ListQuery) -> UserPage {
+        let matched = users().into_iter().filter(|user| query.matches(user)).collect();
+        page_of(matched, query)
+    }
+
+    fn ids(page: &UserPage) -> Vec<u64> {
+        page.users.iter().map(|user| user.id).collect()
+    }
+
+    #[test]
+    fn filters_by_status_role_and_creation_time() {
+        let by_status = |status| select(&ListQuery { status: Some(status), ..Default::default() });
+        assert_eq!(ids(&by_status(UserStatus::Active)), [1, 2]);
+        assert_eq!(ids(&by_status(UserStatus::Disabled)), [3]);
+        assert_eq!(ids(&by_status(UserStatus::Pending)), [4]);
+        assert_eq!(ids(&by_status(UserStatus::Unverified)), [5]);
+
+        let admins = select(&ListQuery { role: Some("admin".to_string()), ..Default::default() });
+        assert_eq!(ids(&admins), [2]);
+        let everyone = select(&ListQuery { role: Some(rbac::USER_ROLE.to_string()), ..Default::default() });
+        assert_eq!(everyone.total, 5);
+
+        let window = select(&ListQuery { created_after: Some(200), created_before: Some(400), ..Default::default() });
+        assert_eq!(ids(&window), [1, 3], "created_after is inclusive, created_before exclusive");
+    }
+
+    #[test]
+    fn sorts_by_key_and_order() {
+        let sorted = |sort, order| ids(&select(&ListQuery { sort, order, ..Default::default() }));
+        assert_eq!(sorted(SortKey::Id, SortOrder::Asc), [1, 2, 3, 4, 5]);
+        assert_eq!(sorted(SortKey::Id, SortOrder::Desc), [5, 4, 3, 2, 1]);
+        assert_eq!(sorted(SortKey::Username, SortOrder::Asc), [2, 3, 1, 4, 5], "usernames sort case-insensitively");
+        assert_eq!(sorted(SortKey::CreatedAt, SortOrder::Asc), [2, 3, 1, 4, 5]);
+        assert_eq!(sorted(SortKey::UpdatedAt, SortOrder::Desc), [5, 4, 1, 3, 2]);
+    }
+
+    #[test]
+    fn pages_report_the_filtered_total() {
+        let page = select(&ListQuery { offset: 1, limit: Some(2), ..Default::default() });
+        assert_eq!(page.total, 5);
+        assert_eq!(ids(&page), [2, 3]);
+        let past_end = select(&ListQuery { offset: 10, ..Default::default() });
+        assert_eq!(past_end.total, 5);
+        assert!(past_end.users.is_empty());
+
+        let many: Vec<UserRecord> = (1..=MAX_PAGE_SIZE as u64 + 10).map(|id| user(id, &format!("u{}", id), 0)).collect();
+        assert_eq!(page_of(many.clone(), &ListQuery::default()).users.len(), DEFAULT_PAGE_SIZE);
+        let capped = page_of(many, &ListQuery { limit: Some(MAX_PAGE_SIZE + 1), ..Default::default() });
+        assert_eq!(capped.users.len(), MAX_PAGE_SIZE);
+    }
+
+    #[test]
+    fn roles_are_trimmed_deduplicated_and_sorted() {
+        let roles = |names: &[&str]| normalize_roles(&names.iter().map(ToString::to_string).collect::<Vec<_>>());
+        assert_eq!(roles(&[" moderator", "admin", "moderator "]).unwrap(), ["admin", "moderator"]);
+        assert_eq!(roles(&[rbac::USER_ROLE, "admin"]).unwrap(), ["admin"], "the implied user role is dropped");
+        assert!(roles(&[]).unwrap().is_empty());
+        assert!(matches!(roles(&["admin", "no-such-role"]), Err(AccountError::InvalidRole(_))));
+
+        let too_many: Vec<String> = (0..=MAX_ROLES).map(|i| format!("role{}", i)).collect();
+        assert!(matches!(normalize_roles(&too_many), Err(AccountError::InvalidRole(message)) if message.contains("at most")));
+    }
+}
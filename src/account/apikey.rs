@@ -0,0 +1,387 @@
+//! # API 密钥
+//!
+//! 自动化脚本和机器人使用服务账户（[`UserRecord::service`]）的 API 密钥调用 JSON-RPC，
+//! 而不是某个人的密码。密钥形如 `btcm_{id}_{secret}`：
+//!
+//! - `btcm_{id}` 是密钥的前缀，可以出现在日志和管理界面中，用于识别密钥；
+//! - `secret` 为 256 位随机数，只在创建或轮换时返回一次，服务器只保存整个密钥的 SHA-256 摘要。
+//!
+//! 每个密钥有一组权限范围 `scopes`，实际拥有的权限是权限范围与服务账户当前权限的交集，
+//! 因此修改服务账户的角色立即影响它的所有密钥。密钥可以设置有效期，
+//! 最后使用时间每分钟最多记录一次。服务账户被停用或删除后，它的密钥随即失效。
+//!
+//! 密钥记录作为附属记录保存在用户存储中（[`crate::userstore::UserStore::swap_record`]，类型为 `apikey`），
+//! 与服务账户一样持久，不随 KV 存储的重启或淘汰丢失。轮换和吊销都按记录的当前内容比较并交换，
+//! 同时进行的轮换和吊销不会互相覆盖。最后使用时间只是参考信息，保存在 KV 存储中。
+//!
+//! 客户端把密钥放在 `JsonRequest.token` 中代替访问令牌，见 [`crate::jsonrpc::authenticate`]。
+
+use serde::{ Deserialize, Serialize };
+
+use super::{ totp::constant_time_eq, AccountError };
+use crate::config::config;
+use crate::kvstore::kv_store;
+use crate::rbac;
+use crate::token::{ self, Claims };
+use crate::userstore::{ self, UserRecord };
+use ring::digest::{ digest, SHA256 };
+
+/// API 密钥的前缀，客户端据此区分 API 密钥和 JWT 访问令牌。
+pub const KEY_PREFIX: &str = "btcm_";
+/// 密钥名称的最大长度（字符数）。
+pub const NAME_MAX_LEN: usize = 64;
+/// 密钥记录在用户存储中的类型，记录的键为密钥 ID。
+const RECORD_KIND: &str = "apikey";
+/// 最后使用时间的键前缀，完整键为 `btcm:apikey:used:{id}`。与密钥记录分开保存，
+/// 避免记录使用时间覆盖同时进行的吊销或轮换。
+const LAST_USED_PREFIX: &str = "btcm:apikey:used:";
+/// 两次记录最后使用时间的最小间隔（秒）。
+const LAST_USED_INTERVAL_SECS: i64 = 60;
+/// 密钥 ID 的长度（十六进制字符数）。
+const ID_LEN: usize = 16;
+
+/// API 密钥的信息，不包含密钥本身。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKey {
+    /// 密钥 ID。
+    pub id: String,
+    /// 密钥所属的服务账户 ID。
+    pub user_id: u64,
+    /// 密钥名称，便于识别用途。
+    pub name: String,
+    /// 权限范围，见 [`crate::rbac`]。
+    pub scopes: Vec<String>,
+    /// 创建密钥的管理员的用户 ID。
+    pub created_by: u64,
+    /// 创建时间（Unix 时间戳，秒）。
+    pub created_at: i64,
+    /// 过期时间（Unix 时间戳，秒），为空时永不过期。
+    pub expires_at: Option<i64>,
+    /// 最后使用时间（Unix 时间戳，秒），从未使用时为空。保存在单独的键中，读取密钥列表时填入。
+    #[serde(default, skip_deserializing)]
+    pub last_used_at: Option<i64>,
+}
+
+impl ApiKey {
+    /// 密钥的前缀，即完整密钥中 `secret` 之前的部分。
+    pub fn prefix(&self) -> String {
+        format!("{}{}", KEY_PREFIX, self.id)
+    }
+}
+
+/// 服务器保存的密钥记录。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct KeyRecord {
+    #[serde(flatten)]
+    key: ApiKey,
+    /// 完整密钥的 SHA-256 摘要（十六进制）。
+    digest: String,
+}
+
+/// 创建 API 密钥的参数。
+#[derive(Debug, Clone, Deserialize)]
+pub struct NewApiKey {
+    /// 服务账户 ID。
+    pub user_id: u64,
+    /// 密钥名称。
+    pub name: String,
+    /// 权限范围，至少一项。
+    pub scopes: Vec<String>,
+    /// 有效期（秒），不指定时使用配置的 `api_keys.max_ttl_secs`。
+    #[serde(default)]
+    pub expires_in_secs: Option<u64>,
+}
+
+fn last_used_key(id: &str) -> String {
+    format!("{}{}", LAST_USED_PREFIX, id)
+}
+
+fn digest_hex(key: &str) -> String {
+    digest(&SHA256, key.as_bytes()).as_ref().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// 从完整密钥中解析出密钥 ID，格式不对时返回 `None`。
+fn parse_id(key: &str) -> Option<&str> {
+    let (id, secret) = key.strip_prefix(KEY_PREFIX)?.split_once('_')?;
+    let hex = |s: &str| s.bytes().all(|b| b.is_ascii_hexdigit());
+    (id.len() == ID_LEN && hex(id) && secret.len() == 64 && hex(secret)).then_some(id)
+}
+
+/// 记录的剩余有效期（秒），用作最后使用时间的过期时间。
+fn ttl_secs(expires_at: Option<i64>, now: i64) -> Option<u64> {
+    expires_at.map(|expires_at| (expires_at - now).max(1) as u64)
+}
+
+/// 按配置的上限确定过期时间。
+fn expiry(expires_in_secs: Option<u64>, now: i64) -> Result<Option<i64>, AccountError> {
+    let max_ttl_secs = config().api_keys.max_ttl_secs;
+    let ttl = match expires_in_secs {
+        Some(0) => return Err(AccountError::InvalidApiKey("expires_in_secs must be positive".into())),
+        Some(ttl) if max_ttl_secs > 0 && ttl > max_ttl_secs => {
+            return Err(AccountError::InvalidApiKey(format!("expires_in_secs may not exceed {}", max_ttl_secs)));
+        }
+        Some(ttl) => ttl,
+        None if max_ttl_secs > 0 => max_ttl_secs,
+        None => return Ok(None),
+    };
+    Ok(Some(now.saturating_add(i64::try_from(ttl).unwrap_or(i64::MAX))))
+}
+
+/// 读取服务账户，用户不存在时返回 [`AccountError::UserNotFound`]，
+/// 不是服务账户时返回 [`AccountError::ServiceAccount`]。
+async fn service_account(user_id: u64) -> Result<UserRecord, AccountError> {
+    let user = userstore::user_store().get_by_id(user_id).await?.ok_or(AccountError::UserNotFound)?;
+    if !user.service {
+        return Err(AccountError::ServiceAccount);
+    }
+    Ok(user)
+}
+
+impl KeyRecord {
+    fn parse(data: &str) -> Result<Self, AccountError> {
+        serde_json::from_str(data).map_err(|err| AccountError::Internal(format!("invalid api key record: {}", err)))
+    }
+
+    fn to_json(&self) -> Result<String, AccountError> {
+        serde_json::to_string(self).map_err(|err| AccountError::Internal(err.to_string()))
+    }
+
+    fn expired(&self, now: i64) -> bool {
+        self.key.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+/// 保存新的密钥记录，返回完整密钥和记录。
+async fn store_key(key: ApiKey) -> Result<(String, KeyRecord), AccountError> {
+    let secret = format!("{}{}", token::new_token_id(), token::new_token_id());
+    let full_key = format!("{}_{}", key.prefix(), secret);
+    let record = KeyRecord { key, digest: digest_hex(&full_key) };
+    if !userstore::user_store().swap_record(RECORD_KIND, &record.key.id, None, Some(&record.to_json()?)).await? {
+        return Err(AccountError::Internal(format!("api key id {} already exists", record.key.id)));
+    }
+    Ok((full_key, record))
+}
+
+/// 读取仍然有效的密钥记录，同时返回记录的原始内容，用于比较并交换。
+async fn get_record(id: &str) -> Result<Option<(String, KeyRecord)>, AccountError> {
+    let Some(data) = userstore::user_store().get_record(RECORD_KIND, id).await? else {
+        return Ok(None);
+    };
+    let record = KeyRecord::parse(&data)?;
+    if record.expired(chrono::Utc::now().timestamp()) {
+        return Ok(None);
+    }
+    Ok(Some((data, record)))
+}
+
+/// 读取服务账户的所有密钥记录，包括已过期的。
+async fn user_records(user_id: u64) -> Result<Vec<(String, KeyRecord)>, AccountError> {
+    let mut records = Vec::new();
+    for (_, data) in userstore::user_store().list_records(RECORD_KIND).await? {
+        let record = KeyRecord::parse(&data)?;
+        if record.key.user_id == user_id {
+            records.push((data, record));
+        }
+    }
+    Ok(records)
+}
+
+/// 校验密钥名称和权限范围，返回整理后的名称和去重排序后的权限范围。
+fn normalize(name: &str, scopes: &[String]) -> Result<(String, Vec<String>), AccountError> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > NAME_MAX_LEN || name.chars().any(char::is_control) {
+        return Err(AccountError::InvalidApiKey(format!("name must be 1 to {} characters", NAME_MAX_LEN)));
+    }
+    let mut scopes: Vec<String> = scopes.iter().map(|scope| scope.trim().to_string()).collect();
+    scopes.sort();
+    scopes.dedup();
+    if scopes.is_empty() {
+        return Err(AccountError::InvalidApiKey("at least one scope is required".into()));
+    }
+    if let Some(invalid) = scopes.iter().find(|scope| !rbac::valid_permission(scope)) {
+        return Err(AccountError::InvalidApiKey(format!("invalid scope {}", invalid)));
+    }
+    Ok((name.to_string(), scopes))
+}
+
+/// 为服务账户创建 API 密钥，`created_by` 为创建者的用户 ID。返回完整密钥（只返回这一次）和密钥信息。
+///
+/// 服务账户的有效密钥数达到配置的 `api_keys.max_per_account` 时返回 [`AccountError::InvalidApiKey`]。
+pub async fn create(new_key: &NewApiKey, created_by: u64) -> Result<(String, ApiKey), AccountError> {
+    let (name, scopes) = normalize(&new_key.name, &new_key.scopes)?;
+    service_account(new_key.user_id).await?;
+    let max_per_account = config().api_keys.max_per_account;
+    if list(new_key.user_id).await?.len() >= max_per_account {
+        return Err(AccountError::InvalidApiKey(format!("at most {} keys are allowed per account", max_per_account)));
+    }
+    let now = chrono::Utc::now().timestamp();
+    let key = ApiKey {
+        id: token::new_token_id()[..ID_LEN].to_string(),
+        user_id: new_key.user_id,
+        name,
+        scopes,
+        created_by,
+        created_at: now,
+        expires_at: expiry(new_key.expires_in_secs, now)?,
+        last_used_at: None,
+    };
+    let (full_key, KeyRecord { key, .. }) = store_key(key).await?;
+    tracing::info!("created api key {} for service account {}", key.prefix(), key.user_id);
+    Ok((full_key, key))
+}
+
+/// 读取一个仍然有效的密钥的信息。
+pub async fn get(id: &str) -> Result<ApiKey, AccountError> {
+    let mut key = get_record(id).await?.ok_or(AccountError::ApiKeyNotFound)?.1.key;
+    key.last_used_at = kv_store().get(&last_used_key(id)).await?.and_then(|at| at.parse().ok());
+    Ok(key)
+}
+
+/// 列出服务账户所有仍然有效的密钥，最新创建的在前。已过期的密钥记录会被删除。
+pub async fn list(user_id: u64) -> Result<Vec<ApiKey>, AccountError> {
+    let store = userstore::user_store();
+    let now = chrono::Utc::now().timestamp();
+    let mut keys = Vec::new();
+    for (data, record) in user_records(user_id).await? {
+        if record.expired(now) {
+            store.swap_record(RECORD_KIND, &record.key.id, Some(&data), None).await?;
+            continue;
+        }
+        let mut key = record.key;
+        key.last_used_at = kv_store().get(&last_used_key(&key.id)).await?.and_then(|at| at.parse().ok());
+        keys.push(key);
+    }
+    keys.sort_by_key(|key| std::cmp::Reverse(key.created_at));
+    Ok(keys)
+}
+
+/// 轮换密钥：创建一个名称和权限范围相同的新密钥，返回新的完整密钥和密钥信息。
+///
+/// 旧密钥在 `grace_secs` 秒后失效（为 0 时立即失效），期间新旧密钥都可以使用，
+/// 便于逐个更新使用该密钥的服务。`expires_in_secs` 为新密钥的有效期，规则与创建时相同。
+/// 旧密钥在轮换期间被吊销或轮换时，新密钥被删除并返回 [`AccountError::ApiKeyNotFound`]。
+pub async fn rotate(id: &str, grace_secs: u64, expires_in_secs: Option<u64>, rotated_by: u64) -> Result<(String, ApiKey), AccountError> {
+    let (data, mut old) = get_record(id).await?.ok_or(AccountError::ApiKeyNotFound)?;
+    service_account(old.key.user_id).await?;
+    let now = chrono::Utc::now().timestamp();
+    let key = ApiKey {
+        id: token::new_token_id()[..ID_LEN].to_string(),
+        created_by: rotated_by,
+        created_at: now,
+        expires_at: expiry(expires_in_secs, now)?,
+        last_used_at: None,
+        ..old.key.clone()
+    };
+    let (full_key, new) = store_key(key).await?;
+    let store = userstore::user_store();
+    let replaced = if grace_secs == 0 {
+        store.swap_record(RECORD_KIND, id, Some(&data), None).await?
+    } else {
+        let grace_until = now.saturating_add(i64::try_from(grace_secs).unwrap_or(i64::MAX));
+        old.key.expires_at = Some(old.key.expires_at.map_or(grace_until, |expires_at| expires_at.min(grace_until)));
+        store.swap_record(RECORD_KIND, id, Some(&data), Some(&old.to_json()?)).await?
+    };
+    if !replaced {
+        remove(&new.to_json()?, &new).await?;
+        return Err(AccountError::ApiKeyNotFound);
+    }
+    if grace_secs == 0 {
+        kv_store().delete(&last_used_key(id)).await?;
+    }
+    tracing::info!("rotated api key {}{} to {}", KEY_PREFIX, id, new.key.prefix());
+    Ok((full_key, new.key))
+}
+
+/// 按原始内容删除一条密钥记录，记录已被修改时返回 `false`。
+async fn remove(data: &str, record: &KeyRecord) -> Result<bool, AccountError> {
+    if !userstore::user_store().swap_record(RECORD_KIND, &record.key.id, Some(data), None).await? {
+        return Ok(false);
+    }
+    kv_store().delete(&last_used_key(&record.key.id)).await?;
+    Ok(true)
+}
+
+/// 吊销密钥。密钥不存在时返回 `false`。
+pub async fn revoke(id: &str) -> Result<bool, AccountError> {
+    loop {
+        let Some((data, record)) = get_record(id).await? else {
+            return Ok(false);
+        };
+        // 记录在读取后被轮换修改时重新读取。
+        if remove(&data, &record).await? {
+            tracing::info!("revoked api key {} of service account {}", record.key.prefix(), record.key.user_id);
+            return Ok(true);
+        }
+    }
+}
+
+/// 吊销服务账户的所有密钥，返回吊销的仍然有效的密钥数。已过期的密钥记录同时被删除。
+pub async fn revoke_all(user_id: u64) -> Result<usize, AccountError> {
+    let now = chrono::Utc::now().timestamp();
+    let mut revoked = 0;
+    loop {
+        let records = user_records(user_id).await?;
+        if records.is_empty() {
+            return Ok(revoked);
+        }
+        for (data, record) in records {
+            if remove(&data, &record).await? && !record.expired(now) {
+                revoked += 1;
+            }
+        }
+    }
+}
+
+/// 校验 API 密钥，返回代表该密钥的声明：`sub` 和 `username` 为服务账户，
+/// `perms` 为密钥权限范围与账户当前权限的交集，`api_key` 为密钥 ID。
+///
+/// 密钥格式错误、未知、已过期或已被吊销，或服务账户已被停用、删除时返回 [`AccountError::Unauthorized`]。
+pub async fn authenticate(key: &str) -> Result<Claims, AccountError> {
+    let id = parse_id(key).ok_or(AccountError::Unauthorized)?;
+    let (_, record) = get_record(id).await?.ok_or(AccountError::Unauthorized)?;
+    if !constant_time_eq(record.digest.as_bytes(), digest_hex(key).as_bytes()) {
+        return Err(AccountError::Unauthorized);
+    }
+    let user = userstore::user_store()
+        .get_by_id(record.key.user_id)
+        .await?
+        .filter(|user| user.service && !user.disabled)
+        .ok_or(AccountError::Unauthorized)?;
+    let now = chrono::Utc::now().timestamp();
+    touch(&record.key, now).await;
+    let roles = rbac::effective_roles(&user);
+    let jwt = &config().jwt;
+    Ok(Claims {
+        sub: user.id.to_string(),
+        username: user.username,
+        iss: jwt.issuer.clone(),
+        aud: jwt.audience.clone(),
+        iat: record.key.created_at,
+        nbf: record.key.created_at,
+        exp: record.key.expires_at.unwrap_or(i64::MAX),
+        jti: record.key.id.clone(),
+        gen: 0,
+        sid: String::new(),
+        unverified: false,
+        mfa: false,
+        perms: rbac::intersect(&rbac::permissions_of(&roles), &record.key.scopes),
+        roles,
+        api_key: Some(record.key.id),
+    })
+}
+
+/// 记录密钥的最后使用时间，距上次记录不足一分钟时跳过。失败只记录日志。
+async fn touch(key: &ApiKey, now: i64) {
+    let store = kv_store();
+    let used_key = last_used_key(&key.id);
+    let result = async {
+        let last_used = store.get(&used_key).await?.and_then(|at| at.parse::<i64>().ok());
+        if last_used.is_some_and(|at| now - at < LAST_USED_INTERVAL_SECS) {
+            return Ok(());
+        }
+        store.set(&used_key, &now.to_string(), ttl_secs(key.expires_at, now)).await
+    }.await;
+    if let Err(err) = result {
+        tracing::warn!("failed to record last use of api key {}: {}", key.prefix(), err);
+    }
+}
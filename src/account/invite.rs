@@ -0,0 +1,210 @@
+//! # 邀请码和注册模式
+//!
+//! `/reguser` 是否可用由配置 `registration.mode` 决定（见 [`RegistrationMode`]）：
+//!
+//! - `open`：任何人都可以注册；
+//! - `invite_only`：必须提供有效的邀请码；
+//! - `approval`：没有邀请码的账户进入审批队列（[`UserRecord::pending_approval`]），
+//!   管理员批准前不能登录，带有效邀请码的账户直接可用；
+//! - `closed`：不允许注册。
+//!
+//! 邀请码由管理员创建，有使用次数上限和有效期，注册成功的账户记录邀请码及其创建者。
+//! 邀请码作为附属记录保存在用户存储中（类型为 `invite`），使用次数就在记录里。
+//! 注册时先按记录内容比较并交换预留一次使用，再创建账户，创建失败时归还，
+//! 因此并发注册不会超出上限，也不会出现先创建后删除的账户。
+
+use serde::{ Deserialize, Serialize };
+
+use super::AccountError;
+use crate::config::{ config, RegistrationMode };
+use crate::token;
+use crate::userstore;
+
+/// 邀请码记录在用户存储中的类型，记录的键为邀请码。
+const RECORD_KIND: &str = "invite";
+/// 邀请码的长度（十六进制字符数）。
+const CODE_LEN: usize = 16;
+/// 备注的最大长度（字符数）。
+pub const NOTE_MAX_LEN: usize = 200;
+
+/// 一个邀请码。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Invite {
+    /// 邀请码。
+    pub code: String,
+    /// 创建者的用户 ID。
+    pub created_by: u64,
+    /// 创建时间（Unix 时间戳，秒）。
+    pub created_at: i64,
+    /// 过期时间（Unix 时间戳，秒），为空时永不过期。
+    pub expires_at: Option<i64>,
+    /// 最多可以使用的次数，0 表示不限。
+    pub max_uses: u64,
+    /// 已经使用的次数，包括正在进行的注册预留的次数。
+    #[serde(default)]
+    pub uses: u64,
+    /// 备注，例如发给了谁。
+    #[serde(default)]
+    pub note: String,
+}
+
+impl Invite {
+    /// 是否还能使用。
+    fn usable(&self) -> bool {
+        self.max_uses == 0 || self.uses < self.max_uses
+    }
+
+    fn expired(&self, now: i64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    fn to_json(&self) -> Result<String, AccountError> {
+        serde_json::to_string(self).map_err(|err| AccountError::Internal(err.to_string()))
+    }
+}
+
+/// 创建邀请码的参数，都可以省略。
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct NewInvite {
+    /// 最多可以使用的次数，默认 1，0 表示不限。
+    pub max_uses: Option<u64>,
+    /// 有效期（秒），默认为配置的 `registration.invite_ttl_secs`，0 表示永不过期。
+    pub expires_in_secs: Option<u64>,
+    /// 备注，超出 [`NOTE_MAX_LEN`] 的部分被截掉。
+    pub note: String,
+}
+
+fn parse(data: &str) -> Result<Invite, AccountError> {
+    serde_json::from_str(data).map_err(|err| AccountError::Internal(format!("invalid invite record: {}", err)))
+}
+
+/// 邀请码的规范形式：去掉首尾空白并转为小写。
+fn normalize_code(code: &str) -> String {
+    code.trim().to_ascii_lowercase()
+}
+
+/// 创建邀请码，`created_by` 为创建者的用户 ID。
+pub async fn create(new_invite: &NewInvite, created_by: u64) -> Result<Invite, AccountError> {
+    let now = chrono::Utc::now().timestamp();
+    let ttl = new_invite.expires_in_secs.unwrap_or(config().registration.invite_ttl_secs);
+    let invite = Invite {
+        code: token::new_token_id()[..CODE_LEN].to_string(),
+        created_by,
+        created_at: now,
+        expires_at: (ttl > 0).then(|| now.saturating_add(i64::try_from(ttl).unwrap_or(i64::MAX))),
+        max_uses: new_invite.max_uses.unwrap_or(1),
+        uses: 0,
+        note: new_invite.note.trim().chars().filter(|c| !c.is_control()).take(NOTE_MAX_LEN).collect(),
+    };
+    if !userstore::user_store().swap_record(RECORD_KIND, &invite.code, None, Some(&invite.to_json()?)).await? {
+        return Err(AccountError::Internal(format!("invite code {} already exists", invite.code)));
+    }
+    Ok(invite)
+}
+
+/// 读取仍然有效的邀请码记录，同时返回记录的原始内容，用于比较并交换。
+async fn get_record(code: &str) -> Result<Option<(String, Invite)>, AccountError> {
+    let Some(data) = userstore::user_store().get_record(RECORD_KIND, code).await? else {
+        return Ok(None);
+    };
+    let invite = parse(&data)?;
+    if invite.expired(chrono::Utc::now().timestamp()) {
+        return Ok(None);
+    }
+    Ok(Some((data, invite)))
+}
+
+/// 读取一个仍然有效（未过期、未被吊销）的邀请码，包括已经用完的。
+pub async fn get(code: &str) -> Result<Option<Invite>, AccountError> {
+    Ok(get_record(&normalize_code(code)).await?.map(|(_, invite)| invite))
+}
+
+/// 列出所有仍然有效的邀请码，最新创建的在前。已过期的邀请码记录会被删除。
+pub async fn list() -> Result<Vec<Invite>, AccountError> {
+    let store = userstore::user_store();
+    let now = chrono::Utc::now().timestamp();
+    let mut invites = Vec::new();
+    for (code, data) in store.list_records(RECORD_KIND).await? {
+        let invite = parse(&data)?;
+        if invite.expired(now) {
+            store.swap_record(RECORD_KIND, &code, Some(&data), None).await?;
+        } else {
+            invites.push(invite);
+        }
+    }
+    invites.sort_by_key(|invite| std::cmp::Reverse(invite.created_at));
+    Ok(invites)
+}
+
+/// 吊销邀请码。邀请码不存在时返回 `false`。
+pub async fn revoke(code: &str) -> Result<bool, AccountError> {
+    let code = normalize_code(code);
+    let store = userstore::user_store();
+    loop {
+        let Some(data) = store.get_record(RECORD_KIND, &code).await? else {
+            return Ok(false);
+        };
+        // 记录在读取后被注册修改了使用次数时重新读取。
+        if store.swap_record(RECORD_KIND, &code, Some(&data), None).await? {
+            return Ok(!parse(&data)?.expired(chrono::Utc::now().timestamp()));
+        }
+    }
+}
+
+/// 按记录内容比较并交换修改邀请码，记录不存在或已过期时返回 `None`。
+/// `change` 返回错误时不写入。
+async fn modify(code: &str, change: impl Fn(&mut Invite) -> Result<(), AccountError>) -> Result<Option<Invite>, AccountError> {
+    loop {
+        let Some((data, mut invite)) = get_record(code).await? else {
+            return Ok(None);
+        };
+        change(&mut invite)?;
+        if userstore::user_store().swap_record(RECORD_KIND, code, Some(&data), Some(&invite.to_json()?)).await? {
+            return Ok(Some(invite));
+        }
+    }
+}
+
+/// 预留邀请码的一次使用。邀请码无效或已用完时返回 [`AccountError::InvalidInvite`]。
+async fn reserve(code: &str) -> Result<Invite, AccountError> {
+    let code = normalize_code(code);
+    modify(&code, |invite| {
+        if !invite.usable() {
+            return Err(AccountError::InvalidInvite);
+        }
+        invite.uses += 1;
+        Ok(())
+    }).await?.ok_or(AccountError::InvalidInvite)
+}
+
+/// 归还 [`admission`] 预留的一次使用，用于注册失败时。邀请码已被吊销或过期时什么也不做。
+pub async fn release(invite: &Invite) -> Result<(), AccountError> {
+    modify(&invite.code, |invite| {
+        invite.uses = invite.uses.saturating_sub(1);
+        Ok(())
+    }).await?;
+    Ok(())
+}
+
+/// 按注册模式和邀请码决定能否注册，返回可用的邀请码和账户是否需要等待批准。
+///
+/// 邀请码为空串视为没有提供。提供了邀请码但无效或已用完时，无论哪种模式都返回
+/// [`AccountError::InvalidInvite`]。返回邀请码时已为这次注册预留了一次使用，
+/// 注册失败时调用方必须用 [`release`] 归还。
+pub async fn admission(code: Option<&str>) -> Result<(Option<Invite>, bool), AccountError> {
+    let mode = config().registration.mode;
+    if mode == RegistrationMode::Closed {
+        return Err(AccountError::RegistrationClosed);
+    }
+    let code = code.map(str::trim).filter(|code| !code.is_empty());
+    if mode == RegistrationMode::InviteOnly && code.is_none() {
+        return Err(AccountError::InviteRequired);
+    }
+    let invite = match code {
+        Some(code) => Some(reserve(code).await?),
+        None => None,
+    };
+    let pending = mode == RegistrationMode::Approval && invite.is_none();
+    Ok((invite, pending))
+}
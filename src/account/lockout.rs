@@ -0,0 +1,260 @@
+//! # 登录失败锁定
+//!
+//! 分别按用户名和客户端 IP 统计 `/login` 的密码错误和 `/login/mfa` 的两步验证错误次数
+//! （见 [`crate::config::LockoutConfig`]）。
+//! 统计窗口内的失败次数达到阈值后锁定，锁定时长从 `base_lockout_secs` 开始，
+//! 每多失败一次翻倍，最长 `max_lockout_secs`。锁定期间即使密码正确也拒绝登录。
+//!
+//! 计数按用户提交的用户名进行，不区分用户名是否存在，因此锁定本身不会泄露账户是否存在；
+//! 所有登录失败的响应都延迟到相同的最短耗时（见 [`pad_failure`]）。
+//!
+//! 同一用户名失败次数达到 `captcha_after` 后，即使换了 IP 也要求回答验证码（见 [`crate::captcha`]）。
+//! 管理员可以通过 `admin.lockout.*` JSON-RPC 方法查看和解除锁定。
+
+use std::time::{ Duration, Instant };
+
+use serde::Serialize;
+
+use super::AccountError;
+use crate::config::{ config, LockoutConfig };
+use crate::kvstore::{ kv_store, KvStoreError };
+use crate::userstore::canonical::canonical_username;
+
+/// 失败计数的键前缀，完整键为 `btcm:lockout:fail:{subject}`。
+const FAILURE_KEY_PREFIX: &str = "btcm:lockout:fail:";
+/// 锁定截止时间的键前缀，完整键为 `btcm:lockout:until:{subject}`。
+const UNTIL_KEY_PREFIX: &str = "btcm:lockout:until:";
+/// 曾被锁定的对象集合，供管理员列出当前的锁定。
+const ACTIVE_SET_KEY: &str = "btcm:lockout:active";
+
+/// 被统计和锁定的对象。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Subject {
+    /// 用户名的规范形式（忽略大小写）。
+    Account(String),
+    /// 客户端 IP。
+    Ip(String),
+}
+
+impl Subject {
+    /// 用户名对象，按规范形式忽略首尾空白、全角半角和大小写。
+    pub fn account(username: &str) -> Self {
+        Subject::Account(canonical_username(username))
+    }
+
+    /// IP 对象。
+    pub fn ip(ip: &str) -> Self {
+        Subject::Ip(ip.to_string())
+    }
+
+    /// 解析 `account:{username}` 或 `ip:{ip}` 形式的字符串。
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.split_once(':')? {
+            ("account", username) => Some(Subject::account(username)),
+            ("ip", ip) => Some(Subject::ip(ip)),
+            _ => None,
+        }
+    }
+
+    fn threshold(&self) -> u32 {
+        let lockout = &config().lockout;
+        match self {
+            Subject::Account(_) => lockout.account_threshold,
+            Subject::Ip(_) => lockout.ip_threshold,
+        }
+    }
+}
+
+impl std::fmt::Display for Subject {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Subject::Account(username) => write!(f, "account:{}", username),
+            Subject::Ip(ip) => write!(f, "ip:{}", ip),
+        }
+    }
+}
+
+/// 一个对象当前的失败和锁定状态。
+#[derive(Debug, Clone, Serialize)]
+pub struct LockoutStatus {
+    /// `account:{username}` 或 `ip:{ip}`。
+    pub subject: String,
+    /// 统计窗口内的失败次数。
+    pub failures: u64,
+    /// 锁定截止时间（Unix 时间戳，秒），未锁定时为空。
+    pub locked_until: Option<i64>,
+}
+
+fn internal(err: KvStoreError) -> AccountError {
+    AccountError::Internal(err.to_string())
+}
+
+/// 失败 `failures` 次后的锁定时长（秒），未达到阈值时为 0。
+fn lockout_secs(lockout: &LockoutConfig, failures: u64, threshold: u32) -> u64 {
+    if threshold == 0 || failures < threshold as u64 {
+        return 0;
+    }
+    let doublings = (failures - threshold as u64).min(32) as u32;
+    lockout.base_lockout_secs.saturating_mul(1u64 << doublings).min(lockout.max_lockout_secs)
+}
+
+/// 查询一个对象的失败和锁定状态。
+pub async fn status(subject: &Subject) -> Result<LockoutStatus, AccountError> {
+    let kv = kv_store();
+    let failures = kv.get(&format!("{}{}", FAILURE_KEY_PREFIX, subject)).await.map_err(internal)?;
+    let locked_until = kv.get(&format!("{}{}", UNTIL_KEY_PREFIX, subject)).await.map_err(internal)?;
+    let now = chrono::Utc::now().timestamp();
+    Ok(LockoutStatus {
+        subject: subject.to_string(),
+        failures: failures.and_then(|value| value.parse().ok()).unwrap_or(0),
+        locked_until: locked_until.and_then(|value| value.parse().ok()).filter(|until| *until > now),
+    })
+}
+
+/// 登录之前检查用户名和 IP 是否被锁定，锁定时返回 [`AccountError::LoginLocked`]。
+pub async fn check(username: &str, ip: &str) -> Result<(), AccountError> {
+    let now = chrono::Utc::now().timestamp();
+    let mut retry_after = 0;
+    for subject in [Subject::account(username), Subject::ip(ip)] {
+        if let Some(until) = status(&subject).await?.locked_until {
+            retry_after = retry_after.max((until - now).max(1) as u64);
+        }
+    }
+    if retry_after > 0 {
+        return Err(AccountError::LoginLocked(retry_after));
+    }
+    Ok(())
+}
+
+/// 该用户名是否因失败次数过多而要求验证码。
+pub async fn captcha_required(username: &str) -> Result<bool, AccountError> {
+    let captcha_after = config().lockout.captcha_after;
+    if captcha_after == 0 || config().captcha.mode == crate::config::CaptchaMode::Off {
+        return Ok(false);
+    }
+    Ok(status(&Subject::account(username)).await?.failures >= captcha_after as u64)
+}
+
+/// 记录一次密码或两步验证错误，达到阈值时锁定对应的用户名或 IP。
+pub async fn record_failure(username: &str, ip: &str) -> Result<(), AccountError> {
+    let kv = kv_store();
+    let window = config().lockout.window_secs.max(1);
+    for subject in [Subject::account(username), Subject::ip(ip)] {
+        let failure_key = format!("{}{}", FAILURE_KEY_PREFIX, subject);
+        let failures = kv.incr(&failure_key, Some(window)).await.map_err(internal)?.max(0) as u64;
+        let secs = lockout_secs(&config().lockout, failures, subject.threshold());
+        if secs == 0 {
+            continue;
+        }
+        // 锁定期间延长统计窗口，使下一次失败继续翻倍，而不是从头计数。
+        kv.set(&failure_key, &failures.to_string(), Some(window.max(secs))).await.map_err(internal)?;
+        let until = chrono::Utc::now().timestamp() + secs as i64;
+        kv.set(&format!("{}{}", UNTIL_KEY_PREFIX, subject), &until.to_string(), Some(secs)).await.map_err(internal)?;
+        kv.set_add(ACTIVE_SET_KEY, &subject.to_string()).await.map_err(internal)?;
+        tracing::warn!("{} locked for {} seconds after {} failed logins", subject, secs, failures);
+    }
+    Ok(())
+}
+
+/// 登录成功后清除该用户名的失败记录。IP 的失败记录保留到统计窗口结束，
+/// 避免攻击者用自己的账户登录来重置计数。
+pub async fn record_success(username: &str) -> Result<(), AccountError> {
+    clear(&Subject::account(username)).await
+}
+
+/// 清除一个对象的失败记录并解除锁定。
+pub async fn clear(subject: &Subject) -> Result<(), AccountError> {
+    let kv = kv_store();
+    kv.delete(&format!("{}{}", FAILURE_KEY_PREFIX, subject)).await.map_err(internal)?;
+    kv.delete(&format!("{}{}", UNTIL_KEY_PREFIX, subject)).await.map_err(internal)?;
+    kv.set_remove(ACTIVE_SET_KEY, &subject.to_string()).await.map_err(internal)?;
+    Ok(())
+}
+
+/// 列出当前被锁定的所有对象，同时从集合中移除已经解除的锁定。
+pub async fn list_locked() -> Result<Vec<LockoutStatus>, AccountError> {
+    let kv = kv_store();
+    let mut locked = Vec::new();
+    for member in kv.set_members(ACTIVE_SET_KEY).await.map_err(internal)? {
+        let Some(subject) = Subject::parse(&member) else {
+            continue;
+        };
+        let status = status(&subject).await?;
+        if status.locked_until.is_some() {
+            locked.push(status);
+        } else {
+            kv.set_remove(ACTIVE_SET_KEY, &member).await.map_err(internal)?;
+        }
+    }
+    locked.sort_by(|a, b| a.subject.cmp(&b.subject));
+    Ok(locked)
+}
+
+/// 把登录失败的响应延迟到 `min_failure_response_ms`，`started` 为开始处理请求的时间。
+pub async fn pad_failure(started: Instant) {
+    pad_failure_to(started, Duration::from_millis(config().lockout.min_failure_response_ms)).await
+}
+
+/// 把从 `started` 开始的耗时延迟到至少 `min`，已经超过时立即返回。
+async fn pad_failure_to(started: Instant, min: Duration) {
+    if let Some(remaining) = min.checked_sub(started.elapsed()) {
+        tokio::time::sleep(remaining).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(base_lockout_secs: u64, max_lockout_secs: u64) -> LockoutConfig {
+        LockoutConfig { base_lockout_secs, max_lockout_secs, ..Default::default() }
+    }
+
+    #[test]
+    fn lockout_doubles_from_the_threshold() {
+        let lockout = policy(30, 3600);
+        assert_eq!(lockout_secs(&lockout, 0, 5), 0);
+        assert_eq!(lockout_secs(&lockout, 4, 5), 0, "below the threshold");
+        assert_eq!(lockout_secs(&lockout, 5, 5), 30);
+        assert_eq!(lockout_secs(&lockout, 6, 5), 60);
+        assert_eq!(lockout_secs(&lockout, 7, 5), 120);
+        assert_eq!(lockout_secs(&lockout, 11, 5), 1920);
+    }
+
+    #[test]
+    fn lockout_is_capped() {
+        let lockout = policy(30, 3600);
+        assert_eq!(lockout_secs(&lockout, 12, 5), 3600);
+        assert_eq!(lockout_secs(&lockout, 1000, 5), 3600);
+        assert_eq!(lockout_secs(&lockout, u64::MAX, 5), 3600, "the shift does not overflow");
+        assert_eq!(lockout_secs(&policy(u64::MAX / 2, u64::MAX), 40, 1), u64::MAX, "the multiplication saturates");
+    }
+
+    #[test]
+    fn zero_threshold_disables_lockout() {
+        assert_eq!(lockout_secs(&policy(30, 3600), 1000, 0), 0);
+    }
+
+    #[test]
+    fn subjects_round_trip() {
+        let account = Subject::account(" Alice ");
+        assert_eq!(account.to_string(), "account:alice");
+        assert_eq!(Subject::parse(&account.to_string()), Some(account));
+        assert_eq!(Subject::parse("ip:::1"), Some(Subject::ip("::1")));
+        assert_eq!(Subject::parse("user:alice"), None);
+        assert_eq!(Subject::parse("alice"), None);
+    }
+
+    #[tokio::test]
+    async fn failures_are_padded_to_the_minimum() {
+        let min = Duration::from_millis(100);
+        let started = Instant::now();
+        pad_failure_to(started, min).await;
+        assert!(started.elapsed() >= min);
+
+        let late = Instant::now() - Duration::from_millis(500);
+        let before = Instant::now();
+        pad_failure_to(late, min).await;
+        assert!(before.elapsed() < min, "slow failures are not delayed further");
+    }
+}
@@ -0,0 +1,257 @@
+//! # 两步验证
+//!
+//! 用户可以为账户启用 TOTP 两步验证（见 [`super::totp`]）：
+//!
+//! 1. [`begin_enrollment`] 生成密钥，返回密钥和 `otpauth://` URI，客户端据此显示二维码；
+//! 2. 用户把密钥添加到身份验证器应用后，[`confirm_enrollment`] 用应用给出的密码确认，
+//!    此时才真正启用，并返回一组一次性的恢复码，用户丢失设备时可以用恢复码代替 TOTP 密码。
+//!
+//! 启用后，密码校验通过的登录不会直接签发令牌，而是由 [`start_challenge`] 返回一个短期的
+//! `mfa_token`，客户端再提交 `mfa_token` 和 TOTP 密码（或恢复码），由 [`complete_challenge`]
+//! 完成登录。每个 `mfa_token` 只能成功使用一次。第二步的错误与密码错误一样按用户名和 IP
+//! 计入登录失败次数（见 [`super::lockout`]），重新输入密码取得新的 `mfa_token` 不会重置计数。
+
+use rand::Rng;
+use ring::digest::{ digest, SHA256 };
+use serde::{ Deserialize, Serialize };
+
+use super::{ lockout, totp, AccountError };
+use crate::config::config;
+use crate::kvstore::kv_store;
+use crate::token::{ self, session::DeviceInfo };
+use crate::userstore::{ self, TotpRecord, UserRecord };
+
+/// 未确认的 TOTP 密钥的键前缀，完整键为 `btcm:mfa:pending:{user_id}`。
+const PENDING_KEY_PREFIX: &str = "btcm:mfa:pending:";
+/// 登录第二步的键前缀，完整键为 `btcm:mfa:challenge:{sha256}`。
+const CHALLENGE_KEY_PREFIX: &str = "btcm:mfa:challenge:";
+/// 未确认的密钥的有效期（秒）。
+const PENDING_TTL_SECS: u64 = 10 * 60;
+/// 每次生成的恢复码个数。
+const RECOVERY_CODE_COUNT: usize = 10;
+/// 恢复码使用的字符，去掉了容易混淆的 `0`、`1`、`l`、`o`。
+const RECOVERY_CODE_ALPHABET: &[u8] = b"23456789abcdefghijkmnpqrstuvwxyz";
+
+/// 开始启用 TOTP 时返回给客户端的内容。
+#[derive(Debug, Clone, Serialize)]
+pub struct Enrollment {
+    /// Base32 编码的密钥，供无法扫描二维码时手动输入。
+    pub secret: String,
+    /// `otpauth://totp/...` URI，客户端将其显示为二维码。
+    pub otpauth_uri: String,
+}
+
+/// 登录第二步保存的内容。
+#[derive(Debug, Serialize, Deserialize)]
+struct Challenge {
+    user_id: u64,
+    /// 第一步提交的用户名，用于统计失败次数。
+    #[serde(default)]
+    username: String,
+    device: DeviceInfo,
+    /// 过期时间（Unix 时间戳，秒），验证码错误时按剩余有效期放回。
+    #[serde(default)]
+    expires_at: u64,
+}
+
+fn digest_hex(value: &str) -> String {
+    digest(&SHA256, value.as_bytes()).as_ref().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn internal(err: impl ToString) -> AccountError {
+    AccountError::Internal(err.to_string())
+}
+
+fn now_secs() -> u64 {
+    chrono::Utc::now().timestamp().max(0) as u64
+}
+
+async fn load_user(user_id: u64) -> Result<UserRecord, AccountError> {
+    userstore::user_store().get_by_id(user_id).await?.ok_or(AccountError::Unauthorized)
+}
+
+/// 调用者本人的记录不存在时与 [`load_user`] 一致，返回 [`AccountError::Unauthorized`]。
+fn not_found_unauthorized(err: AccountError) -> AccountError {
+    match err {
+        AccountError::UserNotFound => AccountError::Unauthorized,
+        err => err,
+    }
+}
+
+/// 生成一组恢复码，返回明文（只展示给用户一次）和保存用的摘要。
+fn generate_recovery_codes() -> (Vec<String>, Vec<String>) {
+    let mut rng = rand::rngs::OsRng;
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let chars: String = (0..10)
+                .map(|_| RECOVERY_CODE_ALPHABET[rng.gen_range(0..RECOVERY_CODE_ALPHABET.len())] as char)
+                .collect();
+            format!("{}-{}", &chars[..5], &chars[5..])
+        })
+        .collect();
+    let hashes = codes.iter().map(|code| digest_hex(&normalize_recovery_code(code))).collect();
+    (codes, hashes)
+}
+
+/// 恢复码比较时忽略大小写、空白和 `-`。
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars().filter(|c| c.is_ascii_alphanumeric()).map(|c| c.to_ascii_lowercase()).collect()
+}
+
+/// 用户是否已启用两步验证。
+pub fn is_enabled(user: &UserRecord) -> bool {
+    user.totp.is_some()
+}
+
+/// 开始启用 TOTP：生成新密钥，在确认前保存 10 分钟。已启用时返回 [`AccountError::MfaAlreadyEnabled`]。
+pub async fn begin_enrollment(user_id: u64) -> Result<Enrollment, AccountError> {
+    let user = load_user(user_id).await?;
+    if is_enabled(&user) {
+        return Err(AccountError::MfaAlreadyEnabled);
+    }
+    let secret = totp::generate_secret();
+    kv_store()
+        .set(&format!("{}{}", PENDING_KEY_PREFIX, user_id), &secret, Some(PENDING_TTL_SECS))
+        .await
+        .map_err(internal)?;
+    let issuer = config().mfa.issuer.clone();
+    let otpauth_uri = totp::otpauth_uri(&issuer, &user.username, &secret);
+    Ok(Enrollment { secret, otpauth_uri })
+}
+
+/// 用身份验证器应用给出的密码确认启用 TOTP，返回恢复码。
+///
+/// 没有进行中的启用流程时返回 [`AccountError::MfaNotEnabled`]，密码错误时返回
+/// [`AccountError::InvalidMfaCode`]，此时可以重试。
+pub async fn confirm_enrollment(user_id: u64, code: &str) -> Result<Vec<String>, AccountError> {
+    let kv = kv_store();
+    let pending_key = format!("{}{}", PENDING_KEY_PREFIX, user_id);
+    let secret = kv.get(&pending_key).await.map_err(internal)?.ok_or(AccountError::MfaNotEnabled)?;
+    if is_enabled(&load_user(user_id).await?) {
+        return Err(AccountError::MfaAlreadyEnabled);
+    }
+    let step = totp::verify(&secret, code, now_secs(), 0).ok_or(AccountError::InvalidMfaCode)?;
+    let (codes, hashes) = generate_recovery_codes();
+    let now = chrono::Utc::now().timestamp();
+    let totp = TotpRecord { secret, last_step: step, recovery_codes: hashes, enabled_at: now };
+    userstore::modify(userstore::user_store().as_ref(), user_id, |user| {
+        if is_enabled(user) {
+            return Err(AccountError::MfaAlreadyEnabled);
+        }
+        user.totp = Some(totp.clone());
+        Ok(true)
+    }).await.map_err(not_found_unauthorized)?;
+    kv.delete(&pending_key).await.map_err(internal)?;
+    tracing::info!("user {} enabled two-factor authentication", user_id);
+    Ok(codes)
+}
+
+/// 校验 TOTP 密码或恢复码，并在记录中更新已使用的时间步或删除已使用的恢复码。
+///
+/// `allow_recovery` 为 `false` 时只接受 TOTP 密码。在 [`userstore::modify`] 中调用，
+/// 按记录版本写回，并发提交同一个密码或恢复码时只有一次成功。
+fn use_second_factor(user: &mut UserRecord, code: &str, allow_recovery: bool) -> Result<(), AccountError> {
+    let Some(record) = user.totp.as_mut() else {
+        return Err(AccountError::MfaNotEnabled);
+    };
+    if let Some(step) = totp::verify(&record.secret, code, now_secs(), record.last_step) {
+        record.last_step = step;
+    } else {
+        let hash = digest_hex(&normalize_recovery_code(code));
+        let position = record.recovery_codes.iter().position(|stored| *stored == hash);
+        match position {
+            Some(position) if allow_recovery => {
+                record.recovery_codes.remove(position);
+                tracing::info!("user {} used a recovery code, {} left", user.id, record.recovery_codes.len());
+            }
+            _ => return Err(AccountError::InvalidMfaCode),
+        }
+    }
+    Ok(())
+}
+
+/// 停用两步验证，需要提供 TOTP 密码或恢复码。
+pub async fn disable(user_id: u64, code: &str) -> Result<(), AccountError> {
+    userstore::modify(userstore::user_store().as_ref(), user_id, |user| {
+        use_second_factor(user, code, true)?;
+        user.totp = None;
+        Ok(true)
+    }).await.map_err(not_found_unauthorized)?;
+    tracing::info!("user {} disabled two-factor authentication", user_id);
+    Ok(())
+}
+
+/// 重新生成恢复码，之前的恢复码全部失效。需要提供 TOTP 密码。
+pub async fn regenerate_recovery_codes(user_id: u64, code: &str) -> Result<Vec<String>, AccountError> {
+    let (codes, hashes) = generate_recovery_codes();
+    userstore::modify(userstore::user_store().as_ref(), user_id, |user| {
+        use_second_factor(user, code, false)?;
+        if let Some(record) = user.totp.as_mut() {
+            record.recovery_codes = hashes.clone();
+        }
+        Ok(true)
+    }).await.map_err(not_found_unauthorized)?;
+    Ok(codes)
+}
+
+/// 返回剩余的恢复码个数，未启用两步验证时返回 `None`。
+pub fn remaining_recovery_codes(user: &UserRecord) -> Option<usize> {
+    user.totp.as_ref().map(|record| record.recovery_codes.len())
+}
+
+/// 密码校验通过后开始登录第二步，返回 `mfa_token`。`username` 为第一步提交的用户名。
+pub async fn start_challenge(user: &UserRecord, username: &str, device: DeviceInfo) -> Result<String, AccountError> {
+    let mfa_token = format!("{}{}", token::new_token_id(), token::new_token_id());
+    let ttl = config().mfa.challenge_ttl_secs.max(1);
+    let challenge = Challenge { user_id: user.id, username: username.to_string(), device, expires_at: now_secs() + ttl };
+    let data = serde_json::to_string(&challenge).map_err(internal)?;
+    let key = format!("{}{}", CHALLENGE_KEY_PREFIX, digest_hex(&mfa_token));
+    kv_store().set(&key, &data, Some(ttl)).await.map_err(internal)?;
+    Ok(mfa_token)
+}
+
+/// 完成登录第二步，返回用户记录和第一步记录的设备信息。`ip` 为客户端 IP。
+///
+/// `mfa_token` 未知、过期或已使用时返回 [`AccountError::InvalidMfaToken`]；
+/// 用户名或 IP 被锁定时返回 [`AccountError::LoginLocked`]；
+/// 密码错误时返回 [`AccountError::InvalidMfaCode`]，并计入该用户名和该 IP 的失败次数。
+/// 成功时清除该用户名的失败记录。
+///
+/// 先取走 `mfa_token` 再消耗 TOTP 时间步或恢复码：并发提交同一个 `mfa_token` 时只有一次能取到，
+/// `mfa_token` 已过期时也不会白白用掉恢复码。验证码错误或被锁定时按剩余有效期放回 `mfa_token`。
+pub async fn complete_challenge(mfa_token: &str, code: &str, ip: &str) -> Result<(UserRecord, DeviceInfo), AccountError> {
+    let kv = kv_store();
+    let key = format!("{}{}", CHALLENGE_KEY_PREFIX, digest_hex(mfa_token.trim()));
+    let data = kv.take(&key).await.map_err(internal)?.ok_or(AccountError::InvalidMfaToken)?;
+    let challenge: Challenge = serde_json::from_str(&data).map_err(internal)?;
+    if let Err(err) = lockout::check(&challenge.username, ip).await {
+        restore_challenge(&key, &data, &challenge).await?;
+        return Err(err);
+    }
+
+    let result = userstore::modify(userstore::user_store().as_ref(), challenge.user_id, |user| {
+        use_second_factor(user, code, true).map(|_| true)
+    }).await;
+    let user = match result {
+        Ok(user) => user,
+        Err(AccountError::UserNotFound) => return Err(AccountError::InvalidMfaToken),
+        Err(AccountError::InvalidMfaCode) => {
+            tracing::warn!("failed two-factor attempt for user {}", challenge.user_id);
+            lockout::record_failure(&challenge.username, ip).await?;
+            restore_challenge(&key, &data, &challenge).await?;
+            return Err(AccountError::InvalidMfaCode);
+        }
+        Err(err) => return Err(err),
+    };
+    lockout::record_success(&challenge.username).await?;
+    Ok((user, challenge.device))
+}
+
+/// 按剩余有效期放回取走的 `mfa_token`，已过期时不放回。
+async fn restore_challenge(key: &str, data: &str, challenge: &Challenge) -> Result<(), AccountError> {
+    let remaining = challenge.expires_at.saturating_sub(now_secs());
+    if remaining > 0 {
+        kv_store().set(key, data, Some(remaining)).await.map_err(internal)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,485 @@
+//! # 账户服务
+//!
+//! 该模块实现注册、登录、两步验证、邮箱验证、找回密码和用户资料的业务逻辑，供 `/reguser`、`/login` 以及 JSON-RPC 方法共用。
+//! 所有失败都以 [`AccountError`] 表示，每个错误对应一个稳定的 `errorid`，
+//! 客户端根据 `errorid` 而不是 `message` 判断失败原因。
+
+pub mod admin;
+pub mod apikey;
+pub mod invite;
+pub mod lockout;
+pub mod mfa;
+pub mod password;
+pub mod policy;
+pub mod profile;
+pub mod reset;
+pub mod strength;
+pub mod totp;
+pub mod verification;
+
+use std::fmt;
+
+use unicode_security::{ GeneralSecurityProfile, RestrictionLevel, RestrictionLevelDetection };
+
+use crate::captcha::CaptchaError;
+use crate::config::config;
+use crate::kvstore::KvStoreError;
+use crate::token::TokenError;
+use crate::userstore::canonical::{ display_username, username_key };
+use crate::userstore::{ self, UserRecord, UserStoreError };
+use password::{ PasswordError, Verification };
+use policy::PolicyViolation;
+use profile::FieldError;
+
+/// 用户名最短长度（字符数）。
+pub const USERNAME_MIN_LEN: usize = 3;
+/// 用户名最长长度（字符数）。
+pub const USERNAME_MAX_LEN: usize = 32;
+/// 邮箱最长长度（字节数）。
+pub const EMAIL_MAX_LEN: usize = 254;
+/// 内置的保留用户名。按混淆骨架比较，大小写变体和易混淆写法同样不能注册；
+/// 可以通过配置 `registration.reserved_usernames` 追加。配置的管理员用户名同样保留，其账户由 `admin::seed_admins` 创建。
+pub const RESERVED_USERNAMES: &[&str] = &[
+    "abuse", "admin", "administrator", "api", "bitcomm", "btcm", "help", "hostmaster", "info",
+    "mail", "moderator", "noreply", "no-reply", "null", "official", "postmaster", "root",
+    "security", "staff", "support", "system", "webmaster", "www",
+];
+
+/// 账户操作的错误，每个变体对应一个 `errorid`。
+#[derive(Debug)]
+pub enum AccountError {
+    /// 服务器内部错误（存储或哈希失败）。
+    Internal(String),
+    /// 用户名不符合规则。
+    InvalidUsername(String),
+    /// 密码不符合规则，包含所有不满足的规则，见 [`policy`]。
+    InvalidPassword(Vec<PolicyViolation>),
+    /// 邮箱格式错误。
+    InvalidEmail,
+    /// 用户名已被注册。
+    UsernameTaken,
+    /// 邮箱已被注册。
+    EmailTaken,
+    /// 用户名或密码错误。不区分两者，避免泄露用户名是否存在。
+    InvalidCredentials,
+    /// 刷新令牌无效、过期、已被吊销或被重复使用。
+    InvalidRefreshToken,
+    /// 缺少访问令牌，或访问令牌无效、过期、已被吊销。
+    Unauthorized,
+    /// 需要回答验证码。
+    CaptchaRequired,
+    /// 验证码错误、已使用或已过期。
+    InvalidCaptcha,
+    /// 邮箱验证链接无效或已过期。
+    InvalidVerificationToken,
+    /// 邮箱尚未验证，该操作不可用。
+    EmailNotVerified,
+    /// 邮箱已经验证过。
+    EmailAlreadyVerified,
+    /// 请求过于频繁，请稍后再试。
+    TooManyRequests,
+    /// 密码重置令牌无效、已使用或已过期。
+    InvalidResetToken,
+    /// 密码正确，还需要完成两步验证。
+    MfaRequired,
+    /// 两步验证的密码或恢复码错误。
+    InvalidMfaCode,
+    /// 登录第二步的 `mfa_token` 无效、已过期或错误次数过多，需要重新输入密码。
+    InvalidMfaToken,
+    /// 两步验证已经启用。
+    MfaAlreadyEnabled,
+    /// 两步验证未启用，或没有进行中的启用流程。
+    MfaNotEnabled,
+    /// 登录失败次数过多，用户名或 IP 被暂时锁定，参数为需要等待的秒数。
+    LoginLocked(u64),
+    /// 用户资料的字段不符合规则，包含所有错误的字段，见 [`profile`]。
+    InvalidProfile(Vec<FieldError>),
+    /// 用户资料已被其他请求修改，需要重新读取后再提交。
+    ProfileConflict,
+    /// 用户不存在。
+    UserNotFound,
+    /// 账户已被管理员停用。
+    AccountDisabled,
+    /// 角色不存在，或角色数超过上限。
+    InvalidRole(String),
+    /// 没有执行该操作的权限，见 [`crate::rbac`]。
+    Forbidden,
+    /// API 密钥的名称、权限范围或有效期不符合规则，或密钥数超过上限。
+    InvalidApiKey(String),
+    /// API 密钥不存在或已被吊销。
+    ApiKeyNotFound,
+    /// 该操作不适用于服务账户（或只适用于服务账户）。
+    ServiceAccount,
+    /// 当前不允许注册。
+    RegistrationClosed,
+    /// 注册需要邀请码。
+    InviteRequired,
+    /// 邀请码无效、已过期、已被吊销或使用次数已满。
+    InvalidInvite,
+    /// 账户正在等待管理员批准注册。
+    PendingApproval,
+}
+
+impl AccountError {
+    /// 返回该错误对应的 `errorid`。
+    pub fn errorid(&self) -> u32 {
+        match self {
+            AccountError::Internal(_) => 1,
+            AccountError::InvalidUsername(_) => 2,
+            AccountError::InvalidPassword(_) => 3,
+            AccountError::InvalidEmail => 4,
+            AccountError::UsernameTaken => 5,
+            AccountError::EmailTaken => 6,
+            AccountError::InvalidCredentials => 7,
+            AccountError::InvalidRefreshToken => 8,
+            AccountError::Unauthorized => 9,
+            AccountError::CaptchaRequired => 10,
+            AccountError::InvalidCaptcha => 11,
+            AccountError::InvalidVerificationToken => 12,
+            AccountError::EmailNotVerified => 13,
+            AccountError::EmailAlreadyVerified => 14,
+            AccountError::TooManyRequests => 15,
+            AccountError::InvalidResetToken => 16,
+            AccountError::MfaRequired => 17,
+            AccountError::InvalidMfaCode => 18,
+            AccountError::InvalidMfaToken => 19,
+            AccountError::MfaAlreadyEnabled => 20,
+            AccountError::MfaNotEnabled => 21,
+            AccountError::LoginLocked(_) => 22,
+            AccountError::InvalidProfile(_) => 23,
+            AccountError::ProfileConflict => 24,
+            AccountError::UserNotFound => 25,
+            AccountError::AccountDisabled => 26,
+            AccountError::InvalidRole(_) => 27,
+            AccountError::Forbidden => 28,
+            AccountError::InvalidApiKey(_) => 29,
+            AccountError::ApiKeyNotFound => 30,
+            AccountError::ServiceAccount => 31,
+            AccountError::RegistrationClosed => 32,
+            AccountError::InviteRequired => 33,
+            AccountError::InvalidInvite => 34,
+            AccountError::PendingApproval => 35,
+        }
+    }
+}
+
+impl fmt::Display for AccountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccountError::Internal(_) => write!(f, "Internal server error"),
+            AccountError::InvalidUsername(reason) => write!(f, "Invalid username: {}", reason),
+            AccountError::InvalidPassword(violations) => {
+                let reasons: Vec<String> = violations.iter().map(ToString::to_string).collect();
+                write!(f, "Invalid password: {}", reasons.join("; "))
+            }
+            AccountError::InvalidEmail => write!(f, "Invalid email address"),
+            AccountError::UsernameTaken => write!(f, "Username already exists"),
+            AccountError::EmailTaken => write!(f, "Email already exists"),
+            AccountError::InvalidCredentials => write!(f, "Invalid username or password"),
+            AccountError::InvalidRefreshToken => write!(f, "Invalid refresh token"),
+            AccountError::Unauthorized => write!(f, "Missing or invalid access token"),
+            AccountError::CaptchaRequired => write!(f, "Captcha required"),
+            AccountError::InvalidCaptcha => write!(f, "Invalid captcha"),
+            AccountError::InvalidVerificationToken => write!(f, "Invalid or expired verification link"),
+            AccountError::EmailNotVerified => write!(f, "Email address not verified"),
+            AccountError::EmailAlreadyVerified => write!(f, "Email address already verified"),
+            AccountError::TooManyRequests => write!(f, "Too many requests, please try again later"),
+            AccountError::InvalidResetToken => write!(f, "Invalid or expired password reset code"),
+            AccountError::MfaRequired => write!(f, "Two-factor authentication required"),
+            AccountError::InvalidMfaCode => write!(f, "Invalid two-factor authentication code"),
+            AccountError::InvalidMfaToken => write!(f, "Two-factor login expired, please log in again"),
+            AccountError::MfaAlreadyEnabled => write!(f, "Two-factor authentication already enabled"),
+            AccountError::MfaNotEnabled => write!(f, "Two-factor authentication not enabled"),
+            AccountError::LoginLocked(secs) => write!(f, "Too many failed login attempts, try again in {} seconds", secs),
+            AccountError::InvalidProfile(errors) => {
+                let reasons: Vec<String> = errors.iter().map(|err| format!("{} {}", err.field, err.message)).collect();
+                write!(f, "Invalid profile: {}", reasons.join("; "))
+            }
+            AccountError::ProfileConflict => write!(f, "Profile was modified by another request, reload and try again"),
+            AccountError::UserNotFound => write!(f, "User not found"),
+            AccountError::AccountDisabled => write!(f, "Account disabled"),
+            AccountError::InvalidRole(reason) => write!(f, "Invalid role: {}", reason),
+            AccountError::Forbidden => write!(f, "Permission denied"),
+            AccountError::InvalidApiKey(reason) => write!(f, "Invalid API key: {}", reason),
+            AccountError::ApiKeyNotFound => write!(f, "API key not found"),
+            AccountError::ServiceAccount => write!(f, "Operation not available for this kind of account"),
+            AccountError::RegistrationClosed => write!(f, "Registration is closed"),
+            AccountError::InviteRequired => write!(f, "An invitation code is required to register"),
+            AccountError::InvalidInvite => write!(f, "Invalid or expired invitation code"),
+            AccountError::PendingApproval => write!(f, "Account is awaiting approval"),
+        }
+    }
+}
+
+impl std::error::Error for AccountError {}
+
+impl From<KvStoreError> for AccountError {
+    fn from(err: KvStoreError) -> Self {
+        AccountError::Internal(err.to_string())
+    }
+}
+
+impl From<PasswordError> for AccountError {
+    fn from(err: PasswordError) -> Self {
+        AccountError::Internal(err.to_string())
+    }
+}
+
+impl From<CaptchaError> for AccountError {
+    fn from(err: CaptchaError) -> Self {
+        match err {
+            CaptchaError::Required => AccountError::CaptchaRequired,
+            CaptchaError::Invalid => AccountError::InvalidCaptcha,
+            err => AccountError::Internal(err.to_string()),
+        }
+    }
+}
+
+impl From<TokenError> for AccountError {
+    fn from(err: TokenError) -> Self {
+        match err {
+            TokenError::InvalidRefreshToken | TokenError::RefreshTokenReused => AccountError::InvalidRefreshToken,
+            TokenError::Revoked => AccountError::Unauthorized,
+            err => AccountError::Internal(err.to_string()),
+        }
+    }
+}
+
+impl From<UserStoreError> for AccountError {
+    fn from(err: UserStoreError) -> Self {
+        match err {
+            UserStoreError::DuplicateUsername => AccountError::UsernameTaken,
+            UserStoreError::DuplicateEmail => AccountError::EmailTaken,
+            UserStoreError::NotFound => AccountError::UserNotFound,
+            err => AccountError::Internal(err.to_string()),
+        }
+    }
+}
+
+/// 校验用户名：长度在限制内，只包含字母、数字、`_`、`.`、`-`，且以字母或数字开头。
+///
+/// `username` 应为 [`display_username`] 处理后的形式。此外还拒绝 Unicode 标识符安全规范
+/// （UTS #39）不推荐的字符、以易混淆方式混用多种文字的名字（如拉丁字母混入西里尔字母），
+/// 以及与保留名骨架相同的名字，见 [`RESERVED_USERNAMES`]。
+pub fn validate_username(username: &str) -> Result<(), AccountError> {
+    validate_username_format(username)?;
+    if is_reserved_username(username) {
+        return Err(AccountError::InvalidUsername("is reserved".into()));
+    }
+    Ok(())
+}
+
+/// 校验用户名中除保留名之外的规则，见 [`validate_username`]。
+fn validate_username_format(username: &str) -> Result<(), AccountError> {
+    let len = username.chars().count();
+    if !(USERNAME_MIN_LEN..=USERNAME_MAX_LEN).contains(&len) {
+        return Err(AccountError::InvalidUsername(format!(
+            "must be {} to {} characters",
+            USERNAME_MIN_LEN,
+            USERNAME_MAX_LEN
+        )));
+    }
+    if !username.chars().next().is_some_and(char::is_alphanumeric) {
+        return Err(AccountError::InvalidUsername("must start with a letter or digit".into()));
+    }
+    if !username.chars().all(|c| c.is_alphanumeric() || matches!(c, '_' | '.' | '-')) {
+        return Err(AccountError::InvalidUsername("may only contain letters, digits, '_', '.' and '-'".into()));
+    }
+    if !username.chars().all(|c| matches!(c, '_' | '.' | '-') || c.identifier_allowed()) {
+        return Err(AccountError::InvalidUsername("contains characters that are not allowed".into()));
+    }
+    if !username.check_restriction_level(RestrictionLevel::HighlyRestrictive) {
+        return Err(AccountError::InvalidUsername("mixes scripts that can be confused".into()));
+    }
+    Ok(())
+}
+
+/// 用户名是否与内置或配置的保留名骨架相同。
+///
+/// 配置的管理员用户名同样是保留名，不能通过注册获得，管理员账户由 [`admin::seed_admins`] 在启动时创建。
+pub fn is_reserved_username(username: &str) -> bool {
+    let config = config();
+    let key = username_key(username);
+    RESERVED_USERNAMES
+        .iter()
+        .copied()
+        .chain(config.registration.reserved_usernames.iter().map(String::as_str))
+        .chain(config.admin.usernames.iter().map(String::as_str))
+        .any(|reserved| username_key(reserved) == key)
+}
+
+/// 按配置的密码规则校验新密码，`username` 和 `email` 为密码所属账户的用户名和邮箱。
+pub async fn validate_password(password: &str, username: &str, email: Option<&str>) -> Result<(), AccountError> {
+    let violations = policy::check(password, username, email).await;
+    if !violations.is_empty() {
+        return Err(AccountError::InvalidPassword(violations));
+    }
+    Ok(())
+}
+
+/// 校验邮箱格式：恰好一个 `@`，本地部分非空，域名包含 `.` 且不以 `.` 开头或结尾。
+pub fn validate_email(email: &str) -> Result<(), AccountError> {
+    if email.len() > EMAIL_MAX_LEN || email.chars().any(char::is_whitespace) {
+        return Err(AccountError::InvalidEmail);
+    }
+    match email.split_once('@') {
+        Some((local, domain))
+            if !local.is_empty()
+                && !domain.contains('@')
+                && domain.contains('.')
+                && !domain.starts_with('.')
+                && !domain.ends_with('.') => Ok(()),
+        _ => Err(AccountError::InvalidEmail),
+    }
+}
+
+/// 注册新用户：校验输入，哈希密码，分配 client id 并写入用户存储。
+///
+/// 用户名以 [`display_username`] 处理后的形式保存。用户名和邮箱的唯一性由存储按规范形式保证，
+/// 重复（包括只有大小写不同或易混淆的用户名）时返回 [`AccountError::UsernameTaken`]
+/// 或 [`AccountError::EmailTaken`]。填写了邮箱时账户处于未验证状态，并发送验证邮件；
+/// 邮件发送失败只记录日志，不影响注册，用户可以稍后重新发送。
+///
+/// 是否允许注册、是否需要邀请码 `invite` 以及账户是否需要等待批准由注册模式决定，见 [`invite`]。
+pub async fn register(username: &str, password: &str, email: Option<&str>, invite: Option<&str>) -> Result<UserRecord, AccountError> {
+    let (invite, pending) = invite::admission(invite).await?;
+    let created = create_user(username, Some(password), email, |user| {
+        user.pending_approval = pending;
+        user.invited_by = invite.as_ref().map(|invite| invite.created_by);
+        user.invite_code = invite.as_ref().map(|invite| invite.code.clone());
+    }).await;
+    let user = match (created, &invite) {
+        (Ok(user), _) => user,
+        (Err(err), Some(invite)) => {
+            if let Err(release_err) = invite::release(invite).await {
+                tracing::error!("failed to release a use of invite {}: {}", invite.code, release_err);
+            }
+            return Err(err);
+        }
+        (Err(err), None) => return Err(err),
+    };
+    if let Err(err) = verification::send_verification_email(&user).await {
+        tracing::error!("failed to send verification email to user {}: {}", user.id, err);
+    }
+    Ok(user)
+}
+
+/// 校验输入并创建用户，不发送验证邮件。`customize` 在写入存储前修改新记录，如设置角色。
+///
+/// `password` 为空时创建没有密码的账户（密码哈希为空串，任何密码都无法通过校验），用于服务账户。
+pub(crate) async fn create_user(
+    username: &str,
+    password: Option<&str>,
+    email: Option<&str>,
+    customize: impl FnOnce(&mut UserRecord)
+) -> Result<UserRecord, AccountError> {
+    insert_user(username, password, email, false, customize).await
+}
+
+/// 与 [`create_user`] 相同，但允许使用保留名。只用于 [`admin::seed_admins`] 创建配置的管理员账户。
+pub(crate) async fn create_reserved_user(
+    username: &str,
+    password: Option<&str>,
+    email: Option<&str>,
+    customize: impl FnOnce(&mut UserRecord)
+) -> Result<UserRecord, AccountError> {
+    insert_user(username, password, email, true, customize).await
+}
+
+async fn insert_user(
+    username: &str,
+    password: Option<&str>,
+    email: Option<&str>,
+    allow_reserved: bool,
+    customize: impl FnOnce(&mut UserRecord)
+) -> Result<UserRecord, AccountError> {
+    let username = display_username(username);
+    let username = username.as_str();
+    let email = email.map(str::trim);
+    if allow_reserved {
+        validate_username_format(username)?;
+    } else {
+        validate_username(username)?;
+    }
+    if let Some(email) = email {
+        validate_email(email)?;
+    }
+    let password_hash = match password {
+        Some(password) => {
+            validate_password(password, username, email).await?;
+            password::hash_password(password).await?
+        }
+        None => String::new(),
+    };
+
+    let store = userstore::user_store();
+    let now = chrono::Utc::now().timestamp();
+    let mut user = UserRecord {
+        id: userstore::next_user_id(store.as_ref()).await?,
+        username: username.to_string(),
+        email: email.map(str::to_string),
+        password_hash,
+        created_at: now,
+        updated_at: now,
+        ..Default::default()
+    };
+    customize(&mut user);
+    store.create(&user).await?;
+    Ok(user)
+}
+
+/// 校验用户名和密码，成功时返回用户记录。
+///
+/// 用户不存在时仍然执行一次哈希校验，使两种失败的耗时相近。
+/// 密码正确但存储的哈希弱于当前策略时，会用当前策略重新哈希，并按记录版本只替换密码哈希；
+/// 哈希在校验之后被并发修改（如密码被修改）时返回 [`AccountError::InvalidCredentials`]，其他保存失败不影响登录。
+/// 密码正确但账户已被停用时返回 [`AccountError::AccountDisabled`]，等待批准时返回
+/// [`AccountError::PendingApproval`]；
+/// 配置不允许未验证账户登录时，密码正确但邮箱未验证返回 [`AccountError::EmailNotVerified`]。
+pub async fn authenticate(username: &str, password: &str) -> Result<UserRecord, AccountError> {
+    let store = userstore::user_store();
+    let Some(user) = store.get_by_username(username).await? else {
+        password::dummy_verify(password).await;
+        return Err(AccountError::InvalidCredentials);
+    };
+    let user = match password::verify_password(password, &user.password_hash).await? {
+        Verification::Invalid => return Err(AccountError::InvalidCredentials),
+        Verification::Valid { needs_rehash: false } => user,
+        Verification::Valid { needs_rehash: true } => match password::hash_password(password).await {
+            Ok(password_hash) => {
+                // 只替换密码哈希，且只在哈希仍是刚校验过的那个时替换，不覆盖并发的改密码或其他修改。
+                let verified_hash = user.password_hash.clone();
+                let rehashed = userstore::modify(store.as_ref(), user.id, |current| {
+                    if current.password_hash != verified_hash {
+                        return Err(AccountError::InvalidCredentials);
+                    }
+                    current.password_hash = password_hash.clone();
+                    Ok(true)
+                }).await;
+                match rehashed {
+                    Ok(current) => current,
+                    // 校验之后密码被修改，刚校验过的密码已经失效。
+                    Err(AccountError::InvalidCredentials) => return Err(AccountError::InvalidCredentials),
+                    Err(err) => {
+                        tracing::warn!("failed to store rehashed password for user {}: {}", user.id, err);
+                        user
+                    }
+                }
+            }
+            Err(err) => {
+                tracing::warn!("failed to rehash password for user {}: {}", user.id, err);
+                user
+            }
+        },
+    };
+    if user.disabled {
+        return Err(AccountError::AccountDisabled);
+    }
+    if user.pending_approval {
+        return Err(AccountError::PendingApproval);
+    }
+    if verification::is_unverified(&user) && !config().email.unverified.allow_login {
+        return Err(AccountError::EmailNotVerified);
+    }
+    Ok(user)
+}
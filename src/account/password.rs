@@ -0,0 +1,221 @@
+//! # 密码哈希
+//!
+//! 使用 Argon2id 生成 PHC 格式（`$argon2id$v=19$m=...,t=...,p=...$salt$hash`）的密码哈希，
+//! 每个哈希使用独立的随机盐，开销参数来自 [`crate::config::PasswordConfig`]。
+//!
+//! 哈希计算是 CPU 和内存密集型操作，因此在 tokio 的阻塞线程池中执行，
+//! 并通过信号量限制同时进行的计算数量，避免大量登录请求拖垮异步运行时。
+
+use std::fmt;
+
+use argon2::{
+    password_hash::{ PasswordHash, PasswordHasher, PasswordVerifier, SaltString },
+    Algorithm,
+    Argon2,
+    Params,
+    Version,
+};
+use lazy_static::lazy_static;
+use rand::rngs::OsRng;
+use tokio::sync::Semaphore;
+
+use crate::config::{ config, PasswordConfig };
+
+lazy_static! {
+    /// 限制同时进行的哈希计算数量。
+    static ref HASH_PERMITS: Semaphore = Semaphore::new(config().password.max_concurrent_hashes.max(1));
+}
+
+/// 密码哈希操作的错误。
+#[derive(Debug)]
+pub enum PasswordError {
+    /// 配置的 Argon2 参数无效。
+    InvalidParams(String),
+    /// 哈希计算失败。
+    Hash(String),
+    /// 阻塞任务被取消或 panic。
+    Task(String),
+}
+
+impl fmt::Display for PasswordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PasswordError::InvalidParams(message) => write!(f, "invalid argon2 parameters: {}", message),
+            PasswordError::Hash(message) => write!(f, "password hashing failed: {}", message),
+            PasswordError::Task(message) => write!(f, "password hashing task failed: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for PasswordError {}
+
+/// 密码校验结果。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verification {
+    /// 密码错误，或存储的哈希无法解析。
+    Invalid,
+    /// 密码正确。`needs_rehash` 表示存储的哈希弱于当前策略，应当用新参数重新哈希。
+    Valid { needs_rehash: bool },
+}
+
+/// 按当前配置构造 Argon2id 哈希器。
+fn hasher(policy: &PasswordConfig) -> Result<Argon2<'static>, PasswordError> {
+    let params = Params::new(policy.memory_kib, policy.iterations, policy.parallelism, None)
+        .map_err(|err| PasswordError::InvalidParams(err.to_string()))?;
+    Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+}
+
+/// 在阻塞线程池中执行哈希相关的计算，并受信号量限制。
+async fn run_blocking<T, F>(f: F) -> Result<T, PasswordError>
+    where T: Send + 'static, F: FnOnce() -> T + Send + 'static
+{
+    let permit = HASH_PERMITS.acquire().await.map_err(|err| PasswordError::Task(err.to_string()))?;
+    tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        f()
+    }).await.map_err(|err| PasswordError::Task(err.to_string()))
+}
+
+/// 使用当前策略和随机盐生成 PHC 格式的 Argon2id 哈希。
+pub async fn hash_password(password: &str) -> Result<String, PasswordError> {
+    let password = password.to_string();
+    run_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        hasher(&config().password)?
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|err| PasswordError::Hash(err.to_string()))
+    }).await?
+}
+
+/// 校验密码，并判断存储的哈希是否需要按当前策略重新生成。
+///
+/// 哈希使用其自身记录的算法和参数校验，因此调整策略后旧哈希仍然可以登录。
+/// 哈希为空或无法解析（如没有密码的服务账户）时仍然对固定的哈希执行一次校验，再返回
+/// [`Verification::Invalid`]，使耗时与密码错误相近。
+pub async fn verify_password(password: &str, password_hash: &str) -> Result<Verification, PasswordError> {
+    let password = password.to_string();
+    let password_hash = password_hash.to_string();
+    run_blocking(move || {
+        let Ok(hash) = PasswordHash::new(&password_hash) else {
+            verify_dummy(&password);
+            return Verification::Invalid;
+        };
+        if Argon2::default().verify_password(password.as_bytes(), &hash).is_err() {
+            return Verification::Invalid;
+        }
+        Verification::Valid { needs_rehash: needs_rehash(&hash, &config().password) }
+    }).await
+}
+
+/// 按当前策略无法生成固定哈希时使用的哈希（默认策略的参数），保证 [`dummy_verify`] 总是执行一次完整的校验。
+const FALLBACK_DUMMY_HASH: &str = "$argon2id$v=19$m=19456,t=2,p=1$YnRjbXdlYmR1bW15c2FsdA$sBD+G8II2uHjQKhUzvtkHL+q0Z38qRvTwxpBmx06/CI";
+
+lazy_static! {
+    /// 按当前策略生成的固定哈希，见 [`dummy_verify`]。
+    static ref DUMMY_HASH: String = {
+        let salt = SaltString::generate(&mut OsRng);
+        hasher(&config().password)
+            .and_then(|argon2| {
+                argon2
+                    .hash_password(b"btcmweb-dummy-password", &salt)
+                    .map(|hash| hash.to_string())
+                    .map_err(|err| PasswordError::Hash(err.to_string()))
+            })
+            .unwrap_or_else(|err| {
+                tracing::error!("failed to create dummy password hash, using the built-in one: {}", err);
+                FALLBACK_DUMMY_HASH.to_string()
+            })
+    };
+}
+
+fn verify_dummy(password: &str) {
+    if let Ok(hash) = PasswordHash::new(&DUMMY_HASH) {
+        let _ = Argon2::default().verify_password(password.as_bytes(), &hash);
+    }
+}
+
+/// 对一个固定的哈希执行校验，使“用户不存在”与“密码错误”的耗时相近。
+pub async fn dummy_verify(password: &str) {
+    let password = password.to_string();
+    let _ = run_blocking(move || verify_dummy(&password)).await;
+}
+
+/// 存储的哈希不是 Argon2id、版本较旧，或任一开销参数低于当前策略时需要重新哈希。
+fn needs_rehash(hash: &PasswordHash<'_>, policy: &PasswordConfig) -> bool {
+    if Algorithm::try_from(hash.algorithm) != Ok(Algorithm::Argon2id) {
+        return true;
+    }
+    if hash.version != Some(Version::V0x13.into()) {
+        return true;
+    }
+    match Params::try_from(hash) {
+        Ok(params) => {
+            params.m_cost() < policy.memory_kib
+                || params.t_cost() < policy.iterations
+                || params.p_cost() < policy.parallelism
+        }
+        Err(_) => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(memory_kib: u32, iterations: u32, parallelism: u32) -> PasswordConfig {
+        PasswordConfig { memory_kib, iterations, parallelism, ..PasswordConfig::default() }
+    }
+
+    /// 用指定算法和参数生成哈希，参数取最小值以加快测试。
+    fn hash_with(algorithm: Algorithm, version: Version, policy: &PasswordConfig) -> String {
+        let params = Params::new(policy.memory_kib, policy.iterations, policy.parallelism, None).unwrap();
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::new(algorithm, version, params).hash_password(b"secret", &salt).unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn hashes_verify_with_the_right_password_only() {
+        let hash = hash_password("correct horse").await.unwrap();
+        assert!(hash.starts_with("$argon2id$v=19$m=19456,t=2,p=1$"), "{}", hash);
+        assert_ne!(hash, hash_password("correct horse").await.unwrap(), "every hash uses a new salt");
+        assert_eq!(verify_password("correct horse", &hash).await.unwrap(), Verification::Valid { needs_rehash: false });
+        assert_eq!(verify_password("correct horsE", &hash).await.unwrap(), Verification::Invalid);
+        assert_eq!(verify_password("", &hash).await.unwrap(), Verification::Invalid);
+    }
+
+    #[tokio::test]
+    async fn unusable_hashes_are_invalid() {
+        for hash in ["", "not a hash", "$argon2id$v=19$m=19456,t=2,p=1$", "$unknown$v=1$abc$def"] {
+            assert_eq!(verify_password("secret", hash).await.unwrap(), Verification::Invalid, "{:?}", hash);
+        }
+    }
+
+    #[tokio::test]
+    async fn weaker_hashes_need_rehash() {
+        let weak = hash_with(Algorithm::Argon2id, Version::V0x13, &policy(8, 1, 1));
+        assert_eq!(verify_password("secret", &weak).await.unwrap(), Verification::Valid { needs_rehash: true });
+        assert_eq!(verify_password("wrong", &weak).await.unwrap(), Verification::Invalid);
+    }
+
+    #[test]
+    fn needs_rehash_compares_algorithm_version_and_costs() {
+        let current = policy(64, 2, 2);
+        let check = |hash: &str, policy: &PasswordConfig| needs_rehash(&PasswordHash::new(hash).unwrap(), policy);
+        assert!(!check(&hash_with(Algorithm::Argon2id, Version::V0x13, &current), &current));
+        assert!(!check(&hash_with(Algorithm::Argon2id, Version::V0x13, &policy(128, 3, 4)), &current), "stronger hashes are kept");
+        assert!(check(&hash_with(Algorithm::Argon2id, Version::V0x13, &policy(32, 2, 2)), &current), "memory");
+        assert!(check(&hash_with(Algorithm::Argon2id, Version::V0x13, &policy(64, 1, 2)), &current), "iterations");
+        assert!(check(&hash_with(Algorithm::Argon2id, Version::V0x13, &policy(64, 2, 1)), &current), "parallelism");
+        assert!(check(&hash_with(Algorithm::Argon2i, Version::V0x13, &current), &current), "algorithm");
+        assert!(check(&hash_with(Algorithm::Argon2id, Version::V0x10, &current), &current), "version");
+    }
+
+    #[test]
+    fn fallback_dummy_hash_is_a_current_argon2id_hash() {
+        let hash = PasswordHash::new(FALLBACK_DUMMY_HASH).unwrap();
+        assert!(!needs_rehash(&hash, &PasswordConfig::default()));
+        assert!(Argon2::default().verify_password(b"btcmweb-dummy-password", &hash).is_ok());
+        assert!(PasswordHash::new(&DUMMY_HASH).is_ok());
+    }
+}
@@ -0,0 +1,374 @@
+//! # 密码规则
+//!
+//! 注册和重置密码时检查新密码（见 [`crate::config::PasswordPolicyConfig`]）：长度、字符类别、
+//! 是否包含用户名或邮箱、强度分数（见 [`super::strength`]），以及是否出现在本地的泄露密码列表中。
+//!
+//! 所有不满足的规则一次性返回，每条都有稳定的 `code`，注册页面可以逐条显示：
+//!
+//! ```json
+//! { "errorid": 3, "message": "Invalid password: ...",
+//!   "reasons": [ { "code": "too_short", "message": "must be at least 8 characters", "min": 8 } ] }
+//! ```
+//!
+//! 泄露密码列表在第一次检查时加载。文件格式的列表整体读入内存；目录格式（k-anonymity，
+//! 按 SHA-1 前 5 位分文件）每次只读取对应前缀的文件，适合很大的列表。
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{ Path, PathBuf };
+
+use lazy_static::lazy_static;
+use ring::digest::{ digest, SHA1_FOR_LEGACY_USE_ONLY };
+use serde::ser::{ Serialize, SerializeMap, Serializer };
+
+use super::strength::{ self, Pattern };
+use crate::config::{ config, PasswordPolicyConfig };
+
+/// 一条不满足的密码规则。
+#[derive(Debug, Clone, PartialEq)]
+pub enum PolicyViolation {
+    /// 太短。
+    TooShort { min: usize },
+    /// 太长。
+    TooLong { max: usize },
+    /// 缺少小写字母。
+    MissingLowercase,
+    /// 缺少大写字母。
+    MissingUppercase,
+    /// 缺少数字。
+    MissingDigit,
+    /// 缺少符号。
+    MissingSymbol,
+    /// 包含用户名。
+    ContainsUsername,
+    /// 包含邮箱。
+    ContainsEmail,
+    /// 强度不足，`hints` 为改进建议。
+    TooWeak { score: u8, min_score: u8, hints: Vec<&'static str> },
+    /// 出现在泄露密码列表中。
+    Breached { count: u64 },
+}
+
+impl PolicyViolation {
+    /// 稳定的规则代码。
+    pub fn code(&self) -> &'static str {
+        match self {
+            PolicyViolation::TooShort { .. } => "too_short",
+            PolicyViolation::TooLong { .. } => "too_long",
+            PolicyViolation::MissingLowercase => "missing_lowercase",
+            PolicyViolation::MissingUppercase => "missing_uppercase",
+            PolicyViolation::MissingDigit => "missing_digit",
+            PolicyViolation::MissingSymbol => "missing_symbol",
+            PolicyViolation::ContainsUsername => "contains_username",
+            PolicyViolation::ContainsEmail => "contains_email",
+            PolicyViolation::TooWeak { .. } => "too_weak",
+            PolicyViolation::Breached { .. } => "breached",
+        }
+    }
+}
+
+impl fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolicyViolation::TooShort { min } => write!(f, "must be at least {} characters", min),
+            PolicyViolation::TooLong { max } => write!(f, "must be at most {} characters", max),
+            PolicyViolation::MissingLowercase => write!(f, "must contain a lowercase letter"),
+            PolicyViolation::MissingUppercase => write!(f, "must contain an uppercase letter"),
+            PolicyViolation::MissingDigit => write!(f, "must contain a digit"),
+            PolicyViolation::MissingSymbol => write!(f, "must contain a symbol"),
+            PolicyViolation::ContainsUsername => write!(f, "must not contain the username"),
+            PolicyViolation::ContainsEmail => write!(f, "must not contain the email address"),
+            PolicyViolation::TooWeak { .. } => write!(f, "is too easy to guess"),
+            PolicyViolation::Breached { .. } => write!(f, "has appeared in a data breach"),
+        }
+    }
+}
+
+/// 序列化为 `{code, message, ...}`，附带各规则的参数。
+impl Serialize for PolicyViolation {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("code", self.code())?;
+        map.serialize_entry("message", &self.to_string())?;
+        match self {
+            PolicyViolation::TooShort { min } => map.serialize_entry("min", min)?,
+            PolicyViolation::TooLong { max } => map.serialize_entry("max", max)?,
+            PolicyViolation::TooWeak { score, min_score, hints } => {
+                map.serialize_entry("score", score)?;
+                map.serialize_entry("min_score", min_score)?;
+                map.serialize_entry("hints", hints)?;
+            }
+            PolicyViolation::Breached { count } => map.serialize_entry("count", count)?,
+            _ => {}
+        }
+        map.end()
+    }
+}
+
+/// 本地的泄露密码列表。
+enum BreachedList {
+    /// 整体读入内存的列表，键为大写的 SHA-1 十六进制摘要。
+    Hashes(HashMap<String, u64>),
+    /// 按 SHA-1 前 5 位分文件的目录。
+    Prefixes(PathBuf),
+}
+
+lazy_static! {
+    static ref BREACHED_LIST: Option<BreachedList> = config().password_policy.breached_list.as_deref().and_then(load_breached_list);
+}
+
+fn sha1_hex(password: &str) -> String {
+    digest(&SHA1_FOR_LEGACY_USE_ONLY, password.as_bytes()).as_ref().iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+/// 解析 `{摘要}:{次数}` 形式的一行，没有次数时计为 1 次。
+fn parse_hash_line(line: &str) -> (&str, u64) {
+    match line.split_once(':') {
+        Some((hash, count)) => (hash.trim(), count.trim().parse().unwrap_or(1)),
+        None => (line, 1),
+    }
+}
+
+fn load_breached_list(path: &str) -> Option<BreachedList> {
+    let path = Path::new(path);
+    if path.is_dir() {
+        tracing::info!("using breached password prefix directory {}", path.display());
+        return Some(BreachedList::Prefixes(path.to_path_buf()));
+    }
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) => {
+            tracing::error!("failed to load breached password list {}: {}", path.display(), err);
+            return None;
+        }
+    };
+    let mut hashes = HashMap::new();
+    for line in content.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+        let (hash, count) = parse_hash_line(line);
+        if hash.len() == 40 && hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+            *hashes.entry(hash.to_ascii_uppercase()).or_insert(0) += count;
+        } else {
+            *hashes.entry(sha1_hex(line)).or_insert(0) += 1;
+        }
+    }
+    tracing::info!("loaded {} breached password hashes from {}", hashes.len(), path.display());
+    Some(BreachedList::Hashes(hashes))
+}
+
+/// 返回密码在泄露列表中出现的次数，未配置列表或没有出现时为 0。
+pub async fn breached_count(password: &str) -> u64 {
+    breached_count_in(BREACHED_LIST.as_ref(), password).await
+}
+
+async fn breached_count_in(list: Option<&BreachedList>, password: &str) -> u64 {
+    let hash = sha1_hex(password);
+    match list {
+        None => 0,
+        Some(BreachedList::Hashes(hashes)) => hashes.get(&hash).copied().unwrap_or(0),
+        Some(BreachedList::Prefixes(dir)) => {
+            let (prefix, suffix) = hash.split_at(5);
+            let mut content = None;
+            for name in [prefix.to_string(), format!("{}.txt", prefix), prefix.to_lowercase()] {
+                if let Ok(data) = tokio::fs::read_to_string(dir.join(name)).await {
+                    content = Some(data);
+                    break;
+                }
+            }
+            content
+                .unwrap_or_default()
+                .lines()
+                .map(|line| parse_hash_line(line.trim()))
+                .find(|(candidate, _)| candidate.eq_ignore_ascii_case(suffix))
+                .map_or(0, |(_, count)| count)
+        }
+    }
+}
+
+/// 按配置检查新密码，返回所有不满足的规则，全部满足时为空。
+pub async fn check(password: &str, username: &str, email: Option<&str>) -> Vec<PolicyViolation> {
+    check_with(&config().password_policy, BREACHED_LIST.as_ref(), password, username, email).await
+}
+
+async fn check_with(
+    policy: &PasswordPolicyConfig,
+    breached_list: Option<&BreachedList>,
+    password: &str,
+    username: &str,
+    email: Option<&str>
+) -> Vec<PolicyViolation> {
+    let mut violations = Vec::new();
+
+    let len = password.chars().count();
+    if len < policy.min_length {
+        violations.push(PolicyViolation::TooShort { min: policy.min_length });
+    }
+    if len > policy.max_length {
+        // 过长的密码不再做其他检查，避免强度估计的开销。
+        violations.push(PolicyViolation::TooLong { max: policy.max_length });
+        return violations;
+    }
+    let classes = [
+        (policy.require_lowercase, password.chars().any(char::is_lowercase), PolicyViolation::MissingLowercase),
+        (policy.require_uppercase, password.chars().any(char::is_uppercase), PolicyViolation::MissingUppercase),
+        (policy.require_digit, password.chars().any(|c| c.is_ascii_digit()), PolicyViolation::MissingDigit),
+        (policy.require_symbol, password.chars().any(|c| !c.is_alphanumeric()), PolicyViolation::MissingSymbol),
+    ];
+    violations.extend(classes.into_iter().filter(|(required, present, _)| *required && !present).map(|(_, _, v)| v));
+
+    let lower = password.to_lowercase();
+    if policy.reject_personal_info {
+        let username = username.trim().to_lowercase();
+        if username.chars().count() >= 3 && lower.contains(&username) {
+            violations.push(PolicyViolation::ContainsUsername);
+        }
+        if let Some(email) = email.map(str::to_lowercase) {
+            let local = email.split('@').next().unwrap_or_default();
+            if lower.contains(&email) || (local.chars().count() >= 3 && lower.contains(local)) {
+                violations.push(PolicyViolation::ContainsEmail);
+            }
+        }
+    }
+
+    let estimate = strength::estimate(password, &[username, email.unwrap_or_default()]);
+    if estimate.score < policy.min_strength {
+        let mut hints: Vec<&'static str> = estimate.patterns.iter().map(|pattern| pattern.hint()).collect();
+        if hints.is_empty() || !estimate.patterns.contains(&Pattern::Dictionary) {
+            hints.push("use a longer password, for example several unrelated words");
+        }
+        violations.push(PolicyViolation::TooWeak { score: estimate.score, min_score: policy.min_strength, hints });
+    }
+
+    let count = breached_count_in(breached_list, password).await;
+    if count > 0 && count >= policy.breached_min_count {
+        violations.push(PolicyViolation::Breached { count });
+    }
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 只检查长度的规则，便于单独测试其他规则。
+    fn lenient() -> PasswordPolicyConfig {
+        PasswordPolicyConfig { min_strength: 0, reject_personal_info: false, ..PasswordPolicyConfig::default() }
+    }
+
+    async fn codes(policy: &PasswordPolicyConfig, password: &str, username: &str, email: Option<&str>) -> Vec<&'static str> {
+        check_with(policy, None, password, username, email).await.iter().map(PolicyViolation::code).collect()
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("btcm-policy-{}-{}", name, std::process::id()))
+    }
+
+    #[tokio::test]
+    async fn length_limits() {
+        let policy = lenient();
+        assert_eq!(check_with(&policy, None, "short", "", None).await, vec![PolicyViolation::TooShort { min: 8 }]);
+        assert!(codes(&policy, "eight ch", "", None).await.is_empty());
+        assert!(codes(&policy, &"x".repeat(128), "", None).await.is_empty());
+        // 超长时直接返回，不再检查字符类别、个人信息和强度。
+        let strict = PasswordPolicyConfig { require_uppercase: true, require_digit: true, reject_personal_info: true, min_strength: 4, ..PasswordPolicyConfig::default() };
+        let long = format!("alice{}", "a".repeat(124));
+        assert_eq!(check_with(&strict, None, &long, "alice", None).await, vec![PolicyViolation::TooLong { max: 128 }]);
+    }
+
+    #[tokio::test]
+    async fn character_classes() {
+        let policy = PasswordPolicyConfig { require_lowercase: true, require_uppercase: true, require_digit: true, require_symbol: true, ..lenient() };
+        assert_eq!(codes(&policy, "abcdefgh", "", None).await, vec!["missing_uppercase", "missing_digit", "missing_symbol"]);
+        assert_eq!(codes(&policy, "ABCDEFG1", "", None).await, vec!["missing_lowercase", "missing_symbol"]);
+        assert_eq!(codes(&policy, "Abcdefg!", "", None).await, vec!["missing_digit"]);
+        assert!(codes(&policy, "Abcdef1!", "", None).await.is_empty());
+        assert!(codes(&lenient(), "abcdefgh", "", None).await.is_empty(), "classes are optional by default");
+    }
+
+    #[tokio::test]
+    async fn personal_information() {
+        let policy = PasswordPolicyConfig { reject_personal_info: true, ..lenient() };
+        assert_eq!(codes(&policy, "xx-ALICE-xx", "Alice", None).await, vec!["contains_username"]);
+        assert!(codes(&policy, "xx-al-xx-yy", "al", None).await.is_empty(), "short usernames are ignored");
+        let email = Some("Bob.Smith@example.com");
+        assert_eq!(codes(&policy, "my bob.smith pw", "carol", email).await, vec!["contains_email"]);
+        assert_eq!(codes(&policy, "BOB.SMITH@EXAMPLE.COM", "carol", email).await, vec!["contains_email"]);
+        assert_eq!(codes(&policy, "carol bob.smith", "carol", email).await, vec!["contains_username", "contains_email"]);
+        assert!(codes(&policy, "unrelated words", "carol", email).await.is_empty());
+        assert!(codes(&lenient(), "xx-alice-xx", "alice", None).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn weak_passwords_get_hints() {
+        let policy = PasswordPolicyConfig { min_strength: 2, ..lenient() };
+        let violations = check_with(&policy, None, "password", "", None).await;
+        let [PolicyViolation::TooWeak { score, min_score, hints }] = violations.as_slice() else { panic!("{:?}", violations) };
+        assert_eq!((*score, *min_score), (0, 2));
+        assert!(hints.contains(&Pattern::Dictionary.hint()), "{:?}", hints);
+        assert!(codes(&policy, "vivid-cobalt-harbor-91", "", None).await.is_empty());
+    }
+
+    #[test]
+    fn hash_lines() {
+        assert_eq!(parse_hash_line("ABCDEF:12"), ("ABCDEF", 12));
+        assert_eq!(parse_hash_line("ABCDEF : 3 "), ("ABCDEF", 3));
+        assert_eq!(parse_hash_line("ABCDEF:x"), ("ABCDEF", 1));
+        assert_eq!(parse_hash_line("ABCDEF"), ("ABCDEF", 1));
+    }
+
+    #[tokio::test]
+    async fn breached_file_list() {
+        let path = temp_path("list.txt");
+        let content = format!(
+            "# comment\n\n{}:5\n{}:2\nletmein2024\nletmein2024\n",
+            sha1_hex("hunter2"),
+            sha1_hex("hunter2").to_lowercase()
+        );
+        std::fs::write(&path, content).unwrap();
+        let list = load_breached_list(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(list, Some(BreachedList::Hashes(_))));
+        assert_eq!(breached_count_in(list.as_ref(), "hunter2").await, 7);
+        assert_eq!(breached_count_in(list.as_ref(), "letmein2024").await, 2);
+        assert_eq!(breached_count_in(list.as_ref(), "not-in-the-list").await, 0);
+        assert_eq!(breached_count_in(None, "hunter2").await, 0);
+        assert!(load_breached_list(temp_path("missing.txt").to_str().unwrap()).is_none());
+
+        let policy = PasswordPolicyConfig { breached_min_count: 5, ..lenient() };
+        let violations = check_with(&policy, list.as_ref(), "hunter2-", "", None).await;
+        assert!(violations.is_empty(), "{:?}", violations);
+        let violations = check_with(&policy, list.as_ref(), "letmein2024", "", None).await;
+        assert!(violations.is_empty(), "below the minimum count: {:?}", violations);
+        let policy = PasswordPolicyConfig { breached_min_count: 2, ..lenient() };
+        assert_eq!(check_with(&policy, list.as_ref(), "letmein2024", "", None).await, vec![PolicyViolation::Breached { count: 2 }]);
+    }
+
+    #[tokio::test]
+    async fn breached_prefix_directory() {
+        let dir = temp_path("prefixes");
+        std::fs::create_dir_all(&dir).unwrap();
+        let hash = sha1_hex("hunter2");
+        let (prefix, suffix) = hash.split_at(5);
+        std::fs::write(dir.join(format!("{}.txt", prefix)), format!("{}:1\n{}:42\n", "0".repeat(35), suffix.to_lowercase())).unwrap();
+        let other = sha1_hex("letmein2024");
+        let (other_prefix, other_suffix) = other.split_at(5);
+        std::fs::write(dir.join(other_prefix), format!("{}:3\n", other_suffix)).unwrap();
+        let list = load_breached_list(dir.to_str().unwrap());
+        assert!(matches!(list, Some(BreachedList::Prefixes(_))));
+        assert_eq!(breached_count_in(list.as_ref(), "hunter2").await, 42);
+        assert_eq!(breached_count_in(list.as_ref(), "letmein2024").await, 3);
+        assert_eq!(breached_count_in(list.as_ref(), "not-in-the-list").await, 0);
+        assert_eq!(check_with(&lenient(), list.as_ref(), "hunter2!", "", None).await, vec![]);
+        assert_eq!(check_with(&lenient(), list.as_ref(), "letmein2024", "", None).await, vec![PolicyViolation::Breached { count: 3 }]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn violations_serialize_with_parameters() {
+        let value = serde_json::to_value(PolicyViolation::TooShort { min: 8 }).unwrap();
+        assert_eq!(value, serde_json::json!({ "code": "too_short", "message": "must be at least 8 characters", "min": 8 }));
+        let value = serde_json::to_value(PolicyViolation::Breached { count: 3 }).unwrap();
+        assert_eq!(value["count"], 3);
+        let value = serde_json::to_value(PolicyViolation::MissingSymbol).unwrap();
+        assert_eq!(value, serde_json::json!({ "code": "missing_symbol", "message": "must contain a symbol" }));
+    }
+}
@@ -0,0 +1,398 @@
+//! # 用户资料
+//!
+//! 用户资料（[`Profile`]）随用户记录一起保存在用户存储中，包括显示名称、头像、简介、语言、
+//! 时区和状态文字。用户只能读取和修改自己的完整资料（[`OwnProfile`]），其他用户只能看到
+//! 不含邮箱、语言和时区的公开资料（[`PublicProfile`]）。
+//!
+//! 修改使用部分更新（[`ProfileUpdate`]）：缺省的字段保持不变，`null` 或空串清空该字段。
+//! 每次修改使资料版本加一；更新时可以带上读取到的版本，或 HTTP 中读取到的 `ETag`（`If-Match`）。
+//! `ETag` 是完整资料（含邮箱和邮箱验证状态）的摘要，见 [`etag`]。
+//! 版本或 `ETag` 不一致说明资料已被其他请求修改，返回 [`AccountError::ProfileConflict`]，客户端应重新读取后再提交。
+//! 修改通过 [`userstore::modify`] 按记录版本比较并写入，不会覆盖同时进行的其他修改。
+
+use ring::digest::{ digest, SHA256 };
+use serde::{ Deserialize, Deserializer, Serialize };
+
+use super::AccountError;
+use crate::userstore::{ self, Profile, UserRecord };
+
+/// 显示名称最长长度（字符数）。
+pub const DISPLAY_NAME_MAX_LEN: usize = 64;
+/// 头像地址最长长度（字节数）。
+pub const AVATAR_URL_MAX_LEN: usize = 512;
+/// 个人简介最长长度（字符数）。
+pub const BIO_MAX_LEN: usize = 500;
+/// 语言标签最长长度（字节数）。
+pub const LOCALE_MAX_LEN: usize = 35;
+/// 时区名最长长度（字节数）。
+pub const TIME_ZONE_MAX_LEN: usize = 64;
+/// 状态文字最长长度（字符数）。
+pub const STATUS_TEXT_MAX_LEN: usize = 140;
+
+/// 一个字段的校验错误。
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldError {
+    /// 字段名。
+    pub field: &'static str,
+    /// 错误描述。
+    pub message: String,
+}
+
+impl FieldError {
+    fn new(field: &'static str, message: impl Into<String>) -> Self {
+        FieldError { field, message: message.into() }
+    }
+}
+
+/// 资料的部分更新。字段缺省表示不修改，`null` 或空串（去掉首尾空白后）表示清空。
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ProfileUpdate {
+    /// 显示名称。
+    #[serde(default, deserialize_with = "patch")]
+    pub display_name: Option<Option<String>>,
+    /// 头像地址。
+    #[serde(default, deserialize_with = "patch")]
+    pub avatar_url: Option<Option<String>>,
+    /// 个人简介。
+    #[serde(default, deserialize_with = "patch")]
+    pub bio: Option<Option<String>>,
+    /// 语言。
+    #[serde(default, deserialize_with = "patch")]
+    pub locale: Option<Option<String>>,
+    /// 时区。
+    #[serde(default, deserialize_with = "patch")]
+    pub time_zone: Option<Option<String>>,
+    /// 状态文字。
+    #[serde(default, deserialize_with = "patch")]
+    pub status_text: Option<Option<String>>,
+    /// 客户端读取到的资料版本。填写时必须与当前版本一致。
+    #[serde(default)]
+    pub version: Option<u64>,
+    /// 客户端读取到的 `ETag`，由 HTTP 请求头 `If-Match` 设置。填写时必须与当前资料的 [`etag`] 强比较一致，见 [`etag_matches_strong`]。
+    #[serde(skip)]
+    pub etag: Option<String>,
+}
+
+/// 区分缺省字段（`None`）和 `null`（`Some(None)`）。
+fn patch<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Option<String>>, D::Error> {
+    Option::<String>::deserialize(deserializer).map(Some)
+}
+
+/// 用户本人看到的资料。
+#[derive(Debug, Clone, Serialize)]
+pub struct OwnProfile {
+    /// 用户 ID。
+    pub user_id: u64,
+    /// 用户名。
+    pub username: String,
+    /// 邮箱。
+    pub email: Option<String>,
+    /// 邮箱是否已验证。
+    pub email_verified: bool,
+    /// 资料的全部字段和版本。
+    #[serde(flatten)]
+    pub profile: Profile,
+}
+
+impl From<UserRecord> for OwnProfile {
+    fn from(user: UserRecord) -> Self {
+        OwnProfile {
+            user_id: user.id,
+            username: user.username,
+            email: user.email,
+            email_verified: user.email_verified,
+            profile: user.profile,
+        }
+    }
+}
+
+/// 其他用户看到的公开资料，不包含邮箱、语言、时区和版本。
+#[derive(Debug, Clone, Serialize)]
+pub struct PublicProfile {
+    /// 用户 ID。
+    pub user_id: u64,
+    /// 用户名。
+    pub username: String,
+    /// 显示名称。
+    pub display_name: Option<String>,
+    /// 头像地址。
+    pub avatar_url: Option<String>,
+    /// 个人简介。
+    pub bio: Option<String>,
+    /// 状态文字。
+    pub status_text: Option<String>,
+}
+
+impl From<UserRecord> for PublicProfile {
+    fn from(user: UserRecord) -> Self {
+        PublicProfile {
+            user_id: user.id,
+            username: user.username,
+            display_name: user.profile.display_name,
+            avatar_url: user.profile.avatar_url,
+            bio: user.profile.bio,
+            status_text: user.profile.status_text,
+        }
+    }
+}
+
+/// 资料的 HTTP `ETag`：序列化后的完整资料的 SHA-256 摘要（前 16 字节，十六进制，带引号）。
+///
+/// 资料中任何字段变化（包括邮箱和邮箱验证状态）都会改变 `ETag`。
+pub fn etag(own: &OwnProfile) -> String {
+    let json = serde_json::to_vec(own).unwrap_or_default();
+    let hash: String = digest(&SHA256, &json).as_ref()[..16].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("\"{}\"", hash)
+}
+
+/// `If-None-Match` 的值是否包含 `etag`。值可以是逗号分隔的列表或 `*`，按弱比较接受 `W/"..."` 形式。
+pub fn etag_matches(value: &str, etag: &str) -> bool {
+    value.split(',').map(str::trim).any(|candidate| {
+        candidate == "*" || candidate.strip_prefix("W/").unwrap_or(candidate) == etag
+    })
+}
+
+/// `If-Match` 的值是否包含 `etag`。`If-Match` 要求强比较（RFC 9110 §13.1.1），弱校验形式 `W/"..."` 不匹配。
+pub fn etag_matches_strong(value: &str, etag: &str) -> bool {
+    value.split(',').map(str::trim).any(|candidate| candidate == "*" || candidate == etag)
+}
+
+/// 读取用户本人的资料。
+pub async fn get_own(user_id: u64) -> Result<OwnProfile, AccountError> {
+    let user = userstore::user_store().get_by_id(user_id).await?.ok_or(AccountError::Unauthorized)?;
+    Ok(user.into())
+}
+
+/// 按用户 ID 读取公开资料。
+pub async fn get_public(user_id: u64) -> Result<PublicProfile, AccountError> {
+    let user = userstore::user_store().get_by_id(user_id).await?.ok_or(AccountError::UserNotFound)?;
+    Ok(user.into())
+}
+
+/// 按用户名读取公开资料。
+pub async fn get_public_by_username(username: &str) -> Result<PublicProfile, AccountError> {
+    let user = userstore::user_store().get_by_username(username).await?.ok_or(AccountError::UserNotFound)?;
+    Ok(user.into())
+}
+
+/// 去掉首尾空白，空串视为清空。
+fn normalize(value: &Option<Option<String>>) -> Option<Option<String>> {
+    value.as_ref().map(|value| {
+        value.as_deref().map(str::trim).filter(|value| !value.is_empty()).map(str::to_string)
+    })
+}
+
+fn check_text(errors: &mut Vec<FieldError>, field: &'static str, value: &str, max_len: usize, multiline: bool) {
+    if value.chars().count() > max_len {
+        errors.push(FieldError::new(field, format!("must be at most {} characters", max_len)));
+    }
+    if value.chars().any(|c| c.is_control() && !(multiline && c == '\n')) {
+        errors.push(FieldError::new(field, "must not contain control characters"));
+    }
+}
+
+fn is_valid_avatar_url(url: &str) -> bool {
+    let Some(rest) = url.strip_prefix("https://") else {
+        return false;
+    };
+    let host = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    !host.is_empty() && !url.chars().any(|c| c.is_whitespace() || c.is_control())
+}
+
+/// 语言标签：2 到 3 个字母的主标签，后跟若干个由 `-` 分隔的 1 到 8 位字母数字子标签。
+fn is_valid_locale(locale: &str) -> bool {
+    let mut subtags = locale.split('-');
+    let primary = subtags.next().unwrap_or_default();
+    (2..=3).contains(&primary.len())
+        && primary.chars().all(|c| c.is_ascii_alphabetic())
+        && subtags.all(|tag| (1..=8).contains(&tag.len()) && tag.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+/// 时区名：`UTC`，或由 `/` 分隔、以大写字母开头的 IANA 时区名（如 `America/Argentina/Buenos_Aires`）。
+fn is_valid_time_zone(time_zone: &str) -> bool {
+    time_zone == "UTC"
+        || time_zone.contains('/')
+            && time_zone.split('/').all(|part| {
+                part.chars().next().is_some_and(|c| c.is_ascii_uppercase())
+                    && part.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '+'))
+            })
+}
+
+/// 校验更新中的所有字段，返回全部错误。
+pub fn validate(update: &ProfileUpdate) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    if let Some(Some(display_name)) = normalize(&update.display_name) {
+        check_text(&mut errors, "display_name", &display_name, DISPLAY_NAME_MAX_LEN, false);
+    }
+    if let Some(Some(avatar_url)) = normalize(&update.avatar_url) {
+        if avatar_url.len() > AVATAR_URL_MAX_LEN {
+            errors.push(FieldError::new("avatar_url", format!("must be at most {} bytes", AVATAR_URL_MAX_LEN)));
+        } else if !is_valid_avatar_url(&avatar_url) {
+            errors.push(FieldError::new("avatar_url", "must be an https:// URL"));
+        }
+    }
+    if let Some(Some(bio)) = normalize(&update.bio) {
+        check_text(&mut errors, "bio", &bio, BIO_MAX_LEN, true);
+    }
+    if let Some(Some(locale)) = normalize(&update.locale) {
+        if locale.len() > LOCALE_MAX_LEN || !is_valid_locale(&locale) {
+            errors.push(FieldError::new("locale", "must be a language tag such as 'zh-CN'"));
+        }
+    }
+    if let Some(Some(time_zone)) = normalize(&update.time_zone) {
+        if time_zone.len() > TIME_ZONE_MAX_LEN || !is_valid_time_zone(&time_zone) {
+            errors.push(FieldError::new("time_zone", "must be a time zone name such as 'Asia/Shanghai'"));
+        }
+    }
+    if let Some(Some(status_text)) = normalize(&update.status_text) {
+        check_text(&mut errors, "status_text", &status_text, STATUS_TEXT_MAX_LEN, false);
+    }
+    errors
+}
+
+/// 把更新应用到资料上，返回是否有字段发生变化。
+fn apply(profile: &mut Profile, update: &ProfileUpdate) -> bool {
+    let mut changed = false;
+    let fields = [
+        (&mut profile.display_name, &update.display_name),
+        (&mut profile.avatar_url, &update.avatar_url),
+        (&mut profile.bio, &update.bio),
+        (&mut profile.locale, &update.locale),
+        (&mut profile.time_zone, &update.time_zone),
+        (&mut profile.status_text, &update.status_text),
+    ];
+    for (current, value) in fields {
+        if let Some(value) = normalize(value) {
+            if *current != value {
+                *current = value;
+                changed = true;
+            }
+        }
+    }
+    changed
+}
+
+/// 修改用户本人的资料，返回修改后的资料。
+///
+/// 字段不符合规则时返回 [`AccountError::InvalidProfile`]，列出所有错误的字段；
+/// `update.version` 或 `update.etag` 与当前资料不一致时返回 [`AccountError::ProfileConflict`]。
+/// 没有字段变化时不增加版本。
+pub async fn update(user_id: u64, update: &ProfileUpdate) -> Result<OwnProfile, AccountError> {
+    let errors = validate(update);
+    if !errors.is_empty() {
+        return Err(AccountError::InvalidProfile(errors));
+    }
+    let store = userstore::user_store();
+    let user = userstore::modify(store.as_ref(), user_id, |user| {
+        if update.version.is_some_and(|version| version != user.profile.version) {
+            return Err(AccountError::ProfileConflict);
+        }
+        if update.etag.as_deref().is_some_and(|value| !etag_matches_strong(value, &etag(&user.clone().into()))) {
+            return Err(AccountError::ProfileConflict);
+        }
+        if !apply(&mut user.profile, update) {
+            return Ok(false);
+        }
+        user.profile.version += 1;
+        Ok(true)
+    }).await;
+    match user {
+        Ok(user) => Ok(user.into()),
+        Err(AccountError::UserNotFound) => Err(AccountError::Unauthorized),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> ProfileUpdate {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn fields(errors: &[FieldError]) -> Vec<&str> {
+        errors.iter().map(|error| error.field).collect()
+    }
+
+    #[test]
+    fn valid_fields_pass() {
+        let update = parse(r#"{
+            "display_name": "  Alice  ", "avatar_url": "https://cdn.example.com/a.png?s=64",
+            "bio": "line one\nline two", "locale": "zh-Hans-CN", "time_zone": "America/Argentina/Buenos_Aires",
+            "status_text": "busy"
+        }"#);
+        assert_eq!(validate(&update), []);
+        assert_eq!(validate(&parse(r#"{"time_zone": "UTC", "locale": "en"}"#)), []);
+    }
+
+    #[test]
+    fn invalid_fields_are_all_reported() {
+        let update = ProfileUpdate {
+            display_name: Some(Some("x".repeat(DISPLAY_NAME_MAX_LEN + 1))),
+            avatar_url: Some(Some("http://example.com/a.png".to_string())),
+            bio: Some(Some("tab\there".to_string())),
+            locale: Some(Some("english".to_string())),
+            time_zone: Some(Some("asia/shanghai".to_string())),
+            status_text: Some(Some("line\nbreak".to_string())),
+            ..Default::default()
+        };
+        assert_eq!(fields(&validate(&update)), ["display_name", "avatar_url", "bio", "locale", "time_zone", "status_text"]);
+
+        let long_url = format!("https://example.com/{}", "a".repeat(AVATAR_URL_MAX_LEN));
+        let errors = validate(&ProfileUpdate { avatar_url: Some(Some(long_url)), ..Default::default() });
+        assert!(errors[0].message.contains("bytes"));
+        for url in ["https://", "https:///path", "https://example.com/a b"] {
+            assert_eq!(fields(&validate(&ProfileUpdate { avatar_url: Some(Some(url.to_string())), ..Default::default() })), ["avatar_url"], "{}", url);
+        }
+        assert_eq!(validate(&ProfileUpdate { display_name: Some(Some("名".repeat(DISPLAY_NAME_MAX_LEN))), ..Default::default() }), [], "lengths count characters");
+    }
+
+    #[test]
+    fn cleared_fields_are_not_validated() {
+        assert_eq!(validate(&parse(r#"{"avatar_url": null, "locale": "   ", "time_zone": ""}"#)), []);
+    }
+
+    #[test]
+    fn missing_fields_are_kept_and_null_or_blank_clears() {
+        let mut profile = Profile {
+            display_name: Some("Alice".to_string()),
+            bio: Some("hello".to_string()),
+            locale: Some("en".to_string()),
+            status_text: Some("busy".to_string()),
+            ..Default::default()
+        };
+        let update = parse(r#"{"bio": null, "locale": "  ", "status_text": " away "}"#);
+        assert!(update.display_name.is_none());
+        assert_eq!(update.bio, Some(None));
+        assert!(apply(&mut profile, &update));
+        assert_eq!(profile.display_name.as_deref(), Some("Alice"), "missing fields are unchanged");
+        assert_eq!(profile.bio, None, "null clears");
+        assert_eq!(profile.locale, None, "blank clears");
+        assert_eq!(profile.status_text.as_deref(), Some("away"), "values are trimmed");
+
+        assert!(!apply(&mut profile, &parse(r#"{"status_text": "away", "bio": null}"#)), "unchanged values are not a change");
+        assert!(!apply(&mut profile, &parse("{}")));
+    }
+
+    #[test]
+    fn if_none_match_uses_weak_comparison() {
+        let etag = "\"0123abcd\"";
+        assert!(etag_matches(etag, etag));
+        assert!(etag_matches("W/\"0123abcd\"", etag));
+        assert!(etag_matches("\"other\", W/\"0123abcd\"", etag));
+        assert!(etag_matches("*", etag));
+        assert!(!etag_matches("\"other\"", etag));
+        assert!(!etag_matches("0123abcd", etag), "quotes are part of the tag");
+    }
+
+    #[test]
+    fn if_match_uses_strong_comparison() {
+        let etag = "\"0123abcd\"";
+        assert!(etag_matches_strong(etag, etag));
+        assert!(etag_matches_strong("\"other\" , \"0123abcd\"", etag));
+        assert!(etag_matches_strong("*", etag));
+        assert!(!etag_matches_strong("W/\"0123abcd\"", etag), "weak tags never match strongly");
+        assert!(!etag_matches_strong("\"other\"", etag));
+    }
+}
@@ -0,0 +1,161 @@
+//! # 找回密码
+//!
+//! 用户提交用户名或邮箱后，服务器向账户的邮箱发送一个随机的重置令牌。令牌只能使用一次，
+//! 在 `email.password_reset_ttl_secs` 后过期，服务器只保存它的 SHA-256 摘要（见 [`crate::kvstore`]）。
+//! 同一用户再次申请时，之前发出的令牌立即失效。令牌与发送到的邮箱绑定，账户的邮箱改变后令牌失效。
+//!
+//! 申请的处理（查找账户和发送邮件）在后台进行，响应与账户是否存在、是否发送了邮件无关。
+//!
+//! 使用令牌设置新密码后，该用户此前签发的所有访问令牌、刷新令牌和会话全部失效
+//! （见 [`crate::token::revoke::revoke_all_tokens`]），用户名的登录锁定也随之解除。
+
+use ring::digest::{ digest, SHA256 };
+use serde::{ Deserialize, Serialize };
+
+use super::{ lockout, password, validate_password, AccountError };
+use crate::config::config;
+use crate::kvstore::kv_store;
+use crate::mailer::{ mailer, MailMessage };
+use crate::token;
+use crate::userstore::{ self, UserRecord };
+
+/// 重置令牌的键前缀，完整键为 `btcm:pwreset:token:{sha256}`，值为 [`ResetRecord`]。
+const TOKEN_KEY_PREFIX: &str = "btcm:pwreset:token:";
+/// 用户当前有效的重置令牌摘要的键前缀，完整键为 `btcm:pwreset:user:{user_id}`。
+const USER_KEY_PREFIX: &str = "btcm:pwreset:user:";
+/// 申请冷却期的键前缀，完整键为 `btcm:pwreset:cooldown:{user_id}`。
+const COOLDOWN_KEY_PREFIX: &str = "btcm:pwreset:cooldown:";
+/// 同一用户两次申请之间的最短间隔（秒）。
+const COOLDOWN_SECS: u64 = 60;
+
+/// 重置令牌对应的账户和令牌发送到的邮箱。
+#[derive(Debug, Serialize, Deserialize)]
+struct ResetRecord {
+    user_id: u64,
+    email: String,
+}
+
+fn digest_hex(token: &str) -> String {
+    digest(&SHA256, token.as_bytes()).as_ref().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn internal(err: impl ToString) -> AccountError {
+    AccountError::Internal(err.to_string())
+}
+
+/// 按用户名或邮箱（包含 `@` 时）查找用户。
+async fn find_user(identifier: &str) -> Result<Option<UserRecord>, AccountError> {
+    let store = userstore::user_store();
+    Ok(if identifier.contains('@') {
+        store.get_by_email(identifier).await?
+    } else {
+        store.get_by_username(identifier).await?
+    })
+}
+
+/// 申请重置密码：在后台查找账户并向账户的邮箱发送重置令牌，立即返回。
+///
+/// 为了不泄露账户是否存在，调用者无法得知用户是否存在、是否有邮箱、是否处于冷却期或发送是否成功，
+/// 这些情况只记录在日志中。
+pub fn request_password_reset(identifier: &str) {
+    let identifier = identifier.trim().to_string();
+    tokio::spawn(async move {
+        let result = async {
+            let Some(user) = find_user(&identifier).await? else {
+                tracing::debug!("password reset requested for unknown account");
+                return Ok(());
+            };
+            send_reset_email(&user).await.map(|_| ())
+        }.await;
+        if let Err(err) = result {
+            tracing::error!("failed to handle password reset request: {}", err);
+        }
+    });
+}
+
+/// 向用户的邮箱发送重置令牌，返回是否发送。用户没有邮箱或处于冷却期时不发送。
+pub async fn send_reset_email(user: &UserRecord) -> Result<bool, AccountError> {
+    let Some(email) = user.email.clone() else {
+        tracing::debug!("password reset requested for user {} without email", user.id);
+        return Ok(false);
+    };
+    let kv = kv_store();
+    let cooldown_key = format!("{}{}", COOLDOWN_KEY_PREFIX, user.id);
+    if !kv.set_nx(&cooldown_key, "1", Some(COOLDOWN_SECS)).await.map_err(internal)? {
+        tracing::debug!("password reset for user {} is in cooldown", user.id);
+        return Ok(false);
+    }
+
+    let ttl = config().email.password_reset_ttl_secs.max(1);
+    let token = format!("{}{}", token::new_token_id(), token::new_token_id());
+    let hash = digest_hex(&token);
+    let user_key = format!("{}{}", USER_KEY_PREFIX, user.id);
+    if let Some(previous) = kv.get(&user_key).await.map_err(internal)? {
+        kv.delete(&format!("{}{}", TOKEN_KEY_PREFIX, previous)).await.map_err(internal)?;
+    }
+    let record = ResetRecord { user_id: user.id, email: email.clone() };
+    let data = serde_json::to_string(&record).map_err(internal)?;
+    kv.set(&format!("{}{}", TOKEN_KEY_PREFIX, hash), &data, Some(ttl as u64)).await.map_err(internal)?;
+    kv.set(&user_key, &hash, Some(ttl as u64)).await.map_err(internal)?;
+
+    let message = MailMessage {
+        to: email,
+        subject: "Reset your Bitcomm password".into(),
+        body: format!(
+            "Hello {},\n\nSomeone asked to reset the password of your Bitcomm account. Use the code below to choose a new password:\n\n{}\n\nThe code can be used once and expires in {} minutes. If you did not ask for this, you can ignore this email; your password has not been changed.\n",
+            user.username,
+            token,
+            ttl / 60
+        ),
+    };
+    mailer().send(&message).await.map_err(internal)?;
+    tracing::info!("sent password reset email to user {}", user.id);
+    Ok(true)
+}
+
+/// 使用重置令牌设置新密码，成功时返回用户记录。
+///
+/// 令牌未知、已使用、已过期，或账户的邮箱已不是令牌发送到的邮箱时返回 [`AccountError::InvalidResetToken`]；
+/// 新密码不符合规则时返回 [`AccountError::InvalidPassword`]，此时令牌仍然有效。
+/// 令牌送达了账户当前的邮箱，因此重置成功同时将邮箱标记为已验证。
+pub async fn reset_password(token: &str, new_password: &str) -> Result<UserRecord, AccountError> {
+    let kv = kv_store();
+    let token_key = format!("{}{}", TOKEN_KEY_PREFIX, digest_hex(token.trim()));
+    let record = kv
+        .get(&token_key)
+        .await
+        .map_err(internal)?
+        .and_then(|data| serde_json::from_str::<ResetRecord>(&data).ok())
+        .ok_or(AccountError::InvalidResetToken)?;
+    let user_id = record.user_id;
+    let store = userstore::user_store();
+    let user = store
+        .get_by_id(user_id)
+        .await?
+        .filter(|user| user.email.as_deref() == Some(record.email.as_str()))
+        .ok_or(AccountError::InvalidResetToken)?;
+    validate_password(new_password, &user.username, user.email.as_deref()).await?;
+    // 校验通过后才使令牌失效；并发的两次重置只有一次能取到令牌。
+    if kv.take(&token_key).await.map_err(internal)?.is_none() {
+        return Err(AccountError::InvalidResetToken);
+    }
+    kv.delete(&format!("{}{}", USER_KEY_PREFIX, user_id)).await.map_err(internal)?;
+
+    let password_hash = password::hash_password(new_password).await?;
+    let user = userstore::modify(store.as_ref(), user_id, |user| {
+        // 邮箱可能在校验之后被修改。
+        if user.email.as_deref() != Some(record.email.as_str()) {
+            return Err(AccountError::InvalidResetToken);
+        }
+        user.password_hash = password_hash.clone();
+        user.email_verified = true;
+        Ok(true)
+    }).await.map_err(|err| match err {
+        AccountError::UserNotFound => AccountError::InvalidResetToken,
+        err => err,
+    })?;
+    token::revoke::revoke_all_tokens(user.id).await?;
+    lockout::clear(&lockout::Subject::account(&user.username)).await?;
+    tracing::info!("user {} reset password", user.id);
+    Ok(user)
+}
@@ -0,0 +1,327 @@
+//! # 密码强度估计
+//!
+//! 参照 zxcvbn 的思路估计猜中密码所需的次数：先在密码中找出常见密码和单词（包括大小写变化和
+//! leet 替换，例如 `p@ssw0rd`）、字母或数字序列、重复字符、键盘上相邻的按键和年份，
+//! 再用动态规划选出让总猜测次数最少的一种切分，没有匹配上的字符按暴力破解计算。
+//!
+//! 猜测次数换算为 0 到 4 的分数，阈值与 zxcvbn 相同：少于 10³ 次为 0，10⁶ 为 1，10⁸ 为 2，
+//! 10¹⁰ 为 3，更多为 4。
+
+use std::collections::HashMap;
+
+use lazy_static::lazy_static;
+
+/// 常见密码和单词，按常见程度排序，排名越靠前越容易被猜中。
+const COMMON_WORDS: &[&str] = &[
+    "password", "123456", "qwerty", "admin", "welcome", "letmein", "monkey", "dragon", "iloveyou", "football",
+    "baseball", "master", "sunshine", "shadow", "princess", "superman", "batman", "trustno1", "login", "abc123",
+    "starwars", "freedom", "whatever", "hello", "charlie", "donald", "michael", "jennifer", "jordan", "hunter",
+    "ranger", "buster", "soccer", "hockey", "killer", "george", "summer", "winter", "spring", "autumn",
+    "love", "secret", "access", "flower", "cheese", "computer", "internet", "server", "root", "user",
+    "test", "guest", "default", "changeme", "passw", "pass", "qwertyuiop", "asdfgh", "zxcvbn", "google",
+    "apple", "orange", "banana", "purple", "silver", "golden", "thomas", "robert", "daniel", "andrew",
+    "joshua", "matthew", "pepper", "ginger", "cookie", "chocolate", "diamond", "tigger", "mustang", "corvette",
+    "ferrari", "harley", "yankees", "liverpool", "chelsea", "arsenal", "matrix", "ninja", "pokemon", "naruto",
+    "bitcoin", "bitcomm", "china", "beijing", "shanghai", "woaini", "wangyi", "aini", "zhang", "wang",
+    "office", "company", "family", "friend", "forever", "dream", "angel", "heaven", "money", "lucky",
+    "happy", "smile", "music", "magic", "power", "energy", "system", "network", "security", "private",
+];
+
+/// 键盘上相邻的按键（QWERTY 布局）。
+const KEYBOARD_ROWS: &[&str] = &["1234567890", "qwertyuiop", "asdfghjkl", "zxcvbnm", "1qaz2wsx3edc4rfv5tgb6yhn7ujm8ik9ol0p"];
+
+/// 多个字符的匹配至少计为这么多次猜测，与 zxcvbn 相同。
+const MIN_MULTI_CHAR_GUESSES: f64 = 50.0;
+
+lazy_static! {
+    static ref COMMON_WORD_RANKS: HashMap<&'static str, usize> =
+        COMMON_WORDS.iter().enumerate().map(|(rank, word)| (*word, rank + 1)).collect();
+}
+
+/// 匹配到的模式，用于给出改进建议。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pattern {
+    /// 常见密码或单词（包括用户名和邮箱）。
+    Dictionary,
+    /// 字母或数字序列，例如 `abcd`、`4321`。
+    Sequence,
+    /// 重复字符，例如 `aaaa`。
+    Repeat,
+    /// 键盘上相邻的按键，例如 `qwer`。
+    Keyboard,
+    /// 年份，例如 `1999`。
+    Year,
+}
+
+impl Pattern {
+    /// 给用户的建议。
+    pub fn hint(self) -> &'static str {
+        match self {
+            Pattern::Dictionary => "avoid common words, names and passwords",
+            Pattern::Sequence => "avoid sequences like abc or 123",
+            Pattern::Repeat => "avoid repeated characters",
+            Pattern::Keyboard => "avoid keyboard patterns like qwerty",
+            Pattern::Year => "avoid years and dates",
+        }
+    }
+}
+
+/// 强度估计的结果。
+#[derive(Debug, Clone)]
+pub struct Estimate {
+    /// 0 到 4 的分数。
+    pub score: u8,
+    /// 猜测次数的常用对数。
+    pub guesses_log10: f64,
+    /// 最优切分中出现的模式，去重后按出现顺序排列。
+    pub patterns: Vec<Pattern>,
+}
+
+/// 一个匹配：`chars[start..end]` 需要 `guesses` 次猜测。
+struct Match {
+    start: usize,
+    end: usize,
+    guesses: f64,
+    pattern: Pattern,
+}
+
+/// 单个字符暴力破解的猜测次数。
+fn char_cardinality(c: char) -> f64 {
+    match c {
+        '0'..='9' => 10.0,
+        'a'..='z' | 'A'..='Z' => 26.0,
+        c if c.is_ascii() => 33.0,
+        _ => 100.0,
+    }
+}
+
+/// 还原常见的 leet 替换。
+fn unleet(c: char) -> char {
+    match c {
+        '@' | '4' => 'a',
+        '3' => 'e',
+        '1' | '!' => 'i',
+        '0' => 'o',
+        '$' | '5' => 's',
+        '7' | '+' => 't',
+        c => c,
+    }
+}
+
+fn dictionary_matches(chars: &[char], user_words: &[String], matches: &mut Vec<Match>) {
+    let lower: Vec<char> = chars.iter().map(|c| c.to_lowercase().next().unwrap_or(*c)).collect();
+    let unleeted: Vec<char> = lower.iter().map(|c| unleet(*c)).collect();
+    for start in 0..chars.len() {
+        for end in start + 3..=chars.len().min(start + 32) {
+            for (variant, leet) in [(&lower, false), (&unleeted, true)] {
+                let word: String = variant[start..end].iter().collect();
+                let rank = match COMMON_WORD_RANKS.get(word.as_str()) {
+                    Some(rank) => *rank as f64,
+                    None if user_words.contains(&word) => 1.0,
+                    None => continue,
+                };
+                let original = &chars[start..end];
+                let uppercase = original.iter().filter(|c| c.is_uppercase()).count();
+                let case_variations = match uppercase {
+                    0 => 1.0,
+                    1 if original[0].is_uppercase() => 2.0,
+                    _ => 4.0 * uppercase as f64,
+                };
+                let leet_variations = if leet && lower[start..end] != unleeted[start..end] { 4.0 } else { 1.0 };
+                matches.push(Match {
+                    start,
+                    end,
+                    guesses: rank * case_variations * leet_variations,
+                    pattern: Pattern::Dictionary,
+                });
+            }
+        }
+    }
+}
+
+fn sequence_matches(chars: &[char], matches: &mut Vec<Match>) {
+    let lower: Vec<char> = chars.iter().map(|c| c.to_ascii_lowercase()).collect();
+    let mut start = 0;
+    while start + 2 < lower.len() {
+        let delta = lower[start + 1] as i64 - lower[start] as i64;
+        let mut end = start + 1;
+        if delta.abs() == 1 {
+            while end < lower.len()
+                && lower[end] as i64 - lower[end - 1] as i64 == delta
+                && lower[end].is_ascii_alphanumeric()
+                && lower[end].is_ascii_digit() == lower[start].is_ascii_digit()
+            {
+                end += 1;
+            }
+        }
+        if end - start >= 3 && lower[start].is_ascii_alphanumeric() {
+            let first = lower[start];
+            let base = if matches!(first, 'a' | 'z' | '0' | '1' | '9') { 4.0 } else if first.is_ascii_digit() { 10.0 } else { 26.0 };
+            matches.push(Match { start, end, guesses: base * (end - start) as f64, pattern: Pattern::Sequence });
+            start = end;
+        } else {
+            start += 1;
+        }
+    }
+}
+
+fn repeat_matches(chars: &[char], matches: &mut Vec<Match>) {
+    let mut start = 0;
+    while start < chars.len() {
+        let end = start + chars[start..].iter().take_while(|c| **c == chars[start]).count();
+        if end - start >= 3 {
+            let guesses = char_cardinality(chars[start]) * (end - start) as f64;
+            matches.push(Match { start, end, guesses, pattern: Pattern::Repeat });
+        }
+        start = end;
+    }
+}
+
+fn keyboard_matches(chars: &[char], matches: &mut Vec<Match>) {
+    let lower: String = chars.iter().map(|c| c.to_ascii_lowercase()).collect();
+    let lower: Vec<char> = lower.chars().collect();
+    for start in 0..lower.len() {
+        for end in (start + 4..=lower.len()).rev() {
+            let run: String = lower[start..end].iter().collect();
+            let reversed: String = run.chars().rev().collect();
+            if KEYBOARD_ROWS.iter().any(|row| row.contains(&run) || row.contains(&reversed)) {
+                matches.push(Match { start, end, guesses: 40.0 * (end - start) as f64, pattern: Pattern::Keyboard });
+                break;
+            }
+        }
+    }
+}
+
+fn year_matches(chars: &[char], matches: &mut Vec<Match>) {
+    for start in 0..chars.len().saturating_sub(3) {
+        let digits: String = chars[start..start + 4].iter().collect();
+        if let Ok(year) = digits.parse::<u32>() {
+            if (1900..=2049).contains(&year) && digits.chars().all(|c| c.is_ascii_digit()) {
+                matches.push(Match { start, end: start + 4, guesses: 150.0, pattern: Pattern::Year });
+            }
+        }
+    }
+}
+
+/// 估计密码强度。`user_inputs` 为用户名、邮箱等与用户相关的词，按最常见的密码计算。
+pub fn estimate(password: &str, user_inputs: &[&str]) -> Estimate {
+    let chars: Vec<char> = password.chars().collect();
+    let user_words: Vec<String> = user_inputs
+        .iter()
+        .flat_map(|input| input.split(|c: char| !c.is_alphanumeric()))
+        .filter(|word| word.chars().count() >= 3)
+        .map(str::to_lowercase)
+        .collect();
+
+    let mut matches = Vec::new();
+    dictionary_matches(&chars, &user_words, &mut matches);
+    sequence_matches(&chars, &mut matches);
+    repeat_matches(&chars, &mut matches);
+    keyboard_matches(&chars, &mut matches);
+    year_matches(&chars, &mut matches);
+
+    // best[i] 为前 i 个字符的最少猜测次数（以 2 为底的对数），from[i] 为取得最优值的匹配。
+    let n = chars.len();
+    let mut best = vec![f64::INFINITY; n + 1];
+    let mut from: Vec<Option<usize>> = vec![None; n + 1];
+    best[0] = 0.0;
+    for end in 1..=n {
+        best[end] = best[end - 1] + char_cardinality(chars[end - 1]).log2();
+        for (index, m) in matches.iter().enumerate().filter(|(_, m)| m.end == end) {
+            let cost = best[m.start] + m.guesses.max(MIN_MULTI_CHAR_GUESSES).log2();
+            if cost < best[end] {
+                best[end] = cost;
+                from[end] = Some(index);
+            }
+        }
+    }
+
+    let mut used = Vec::new();
+    let mut position = n;
+    while position > 0 {
+        match from[position] {
+            Some(index) => {
+                used.push(matches[index].pattern);
+                position = matches[index].start;
+            }
+            None => position -= 1,
+        }
+    }
+    let mut patterns: Vec<Pattern> = Vec::new();
+    for pattern in used.into_iter().rev() {
+        if !patterns.contains(&pattern) {
+            patterns.push(pattern);
+        }
+    }
+
+    let guesses_log10 = best[n] / 10f64.log2();
+    let score = match guesses_log10 {
+        g if g < 3.0 => 0,
+        g if g < 6.0 => 1,
+        g if g < 8.0 => 2,
+        g if g < 10.0 => 3,
+        _ => 4,
+    };
+    Estimate { score, guesses_log10, patterns }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn common_patterns_are_weak() {
+        for (password, pattern) in [
+            ("password", Pattern::Dictionary),
+            ("P@ssw0rd", Pattern::Dictionary),
+            ("abcdefgh", Pattern::Sequence),
+            ("98765432", Pattern::Sequence),
+            ("aaaaaaaa", Pattern::Repeat),
+            ("qwertyui", Pattern::Keyboard),
+            ("1qaz2wsx", Pattern::Keyboard),
+            ("19871999", Pattern::Year),
+        ] {
+            let estimate = estimate(password, &[]);
+            assert!(estimate.score <= 1, "{} scored {}", password, estimate.score);
+            assert!(estimate.patterns.contains(&pattern), "{}: {:?}", password, estimate.patterns);
+        }
+    }
+
+    #[test]
+    fn user_inputs_count_as_dictionary_words() {
+        let without = estimate("zorglub1987", &[]);
+        let with = estimate("zorglub1987", &["Zorglub", "zorglub@example.com"]);
+        assert!(with.guesses_log10 < without.guesses_log10);
+        assert!(with.patterns.contains(&Pattern::Dictionary));
+        assert!(!without.patterns.contains(&Pattern::Dictionary));
+    }
+
+    #[test]
+    fn random_and_long_passwords_are_strong() {
+        for password in ["xK9#mQ2$vL7!zz", "vivid-cobalt-harbor-91", "Tr4v3l!ng-Orca-Lamp"] {
+            let estimate = estimate(password, &[]);
+            assert_eq!(estimate.score, 4, "{}: {:?}", password, estimate);
+        }
+    }
+
+    #[test]
+    fn scores_follow_the_zxcvbn_thresholds() {
+        assert_eq!(estimate("", &[]).score, 0);
+        // 纯数字按每位 10 次猜测计算，且不构成序列或年份时，位数即猜测次数的对数；阈值本身属于更高一档。
+        for (password, score) in [
+            ("13", 0),
+            ("135", 1),
+            ("13579", 1),
+            ("135792", 2),
+            ("1357924", 2),
+            ("13579246", 3),
+            ("135792468", 3),
+            ("1357924680", 4),
+        ] {
+            let estimate = estimate(password, &[]);
+            assert_eq!(estimate.score, score, "{}: {:?}", password, estimate);
+        }
+        let longer = ["password", "password1", "password1x9Q"].map(|password| estimate(password, &[]).guesses_log10);
+        assert!(longer.windows(2).all(|pair| pair[0] < pair[1]), "appending characters adds guesses: {:?}", longer);
+    }
+}
@@ -0,0 +1,192 @@
+//! # TOTP 一次性密码
+//!
+//! 按 RFC 6238 计算基于时间的一次性密码：HMAC-SHA1、30 秒步长、6 位数字，
+//! 这是常见身份验证器应用的默认参数。密钥以 RFC 4648 Base32（无填充）表示，
+//! 并通过 `otpauth://` URI 提供给客户端生成二维码。
+
+use rand::RngCore;
+use ring::hmac;
+
+/// 时间步长（秒）。
+pub const PERIOD_SECS: u64 = 30;
+/// 一次性密码的位数。
+pub const DIGITS: u32 = 6;
+/// 密钥长度（字节），RFC 4226 建议至少 160 位。
+const SECRET_LEN: usize = 20;
+/// 校验时允许的时钟偏差（步数），前后各一步。
+const SKEW_STEPS: u64 = 1;
+/// RFC 4648 Base32 字母表。
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// 生成一个随机密钥，返回其 Base32 表示。
+pub fn generate_secret() -> String {
+    let mut secret = [0u8; SECRET_LEN];
+    rand::rngs::OsRng.fill_bytes(&mut secret);
+    base32_encode(&secret)
+}
+
+/// 返回身份验证器应用使用的 `otpauth://totp/...` URI。
+pub fn otpauth_uri(issuer: &str, account: &str, secret: &str) -> String {
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        percent_encode(issuer),
+        percent_encode(account),
+        secret,
+        percent_encode(issuer),
+        DIGITS,
+        PERIOD_SECS
+    )
+}
+
+/// 返回 Unix 时间戳所在的时间步。
+pub fn time_step(unix_secs: u64) -> u64 {
+    unix_secs / PERIOD_SECS
+}
+
+/// 计算指定时间步的一次性密码。密钥不是合法的 Base32 时返回 `None`。
+pub fn code_at(secret: &str, step: u64) -> Option<String> {
+    let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, &base32_decode(secret)?);
+    let tag = hmac::sign(&key, &step.to_be_bytes());
+    let hash = tag.as_ref();
+    // RFC 4226 动态截断。
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([hash[offset] & 0x7f, hash[offset + 1], hash[offset + 2], hash[offset + 3]]);
+    Some(format!("{:0width$}", binary % 10u32.pow(DIGITS), width = DIGITS as usize))
+}
+
+/// 在当前时间步前后 [`SKEW_STEPS`] 步内校验一次性密码，成功时返回匹配的时间步。
+///
+/// 只接受大于 `last_step` 的时间步，同一个密码不能使用两次。
+pub fn verify(secret: &str, code: &str, now: u64, last_step: u64) -> Option<u64> {
+    let code = code.trim();
+    if code.len() != DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let current = time_step(now);
+    (current.saturating_sub(SKEW_STEPS)..=current + SKEW_STEPS)
+        .filter(|step| *step > last_step)
+        .find(|step| code_at(secret, *step).is_some_and(|expected| constant_time_eq(expected.as_bytes(), code.as_bytes())))
+}
+
+/// 按固定耗时比较两个字节串，用于比较密码、摘要等秘密值。
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn base32_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(5) * 8);
+    let (mut buffer, mut bits) = (0u32, 0u32);
+    for &byte in data {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    out
+}
+
+/// 解码 Base32，忽略大小写、空格和填充。
+fn base32_decode(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() * 5 / 8);
+    let (mut buffer, mut bits) = (0u32, 0u32);
+    for c in text.chars().filter(|c| !c.is_whitespace() && *c != '=') {
+        let value = BASE32_ALPHABET.iter().position(|&a| a as char == c.to_ascii_uppercase())? as u32;
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+/// 对 URI 路径和查询参数中的文本做百分号编码，只保留 RFC 3986 的非保留字符。
+fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// RFC 6238 附录 B 的 SHA-1 密钥 `12345678901234567890`。
+    const RFC_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    #[test]
+    fn rfc6238_sha1_vectors() {
+        // 附录 B 给出 8 位密码，6 位密码为其后 6 位。
+        for (time, expected) in [
+            (59, "94287082"),
+            (1111111109, "07081804"),
+            (1111111111, "14050471"),
+            (1234567890, "89005924"),
+            (2000000000, "69279037"),
+            (20000000000, "65353130"),
+        ] {
+            assert_eq!(code_at(RFC_SECRET, time_step(time)).unwrap(), expected[2..], "time {}", time);
+        }
+    }
+
+    #[test]
+    fn base32_round_trip() {
+        assert_eq!(base32_decode(RFC_SECRET).unwrap(), b"12345678901234567890");
+        for (data, encoded) in [("", ""), ("f", "MY"), ("fo", "MZXQ"), ("foo", "MZXW6"), ("foob", "MZXW6YQ"), ("fooba", "MZXW6YTB"), ("foobar", "MZXW6YTBOI")] {
+            assert_eq!(base32_encode(data.as_bytes()), encoded);
+            assert_eq!(base32_decode(encoded).unwrap(), data.as_bytes());
+        }
+        let data: Vec<u8> = (0..=255).collect();
+        for len in 0..=data.len() {
+            assert_eq!(base32_decode(&base32_encode(&data[..len])).unwrap(), &data[..len]);
+        }
+        assert_eq!(base32_decode("mzxw 6ytb oi======").unwrap(), b"foobar");
+        assert_eq!(base32_decode("MZXW1"), None);
+        assert_eq!(generate_secret().len(), 32);
+    }
+
+    #[test]
+    fn verify_accepts_the_skew_window_once() {
+        let now = 1111111111;
+        let step = time_step(now);
+        let code = code_at(RFC_SECRET, step).unwrap();
+        assert_eq!(verify(RFC_SECRET, &code, now, 0), Some(step));
+        assert_eq!(verify(RFC_SECRET, &format!(" {} ", code), now, 0), Some(step));
+        assert_eq!(verify(RFC_SECRET, &code, now, step), None, "a used step is rejected");
+        assert_eq!(verify(RFC_SECRET, &code, now, step + 1), None, "an older step is rejected");
+
+        let previous = code_at(RFC_SECRET, step - 1).unwrap();
+        assert_eq!(verify(RFC_SECRET, &previous, now, 0), Some(step - 1));
+        assert_eq!(verify(RFC_SECRET, &previous, now, step - 1), None);
+        assert_eq!(verify(RFC_SECRET, &code_at(RFC_SECRET, step + 1).unwrap(), now, 0), Some(step + 1));
+        assert_eq!(verify(RFC_SECRET, &code_at(RFC_SECRET, step + 2).unwrap(), now, 0), None);
+        assert_eq!(verify(RFC_SECRET, &code_at(RFC_SECRET, step - 2).unwrap(), now, 0), None);
+    }
+
+    #[test]
+    fn verify_rejects_malformed_codes() {
+        let now = 1111111111;
+        let code = code_at(RFC_SECRET, time_step(now)).unwrap();
+        assert_eq!(verify(RFC_SECRET, &code[..5], now, 0), None);
+        assert_eq!(verify(RFC_SECRET, &format!("{}0", code), now, 0), None);
+        assert_eq!(verify(RFC_SECRET, "12a456", now, 0), None);
+        assert_eq!(verify("not base32!", "123456", now, 0), None);
+    }
+
+    #[test]
+    fn otpauth_uri_is_percent_encoded() {
+        assert_eq!(
+            otpauth_uri("Bit comm", "alice@example.com", RFC_SECRET),
+            format!("otpauth://totp/Bit%20comm:alice%40example.com?secret={}&issuer=Bit%20comm&algorithm=SHA1&digits=6&period=30", RFC_SECRET)
+        );
+    }
+}
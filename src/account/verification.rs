@@ -0,0 +1,160 @@
+//! # 邮箱验证
+//!
+//! 注册时填写了邮箱的账户处于"未验证"状态，服务器向该邮箱发送一个验证链接
+//! `{public_url}/email/verify?token=...`。链接中的令牌包含用户 ID、邮箱和过期时间，
+//! 并以 HMAC-SHA256 签名，服务器无需保存任何状态。修改邮箱后，旧邮箱的链接自动失效。
+//!
+//! 打开链接（GET）只显示确认页面，用户在页面上提交（POST）后才完成验证，
+//! 避免邮件客户端或安全扫描器预先访问链接时替用户确认。
+//!
+//! 未验证账户受到的限制见 [`crate::config::UnverifiedConfig`]。
+
+use base64::{ engine::general_purpose::URL_SAFE_NO_PAD, Engine };
+use lazy_static::lazy_static;
+use rand::RngCore;
+use ring::hmac;
+use serde::{ Deserialize, Serialize };
+
+use super::AccountError;
+use crate::config::config;
+use crate::kvstore::kv_store;
+use crate::mailer::{ mailer, MailMessage };
+use crate::token;
+use crate::userstore::{ self, UserRecord };
+
+/// 未配置 `email.link_secret` 时读取签名密钥的环境变量名。
+pub static LINK_SECRET_ENV_VAR: &str = "BTCMWEB_LINK_SECRET";
+/// 重发验证邮件冷却期的键前缀，完整键为 `btcm:email:resend:{user_id}`。
+const RESEND_KEY_PREFIX: &str = "btcm:email:resend:";
+/// 两次发送验证邮件之间的最短间隔（秒）。
+const RESEND_COOLDOWN_SECS: u64 = 60;
+
+lazy_static! {
+    /// 签名验证链接的密钥。
+    static ref LINK_KEY: hmac::Key = {
+        let secret = config().email.link_secret.clone()
+            .or_else(|| std::env::var(LINK_SECRET_ENV_VAR).ok().filter(|secret| !secret.is_empty()));
+        match secret {
+            Some(secret) => hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes()),
+            None => {
+                tracing::warn!("no email link secret configured and {} is not set, using a random secret", LINK_SECRET_ENV_VAR);
+                let mut secret = [0u8; 32];
+                rand::rngs::OsRng.fill_bytes(&mut secret);
+                hmac::Key::new(hmac::HMAC_SHA256, &secret)
+            }
+        }
+    };
+}
+
+/// 验证令牌中签名的内容。
+#[derive(Debug, Serialize, Deserialize)]
+struct VerificationClaims {
+    /// 用户 ID。
+    uid: u64,
+    /// 待验证的邮箱。
+    email: String,
+    /// 过期时间（Unix 时间戳，秒）。
+    exp: i64,
+}
+
+/// 账户是否填写了邮箱但尚未验证。
+pub fn is_unverified(user: &UserRecord) -> bool {
+    user.email.is_some() && !user.email_verified
+}
+
+/// 为用户当前的邮箱生成签名的验证令牌。用户没有邮箱时返回 `None`。
+pub fn verification_token(user: &UserRecord) -> Option<String> {
+    let claims = VerificationClaims {
+        uid: user.id,
+        email: user.email.clone()?,
+        exp: chrono::Utc::now().timestamp() + config().email.verification_ttl_secs,
+    };
+    let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims).ok()?);
+    let signature = URL_SAFE_NO_PAD.encode(hmac::sign(&LINK_KEY, payload.as_bytes()));
+    Some(format!("{}.{}", payload, signature))
+}
+
+/// 校验令牌的签名和有效期，返回其中的内容。
+fn decode_token(token: &str) -> Option<VerificationClaims> {
+    let (payload, signature) = token.split_once('.')?;
+    let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+    hmac::verify(&LINK_KEY, payload.as_bytes(), &signature).ok()?;
+    let claims: VerificationClaims = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).ok()?).ok()?;
+    (claims.exp > chrono::Utc::now().timestamp()).then_some(claims)
+}
+
+/// 令牌的签名正确且未过期。只用于显示确认页面，不修改任何状态。
+pub fn is_valid_token(token: &str) -> bool {
+    decode_token(token).is_some()
+}
+
+/// 向用户的邮箱发送验证邮件。用户没有邮箱或邮箱已验证时不做任何操作。
+pub async fn send_verification_email(user: &UserRecord) -> Result<(), AccountError> {
+    if !is_unverified(user) {
+        return Ok(());
+    }
+    let Some(token) = verification_token(user) else {
+        return Err(AccountError::Internal("failed to create verification token".into()));
+    };
+    let email = config().email.clone();
+    let link = format!("{}/email/verify?token={}", email.public_url.trim_end_matches('/'), token);
+    let message = MailMessage {
+        to: user.email.clone().unwrap_or_default(),
+        subject: "Verify your Bitcomm email address".into(),
+        body: format!(
+            "Hello {},\n\nPlease confirm your email address by opening the link below:\n\n{}\n\nThe link expires in {} hours. If you did not create this account, you can ignore this email.\n",
+            user.username,
+            link,
+            email.verification_ttl_secs / 3600
+        ),
+    };
+    mailer().send(&message).await.map_err(|err| AccountError::Internal(err.to_string()))
+}
+
+/// 重新发送验证邮件，同一用户每分钟最多一次。
+pub async fn resend_verification_email(user_id: u64) -> Result<(), AccountError> {
+    let user = userstore::user_store().get_by_id(user_id).await?.ok_or(AccountError::Unauthorized)?;
+    if !is_unverified(&user) {
+        return Err(AccountError::EmailAlreadyVerified);
+    }
+    let key = format!("{}{}", RESEND_KEY_PREFIX, user_id);
+    let allowed = kv_store()
+        .set_nx(&key, "1", Some(RESEND_COOLDOWN_SECS))
+        .await
+        .map_err(|err| AccountError::Internal(err.to_string()))?;
+    if !allowed {
+        return Err(AccountError::TooManyRequests);
+    }
+    send_verification_email(&user).await
+}
+
+/// 确认邮箱：校验验证令牌，并将令牌中的邮箱标记为已验证。
+///
+/// 令牌签名错误、已过期，或令牌中的邮箱与用户当前的邮箱不一致时返回
+/// [`AccountError::InvalidVerificationToken`]。重复确认同一个邮箱不视为错误。
+///
+/// 已签发的令牌中带有 `unverified` 声明，验证成功后吊销该用户所有的令牌和会话，用户重新登录后取得新的令牌。
+pub async fn confirm_email(token: &str) -> Result<UserRecord, AccountError> {
+    let claims = decode_token(token).ok_or(AccountError::InvalidVerificationToken)?;
+    let mut changed = false;
+    let user = userstore::modify(userstore::user_store().as_ref(), claims.uid, |user| {
+        if user.email.as_deref() != Some(claims.email.as_str()) {
+            return Err(AccountError::InvalidVerificationToken);
+        }
+        if user.email_verified {
+            return Ok(false);
+        }
+        user.email_verified = true;
+        changed = true;
+        tracing::info!("user {} verified email address", user.id);
+        Ok(true)
+    }).await;
+    let user = match user {
+        Err(AccountError::UserNotFound) => return Err(AccountError::InvalidVerificationToken),
+        user => user?,
+    };
+    if changed {
+        token::revoke::revoke_all_tokens(user.id).await?;
+    }
+    Ok(user)
+}
@@ -0,0 +1,64 @@
+//! # 审计日志
+//!
+//! 管理员对用户、会话、登录锁定和签名密钥所做的修改都记录为一条审计事件 [`AuditEvent`]。
+//! 事件总是以 `audit` 为 target 写入 tracing 日志；配置了 `audit.file` 时，
+//! 还以一行 JSON 追加写入该文件，便于单独保存和检索。
+//!
+//! 写入审计文件失败只记录日志，不影响已经完成的操作。
+
+use lazy_static::lazy_static;
+use serde::Serialize;
+use serde_json::Value;
+use tokio::{ fs::OpenOptions, io::AsyncWriteExt, sync::Mutex };
+
+use crate::config::config;
+use crate::token::Claims;
+
+lazy_static! {
+    /// 串行化对审计文件的写入，避免并发写出交错的行。
+    static ref AUDIT_LOCK: Mutex<()> = Mutex::new(());
+}
+
+/// 一条审计事件。
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEvent {
+    /// 发生时间（Unix 时间戳，秒）。
+    pub at: i64,
+    /// 执行操作的用户 ID。
+    pub actor_id: u64,
+    /// 执行操作的用户名。
+    pub actor: String,
+    /// 操作名，通常为 JSON-RPC 方法名，如 `admin.user.disable`。
+    pub action: String,
+    /// 操作对象，如用户 ID、会话 ID 或 `account:{username}`。
+    pub target: String,
+    /// 操作的其他参数，不包含密码等敏感信息。
+    #[serde(skip_serializing_if = "Value::is_null")]
+    pub details: Value,
+}
+
+/// 记录一条由 `actor` 执行的审计事件。
+pub async fn record(actor: &Claims, action: &str, target: impl ToString, details: Value) {
+    let event = AuditEvent {
+        at: chrono::Utc::now().timestamp(),
+        actor_id: actor.user_id().unwrap_or_default(),
+        actor: actor.username.clone(),
+        action: action.to_string(),
+        target: target.to_string(),
+        details,
+    };
+    tracing::info!(target: "audit", actor = %event.actor, action = %event.action, object = %event.target, details = %event.details, "audit event");
+    if let Some(path) = config().audit.file.as_deref() {
+        if let Err(err) = append(path, &event).await {
+            tracing::warn!("failed to write audit event to {}: {}", path, err);
+        }
+    }
+}
+
+async fn append(path: &str, event: &AuditEvent) -> std::io::Result<()> {
+    let mut line = serde_json::to_vec(event)?;
+    line.push(b'\n');
+    let _guard = AUDIT_LOCK.lock().await;
+    let mut file = OpenOptions::new().create(true).append(true).open(path).await?;
+    file.write_all(&line).await
+}
@@ -0,0 +1,150 @@
+//! # 验证码
+//!
+//! `/reguser` 和 `/login` 请求体中的 `codekey` 和 `codevalue` 用于回答验证码：
+//! 客户端先调用 `GET /captcha` 获取一道算术题（PNG 图片，见 [`render`]）和对应的 `codekey`，
+//! 再把答案放在 `codevalue` 中随请求提交。每个 `codekey` 只能使用一次，过期后失效。
+//!
+//! 是否要求验证码由配置文件的 `captcha.mode` 决定（见 [`crate::config::CaptchaConfig`]）：
+//!
+//! - `off`：从不要求；
+//! - `always`：总是要求；
+//! - `adaptive`（默认）：同一 IP 在时间窗口内失败次数达到阈值后才要求。登录成功不清除计数，
+//!   避免攻击者穿插登录自己的账户来绕过验证码。
+//!
+//! 无论是否要求，请求中带了 `codekey` 时都会校验答案。
+
+pub mod render;
+
+use std::fmt;
+
+use base64::{ engine::general_purpose::STANDARD, Engine };
+use rand::Rng;
+use serde::Serialize;
+
+use crate::config::{ config, CaptchaMode };
+use crate::kvstore::{ kv_store, KvStoreError };
+
+/// 验证码答案的键前缀，完整键为 `btcm:captcha:{codekey}`。
+const CHALLENGE_KEY_PREFIX: &str = "btcm:captcha:";
+/// 失败计数的键前缀，完整键为 `btcm:captcha:fail:{ip}`。
+const FAILURE_KEY_PREFIX: &str = "btcm:captcha:fail:";
+
+/// 验证码操作的错误。
+#[derive(Debug)]
+pub enum CaptchaError {
+    /// 需要验证码，但请求中没有提供。
+    Required,
+    /// 验证码错误、已使用或已过期。
+    Invalid,
+    /// 存储错误。
+    Store(String),
+}
+
+impl fmt::Display for CaptchaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaptchaError::Required => write!(f, "captcha required"),
+            CaptchaError::Invalid => write!(f, "invalid captcha"),
+            CaptchaError::Store(message) => write!(f, "captcha store error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for CaptchaError {}
+
+impl From<KvStoreError> for CaptchaError {
+    fn from(err: KvStoreError) -> Self {
+        CaptchaError::Store(err.to_string())
+    }
+}
+
+/// 发给客户端的验证码。
+#[derive(Debug, Clone, Serialize)]
+pub struct Challenge {
+    /// 提交答案时使用的 `codekey`。
+    pub codekey: String,
+    /// 题目图片，`data:image/png;base64,...` 形式的 data URL。
+    pub image: String,
+    /// 有效期（秒）。
+    pub expires_in: u64,
+    /// 当前客户端的下一次注册或登录是否必须回答验证码。
+    pub required: bool,
+}
+
+/// 生成一道算术题，返回题目文本和答案。
+fn arithmetic() -> (String, String) {
+    let mut rng = rand::thread_rng();
+    match rng.gen_range(0..3) {
+        0 => {
+            let (a, b) = (rng.gen_range(10..50), rng.gen_range(1..50));
+            (format!("{} + {} =", a, b), (a + b).to_string())
+        }
+        1 => {
+            let (a, b) = (rng.gen_range(20..99), rng.gen_range(1..20));
+            (format!("{} - {} =", a, b), (a - b).to_string())
+        }
+        _ => {
+            let (a, b) = (rng.gen_range(2..10), rng.gen_range(2..10));
+            (format!("{} x {} =", a, b), (a * b).to_string())
+        }
+    }
+}
+
+/// 生成一道新的验证码并保存答案。
+pub async fn issue_challenge(ip: &str) -> Result<Challenge, CaptchaError> {
+    let ttl = config().captcha.ttl_secs;
+    let (question, answer) = arithmetic();
+    let codekey = crate::token::new_token_id();
+    kv_store().set(&format!("{}{}", CHALLENGE_KEY_PREFIX, codekey), &answer, Some(ttl)).await?;
+    let image = format!("data:image/png;base64,{}", STANDARD.encode(render::render_png(&question)));
+    Ok(Challenge { codekey, image, expires_in: ttl, required: is_required(ip).await? })
+}
+
+/// 校验验证码答案。答案无论对错，`codekey` 都会被删除。
+pub async fn verify(codekey: &str, codevalue: &str) -> Result<bool, CaptchaError> {
+    let Some(answer) = kv_store().take(&format!("{}{}", CHALLENGE_KEY_PREFIX, codekey)).await? else {
+        return Ok(false);
+    };
+    Ok(answer == codevalue.trim())
+}
+
+/// 来自该 IP 的下一次注册或登录是否必须回答验证码。
+pub async fn is_required(ip: &str) -> Result<bool, CaptchaError> {
+    let captcha = &config().captcha;
+    match captcha.mode {
+        CaptchaMode::Off => Ok(false),
+        CaptchaMode::Always => Ok(true),
+        CaptchaMode::Adaptive => {
+            let failures = kv_store().get(&format!("{}{}", FAILURE_KEY_PREFIX, ip)).await?;
+            Ok(failures.and_then(|value| value.parse::<u32>().ok()).unwrap_or(0) >= captcha.failure_threshold)
+        }
+    }
+}
+
+/// 在注册或登录之前检查验证码。
+///
+/// 请求带有 `codekey` 时校验答案，答案错误时记录一次失败并返回 [`CaptchaError::Invalid`]；
+/// 没有 `codekey` 而该 IP 需要验证码时返回 [`CaptchaError::Required`]。
+pub async fn check(ip: &str, codekey: &str, codevalue: &str) -> Result<(), CaptchaError> {
+    if config().captcha.mode == CaptchaMode::Off {
+        return Ok(());
+    }
+    if !codekey.is_empty() {
+        if verify(codekey, codevalue).await? {
+            return Ok(());
+        }
+        record_failure(ip).await?;
+        return Err(CaptchaError::Invalid);
+    }
+    if is_required(ip).await? {
+        return Err(CaptchaError::Required);
+    }
+    Ok(())
+}
+
+/// 记录该 IP 的一次失败（密码错误或验证码错误）。计数在窗口结束后过期，不会因登录成功而清除。
+pub async fn record_failure(ip: &str) -> Result<(), CaptchaError> {
+    let window = config().captcha.failure_window_secs;
+    kv_store().incr(&format!("{}{}", FAILURE_KEY_PREFIX, ip), Some(window)).await?;
+    Ok(())
+}
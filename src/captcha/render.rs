@@ -0,0 +1,274 @@
+//! 将算术题绘制为 PNG 图片。
+//!
+//! 字符以七段数码管的笔画光栅化为像素，每个字符带有随机的位移、旋转和颜色；
+//! 干扰线与笔画使用相同的线宽和颜色，整幅图片再经过随机的波形扭曲并撒上噪点。
+//! 图片中不含任何文本或矢量信息，读出题目需要图像识别。这只能提高自动识别的成本，
+//! 不能阻止专门训练的识别程序，因此验证码应与登录锁定（见 [`crate::account::lockout`]）一起使用。
+
+use rand::Rng;
+
+/// 字符宽度（像素）。
+const GLYPH_WIDTH: f64 = 16.0;
+/// 字符高度（像素）。
+const GLYPH_HEIGHT: f64 = 28.0;
+/// 字符间距（像素）。
+const GLYPH_ADVANCE: f64 = 26.0;
+/// 图片高度（像素）。
+const IMAGE_HEIGHT: usize = 56;
+/// 笔画和干扰线的线宽（像素）。
+const STROKE_WIDTH: f64 = 3.0;
+/// 干扰线数量。
+const NOISE_LINES: usize = 6;
+/// 噪点占像素总数的比例。
+const SPECKLE_RATIO: f64 = 0.04;
+/// 调色板：背景色和笔画颜色（RGB）。
+const PALETTE: [[u8; 3]; 6] = [
+    [0xf4, 0xf1, 0xea],
+    [0x1f, 0x4e, 0x79],
+    [0x7a, 0x1f, 0x1f],
+    [0x1f, 0x5e, 0x2e],
+    [0x5a, 0x2d, 0x82],
+    [0x8a, 0x5a, 0x00],
+];
+
+/// 七段数码管的笔画，坐标为字符框内的相对位置（0..1）。
+const SEGMENTS: [((f64, f64), (f64, f64)); 7] = [
+    ((0.0, 0.0), (1.0, 0.0)), // a：上
+    ((1.0, 0.0), (1.0, 0.5)), // b：右上
+    ((1.0, 0.5), (1.0, 1.0)), // c：右下
+    ((0.0, 1.0), (1.0, 1.0)), // d：下
+    ((0.0, 0.5), (0.0, 1.0)), // e：左下
+    ((0.0, 0.0), (0.0, 0.5)), // f：左上
+    ((0.0, 0.5), (1.0, 0.5)), // g：中
+];
+
+/// 数字 0-9 点亮的笔画（按 a..g 的位掩码）。
+const DIGIT_MASKS: [u8; 10] = [
+    0b0111111, 0b0000110, 0b1011011, 0b1001111, 0b1100110,
+    0b1101101, 0b1111101, 0b0000111, 0b1111111, 0b1101111,
+];
+
+/// 返回字符的笔画，坐标为字符框内的相对位置。不支持的字符没有笔画。
+fn strokes(c: char) -> Vec<((f64, f64), (f64, f64))> {
+    match c {
+        '0'..='9' => {
+            let mask = DIGIT_MASKS[c as usize - '0' as usize];
+            SEGMENTS.iter().enumerate().filter(|(i, _)| mask & (1 << i) != 0).map(|(_, s)| *s).collect()
+        }
+        '+' => vec![((0.0, 0.5), (1.0, 0.5)), ((0.5, 0.2), (0.5, 0.8))],
+        '-' => vec![((0.0, 0.5), (1.0, 0.5))],
+        'x' => vec![((0.1, 0.25), (0.9, 0.75)), ((0.1, 0.75), (0.9, 0.25))],
+        '=' => vec![((0.0, 0.35), (1.0, 0.35)), ((0.0, 0.65), (1.0, 0.65))],
+        _ => Vec::new(),
+    }
+}
+
+/// 以调色板索引表示的位图。
+struct Canvas {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    fn new(width: usize, height: usize) -> Self {
+        Canvas { width, height, pixels: vec![0; width * height] }
+    }
+
+    /// 以 [`STROKE_WIDTH`] 的线宽绘制线段。
+    fn line(&mut self, (x1, y1): (f64, f64), (x2, y2): (f64, f64), color: u8) {
+        let radius = STROKE_WIDTH / 2.0;
+        let clamp = |value: f64, max: usize| (value.max(0.0) as usize).min(max);
+        let (left, right) = (clamp(x1.min(x2) - radius, self.width), clamp(x1.max(x2) + radius + 1.0, self.width));
+        let (top, bottom) = (clamp(y1.min(y2) - radius, self.height), clamp(y1.max(y2) + radius + 1.0, self.height));
+        let (dx, dy) = (x2 - x1, y2 - y1);
+        let length_sq = (dx * dx + dy * dy).max(f64::EPSILON);
+        for y in top..bottom {
+            for x in left..right {
+                let (px, py) = (x as f64 + 0.5, y as f64 + 0.5);
+                let t = (((px - x1) * dx + (py - y1) * dy) / length_sq).clamp(0.0, 1.0);
+                let (cx, cy) = (x1 + t * dx - px, y1 + t * dy - py);
+                if cx * cx + cy * cy <= radius * radius {
+                    self.pixels[y * self.width + x] = color;
+                }
+            }
+        }
+    }
+
+    /// 按随机的正弦波扭曲整幅图片。
+    fn warp(&mut self, rng: &mut impl Rng) {
+        let (amplitude_x, period_x, phase_x) = (rng.gen_range(1.5..3.0), rng.gen_range(18.0..30.0), rng.gen_range(0.0..6.3));
+        let (amplitude_y, period_y, phase_y) = (rng.gen_range(1.5..3.5), rng.gen_range(40.0..80.0), rng.gen_range(0.0..6.3));
+        let mut warped = vec![0; self.pixels.len()];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let sx = x as f64 + amplitude_x * (y as f64 / period_x * std::f64::consts::TAU + phase_x).sin();
+                let sy = y as f64 + amplitude_y * (x as f64 / period_y * std::f64::consts::TAU + phase_y).sin();
+                let (sx, sy) = (sx.round(), sy.round());
+                if sx >= 0.0 && sy >= 0.0 && (sx as usize) < self.width && (sy as usize) < self.height {
+                    warped[y * self.width + x] = self.pixels[sy as usize * self.width + sx as usize];
+                }
+            }
+        }
+        self.pixels = warped;
+    }
+
+    /// 编码为 8 位调色板 PNG。
+    fn to_png(&self) -> Vec<u8> {
+        let mut raw = Vec::with_capacity((self.width + 1) * self.height);
+        for row in self.pixels.chunks(self.width) {
+            raw.push(0);
+            raw.extend_from_slice(row);
+        }
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&(self.width as u32).to_be_bytes());
+        header.extend_from_slice(&(self.height as u32).to_be_bytes());
+        header.extend_from_slice(&[8, 3, 0, 0, 0]);
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png_chunk(&mut png, b"IHDR", &header);
+        png_chunk(&mut png, b"PLTE", PALETTE.as_flattened());
+        png_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+        png_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+}
+
+/// 写入一个 PNG 数据块。
+fn png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// 以不压缩的 deflate 块封装为 zlib 数据流。
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    const MAX_BLOCK: usize = 65535;
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        out.push(u8::from(blocks.peek().is_none()));
+        let len = block.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u32, |crc, _| if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 })
+    })
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), &byte| {
+        let a = (a + byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    (b << 16) | a
+}
+
+/// 将由数字和 `+`、`-`、`x`、`=` 组成的字符串绘制为 PNG。
+pub fn render_png(text: &str) -> Vec<u8> {
+    let mut rng = rand::thread_rng();
+    let glyphs: Vec<char> = text.chars().filter(|c| !c.is_whitespace()).collect();
+    let width = 20 + glyphs.len() * GLYPH_ADVANCE as usize;
+    let mut canvas = Canvas::new(width, IMAGE_HEIGHT);
+    let color = |rng: &mut rand::rngs::ThreadRng| rng.gen_range(1..PALETTE.len()) as u8;
+    for (i, c) in glyphs.iter().enumerate() {
+        let x = 12.0 + i as f64 * GLYPH_ADVANCE + rng.gen_range(-2.0..2.0);
+        let y = (IMAGE_HEIGHT as f64 - GLYPH_HEIGHT) / 2.0 + rng.gen_range(-4.0..4.0);
+        let angle = rng.gen_range(-12.0f64..12.0).to_radians();
+        let (center_x, center_y) = (x + GLYPH_WIDTH / 2.0, y + GLYPH_HEIGHT / 2.0);
+        let place = |(u, v): (f64, f64)| {
+            let (dx, dy) = (x + u * GLYPH_WIDTH - center_x, y + v * GLYPH_HEIGHT - center_y);
+            (center_x + dx * angle.cos() - dy * angle.sin(), center_y + dx * angle.sin() + dy * angle.cos())
+        };
+        let glyph_color = color(&mut rng);
+        for (from, to) in strokes(*c) {
+            canvas.line(place(from), place(to), glyph_color);
+        }
+    }
+    for _ in 0..NOISE_LINES {
+        let from = (rng.gen_range(0.0..width as f64), rng.gen_range(0.0..IMAGE_HEIGHT as f64));
+        let to = (rng.gen_range(0.0..width as f64), rng.gen_range(0.0..IMAGE_HEIGHT as f64));
+        let noise_color = color(&mut rng);
+        canvas.line(from, to, noise_color);
+    }
+    canvas.warp(&mut rng);
+    let speckles = (width * IMAGE_HEIGHT) as f64 * SPECKLE_RATIO;
+    for _ in 0..speckles as usize {
+        let index = rng.gen_range(0..canvas.pixels.len());
+        canvas.pixels[index] = rng.gen_range(0..PALETTE.len()) as u8;
+    }
+    canvas.to_png()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 解析 PNG，校验每个数据块的 CRC，返回 IHDR 和解开的 IDAT 数据。
+    fn decode(png: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        let (mut rest, mut header, mut idat) = (&png[8..], Vec::new(), Vec::new());
+        while !rest.is_empty() {
+            let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let (kind, data) = (&rest[4..8], &rest[8..8 + len]);
+            assert_eq!(u32::from_be_bytes(rest[8 + len..12 + len].try_into().unwrap()), crc32(&rest[4..8 + len]));
+            match kind {
+                b"IHDR" => header = data.to_vec(),
+                b"IDAT" => idat.extend_from_slice(data),
+                _ => {}
+            }
+            rest = &rest[12 + len..];
+        }
+        // 只有不压缩的块：跳过 zlib 头，逐块取出数据。
+        let (mut stream, mut raw) = (&idat[2..idat.len() - 4], Vec::new());
+        while !stream.is_empty() {
+            let len = u16::from_le_bytes([stream[1], stream[2]]) as usize;
+            assert_eq!(!(len as u16), u16::from_le_bytes([stream[3], stream[4]]));
+            raw.extend_from_slice(&stream[5..5 + len]);
+            stream = &stream[5 + len..];
+        }
+        assert_eq!(u32::from_be_bytes(idat[idat.len() - 4..].try_into().unwrap()), adler32(&raw));
+        (header, raw)
+    }
+
+    #[test]
+    fn checksums_match_reference_values() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+
+    #[test]
+    fn renders_a_valid_png_without_text() {
+        let png = render_png("12 + 34 =");
+        let (header, raw) = decode(&png);
+        let width = u32::from_be_bytes(header[..4].try_into().unwrap()) as usize;
+        let height = u32::from_be_bytes(header[4..8].try_into().unwrap()) as usize;
+        assert_eq!((width, height), (20 + 6 * GLYPH_ADVANCE as usize, IMAGE_HEIGHT));
+        assert_eq!(raw.len(), (width + 1) * height);
+        assert!(raw.iter().all(|&index| (index as usize) < PALETTE.len()));
+        let painted = raw.chunks(width + 1).flat_map(|row| &row[1..]).filter(|&&index| index != 0).count();
+        assert!(painted > width * height / 20, "glyphs and noise are drawn");
+        assert!(!png.windows(2).any(|window| window == b"12"), "the question does not appear as text");
+    }
+
+    #[test]
+    fn large_images_span_several_deflate_blocks() {
+        let data: Vec<u8> = (0..150_000u32).map(|i| (i % 251) as u8).collect();
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png_chunk(&mut png, b"IHDR", &[0; 13]);
+        png_chunk(&mut png, b"IDAT", &zlib_stored(&data));
+        assert_eq!(decode(&png).1, data);
+    }
+}
@@ -0,0 +1,484 @@
+//! # 服务器配置
+//!
+//! 配置从环境变量 `BTCMWEB_CONFIG` 指向的 JSON 文件读取，文件中缺省的字段使用默认值；
+//! 未设置该环境变量时全部使用默认值。配置在第一次访问时加载，之后不再变化。
+//!
+//! ```json
+//! {
+//!     "password": { "memory_kib": 65536, "iterations": 3 },
+//!     "password_policy": { "min_length": 10, "min_strength": 3, "breached_list": "data/pwned" },
+//!     "jwt": {
+//!         "issuer": "btcmweb",
+//!         "audience": ["btcmnetwork"],
+//!         "rotation_interval_secs": 2592000,
+//!         "keys": [
+//!             { "kid": "2026-10", "algorithm": "EdDSA",
+//!               "private_key_file": "keys/ed25519.pem", "public_key_file": "keys/ed25519.pub.pem" }
+//!         ]
+//!     },
+//!     "admin": { "usernames": ["root"] },
+//!     "roles": { "custom": { "support": ["users.read", "sessions.admin", "lockout.admin"] } },
+//!     "registration": { "mode": "invite_only", "reserved_usernames": ["bitcomm-team"] },
+//!     "audit": { "file": "logs/audit.jsonl" },
+//!     "api_keys": { "max_per_account": 10, "max_ttl_secs": 31536000 },
+//!     "ids": { "generator": "snowflake", "node_id": 3 },
+//!     "captcha": { "mode": "adaptive", "failure_threshold": 3 },
+//!     "email": {
+//!         "from": "Bitcomm <noreply@example.com>",
+//!         "public_url": "https://im.example.com",
+//!         "password_reset_ttl_secs": 3600,
+//!         "unverified": { "allow_login": true, "allow_rpc": false }
+//!     },
+//!     "mfa": { "issuer": "Bitcomm", "require_for_admins": true },
+//!     "lockout": { "account_threshold": 5, "ip_threshold": 20, "max_lockout_secs": 3600 }
+//! }
+//! ```
+
+use std::collections::BTreeMap;
+
+use lazy_static::lazy_static;
+use serde::Deserialize;
+
+/// 读取配置文件路径的环境变量名。
+pub static CONFIG_ENV_VAR: &str = "BTCMWEB_CONFIG";
+
+/// 服务器配置。
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AppConfig {
+    /// 密码哈希配置。
+    pub password: PasswordConfig,
+    /// 密码规则配置。
+    pub password_policy: PasswordPolicyConfig,
+    /// JWT 签发配置。
+    pub jwt: JwtConfig,
+    /// 管理员配置。
+    pub admin: AdminConfig,
+    /// 角色配置。
+    pub roles: RolesConfig,
+    /// 验证码配置。
+    pub captcha: CaptchaConfig,
+    /// 邮件、邮箱验证和找回密码配置。
+    pub email: EmailConfig,
+    /// 两步验证配置。
+    pub mfa: MfaConfig,
+    /// 登录失败锁定配置。
+    pub lockout: LockoutConfig,
+    /// 注册配置。
+    pub registration: RegistrationConfig,
+    /// 审计日志配置。
+    pub audit: AuditConfig,
+    /// API 密钥配置。
+    pub api_keys: ApiKeyConfig,
+    /// 用户 ID 分配配置。
+    pub ids: IdConfig,
+}
+
+/// 新用户 ID 的分配方式。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IdGenerator {
+    /// 由用户存储分配从 1 开始的连续 ID，多个实例共享同一个存储时才不会冲突。
+    #[default]
+    Store,
+    /// 由本机的 snowflake 生成器分配，见 [`crate::userstore::snowflake`]。
+    /// 不同实例的节点 ID 不同即可保证不冲突，不需要协调；ID 不超过 JavaScript 的安全整数范围。
+    Snowflake,
+}
+
+/// 用户 ID 分配配置。
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct IdConfig {
+    /// 分配方式。
+    pub generator: IdGenerator,
+    /// snowflake 生成器的节点 ID（0 到 63），同时运行的每个实例必须不同。
+    /// 环境变量 `BTCMWEB_NODE_ID` 优先；使用 snowflake 时两者必须设置一个。
+    pub node_id: Option<u16>,
+    /// snowflake ID 中时间戳的起点（Unix 时间戳，毫秒），设置后不能再修改。
+    pub epoch_ms: u64,
+}
+
+impl Default for IdConfig {
+    fn default() -> Self {
+        IdConfig {
+            generator: IdGenerator::Store,
+            node_id: None,
+            epoch_ms: crate::userstore::snowflake::DEFAULT_EPOCH_MS,
+        }
+    }
+}
+
+/// API 密钥配置，见 [`crate::account::apikey`]。
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ApiKeyConfig {
+    /// 每个服务账户最多的有效密钥数。
+    pub max_per_account: usize,
+    /// 密钥有效期的上限（秒），创建时不指定有效期则使用该值；0 表示不限，密钥默认永不过期。
+    pub max_ttl_secs: u64,
+}
+
+impl Default for ApiKeyConfig {
+    fn default() -> Self {
+        ApiKeyConfig { max_per_account: 10, max_ttl_secs: 0 }
+    }
+}
+
+/// 审计日志配置，见 [`crate::audit`]。
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AuditConfig {
+    /// 审计事件以 JSON 行追加写入的文件，为空时只写入日志。
+    pub file: Option<String>,
+}
+
+/// 谁可以通过 `/reguser` 注册，见 [`crate::account::invite`]。管理员创建的用户不受限制。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RegistrationMode {
+    /// 任何人都可以注册，邀请码可选。
+    #[default]
+    Open,
+    /// 必须提供有效的邀请码。
+    InviteOnly,
+    /// 任何人都可以注册，但没有邀请码的账户需要管理员批准后才能登录。
+    Approval,
+    /// 不允许注册。
+    Closed,
+}
+
+/// 注册配置。
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RegistrationConfig {
+    /// 注册模式。
+    pub mode: RegistrationMode,
+    /// 内置保留名之外额外保留、不允许注册的用户名，按混淆骨架比较，见
+    /// [`crate::account::RESERVED_USERNAMES`]。
+    pub reserved_usernames: Vec<String>,
+    /// 创建邀请码时不指定有效期则使用的有效期（秒）。
+    pub invite_ttl_secs: u64,
+}
+
+impl Default for RegistrationConfig {
+    fn default() -> Self {
+        RegistrationConfig {
+            mode: RegistrationMode::Open,
+            reserved_usernames: Vec::new(),
+            invite_ttl_secs: 7 * 24 * 60 * 60,
+        }
+    }
+}
+
+/// 登录失败锁定配置，见 [`crate::account::lockout`]。
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LockoutConfig {
+    /// 同一用户名在统计窗口内失败多少次后开始锁定。
+    pub account_threshold: u32,
+    /// 同一 IP 在统计窗口内失败多少次后开始锁定。
+    pub ip_threshold: u32,
+    /// 失败次数的统计窗口（秒），锁定期间自动延长。
+    pub window_secs: u64,
+    /// 第一次锁定的时长（秒），之后每多失败一次翻倍。
+    pub base_lockout_secs: u64,
+    /// 锁定时长的上限（秒）。
+    pub max_lockout_secs: u64,
+    /// 同一用户名失败多少次后，无论来自哪个 IP 都要求验证码；0 表示不按用户名要求。
+    /// 验证码模式为 `off` 时不生效。
+    pub captcha_after: u32,
+    /// 登录失败响应的最短耗时（毫秒），使各种失败的耗时一致。
+    pub min_failure_response_ms: u64,
+}
+
+impl Default for LockoutConfig {
+    fn default() -> Self {
+        LockoutConfig {
+            account_threshold: 5,
+            ip_threshold: 20,
+            window_secs: 15 * 60,
+            base_lockout_secs: 30,
+            max_lockout_secs: 60 * 60,
+            captcha_after: 3,
+            min_failure_response_ms: 250,
+        }
+    }
+}
+
+/// 两步验证（TOTP）配置。
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct MfaConfig {
+    /// 身份验证器应用中显示的签发者名称。
+    pub issuer: String,
+    /// 是否要求通过两步验证登录后才能使用内置 `user` 角色之外的权限（调用 `admin.*` 方法），默认要求。
+    /// 启用两步验证的 `mfa.*` 方法只需要登录，尚未启用的管理员可以用密码登录后启用，之后重新登录。
+    pub require_for_admins: bool,
+    /// 密码校验通过后，完成第二步验证的期限（秒）。
+    pub challenge_ttl_secs: u64,
+}
+
+impl Default for MfaConfig {
+    fn default() -> Self {
+        MfaConfig {
+            issuer: "Bitcomm".into(),
+            require_for_admins: true,
+            challenge_ttl_secs: 5 * 60,
+        }
+    }
+}
+
+/// 邮件、邮箱验证和找回密码配置。
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct EmailConfig {
+    /// 发件人，例如 `Bitcomm <noreply@example.com>`。
+    pub from: String,
+    /// 邮件中链接的前缀，即服务器对外的地址。
+    pub public_url: String,
+    /// 邮箱验证链接的有效期（秒）。
+    pub verification_ttl_secs: i64,
+    /// 密码重置令牌的有效期（秒）。
+    pub password_reset_ttl_secs: i64,
+    /// 签名邮箱验证链接的密钥。未设置时读取环境变量 `BTCMWEB_LINK_SECRET`，
+    /// 仍未设置时随机生成，此时重启服务器会使未使用的链接失效。
+    pub link_secret: Option<String>,
+    /// 邮箱未验证的账户受到的限制。
+    pub unverified: UnverifiedConfig,
+}
+
+impl Default for EmailConfig {
+    fn default() -> Self {
+        EmailConfig {
+            from: "Bitcomm <noreply@localhost>".into(),
+            public_url: "http://localhost:1220".into(),
+            verification_ttl_secs: 24 * 60 * 60,
+            password_reset_ttl_secs: 60 * 60,
+            link_secret: None,
+            unverified: UnverifiedConfig::default(),
+        }
+    }
+}
+
+/// 邮箱未验证的账户受到的限制。没有填写邮箱的账户不受限制。
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct UnverifiedConfig {
+    /// 是否允许登录。
+    pub allow_login: bool,
+    /// 是否允许调用需要认证的 JSON-RPC 方法。
+    pub allow_rpc: bool,
+}
+
+impl Default for UnverifiedConfig {
+    fn default() -> Self {
+        UnverifiedConfig { allow_login: true, allow_rpc: false }
+    }
+}
+
+/// 何时要求注册和登录回答验证码。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptchaMode {
+    /// 从不要求。
+    Off,
+    /// 总是要求。
+    Always,
+    /// 同一 IP 失败次数达到阈值后要求。
+    #[default]
+    Adaptive,
+}
+
+/// 验证码配置。
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CaptchaConfig {
+    /// 何时要求验证码。
+    pub mode: CaptchaMode,
+    /// 验证码有效期（秒）。
+    pub ttl_secs: u64,
+    /// `adaptive` 模式下开始要求验证码的失败次数。
+    pub failure_threshold: u32,
+    /// 失败次数的统计窗口（秒）。
+    pub failure_window_secs: u64,
+}
+
+impl Default for CaptchaConfig {
+    fn default() -> Self {
+        CaptchaConfig {
+            mode: CaptchaMode::Adaptive,
+            ttl_secs: 5 * 60,
+            failure_threshold: 3,
+            failure_window_secs: 15 * 60,
+        }
+    }
+}
+
+/// 管理员配置。
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AdminConfig {
+    /// 初次部署时创建的管理员用户名。这些用户名是保留名，不能注册；
+    /// 服务器启动时为其中还不存在的用户名创建拥有 `superadmin` 角色的账户，见 [`crate::account::admin::seed_admins`]。
+    /// 权限只来自存储的角色，已存在的同名账户不会因此获得权限。
+    pub usernames: Vec<String>,
+    /// 服务器启动时被分配 `superadmin` 角色的用户 ID，用于为已有账户授予管理员权限。
+    pub user_ids: Vec<u64>,
+}
+
+/// 角色配置，见 [`crate::rbac`]。
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct RolesConfig {
+    /// 内置角色之外的自定义角色：角色名到权限列表的映射。与内置角色同名的项被忽略。
+    pub custom: BTreeMap<String, Vec<String>>,
+}
+
+/// 密码哈希配置（Argon2id）。
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PasswordConfig {
+    /// 内存开销（KiB）。
+    pub memory_kib: u32,
+    /// 迭代次数。
+    pub iterations: u32,
+    /// 并行度。
+    pub parallelism: u32,
+    /// 同时进行的哈希计算数量上限，超出的请求排队等待。
+    pub max_concurrent_hashes: usize,
+}
+
+impl Default for PasswordConfig {
+    /// 默认值取自 OWASP 对 Argon2id 的最低建议：19 MiB 内存、2 次迭代、并行度 1。
+    fn default() -> Self {
+        PasswordConfig {
+            memory_kib: 19 * 1024,
+            iterations: 2,
+            parallelism: 1,
+            max_concurrent_hashes: 4,
+        }
+    }
+}
+
+/// 注册和重置密码时新密码必须满足的规则，见 [`crate::account::policy`]。
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PasswordPolicyConfig {
+    /// 最短长度（字符数）。
+    pub min_length: usize,
+    /// 最长长度（字符数），限制哈希计算的开销。
+    pub max_length: usize,
+    /// 是否必须包含小写字母。
+    pub require_lowercase: bool,
+    /// 是否必须包含大写字母。
+    pub require_uppercase: bool,
+    /// 是否必须包含数字。
+    pub require_digit: bool,
+    /// 是否必须包含符号。
+    pub require_symbol: bool,
+    /// 是否禁止密码包含用户名或邮箱。
+    pub reject_personal_info: bool,
+    /// 最低强度分数（0 到 4），见 [`crate::account::strength`]。
+    pub min_strength: u8,
+    /// 泄露密码列表的路径。可以是一个文件，每行为 SHA-1 十六进制摘要（可带 `:次数`）或明文密码；
+    /// 也可以是按 SHA-1 前 5 位分文件的目录（k-anonymity 格式），文件 `{前缀}` 或 `{前缀}.txt`
+    /// 中每行为 `{其余 35 位}:{次数}`。未设置时不检查。
+    pub breached_list: Option<String>,
+    /// 在泄露列表中出现至少多少次才拒绝。
+    pub breached_min_count: u64,
+}
+
+impl Default for PasswordPolicyConfig {
+    fn default() -> Self {
+        PasswordPolicyConfig {
+            min_length: 8,
+            max_length: 128,
+            require_lowercase: false,
+            require_uppercase: false,
+            require_digit: false,
+            require_symbol: false,
+            reject_personal_info: true,
+            min_strength: 2,
+            breached_list: None,
+            breached_min_count: 1,
+        }
+    }
+}
+
+/// JWT 签发配置。
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct JwtConfig {
+    /// 签发者，写入 `iss`。
+    pub issuer: String,
+    /// 受众，写入 `aud`。
+    pub audience: Vec<String>,
+    /// 访问令牌有效期（秒）。
+    pub access_token_ttl_secs: i64,
+    /// 刷新令牌有效期（秒）。每次刷新都会重新计时，超过该时间未使用则需要重新登录。
+    pub refresh_token_ttl_secs: i64,
+    /// 密钥列表，第一个用于签名。为空时使用 `BTCMWEB_JWT_SECRET` 的 HS256 密钥。
+    pub keys: Vec<JwtKeyConfig>,
+    /// 自动轮换签名密钥的间隔（秒），0 表示不自动轮换。轮换生成的密钥保存在用户存储中，
+    /// 还没有生成过密钥时启动后立即轮换一次。
+    pub rotation_interval_secs: u64,
+    /// 轮换时生成的密钥算法：`EdDSA` 或 `HS256`。
+    pub rotation_algorithm: String,
+    /// 加密保存在用户存储中的生成的密钥的口令，所有实例必须相同。为空时读取 `BTCMWEB_KEYRING_SECRET`，
+    /// 都未设置时私钥以明文保存，见 [`crate::token::keyring::RECORD_KIND`]。
+    pub keyring_secret: Option<String>,
+}
+
+impl Default for JwtConfig {
+    fn default() -> Self {
+        JwtConfig {
+            issuer: "btcmweb".into(),
+            audience: vec!["btcmnetwork".into()],
+            access_token_ttl_secs: 15 * 60,
+            refresh_token_ttl_secs: 30 * 24 * 60 * 60,
+            keys: Vec::new(),
+            rotation_interval_secs: 0,
+            rotation_algorithm: "EdDSA".into(),
+            keyring_secret: None,
+        }
+    }
+}
+
+/// 一个 JWT 签名密钥的配置。
+#[derive(Debug, Clone, Deserialize)]
+pub struct JwtKeyConfig {
+    /// 密钥 ID，写入 JWT 头部的 `kid`。
+    pub kid: String,
+    /// 签名算法：`HS256`、`RS256` 或 `EdDSA`。
+    pub algorithm: String,
+    /// HS256 的共享密钥。
+    #[serde(default)]
+    pub secret: Option<String>,
+    /// RS256/EdDSA 私钥 PEM 文件路径（PKCS#8 或 PKCS#1）。
+    #[serde(default)]
+    pub private_key_file: Option<String>,
+    /// RS256/EdDSA 公钥 PEM 文件路径。
+    #[serde(default)]
+    pub public_key_file: Option<String>,
+}
+
+lazy_static! {
+    static ref CONFIG: AppConfig = load();
+}
+
+/// 获取服务器配置。
+pub fn config() -> &'static AppConfig {
+    &CONFIG
+}
+
+/// 加载配置文件。文件无法读取或解析时直接 panic，避免以错误的配置启动。
+fn load() -> AppConfig {
+    let Ok(path) = std::env::var(CONFIG_ENV_VAR) else {
+        return AppConfig::default();
+    };
+    let content = std::fs::read_to_string(&path)
+        .unwrap_or_else(|err| panic!("failed to read config file {}: {}", path, err));
+    serde_json::from_str(&content)
+        .unwrap_or_else(|err| panic!("failed to parse config file {}: {}", path, err))
+}
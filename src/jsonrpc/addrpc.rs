@@ -19,6 +19,7 @@ impl JsonRpcHandle for AddJsonRpcHandler {
                         jsonrpc: req.jsonrpc.clone(),
                         result: serde_json::to_value(a + b).unwrap(),
                         id: req.id.clone(),
+                        error: None,
                     });
                 }
             }
@@ -0,0 +1,156 @@
+//! 服务账户和 API 密钥的管理方法，见 [`crate::account::apikey`]。创建服务账户需要 `users.write` 权限，
+//! 管理密钥需要 `apikeys.admin` 权限；密钥的权限范围不能超出调用者自己的权限。
+//! 每次修改都写入审计日志，见 [`crate::audit`]。
+
+use axum::{ extract::Json, response::Json as JsonResponse };
+use async_trait::async_trait;
+use serde_json::{ json, Value };
+
+use super::userrpc::{ manageable, user_value };
+use super::{ require_permission, respond, JsonRequest, JsonResponseWrapper, JsonRpcHandle, RpcError, FORBIDDEN };
+use crate::account::{ admin, AccountError };
+use crate::account::apikey::{ self, ApiKey, NewApiKey };
+use crate::audit;
+use crate::rbac;
+use crate::token::Claims;
+
+/// 密钥信息的展示形式，附带密钥前缀 `prefix`。
+fn key_value(key: &ApiKey) -> Result<Value, RpcError> {
+    let mut value = serde_json::to_value(key).map_err(RpcError::internal)?;
+    value["prefix"] = Value::String(key.prefix());
+    Ok(value)
+}
+
+/// 调用者必须拥有密钥权限范围内的每个权限，避免通过密钥获得自己没有的权限。
+fn check_scopes(claims: &Claims, scopes: &[String]) -> Result<(), RpcError> {
+    if let Some(scope) = scopes.iter().find(|scope| !claims.has_permission(scope.trim())) {
+        return Err(RpcError::new(FORBIDDEN, format!("Cannot grant scope '{}'", scope)));
+    }
+    Ok(())
+}
+
+/// `admin.serviceAccount.create`：创建服务账户。
+pub struct ServiceAccountCreateJsonRpcHandler;
+
+#[async_trait]
+impl JsonRpcHandle for ServiceAccountCreateJsonRpcHandler {
+    /// 参数 `username`，可选 `roles`（还需要 `users.roles` 权限，且只能分配调用者权限范围内的角色）。
+    /// 返回新账户的信息，格式与 `admin.user.get` 相同。
+    async fn json_rpc_handle(&self, req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper> {
+        let result = async {
+            let claims = require_permission(&req, rbac::USERS_WRITE).await?;
+            let username = req.param_str("username")?;
+            let roles: Vec<String> = match req.params.as_ref().and_then(|params| params.get("roles")) {
+                Some(roles) => serde_json::from_value(roles.clone())
+                    .map_err(|_| RpcError::invalid_params("parameter 'roles' must be a string array"))?,
+                None => Vec::new(),
+            };
+            if !roles.is_empty() {
+                rbac::authorize(&claims, rbac::USERS_ROLES)?;
+                rbac::can_assign(&claims, &roles)?;
+            }
+            let user = admin::create_service_account(username, &roles).await?;
+            audit::record(&claims, "admin.serviceAccount.create", user.id, json!({
+                "username": user.username,
+                "roles": user.roles,
+            })).await;
+            user_value(&user)
+        }.await;
+        respond(&req, result)
+    }
+}
+
+/// `admin.apiKey.create`：为服务账户创建 API 密钥。
+pub struct ApiKeyCreateJsonRpcHandler;
+
+#[async_trait]
+impl JsonRpcHandle for ApiKeyCreateJsonRpcHandler {
+    /// 参数 `user_id`、`name`、`scopes`（权限名数组），可选 `expires_in_secs`。
+    /// 返回 `{key, api_key}`：`key` 为完整密钥，只在这里返回一次；`api_key` 为密钥信息。
+    async fn json_rpc_handle(&self, req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper> {
+        let result = async {
+            let claims = require_permission(&req, rbac::API_KEYS_ADMIN).await?;
+            let new_key: NewApiKey = req.params_as()?;
+            manageable(&claims, new_key.user_id).await?;
+            check_scopes(&claims, &new_key.scopes)?;
+            let (full_key, key) = apikey::create(&new_key, claims.user_id().unwrap_or_default()).await?;
+            audit::record(&claims, "admin.apiKey.create", key.prefix(), json!({
+                "user_id": key.user_id,
+                "name": key.name,
+                "scopes": key.scopes,
+                "expires_at": key.expires_at,
+            })).await;
+            Ok(json!({ "key": full_key, "api_key": key_value(&key)? }))
+        }.await;
+        respond(&req, result)
+    }
+}
+
+/// `admin.apiKey.list`：列出服务账户的 API 密钥。
+pub struct ApiKeyListJsonRpcHandler;
+
+#[async_trait]
+impl JsonRpcHandle for ApiKeyListJsonRpcHandler {
+    /// 参数 `user_id`。返回密钥信息的数组，包括 `prefix`、`scopes`、`expires_at` 和 `last_used_at`，
+    /// 不包括密钥本身。
+    async fn json_rpc_handle(&self, req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper> {
+        let result = async {
+            require_permission(&req, rbac::API_KEYS_ADMIN).await?;
+            let keys = apikey::list(req.param_u64("user_id")?).await?;
+            keys.iter().map(key_value).collect::<Result<Vec<_>, _>>().map(Value::Array)
+        }.await;
+        respond(&req, result)
+    }
+}
+
+/// `admin.apiKey.rotate`：轮换 API 密钥。
+pub struct ApiKeyRotateJsonRpcHandler;
+
+#[async_trait]
+impl JsonRpcHandle for ApiKeyRotateJsonRpcHandler {
+    /// 参数 `id`，可选 `grace_secs`（旧密钥继续有效的秒数，默认 0，即立即失效）和 `expires_in_secs`。
+    /// 返回格式与 `admin.apiKey.create` 相同。
+    async fn json_rpc_handle(&self, req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper> {
+        let result = async {
+            let claims = require_permission(&req, rbac::API_KEYS_ADMIN).await?;
+            let id = req.param_str("id")?;
+            let grace_secs = req.param_u64("grace_secs").unwrap_or(0);
+            let expires_in_secs = req.param_u64("expires_in_secs").ok();
+            let old = apikey::get(id).await?;
+            manageable(&claims, old.user_id).await?;
+            check_scopes(&claims, &old.scopes)?;
+            let (full_key, key) = apikey::rotate(id, grace_secs, expires_in_secs, claims.user_id().unwrap_or_default()).await?;
+            audit::record(&claims, "admin.apiKey.rotate", old.prefix(), json!({
+                "user_id": key.user_id,
+                "new_key": key.prefix(),
+                "grace_secs": grace_secs,
+            })).await;
+            Ok(json!({ "key": full_key, "api_key": key_value(&key)? }))
+        }.await;
+        respond(&req, result)
+    }
+}
+
+/// `admin.apiKey.revoke`：吊销 API 密钥。
+pub struct ApiKeyRevokeJsonRpcHandler;
+
+#[async_trait]
+impl JsonRpcHandle for ApiKeyRevokeJsonRpcHandler {
+    /// 参数 `id`。返回 `{revoked}`，密钥不存在时为 `false`。
+    async fn json_rpc_handle(&self, req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper> {
+        let result = async {
+            let claims = require_permission(&req, rbac::API_KEYS_ADMIN).await?;
+            let id = req.param_str("id")?;
+            let key = match apikey::get(id).await {
+                Ok(key) => key,
+                Err(AccountError::ApiKeyNotFound) => return Ok(json!({ "revoked": false })),
+                Err(err) => return Err(err.into()),
+            };
+            manageable(&claims, key.user_id).await?;
+            let revoked = apikey::revoke(id).await?;
+            audit::record(&claims, "admin.apiKey.revoke", key.prefix(), json!({ "user_id": key.user_id })).await;
+            Ok(json!({ "revoked": revoked }))
+        }.await;
+        respond(&req, result)
+    }
+}
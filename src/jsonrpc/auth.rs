@@ -0,0 +1,64 @@
+//! JSON-RPC 请求的身份认证：校验 `JsonRequest.token` 中的访问令牌，包括吊销状态。
+//! `token` 也可以是服务账户的 API 密钥，见 [`crate::account::apikey`]。
+
+use super::{ JsonRequest, RpcError, FORBIDDEN, UNAUTHORIZED };
+use crate::account::{ apikey, AccountError };
+use crate::config::config;
+use crate::rbac;
+use crate::token::{ self, Claims, TokenError };
+
+/// 校验请求携带的访问令牌或 API 密钥，返回其中的声明。
+///
+/// # 参数
+///
+/// - `req`: JSON-RPC 请求。
+///
+/// # 返回
+///
+/// 令牌或密钥缺失、无效或已被吊销时返回 `UNAUTHORIZED` 错误；令牌属于邮箱未验证的账户，
+/// 且配置不允许这类账户调用 JSON-RPC 时返回 `FORBIDDEN` 错误。
+pub async fn authenticate(req: &JsonRequest) -> Result<Claims, RpcError> {
+    if req.token.is_empty() {
+        return Err(RpcError::new(UNAUTHORIZED, "Missing token"));
+    }
+    if req.token.starts_with(apikey::KEY_PREFIX) {
+        return apikey::authenticate(&req.token).await.map_err(|err| match err {
+            AccountError::Unauthorized => RpcError::new(UNAUTHORIZED, "Invalid API key"),
+            err => err.into(),
+        });
+    }
+    let claims = token::authenticate_access_token(&req.token).await.map_err(|err| match err {
+        TokenError::Store(_) => RpcError::internal(err),
+        TokenError::Revoked => RpcError::new(UNAUTHORIZED, "Token has been revoked"),
+        err => {
+            tracing::debug!("rejected json-rpc token: {}", err);
+            RpcError::new(UNAUTHORIZED, "Invalid token")
+        }
+    })?;
+    if claims.unverified && !config().email.unverified.allow_rpc {
+        return Err(RpcError::new(FORBIDDEN, "Email not verified"));
+    }
+    Ok(claims)
+}
+
+/// 校验请求携带的访问令牌，并要求令牌拥有 `permission`，见 [`crate::rbac`]。
+///
+/// # 参数
+///
+/// - `req`: JSON-RPC 请求。
+/// - `permission`: 所需的权限，如 [`crate::rbac::USERS_READ`]。
+///
+/// # 返回
+///
+/// 令牌无效时返回 `UNAUTHORIZED` 错误；没有该权限，或该权限超出 `user` 角色、配置要求
+/// 两步验证而该次登录没有通过两步验证时返回 `FORBIDDEN` 错误。API 密钥不要求两步验证。
+pub async fn require_permission(req: &JsonRequest, permission: &str) -> Result<Claims, RpcError> {
+    let claims = authenticate(req).await?;
+    if !claims.has_permission(permission) {
+        return Err(RpcError::new(FORBIDDEN, format!("Permission '{}' required", permission)));
+    }
+    if rbac::is_privileged(permission) && config().mfa.require_for_admins && !claims.mfa && claims.api_key.is_none() {
+        return Err(RpcError::new(FORBIDDEN, "Two-factor authentication required"));
+    }
+    Ok(claims)
+}
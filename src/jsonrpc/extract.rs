@@ -0,0 +1,137 @@
+use axum::{
+    async_trait,
+    body::Bytes,
+    extract::{ FromRequest, Request },
+    response::Json as JsonResponse,
+};
+
+use super::{ error_response_with, JsonRequest, JsonResponseWrapper, INVALID_REQUEST, PARSE_ERROR };
+
+/// 宽松的 JSON-RPC 请求提取器。
+///
+/// 与 axum 的 `Json` 提取器不同，它不检查 `Content-Type`，接受任意请求体，
+/// 并在请求体无法使用时始终以 JSON-RPC 错误响应拒绝请求：
+///
+/// - 请求体不是合法的 JSON：`-32700` 解析错误，`id` 为 `null`；
+/// - JSON 合法但不是有效的请求对象：`-32600` 无效请求，能识别出 `id` 时原样带回。
+pub struct JsonRpcPayload(pub JsonRequest);
+
+#[async_trait]
+impl<S> FromRequest<S> for JsonRpcPayload where S: Send + Sync {
+    type Rejection = JsonResponse<JsonResponseWrapper>;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let bytes = Bytes::from_request(req, state).await.map_err(|rejection| {
+            PayloadError::new(serde_json::Value::Null, INVALID_REQUEST, rejection.body_text()).into_response()
+        })?;
+        parse_payload(&bytes).map(JsonRpcPayload).map_err(PayloadError::into_response)
+    }
+}
+
+/// 请求体无法转换为 JSON-RPC 请求时的错误，携带能够恢复的 `id`。
+pub(crate) struct PayloadError {
+    id: serde_json::Value,
+    code: i64,
+    message: String,
+}
+
+impl PayloadError {
+    pub(crate) fn new(id: serde_json::Value, code: i64, message: impl Into<String>) -> Self {
+        PayloadError { id, code, message: message.into() }
+    }
+
+    /// 转换为 JSON-RPC 错误响应。
+    pub(crate) fn into_response(self) -> JsonResponse<JsonResponseWrapper> {
+        error_response_with("2.0".into(), self.id, self.code, self.message)
+    }
+}
+
+/// 将原始请求体解析为 JSON-RPC 请求。
+pub(crate) fn parse_payload(bytes: &[u8]) -> Result<JsonRequest, PayloadError> {
+    let value: serde_json::Value = serde_json::from_slice(bytes).map_err(|err| {
+        PayloadError::new(serde_json::Value::Null, PARSE_ERROR, format!("Parse error: {}", err))
+    })?;
+    parse_value(value)
+}
+
+/// 将已解析的 JSON 值转换为 JSON-RPC 请求。
+pub(crate) fn parse_value(value: serde_json::Value) -> Result<JsonRequest, PayloadError> {
+    let id = recover_id(&value);
+    let request: JsonRequest = serde_json::from_value(value).map_err(|err| {
+        PayloadError::new(id.clone(), INVALID_REQUEST, format!("Invalid Request: {}", err))
+    })?;
+    if request.method.is_empty() {
+        return Err(PayloadError::new(id, INVALID_REQUEST, "Invalid Request: method must not be empty"));
+    }
+    Ok(request)
+}
+
+/// 从无效请求中尽量恢复 `id`，只接受规范允许的字符串、数字或 `null`。
+fn recover_id(value: &serde_json::Value) -> serde_json::Value {
+    match value.get("id") {
+        Some(id @ (serde_json::Value::String(_) | serde_json::Value::Number(_))) => id.clone(),
+        _ => serde_json::Value::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use serde_json::json;
+
+    use super::*;
+
+    fn rejected(body: &[u8]) -> serde_json::Value {
+        let err = parse_payload(body).expect_err("payload should be rejected");
+        serde_json::to_value(&err.into_response().0).unwrap()
+    }
+
+    #[test]
+    fn invalid_json_is_a_parse_error() {
+        for body in [&b"{"[..], b"", b"not json", b"{\"id\": 1,}"] {
+            let response = rejected(body);
+            assert_eq!(response["error"]["code"], PARSE_ERROR, "{}", response);
+            assert_eq!(response["id"], serde_json::Value::Null);
+            assert_eq!(response["jsonrpc"], "2.0");
+        }
+    }
+
+    #[test]
+    fn invalid_requests_keep_a_recoverable_id() {
+        let cases = [
+            (json!({ "jsonrpc": "2.0", "id": 7, "method": 3, "token": "" }), json!(7)),
+            (json!({ "jsonrpc": "2.0", "id": "abc", "params": {}, "token": "" }), json!("abc")),
+            (json!({ "jsonrpc": "2.0", "id": 8, "method": "", "token": "" }), json!(8)),
+            (json!({ "jsonrpc": "2.0", "id": { "nested": 1 }, "method": 3 }), serde_json::Value::Null),
+            (json!({ "jsonrpc": "2.0", "id": [1], "method": 3 }), serde_json::Value::Null),
+            (json!({ "jsonrpc": "2.0", "id": true, "method": 3 }), serde_json::Value::Null),
+            (json!([{ "jsonrpc": "2.0", "id": 1, "method": "add", "token": "" }]), serde_json::Value::Null),
+            (json!("add"), serde_json::Value::Null),
+        ];
+        for (body, id) in cases {
+            let response = rejected(body.to_string().as_bytes());
+            assert_eq!(response["error"]["code"], INVALID_REQUEST, "{}", response);
+            assert_eq!(response["id"], id, "{}", body);
+        }
+    }
+
+    #[test]
+    fn valid_requests_are_accepted() {
+        let body = json!({ "jsonrpc": "2.0", "id": 1, "method": "add", "token": "", "params": { "a": 1 } });
+        let request = parse_payload(body.to_string().as_bytes()).ok().unwrap();
+        assert_eq!(request.method, "add");
+        assert_eq!(request.id, json!(1));
+    }
+
+    #[tokio::test]
+    async fn extractor_ignores_the_content_type() {
+        let body = json!({ "jsonrpc": "2.0", "id": 2, "method": "add", "token": "" }).to_string();
+        let request = Request::post("/jsonrpc").header("content-type", "text/plain").body(Body::from(body)).unwrap();
+        let JsonRpcPayload(request) = JsonRpcPayload::from_request(request, &()).await.ok().unwrap();
+        assert_eq!(request.id, json!(2));
+
+        let request = Request::post("/jsonrpc").body(Body::from("{")).unwrap();
+        let JsonResponse(response) = JsonRpcPayload::from_request(request, &()).await.err().unwrap();
+        assert_eq!(serde_json::to_value(&response).unwrap()["error"]["code"], PARSE_ERROR);
+    }
+}
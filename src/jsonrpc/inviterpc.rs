@@ -0,0 +1,132 @@
+//! 邀请码和注册审批的管理方法，见 [`crate::account::invite`]。管理邀请码需要 `invites.admin` 权限，
+//! 查看和处理等待批准的注册需要 `registrations.approve` 权限。
+//! 每次修改都写入审计日志，见 [`crate::audit`]。
+
+use axum::{ extract::Json, response::Json as JsonResponse };
+use async_trait::async_trait;
+use serde_json::{ json, Value };
+
+use super::userrpc::user_value;
+use super::{ require_permission, respond, JsonRequest, JsonResponseWrapper, JsonRpcHandle, RpcError };
+use crate::account::admin::{ self, ListQuery, SortKey, UserStatus };
+use crate::account::invite::{ self, NewInvite };
+use crate::audit;
+use crate::rbac;
+
+/// `admin.invite.create`：创建邀请码。
+pub struct InviteCreateJsonRpcHandler;
+
+#[async_trait]
+impl JsonRpcHandle for InviteCreateJsonRpcHandler {
+    /// 可选参数 `max_uses`（默认 1，0 表示不限）、`expires_in_secs`（默认为配置的
+    /// `registration.invite_ttl_secs`，0 表示永不过期）和 `note`。返回邀请码信息。
+    async fn json_rpc_handle(&self, req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper> {
+        let result = async {
+            let claims = require_permission(&req, rbac::INVITES_ADMIN).await?;
+            let new_invite: NewInvite = req.params_as()?;
+            let invite = invite::create(&new_invite, claims.user_id().unwrap_or_default()).await?;
+            audit::record(&claims, "admin.invite.create", &invite.code, json!({
+                "max_uses": invite.max_uses,
+                "expires_at": invite.expires_at,
+                "note": invite.note,
+            })).await;
+            serde_json::to_value(&invite).map_err(RpcError::internal)
+        }.await;
+        respond(&req, result)
+    }
+}
+
+/// `admin.invite.list`：列出仍然有效的邀请码。
+pub struct InviteListJsonRpcHandler;
+
+#[async_trait]
+impl JsonRpcHandle for InviteListJsonRpcHandler {
+    /// 无参数。返回邀请码信息的数组，最新创建的在前，包括已经使用的次数 `uses`。
+    async fn json_rpc_handle(&self, req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper> {
+        let result = async {
+            require_permission(&req, rbac::INVITES_ADMIN).await?;
+            let invites = invite::list().await?;
+            serde_json::to_value(&invites).map_err(RpcError::internal)
+        }.await;
+        respond(&req, result)
+    }
+}
+
+/// `admin.invite.revoke`：吊销邀请码，已经用它注册的账户不受影响。
+pub struct InviteRevokeJsonRpcHandler;
+
+#[async_trait]
+impl JsonRpcHandle for InviteRevokeJsonRpcHandler {
+    /// 参数 `code`。返回 `{revoked}`，邀请码不存在或已过期时为 `false`。
+    async fn json_rpc_handle(&self, req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper> {
+        let result = async {
+            let claims = require_permission(&req, rbac::INVITES_ADMIN).await?;
+            let code = req.param_str("code")?;
+            let revoked = invite::revoke(code).await?;
+            if revoked {
+                audit::record(&claims, "admin.invite.revoke", code.trim().to_ascii_lowercase(), Value::Null).await;
+            }
+            Ok(json!({ "revoked": revoked }))
+        }.await;
+        respond(&req, result)
+    }
+}
+
+/// `admin.registration.list`：列出等待批准的注册。
+pub struct RegistrationListJsonRpcHandler;
+
+#[async_trait]
+impl JsonRpcHandle for RegistrationListJsonRpcHandler {
+    /// 可选参数 `offset` 和 `limit`。返回 `{total, users}`，按注册时间排序，最早的在前。
+    async fn json_rpc_handle(&self, req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper> {
+        let result = async {
+            require_permission(&req, rbac::REGISTRATIONS_APPROVE).await?;
+            let query = ListQuery {
+                status: Some(UserStatus::Pending),
+                sort: SortKey::CreatedAt,
+                offset: req.param_u64("offset").map_or(0, |offset| offset as usize),
+                limit: req.param_u64("limit").ok().map(|limit| limit as usize),
+                ..Default::default()
+            };
+            let page = admin::list_users(&query).await?;
+            serde_json::to_value(&page).map_err(RpcError::internal)
+        }.await;
+        respond(&req, result)
+    }
+}
+
+/// `admin.registration.approve`：批准注册，之后用户可以登录。
+pub struct RegistrationApproveJsonRpcHandler;
+
+#[async_trait]
+impl JsonRpcHandle for RegistrationApproveJsonRpcHandler {
+    /// 参数 `user_id`。用户不存在或不在等待批准时返回 `UserNotFound`。返回用户信息。
+    async fn json_rpc_handle(&self, req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper> {
+        let result = async {
+            let claims = require_permission(&req, rbac::REGISTRATIONS_APPROVE).await?;
+            let user_id = req.param_u64("user_id")?;
+            let user = admin::approve(user_id).await?;
+            audit::record(&claims, "admin.registration.approve", user_id, json!({ "username": user.username })).await;
+            user_value(&user)
+        }.await;
+        respond(&req, result)
+    }
+}
+
+/// `admin.registration.reject`：拒绝注册并删除该账户。
+pub struct RegistrationRejectJsonRpcHandler;
+
+#[async_trait]
+impl JsonRpcHandle for RegistrationRejectJsonRpcHandler {
+    /// 参数 `user_id`。用户不存在或不在等待批准时返回 `UserNotFound`。返回 `{rejected: true}`。
+    async fn json_rpc_handle(&self, req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper> {
+        let result = async {
+            let claims = require_permission(&req, rbac::REGISTRATIONS_APPROVE).await?;
+            let user_id = req.param_u64("user_id")?;
+            let user = admin::reject(user_id).await?;
+            audit::record(&claims, "admin.registration.reject", user_id, json!({ "username": user.username })).await;
+            Ok(json!({ "rejected": true }))
+        }.await;
+        respond(&req, result)
+    }
+}
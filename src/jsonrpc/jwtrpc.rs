@@ -0,0 +1,46 @@
+//! JWT 签名密钥的管理方法，需要 [`rbac::KEYS_ADMIN`] 权限。
+
+use axum::{ extract::Json, response::Json as JsonResponse };
+use async_trait::async_trait;
+use serde_json::json;
+
+use super::{ require_permission, respond, JsonRequest, JsonResponseWrapper, JsonRpcHandle, RpcError };
+use crate::audit;
+use crate::rbac;
+use crate::token::token_service;
+
+/// `admin.jwt.keys`：列出密钥环中的所有密钥。
+pub struct JwtKeysJsonRpcHandler;
+
+#[async_trait]
+impl JsonRpcHandle for JwtKeysJsonRpcHandler {
+    /// 返回每个密钥的 `kid`、算法、是否为当前签名密钥以及移除时间。
+    async fn json_rpc_handle(&self, req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper> {
+        let result = require_permission(&req, rbac::KEYS_ADMIN).await.and_then(|_| {
+            serde_json::to_value(token_service().key_summaries()).map_err(RpcError::internal)
+        });
+        respond(&req, result)
+    }
+}
+
+/// `admin.jwt.rotate`：轮换签名密钥。
+pub struct JwtRotateJsonRpcHandler;
+
+#[async_trait]
+impl JsonRpcHandle for JwtRotateJsonRpcHandler {
+    /// 按配置的 `rotation_algorithm` 生成新密钥并保存到用户存储，返回新密钥的 `kid` 和开始用于签名的时间 `activate_at`。
+    ///
+    /// 不接受参数：密钥文件只能通过配置文件加载，见 [`crate::token::keyring`]。
+    async fn json_rpc_handle(&self, req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper> {
+        let result = async {
+            let claims = require_permission(&req, rbac::KEYS_ADMIN).await?;
+            if req.params.as_ref().is_some_and(|params| !params.is_empty()) {
+                return Err(RpcError::invalid_params("admin.jwt.rotate takes no parameters, configure key files in the config file"));
+            }
+            let key = token_service().rotate().await.map_err(RpcError::internal)?;
+            audit::record(&claims, "admin.jwt.rotate", &key.kid, json!({ "activate_at": key.activate_at })).await;
+            Ok(json!({ "kid": key.kid, "activate_at": key.activate_at }))
+        }.await;
+        respond(&req, result)
+    }
+}
@@ -0,0 +1,88 @@
+//! 登录失败锁定的查看和解除，需要 [`rbac::LOCKOUT_ADMIN`] 权限，见 [`crate::account::lockout`]。
+
+use axum::{ extract::Json, response::Json as JsonResponse };
+use async_trait::async_trait;
+use serde_json::{ json, Value };
+
+use super::{ require_permission, respond, JsonRequest, JsonResponseWrapper, JsonRpcHandle, RpcError };
+use crate::audit;
+use crate::account::lockout::{ self, Subject };
+use crate::rbac;
+
+/// 读取参数 `username` 和 `ip`，至少需要其中一个。
+fn subjects(req: &JsonRequest) -> Result<Vec<Subject>, RpcError> {
+    let param = |name: &str| {
+        req.params
+            .as_ref()
+            .and_then(|params| params.get(name))
+            .and_then(Value::as_str)
+            .filter(|value| !value.is_empty())
+    };
+    let mut subjects = Vec::new();
+    if let Some(username) = param("username") {
+        subjects.push(Subject::account(username));
+    }
+    if let Some(ip) = param("ip") {
+        subjects.push(Subject::ip(ip));
+    }
+    if subjects.is_empty() {
+        return Err(RpcError::invalid_params("missing parameter 'username' or 'ip'"));
+    }
+    Ok(subjects)
+}
+
+/// `admin.lockout.list`：列出当前被锁定的用户名和 IP。
+pub struct LockoutListJsonRpcHandler;
+
+#[async_trait]
+impl JsonRpcHandle for LockoutListJsonRpcHandler {
+    /// 返回数组，每项包含 `subject`（`account:{username}` 或 `ip:{ip}`）、`failures` 和 `locked_until`。
+    async fn json_rpc_handle(&self, req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper> {
+        let result = async {
+            require_permission(&req, rbac::LOCKOUT_ADMIN).await?;
+            let locked = lockout::list_locked().await?;
+            serde_json::to_value(locked).map_err(RpcError::internal)
+        }.await;
+        respond(&req, result)
+    }
+}
+
+/// `admin.lockout.get`：查看用户名或 IP 的失败次数和锁定状态。
+pub struct LockoutGetJsonRpcHandler;
+
+#[async_trait]
+impl JsonRpcHandle for LockoutGetJsonRpcHandler {
+    /// 参数 `username` 和/或 `ip`。返回数组，格式与 `admin.lockout.list` 相同，未锁定时 `locked_until` 为 `null`。
+    async fn json_rpc_handle(&self, req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper> {
+        let result = async {
+            require_permission(&req, rbac::LOCKOUT_ADMIN).await?;
+            let mut statuses = Vec::new();
+            for subject in subjects(&req)? {
+                statuses.push(lockout::status(&subject).await?);
+            }
+            serde_json::to_value(statuses).map_err(RpcError::internal)
+        }.await;
+        respond(&req, result)
+    }
+}
+
+/// `admin.lockout.clear`：清除用户名或 IP 的失败记录并解除锁定。
+pub struct LockoutClearJsonRpcHandler;
+
+#[async_trait]
+impl JsonRpcHandle for LockoutClearJsonRpcHandler {
+    /// 参数 `username` 和/或 `ip`。返回 `{cleared}`，为被清除的对象列表。
+    async fn json_rpc_handle(&self, req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper> {
+        let result = async {
+            let claims = require_permission(&req, rbac::LOCKOUT_ADMIN).await?;
+            let mut cleared = Vec::new();
+            for subject in subjects(&req)? {
+                lockout::clear(&subject).await?;
+                audit::record(&claims, "admin.lockout.clear", &subject, Value::Null).await;
+                cleared.push(subject.to_string());
+            }
+            Ok::<Value, RpcError>(json!({ "cleared": cleared }))
+        }.await;
+        respond(&req, result)
+    }
+}
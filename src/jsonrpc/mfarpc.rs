@@ -0,0 +1,97 @@
+//! 两步验证的启用、停用和恢复码管理，均作用于调用者自己的账户，见 [`crate::account::mfa`]。
+
+use axum::{ extract::Json, response::Json as JsonResponse };
+use async_trait::async_trait;
+use serde_json::{ json, Value };
+
+use super::{ authenticate, respond, JsonRequest, JsonResponseWrapper, JsonRpcHandle, RpcError };
+use crate::account::mfa;
+use crate::userstore;
+
+/// `mfa.status`：查看调用者是否已启用两步验证。
+pub struct MfaStatusJsonRpcHandler;
+
+#[async_trait]
+impl JsonRpcHandle for MfaStatusJsonRpcHandler {
+    /// 返回 `{enabled, recovery_codes_left, session_mfa}`，`session_mfa` 表示当前登录是否通过了两步验证。
+    async fn json_rpc_handle(&self, req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper> {
+        let result = async {
+            let claims = authenticate(&req).await?;
+            let user = userstore::user_store()
+                .get_by_id(claims.user_id().unwrap_or_default())
+                .await
+                .map_err(RpcError::internal)?;
+            let recovery_codes_left = user.as_ref().and_then(mfa::remaining_recovery_codes);
+            Ok(json!({
+                "enabled": recovery_codes_left.is_some(),
+                "recovery_codes_left": recovery_codes_left,
+                "session_mfa": claims.mfa,
+            }))
+        }.await;
+        respond(&req, result)
+    }
+}
+
+/// `mfa.totp.enroll`：开始启用 TOTP。
+pub struct TotpEnrollJsonRpcHandler;
+
+#[async_trait]
+impl JsonRpcHandle for TotpEnrollJsonRpcHandler {
+    /// 返回 `{secret, otpauth_uri}`，需要在 10 分钟内调用 `mfa.totp.confirm` 确认。
+    async fn json_rpc_handle(&self, req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper> {
+        let result = async {
+            let claims = authenticate(&req).await?;
+            let enrollment = mfa::begin_enrollment(claims.user_id().unwrap_or_default()).await?;
+            serde_json::to_value(enrollment).map_err(RpcError::internal)
+        }.await;
+        respond(&req, result)
+    }
+}
+
+/// `mfa.totp.confirm`：用身份验证器应用给出的密码确认启用 TOTP。
+pub struct TotpConfirmJsonRpcHandler;
+
+#[async_trait]
+impl JsonRpcHandle for TotpConfirmJsonRpcHandler {
+    /// 参数 `code` 为 6 位密码。返回 `{recovery_codes}`，恢复码只返回这一次。
+    async fn json_rpc_handle(&self, req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper> {
+        let result = async {
+            let claims = authenticate(&req).await?;
+            let codes = mfa::confirm_enrollment(claims.user_id().unwrap_or_default(), req.param_str("code")?).await?;
+            Ok(json!({ "recovery_codes": codes }))
+        }.await;
+        respond(&req, result)
+    }
+}
+
+/// `mfa.totp.disable`：停用两步验证。
+pub struct TotpDisableJsonRpcHandler;
+
+#[async_trait]
+impl JsonRpcHandle for TotpDisableJsonRpcHandler {
+    /// 参数 `code` 为 6 位密码或恢复码。返回 `{disabled: true}`。
+    async fn json_rpc_handle(&self, req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper> {
+        let result = async {
+            let claims = authenticate(&req).await?;
+            mfa::disable(claims.user_id().unwrap_or_default(), req.param_str("code")?).await?;
+            Ok::<Value, RpcError>(json!({ "disabled": true }))
+        }.await;
+        respond(&req, result)
+    }
+}
+
+/// `mfa.recovery.regenerate`：重新生成恢复码。
+pub struct RecoveryRegenerateJsonRpcHandler;
+
+#[async_trait]
+impl JsonRpcHandle for RecoveryRegenerateJsonRpcHandler {
+    /// 参数 `code` 为 6 位密码。返回 `{recovery_codes}`，之前的恢复码全部失效。
+    async fn json_rpc_handle(&self, req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper> {
+        let result = async {
+            let claims = authenticate(&req).await?;
+            let codes = mfa::regenerate_recovery_codes(claims.user_id().unwrap_or_default(), req.param_str("code")?).await?;
+            Ok(json!({ "recovery_codes": codes }))
+        }.await;
+        respond(&req, result)
+    }
+}
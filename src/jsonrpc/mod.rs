@@ -1,6 +1,22 @@
 mod addrpc;
+mod apikeyrpc;
+mod auth;
+mod extract;
+mod inviterpc;
+mod jwtrpc;
+mod lockoutrpc;
+mod mfarpc;
+mod query;
+mod sessionrpc;
+mod userrpc;
+pub mod replay;
+
+pub use auth::{ authenticate, require_permission };
+pub use extract::JsonRpcPayload;
+pub use query::call_json_rpc_get_handler;
 
 use std::collections::HashMap;
+use std::fmt;
 use std::sync::Arc;
 use axum::{ extract::Json, response::Json as JsonResponse };
 use serde::{ Deserialize, Serialize };
@@ -8,8 +24,10 @@ use async_trait::async_trait;
 use lazy_static::lazy_static;
 use tokio::sync::RwLock;
 
+use crate::account::AccountError;
+
 /// 表示一个 JSON-RPC 请求。
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JsonRequest {
     /// JSON-RPC 协议版本。
     jsonrpc :   String,
@@ -23,8 +41,34 @@ pub struct JsonRequest {
     params  :   Option<HashMap<String, serde_json::Value>>,
 }
 
+impl JsonRequest {
+    /// 读取字符串参数，缺少或类型错误时返回 `INVALID_PARAMS` 错误。
+    pub fn param_str(&self, name: &str) -> Result<&str, RpcError> {
+        self.params
+            .as_ref()
+            .and_then(|params| params.get(name))
+            .and_then(serde_json::Value::as_str)
+            .ok_or_else(|| RpcError::invalid_params(format!("missing string parameter '{}'", name)))
+    }
+
+    /// 将全部参数反序列化为 `T`，没有参数时按空对象处理，类型错误时返回 `INVALID_PARAMS` 错误。
+    pub fn params_as<T: serde::de::DeserializeOwned>(&self) -> Result<T, RpcError> {
+        let params = serde_json::to_value(self.params.clone().unwrap_or_default()).map_err(RpcError::internal)?;
+        serde_json::from_value(params).map_err(|err| RpcError::invalid_params(format!("invalid parameters: {}", err)))
+    }
+
+    /// 读取非负整数参数，缺少或类型错误时返回 `INVALID_PARAMS` 错误。
+    pub fn param_u64(&self, name: &str) -> Result<u64, RpcError> {
+        self.params
+            .as_ref()
+            .and_then(|params| params.get(name))
+            .and_then(serde_json::Value::as_u64)
+            .ok_or_else(|| RpcError::invalid_params(format!("missing integer parameter '{}'", name)))
+    }
+}
+
 /// 表示一个 JSON-RPC 响应包装器。
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JsonResponseWrapper {
     /// JSON-RPC 协议版本。
     jsonrpc: String,
@@ -32,6 +76,94 @@ pub struct JsonResponseWrapper {
     result: serde_json::Value,
     /// JSON-RPC 请求 ID。
     id: serde_json::Value,
+    /// JSON-RPC 错误对象，仅在请求失败时出现。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<JsonRpcError>,
+}
+
+/// 表示一个 JSON-RPC 错误对象。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonRpcError {
+    /// 错误码，`-32768` 到 `-32000` 为协议保留。
+    pub code: i64,
+    /// 错误的简短描述。
+    pub message: String,
+    /// 附加的错误信息。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+/// 解析错误：服务器收到的不是合法的 JSON。
+pub const PARSE_ERROR: i64 = -32700;
+/// 无效请求：JSON 合法，但不是一个有效的请求对象。
+pub const INVALID_REQUEST: i64 = -32600;
+/// 方法不存在。
+pub const METHOD_NOT_FOUND: i64 = -32601;
+/// 无效的方法参数。
+pub const INVALID_PARAMS: i64 = -32602;
+/// 缺少有效的 jwt token。
+pub const UNAUTHORIZED: i64 = -32001;
+/// 已认证，但没有执行该方法的权限。
+pub const FORBIDDEN: i64 = -32003;
+/// 方法未被标记为只读安全，不允许通过 HTTP GET 调用。
+pub const METHOD_NOT_ALLOWED: i64 = -32005;
+/// 服务器内部错误。
+pub const INTERNAL_ERROR: i64 = -32603;
+
+/// 处理函数内部使用的错误，转换为带错误对象的 JSON-RPC 响应。
+#[derive(Debug, Clone)]
+pub struct RpcError {
+    /// 错误码。
+    pub code: i64,
+    /// 错误描述。
+    pub message: String,
+    /// 附加的错误信息。
+    pub data: Option<serde_json::Value>,
+}
+
+impl RpcError {
+    /// 创建一个错误。
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        RpcError { code, message: message.into(), data: None }
+    }
+
+    /// 无效参数错误。
+    pub fn invalid_params(message: impl Into<String>) -> Self {
+        RpcError::new(INVALID_PARAMS, message)
+    }
+
+    /// 内部错误，详细信息只写入日志。
+    pub fn internal(detail: impl fmt::Display) -> Self {
+        tracing::error!("json-rpc internal error: {}", detail);
+        RpcError::new(INTERNAL_ERROR, "Internal error")
+    }
+}
+
+/// 账户操作的错误：内部错误、未认证和没有权限分别对应 `INTERNAL_ERROR`、`UNAUTHORIZED` 和 `FORBIDDEN`，
+/// 其余为 `INVALID_PARAMS`，并在 `data.errorid` 中带上 [`AccountError::errorid`]；
+/// 资料字段错误还在 `data.fields` 中列出每个字段的错误。
+impl From<AccountError> for RpcError {
+    fn from(err: AccountError) -> Self {
+        match err {
+            AccountError::Internal(detail) => RpcError::internal(detail),
+            AccountError::Unauthorized => RpcError::new(UNAUTHORIZED, err.to_string()),
+            AccountError::Forbidden => RpcError {
+                code: FORBIDDEN,
+                message: err.to_string(),
+                data: Some(serde_json::json!({ "errorid": err.errorid() })),
+            },
+            AccountError::InvalidProfile(ref fields) => RpcError {
+                code: INVALID_PARAMS,
+                message: err.to_string(),
+                data: Some(serde_json::json!({ "errorid": err.errorid(), "fields": fields })),
+            },
+            err => RpcError {
+                code: INVALID_PARAMS,
+                message: err.to_string(),
+                data: Some(serde_json::json!({ "errorid": err.errorid() })),
+            },
+        }
+    }
 }
 
 /// 定义 JSON-RPC 处理器的 trait。
@@ -49,14 +181,61 @@ pub trait JsonRpcHandle {
     async fn json_rpc_handle(&self, req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper>;
 }
 
+/// 注册表中的一个 JSON-RPC 方法。
+#[derive(Clone)]
+pub struct RegisteredHandle {
+    /// 方法的处理函数。
+    pub handle: Arc<dyn JsonRpcHandle + Send + Sync>,
+    /// 方法是否只读安全。只有安全的方法才能通过 HTTP GET 调用；
+    /// 返回敏感数据的 `admin.*` 方法即使只读也不标记为安全，只能通过 POST 调用。
+    pub safe: bool,
+}
+
 lazy_static! {
     /// 将 JSON-RPC 方法名与处理函数的映射。
     ///
     /// 该映射存储了 JSON-RPC 方法名与相应处理函数的关联关系。
-    static ref JSON_RPC_HANDLE_MAP: RwLock<HashMap<&'static str, Arc<dyn JsonRpcHandle + Send + Sync>>> 
+    static ref JSON_RPC_HANDLE_MAP: RwLock<HashMap<&'static str, RegisteredHandle>> 
         = {
-            let mut m: HashMap<&'static str, Arc<dyn JsonRpcHandle + Send + Sync>> = HashMap::new();
-            m.insert("add", Arc::new(addrpc::AddJsonRpcHandler));
+            let mut m: HashMap<&'static str, RegisteredHandle> = HashMap::new();
+            m.insert("add", RegisteredHandle { handle: Arc::new(addrpc::AddJsonRpcHandler), safe: true });
+            m.insert("admin.jwt.keys", RegisteredHandle { handle: Arc::new(jwtrpc::JwtKeysJsonRpcHandler), safe: false });
+            m.insert("admin.jwt.rotate", RegisteredHandle { handle: Arc::new(jwtrpc::JwtRotateJsonRpcHandler), safe: false });
+            m.insert("session.list", RegisteredHandle { handle: Arc::new(sessionrpc::SessionListJsonRpcHandler), safe: true });
+            m.insert("session.revoke", RegisteredHandle { handle: Arc::new(sessionrpc::SessionRevokeJsonRpcHandler), safe: false });
+            m.insert("admin.session.list", RegisteredHandle { handle: Arc::new(sessionrpc::AdminSessionListJsonRpcHandler), safe: false });
+            m.insert("admin.session.revoke", RegisteredHandle { handle: Arc::new(sessionrpc::AdminSessionRevokeJsonRpcHandler), safe: false });
+            m.insert("admin.lockout.list", RegisteredHandle { handle: Arc::new(lockoutrpc::LockoutListJsonRpcHandler), safe: false });
+            m.insert("admin.lockout.get", RegisteredHandle { handle: Arc::new(lockoutrpc::LockoutGetJsonRpcHandler), safe: false });
+            m.insert("admin.lockout.clear", RegisteredHandle { handle: Arc::new(lockoutrpc::LockoutClearJsonRpcHandler), safe: false });
+            m.insert("user.profile.get", RegisteredHandle { handle: Arc::new(userrpc::ProfileGetJsonRpcHandler), safe: true });
+            m.insert("user.profile.update", RegisteredHandle { handle: Arc::new(userrpc::ProfileUpdateJsonRpcHandler), safe: false });
+            m.insert("user.profile.public", RegisteredHandle { handle: Arc::new(userrpc::PublicProfileJsonRpcHandler), safe: true });
+            m.insert("admin.user.list", RegisteredHandle { handle: Arc::new(userrpc::AdminUserListJsonRpcHandler), safe: false });
+            m.insert("admin.user.get", RegisteredHandle { handle: Arc::new(userrpc::AdminUserGetJsonRpcHandler), safe: false });
+            m.insert("admin.user.create", RegisteredHandle { handle: Arc::new(userrpc::AdminUserCreateJsonRpcHandler), safe: false });
+            m.insert("admin.user.disable", RegisteredHandle { handle: Arc::new(userrpc::AdminUserDisableJsonRpcHandler), safe: false });
+            m.insert("admin.user.enable", RegisteredHandle { handle: Arc::new(userrpc::AdminUserEnableJsonRpcHandler), safe: false });
+            m.insert("admin.user.delete", RegisteredHandle { handle: Arc::new(userrpc::AdminUserDeleteJsonRpcHandler), safe: false });
+            m.insert("admin.user.resetPassword", RegisteredHandle { handle: Arc::new(userrpc::AdminUserResetPasswordJsonRpcHandler), safe: false });
+            m.insert("admin.user.setRoles", RegisteredHandle { handle: Arc::new(userrpc::AdminUserSetRolesJsonRpcHandler), safe: false });
+            m.insert("admin.role.list", RegisteredHandle { handle: Arc::new(userrpc::AdminRoleListJsonRpcHandler), safe: false });
+            m.insert("admin.serviceAccount.create", RegisteredHandle { handle: Arc::new(apikeyrpc::ServiceAccountCreateJsonRpcHandler), safe: false });
+            m.insert("admin.apiKey.create", RegisteredHandle { handle: Arc::new(apikeyrpc::ApiKeyCreateJsonRpcHandler), safe: false });
+            m.insert("admin.apiKey.list", RegisteredHandle { handle: Arc::new(apikeyrpc::ApiKeyListJsonRpcHandler), safe: false });
+            m.insert("admin.apiKey.rotate", RegisteredHandle { handle: Arc::new(apikeyrpc::ApiKeyRotateJsonRpcHandler), safe: false });
+            m.insert("admin.apiKey.revoke", RegisteredHandle { handle: Arc::new(apikeyrpc::ApiKeyRevokeJsonRpcHandler), safe: false });
+            m.insert("admin.invite.create", RegisteredHandle { handle: Arc::new(inviterpc::InviteCreateJsonRpcHandler), safe: false });
+            m.insert("admin.invite.list", RegisteredHandle { handle: Arc::new(inviterpc::InviteListJsonRpcHandler), safe: false });
+            m.insert("admin.invite.revoke", RegisteredHandle { handle: Arc::new(inviterpc::InviteRevokeJsonRpcHandler), safe: false });
+            m.insert("admin.registration.list", RegisteredHandle { handle: Arc::new(inviterpc::RegistrationListJsonRpcHandler), safe: false });
+            m.insert("admin.registration.approve", RegisteredHandle { handle: Arc::new(inviterpc::RegistrationApproveJsonRpcHandler), safe: false });
+            m.insert("admin.registration.reject", RegisteredHandle { handle: Arc::new(inviterpc::RegistrationRejectJsonRpcHandler), safe: false });
+            m.insert("mfa.status", RegisteredHandle { handle: Arc::new(mfarpc::MfaStatusJsonRpcHandler), safe: true });
+            m.insert("mfa.totp.enroll", RegisteredHandle { handle: Arc::new(mfarpc::TotpEnrollJsonRpcHandler), safe: false });
+            m.insert("mfa.totp.confirm", RegisteredHandle { handle: Arc::new(mfarpc::TotpConfirmJsonRpcHandler), safe: false });
+            m.insert("mfa.totp.disable", RegisteredHandle { handle: Arc::new(mfarpc::TotpDisableJsonRpcHandler), safe: false });
+            m.insert("mfa.recovery.regenerate", RegisteredHandle { handle: Arc::new(mfarpc::RecoveryRegenerateJsonRpcHandler), safe: false });
             RwLock::new(m)
         };
 }
@@ -67,12 +246,14 @@ lazy_static! {
 ///
 /// - `name`: JSON-RPC 方法名。
 /// - `json_rpc_handle`: JSON-RPC 处理函数。
-async fn _add_json_rpc_handle(
+/// - `safe`: 方法是否只读安全，安全的方法允许通过 HTTP GET 调用。
+pub async fn add_json_rpc_handle(
     name: &'static str,
-    json_rpc_handle: Arc<dyn JsonRpcHandle + Send + Sync>
+    json_rpc_handle: Arc<dyn JsonRpcHandle + Send + Sync>,
+    safe: bool
 ) {
     let mut hashmap = JSON_RPC_HANDLE_MAP.write().await;
-    hashmap.insert(name, json_rpc_handle);
+    hashmap.insert(name, RegisteredHandle { handle: json_rpc_handle, safe });
 }
 
 /// 获取指定名称的 JSON-RPC 处理函数。
@@ -83,28 +264,51 @@ async fn _add_json_rpc_handle(
 ///
 /// # 返回
 ///
-/// 返回一个包含 JSON-RPC 处理函数的 `Option`。
-async fn get_json_rpc_handle(name: String) -> Option<Arc<dyn JsonRpcHandle + Send + Sync>> {
+/// 返回一个包含 JSON-RPC 处理函数及其安全标记的 `Option`。
+async fn get_json_rpc_handle(name: &str) -> Option<RegisteredHandle> {
     let hashmap = JSON_RPC_HANDLE_MAP.read().await;
-    hashmap.get(name.as_str()).map(|x| x.clone())
+    hashmap.get(name).cloned()
 }
 
 /// 调用 JSON-RPC 处理函数。
 ///
-/// 如果未找到指定名称的处理函数，则返回一个错误响应。
+/// 如果未找到指定名称的处理函数，则返回 `METHOD_NOT_FOUND` 错误响应。
+/// 开启录制（见 [`replay`]）时，请求与响应会被追加到录制文件中。
 ///
 /// # 参数
 ///
-/// - `req`: 包含 JSON-RPC 请求数据的 `Json` 结构。
+/// - `payload`: 由 [`JsonRpcPayload`] 提取的 JSON-RPC 请求；请求体无法解析时，
+///   提取器会直接返回 JSON-RPC 错误响应，不会进入该函数。
 ///
 /// # 返回
 ///
 /// 返回一个 `JsonResponse` 包含 JSON-RPC 响应数据的 `JsonResponseWrapper` 结构。
-pub async fn call_json_rpc_handler(req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper> {
-    match get_json_rpc_handle(req.method.clone()).await {
-        Some(func_pointer) => func_pointer.json_rpc_handle(req).await,
-        None => error_response(req.jsonrpc.clone(), req.id.clone()),
-    }
+pub async fn call_json_rpc_handler(JsonRpcPayload(request): JsonRpcPayload) -> JsonResponse<JsonResponseWrapper> {
+    dispatch(request, false).await
+}
+
+/// 将请求分发给已注册的处理函数，并在开启录制时记录请求与响应。
+///
+/// `safe_only` 为 `true` 时（HTTP GET），未标记为安全的方法会被拒绝。
+async fn dispatch(request: JsonRequest, safe_only: bool) -> JsonResponse<JsonResponseWrapper> {
+    let req = Json(request.clone());
+    let response = match get_json_rpc_handle(&req.method).await {
+        Some(registered) if safe_only && !registered.safe => error_response_with(
+            req.jsonrpc.clone(),
+            req.id.clone(),
+            METHOD_NOT_ALLOWED,
+            format!("Method '{}' is not allowed over GET", req.method)
+        ),
+        Some(registered) => registered.handle.json_rpc_handle(req).await,
+        None => error_response_with(
+            req.jsonrpc.clone(),
+            req.id.clone(),
+            METHOD_NOT_FOUND,
+            format!("Method '{}' not found", req.method)
+        ),
+    };
+    replay::record_exchange(&request, &response).await;
+    response
 }
 
 /// 创建一个 JSON-RPC 错误响应。
@@ -122,10 +326,64 @@ pub fn error_response(jsonrpc: String, id: serde_json::Value) -> JsonResponse<Js
         jsonrpc,
         result: serde_json::Value::Null,
         id,
+        error: None,
     };
     JsonResponse(response)
 }
 
+/// 创建一个带有错误对象的 JSON-RPC 错误响应。
+///
+/// # 参数
+///
+/// - `jsonrpc`: JSON-RPC 协议版本。
+/// - `id`: JSON-RPC 请求 ID，无法确定时为 `null`。
+/// - `code`: 错误码。
+/// - `message`: 错误描述。
+///
+/// # 返回
+///
+/// 返回一个包含错误对象的 `JsonResponseWrapper` 结构。
+pub fn error_response_with(
+    jsonrpc: String,
+    id: serde_json::Value,
+    code: i64,
+    message: impl Into<String>
+) -> JsonResponse<JsonResponseWrapper> {
+    JsonResponse(JsonResponseWrapper {
+        jsonrpc,
+        result: serde_json::Value::Null,
+        id,
+        error: Some(JsonRpcError { code, message: message.into(), data: None }),
+    })
+}
+
+/// 将处理结果转换为 JSON-RPC 响应。
+///
+/// # 参数
+///
+/// - `req`: 原始请求，用于回填协议版本和请求 ID。
+/// - `result`: 处理结果，失败时转换为错误对象。
+///
+/// # 返回
+///
+/// 返回一个 `JsonResponse` 包含 JSON-RPC 响应数据的 `JsonResponseWrapper` 结构。
+pub fn respond(req: &JsonRequest, result: Result<serde_json::Value, RpcError>) -> JsonResponse<JsonResponseWrapper> {
+    match result {
+        Ok(result) => JsonResponse(JsonResponseWrapper {
+            jsonrpc: req.jsonrpc.clone(),
+            result,
+            id: req.id.clone(),
+            error: None,
+        }),
+        Err(err) => JsonResponse(JsonResponseWrapper {
+            jsonrpc: req.jsonrpc.clone(),
+            result: serde_json::Value::Null,
+            id: req.id.clone(),
+            error: Some(JsonRpcError { code: err.code, message: err.message, data: err.data }),
+        }),
+    }
+}
+
 // 处理 JSON-RPC 请求的函数。
 // ///
 // /// 该函数接收一个 JSON-RPC 请求，并根据请求中指定的方法将其分发给相应的处理函数。
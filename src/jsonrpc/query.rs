@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{ rejection::QueryRejection, Query },
+    http::{ header, HeaderMap, HeaderValue },
+    response::{ IntoResponse, Response },
+};
+use base64::{ engine::general_purpose::URL_SAFE_NO_PAD, Engine };
+
+use super::{ dispatch, JsonRequest, extract::PayloadError, INVALID_PARAMS, INVALID_REQUEST };
+
+/// 成功响应允许被缓存的秒数。
+const GET_CACHE_MAX_AGE: u32 = 60;
+
+/// 通过 HTTP GET 调用 JSON-RPC 方法。
+///
+/// 查询参数：
+///
+/// - `method`：方法名，必填；
+/// - `id`：请求 ID，数字会按数字处理，其余按字符串处理，缺省为 `null`；
+/// - `params`：base64url 编码（可带或不带填充）的 JSON 对象。
+///
+/// 令牌只能通过请求头 `Authorization: Bearer <token>` 传递，不接受查询参数 `token`，
+/// 避免访问令牌和 API 密钥出现在 URL、代理日志和浏览器历史中。
+///
+/// 只有注册时标记为安全的方法才能通过 GET 调用，所有 `admin.*` 方法都不安全。
+/// 查询参数无法解析时返回 `-32600` 无效请求。成功的响应带有 `Cache-Control`，
+/// 携带令牌的请求只允许私有缓存。
+pub async fn call_json_rpc_get_handler(
+    headers: HeaderMap,
+    query: Result<Query<HashMap<String, String>>, QueryRejection>
+) -> Response {
+    let request = query
+        .map_err(|rejection| PayloadError::new(serde_json::Value::Null, INVALID_REQUEST, rejection.body_text()))
+        .and_then(|Query(query)| request_from_query(&query, &headers));
+    let request = match request {
+        Ok(request) => request,
+        Err(err) => return ([(header::CACHE_CONTROL, HeaderValue::from_static("no-store"))], err.into_response()).into_response(),
+    };
+    let private = !request.token.is_empty();
+    let response = dispatch(request, true).await;
+    let cache_control = if response.error.is_some() || response.result.is_null() {
+        HeaderValue::from_static("no-store")
+    } else if private {
+        HeaderValue::from_str(&format!("private, max-age={}", GET_CACHE_MAX_AGE)).unwrap()
+    } else {
+        HeaderValue::from_str(&format!("public, max-age={}", GET_CACHE_MAX_AGE)).unwrap()
+    };
+    ([(header::CACHE_CONTROL, cache_control), (header::VARY, HeaderValue::from_static("Authorization"))], response).into_response()
+}
+
+/// 将查询参数和 `Authorization` 请求头转换为 JSON-RPC 请求。
+fn request_from_query(query: &HashMap<String, String>, headers: &HeaderMap) -> Result<JsonRequest, PayloadError> {
+    let id = query.get("id").map_or(serde_json::Value::Null, |id| parse_id(id));
+    if query.contains_key("token") {
+        return Err(PayloadError::new(id, INVALID_REQUEST, "Invalid Request: send the token in the Authorization header"));
+    }
+    let token = match headers.get(header::AUTHORIZATION) {
+        Some(value) => match value.to_str().ok().and_then(|value| value.strip_prefix("Bearer ")) {
+            Some(token) => token.trim().to_string(),
+            None => return Err(PayloadError::new(id, INVALID_REQUEST, "Invalid Request: malformed Authorization header")),
+        },
+        None => String::new(),
+    };
+    let method = match query.get("method") {
+        Some(method) if !method.is_empty() => method.clone(),
+        _ => return Err(PayloadError::new(id, INVALID_REQUEST, "Invalid Request: missing method")),
+    };
+    let params = match query.get("params") {
+        Some(encoded) => Some(decode_params(encoded).map_err(|message| {
+            PayloadError::new(id.clone(), INVALID_PARAMS, message)
+        })?),
+        None => None,
+    };
+    Ok(JsonRequest {
+        jsonrpc: "2.0".into(),
+        method,
+        id,
+        token,
+        params,
+    })
+}
+
+/// 解析查询参数中的 `id`。
+fn parse_id(id: &str) -> serde_json::Value {
+    match id.parse::<serde_json::Number>() {
+        Ok(number) => serde_json::Value::Number(number),
+        Err(_) => serde_json::Value::String(id.to_string()),
+    }
+}
+
+/// 解码 base64url 编码的参数对象。
+fn decode_params(encoded: &str) -> Result<HashMap<String, serde_json::Value>, String> {
+    let bytes = URL_SAFE_NO_PAD
+        .decode(encoded.trim_end_matches('='))
+        .map_err(|err| format!("Invalid params: not base64url: {}", err))?;
+    serde_json::from_slice(&bytes).map_err(|err| format!("Invalid params: not a JSON object: {}", err))
+}
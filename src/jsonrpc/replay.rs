@@ -0,0 +1,458 @@
+//! # JSON-RPC 录制与回放
+//!
+//! 该模块用于复现线上问题：把真实的 `/jsonrpc` 请求/响应对录制到文件中，
+//! 再在本地进程内的服务器上回放，并与录制时的响应做差异比较。
+//!
+//! ## 录制
+//!
+//! 设置环境变量 `BTCMWEB_RPC_RECORD` 为文件路径后启动服务器，每个 `/jsonrpc` 交换（POST 和 GET）
+//! 都会以一行 JSON（`{"request":..., "response":...}`）追加到该文件中，回放时统一以 POST 发送。
+//!
+//! 写入前会清空请求的 `token`，并将参数和结果中的敏感字段（见 [`REDACTED_KEYS`]）替换为
+//! [`REDACTED`]，录制文件中不包含令牌、密码、验证码或密钥。因此需要登录的方法回放时没有令牌，
+//! 比较时应忽略相应的路径。
+//!
+//! ## 回放
+//!
+//! ```rust,ignore
+//! use btcmweb::jsonrpc::replay::{replay_file, ReplayOptions};
+//!
+//! #[tokio::test]
+//! async fn replay_production_capture() {
+//!     let options = ReplayOptions::default().ignore("/result/timestamp");
+//!     let report = replay_file("captures/rpc.jsonl", &options).await.unwrap();
+//!     assert!(report.is_clean(), "{}", report);
+//! }
+//! ```
+//!
+//! 忽略路径使用 JSON Pointer 语法（如 `/result/created_at`），其中 `*` 可匹配任意一段。
+
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use axum::{ body::Body, http::{ header, Request }, Router };
+use lazy_static::lazy_static;
+use serde::{ Deserialize, Serialize };
+use tokio::{ fs::OpenOptions, io::AsyncWriteExt, sync::Mutex };
+use tower::ServiceExt;
+
+use super::{ JsonRequest, JsonResponseWrapper };
+
+/// 开启录制时读取的环境变量名。
+pub static RECORD_ENV_VAR: &str = "BTCMWEB_RPC_RECORD";
+/// 录制时替换敏感字段的值。
+pub static REDACTED: &str = "[REDACTED]";
+/// 录制时需要替换的参数和结果字段名（不区分大小写，任意嵌套层级）。
+pub static REDACTED_KEYS: &[&str] = &[
+    "password",
+    "new_password",
+    "current_password",
+    "code",
+    "codes",
+    "recovery_codes",
+    "token",
+    "access_token",
+    "refresh_token",
+    "mfa_token",
+    "secret",
+    "otpauth_uri",
+    "key",
+];
+
+/// 录制文件中的一条记录：一次完整的请求/响应交换。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedExchange {
+    /// 客户端发送的 JSON-RPC 请求。
+    pub request: JsonRequest,
+    /// 服务器返回的 JSON-RPC 响应。
+    pub response: JsonResponseWrapper,
+}
+
+lazy_static! {
+    /// 录制文件路径，未设置 `BTCMWEB_RPC_RECORD` 时为 `None`。
+    static ref RECORD_PATH: Option<String> = std::env::var(RECORD_ENV_VAR).ok();
+    /// 串行化对录制文件的写入，避免并发请求交错写出半行数据。
+    static ref RECORD_LOCK: Mutex<()> = Mutex::new(());
+}
+
+/// 如果开启了录制，将一次请求/响应交换追加到录制文件中。
+///
+/// 录制失败只记录日志，不影响对客户端的响应。
+pub async fn record_exchange(request: &JsonRequest, response: &JsonResponseWrapper) {
+    let Some(path) = RECORD_PATH.as_ref() else {
+        return;
+    };
+    let exchange = redact_exchange(request, response);
+    if let Err(err) = append_exchange(path, &exchange).await {
+        tracing::warn!("failed to record json-rpc exchange to {}: {}", path, err);
+    }
+}
+
+/// 复制一次交换，清空请求的令牌并替换参数和结果中的敏感字段。
+pub fn redact_exchange(request: &JsonRequest, response: &JsonResponseWrapper) -> RecordedExchange {
+    let mut request = request.clone();
+    request.token.clear();
+    if let Some(params) = request.params.as_mut() {
+        for (name, value) in params.iter_mut() {
+            redact_value(Some(name), value);
+        }
+    }
+    let mut response = response.clone();
+    redact_value(None, &mut response.result);
+    RecordedExchange { request, response }
+}
+
+/// 将名称在 [`REDACTED_KEYS`] 中的字段替换为 [`REDACTED`]，并递归处理对象和数组。
+fn redact_value(name: Option<&str>, value: &mut serde_json::Value) {
+    if name.is_some_and(|name| REDACTED_KEYS.iter().any(|key| key.eq_ignore_ascii_case(name))) {
+        if !value.is_null() {
+            *value = serde_json::Value::String(REDACTED.to_string());
+        }
+        return;
+    }
+    match value {
+        serde_json::Value::Object(map) => {
+            for (name, value) in map.iter_mut() {
+                redact_value(Some(name), value);
+            }
+        }
+        serde_json::Value::Array(items) => {
+            for item in items.iter_mut() {
+                redact_value(None, item);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// 将一条记录以 JSON 行的形式追加到指定文件。
+pub async fn append_exchange(path: impl AsRef<Path>, exchange: &RecordedExchange) -> io::Result<()> {
+    let mut line = serde_json::to_vec(exchange)?;
+    line.push(b'\n');
+    let _guard = RECORD_LOCK.lock().await;
+    let mut file = OpenOptions::new().create(true).append(true).open(path).await?;
+    file.write_all(&line).await
+}
+
+/// 从录制文件中读取所有记录，跳过空行。
+pub async fn load_exchanges(path: impl AsRef<Path>) -> io::Result<Vec<RecordedExchange>> {
+    let content = tokio::fs::read_to_string(path).await?;
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).map_err(io::Error::from))
+        .collect()
+}
+
+/// 回放选项。
+#[derive(Debug, Clone, Default)]
+pub struct ReplayOptions {
+    /// 比较响应时忽略的 JSON Pointer 路径，`*` 匹配任意一段。
+    pub ignore_paths: Vec<String>,
+}
+
+impl ReplayOptions {
+    /// 添加一个忽略路径。
+    pub fn ignore(mut self, path: impl Into<String>) -> Self {
+        self.ignore_paths.push(path.into());
+        self
+    }
+
+    /// 判断给定路径是否被忽略（忽略路径的子路径同样被忽略）。
+    fn is_ignored(&self, path: &[String]) -> bool {
+        self.ignore_paths.iter().any(|pattern| {
+            let segments: Vec<&str> = pattern.split('/').skip(1).collect();
+            segments.len() <= path.len()
+                && segments
+                    .iter()
+                    .zip(path)
+                    .all(|(pattern, segment)| *pattern == "*" || unescape_pointer(pattern) == *segment)
+        })
+    }
+}
+
+/// 还原 JSON Pointer 中的转义（`~1` 为 `/`，`~0` 为 `~`）。
+fn unescape_pointer(segment: &str) -> String {
+    segment.replace("~1", "/").replace("~0", "~")
+}
+
+/// 一处响应差异。
+#[derive(Debug, Clone)]
+pub struct ReplayMismatch {
+    /// 记录在录制文件中的序号（从 0 开始）。
+    pub index: usize,
+    /// 请求的方法名。
+    pub method: String,
+    /// 差异所在的 JSON Pointer 路径。
+    pub path: String,
+    /// 录制时的值，`None` 表示该字段不存在。
+    pub expected: Option<serde_json::Value>,
+    /// 回放时的值，`None` 表示该字段不存在。
+    pub actual: Option<serde_json::Value>,
+}
+
+/// 回放结果汇总。
+#[derive(Debug, Clone, Default)]
+pub struct ReplayReport {
+    /// 回放的记录条数。
+    pub total: usize,
+    /// 所有发现的差异。
+    pub mismatches: Vec<ReplayMismatch>,
+}
+
+impl ReplayReport {
+    /// 没有任何差异时返回 `true`。
+    pub fn is_clean(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+impl fmt::Display for ReplayReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "replayed {} exchanges, {} mismatches", self.total, self.mismatches.len())?;
+        for m in &self.mismatches {
+            writeln!(
+                f,
+                "  #{} {} at {}: expected {}, got {}",
+                m.index,
+                m.method,
+                if m.path.is_empty() { "/" } else { m.path.as_str() },
+                display_value(&m.expected),
+                display_value(&m.actual)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn display_value(value: &Option<serde_json::Value>) -> String {
+    value.as_ref().map_or_else(|| "<missing>".to_string(), |v| v.to_string())
+}
+
+/// 读取录制文件，并在进程内的完整路由上回放。
+pub async fn replay_file(path: impl AsRef<Path>, options: &ReplayOptions) -> io::Result<ReplayReport> {
+    let exchanges = load_exchanges(path).await?;
+    replay_exchanges(crate::webserver::app_router(), &exchanges, options).await
+}
+
+/// 在给定的路由上回放记录，并逐条比较响应。
+pub async fn replay_exchanges(
+    router: Router,
+    exchanges: &[RecordedExchange],
+    options: &ReplayOptions
+) -> io::Result<ReplayReport> {
+    let mut report = ReplayReport { total: exchanges.len(), mismatches: Vec::new() };
+    for (index, exchange) in exchanges.iter().enumerate() {
+        let actual = send_request(router.clone(), &exchange.request).await?;
+        let expected = serde_json::to_value(&exchange.response)?;
+        let mut path = Vec::new();
+        diff_values(index, &exchange.request.method, &mut path, Some(&expected), Some(&actual), options, &mut report.mismatches);
+    }
+    Ok(report)
+}
+
+/// 以 POST `/jsonrpc` 的方式把请求发送给路由，返回解析后的响应 JSON。
+async fn send_request(router: Router, request: &JsonRequest) -> io::Result<serde_json::Value> {
+    let body = serde_json::to_vec(request)?;
+    let http_request = Request::post("/jsonrpc")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .map_err(io::Error::other)?;
+    let response = router.oneshot(http_request).await.map_err(io::Error::other)?;
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.map_err(io::Error::other)?;
+    Ok(serde_json::from_slice(&bytes).unwrap_or_else(|_| {
+        serde_json::Value::String(String::from_utf8_lossy(&bytes).into_owned())
+    }))
+}
+
+/// 递归比较两个 JSON 值，将未被忽略的差异追加到 `out`。
+fn diff_values(
+    index: usize,
+    method: &str,
+    path: &mut Vec<String>,
+    expected: Option<&serde_json::Value>,
+    actual: Option<&serde_json::Value>,
+    options: &ReplayOptions,
+    out: &mut Vec<ReplayMismatch>
+) {
+    if options.is_ignored(path) {
+        return;
+    }
+    match (expected, actual) {
+        (Some(serde_json::Value::Object(e)), Some(serde_json::Value::Object(a))) => {
+            let mut keys: Vec<&String> = e.keys().chain(a.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                path.push(key.clone());
+                diff_values(index, method, path, e.get(key), a.get(key), options, out);
+                path.pop();
+            }
+        }
+        (Some(serde_json::Value::Array(e)), Some(serde_json::Value::Array(a))) => {
+            for i in 0..e.len().max(a.len()) {
+                path.push(i.to_string());
+                diff_values(index, method, path, e.get(i), a.get(i), options, out);
+                path.pop();
+            }
+        }
+        (e, a) if e == a => {}
+        (e, a) => {
+            out.push(ReplayMismatch {
+                index,
+                method: method.to_string(),
+                path: path
+                    .iter()
+                    .map(|s| format!("/{}", s.replace('~', "~0").replace('/', "~1")))
+                    .collect(),
+                expected: e.cloned(),
+                actual: a.cloned(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{ AtomicU64, Ordering };
+    use std::sync::Arc;
+
+    use axum::{ routing::post, Json };
+    use serde_json::json;
+
+    use super::*;
+
+    /// 把 `params` 原样放回 `result.echo`，并在 `result.timestamp` 中放一个每次不同的值。
+    fn echo_router() -> Router {
+        let counter = Arc::new(AtomicU64::new(0));
+        Router::new().route("/jsonrpc", post(move |Json(request): Json<serde_json::Value>| {
+            let timestamp = counter.fetch_add(1, Ordering::SeqCst);
+            async move {
+                Json(json!({
+                    "jsonrpc": "2.0",
+                    "result": { "echo": request["params"], "timestamp": timestamp },
+                    "id": request["id"],
+                }))
+            }
+        }))
+    }
+
+    fn exchange(id: u64, params: serde_json::Value, result: serde_json::Value) -> RecordedExchange {
+        serde_json::from_value(json!({
+            "request": { "jsonrpc": "2.0", "method": "test.echo", "id": id, "token": "", "params": params },
+            "response": { "jsonrpc": "2.0", "result": result, "id": id },
+        })).unwrap()
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("btcm-replay-{}-{}.jsonl", name, std::process::id()))
+    }
+
+    #[tokio::test]
+    async fn matching_responses_are_clean() {
+        let exchanges = [
+            exchange(1, json!({ "a": 1 }), json!({ "echo": { "a": 1 }, "timestamp": 0 })),
+            exchange(2, json!({ "b": [1, 2] }), json!({ "echo": { "b": [1, 2] }, "timestamp": 1 })),
+        ];
+        let report = replay_exchanges(echo_router(), &exchanges, &ReplayOptions::default()).await.unwrap();
+        assert_eq!(report.total, 2);
+        assert!(report.is_clean(), "{}", report);
+    }
+
+    #[tokio::test]
+    async fn mismatches_are_reported_with_pointer_paths() {
+        let exchanges = [exchange(
+            1,
+            json!({ "a/b": [1, 2, 3] }),
+            json!({ "echo": { "a/b": [1, 5], "gone": true }, "timestamp": 7 }),
+        )];
+        let report = replay_exchanges(echo_router(), &exchanges, &ReplayOptions::default()).await.unwrap();
+        let mut found: Vec<(String, Option<serde_json::Value>, Option<serde_json::Value>)> = report
+            .mismatches
+            .iter()
+            .map(|m| (m.path.clone(), m.expected.clone(), m.actual.clone()))
+            .collect();
+        found.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(found, vec![
+            ("/result/echo/a~1b/1".to_string(), Some(json!(5)), Some(json!(2))),
+            ("/result/echo/a~1b/2".to_string(), None, Some(json!(3))),
+            ("/result/echo/gone".to_string(), Some(json!(true)), None),
+            ("/result/timestamp".to_string(), Some(json!(7)), Some(json!(0))),
+        ]);
+        assert!(report.mismatches.iter().all(|m| m.index == 0 && m.method == "test.echo"));
+        assert!(report.to_string().contains("expected true, got <missing>"));
+
+        let options = ReplayOptions::default().ignore("/result/timestamp").ignore("/result/*/a~1b").ignore("/result/echo/gone");
+        let report = replay_exchanges(echo_router(), &exchanges, &options).await.unwrap();
+        assert!(report.is_clean(), "{}", report);
+    }
+
+    #[tokio::test]
+    async fn non_json_responses_are_mismatches() {
+        let router = Router::new().route("/jsonrpc", post(|| async { "internal error" }));
+        let exchanges = [exchange(1, json!({}), json!({}))];
+        let report = replay_exchanges(router, &exchanges, &ReplayOptions::default()).await.unwrap();
+        assert_eq!(report.mismatches.len(), 1);
+        assert_eq!(report.mismatches[0].path, "");
+        assert_eq!(report.mismatches[0].actual, Some(json!("internal error")));
+        assert!(report.to_string().contains("at /:"));
+    }
+
+    #[tokio::test]
+    async fn recorded_exchanges_round_trip() {
+        let path = temp_path("roundtrip");
+        let _ = tokio::fs::remove_file(&path).await;
+        let first = exchange(1, json!({ "a": 1 }), json!({ "echo": { "a": 1 }, "timestamp": 0 }));
+        let second = exchange(2, json!({ "a": 2 }), json!({ "echo": { "a": 2 }, "timestamp": 1 }));
+        append_exchange(&path, &first).await.unwrap();
+        append_exchange(&path, &second).await.unwrap();
+        tokio::fs::write(&path, tokio::fs::read_to_string(&path).await.unwrap() + "\n  \n").await.unwrap();
+
+        let loaded = load_exchanges(&path).await.unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded[1].request.method, "test.echo");
+        assert_eq!(serde_json::to_value(&loaded[0].response).unwrap(), serde_json::to_value(&first.response).unwrap());
+        let report = replay_exchanges(echo_router(), &loaded, &ReplayOptions::default()).await.unwrap();
+        assert!(report.is_clean(), "{}", report);
+        tokio::fs::remove_file(&path).await.unwrap();
+    }
+
+    #[test]
+    fn secrets_are_redacted() {
+        let recorded = exchange(
+            3,
+            json!({ "username": "alice", "password": "hunter2", "nested": [{ "Code": "123456" }], "mfa_token": null }),
+            json!({ "key": "btcm_abc", "api_key": { "prefix": "btcm_a", "scopes": ["users.read"] }, "recovery_codes": ["a", "b"] }),
+        );
+        let mut request = recorded.request.clone();
+        request.token = "eyJhbGciOi.secret.jwt".into();
+        let redacted = redact_exchange(&request, &recorded.response);
+        let value = serde_json::to_value(&redacted).unwrap();
+        assert_eq!(value["request"]["token"], "");
+        assert_eq!(value["request"]["params"]["username"], "alice");
+        assert_eq!(value["request"]["params"]["password"], REDACTED);
+        assert_eq!(value["request"]["params"]["nested"][0]["Code"], REDACTED);
+        assert_eq!(value["request"]["params"]["mfa_token"], serde_json::Value::Null);
+        assert_eq!(value["response"]["result"]["key"], REDACTED);
+        assert_eq!(value["response"]["result"]["recovery_codes"], REDACTED);
+        assert_eq!(value["response"]["result"]["api_key"]["prefix"], "btcm_a");
+        let line = serde_json::to_string(&redacted).unwrap();
+        for secret in ["hunter2", "123456", "btcm_abc", "eyJhbGciOi"] {
+            assert!(!line.contains(secret), "{} leaked: {}", secret, line);
+        }
+    }
+
+    #[tokio::test]
+    async fn unreadable_captures_are_errors() {
+        let missing = load_exchanges(temp_path("missing")).await.unwrap_err();
+        assert_eq!(missing.kind(), io::ErrorKind::NotFound);
+
+        let path = temp_path("malformed");
+        tokio::fs::write(&path, "{\"request\": {}}\nnot json\n").await.unwrap();
+        let malformed = load_exchanges(&path).await.unwrap_err();
+        assert_eq!(malformed.kind(), io::ErrorKind::InvalidData);
+        assert!(replay_file(&path, &ReplayOptions::default()).await.is_err());
+        tokio::fs::remove_file(&path).await.unwrap();
+    }
+}
@@ -0,0 +1,103 @@
+//! 登录会话的查看和吊销。`session.*` 作用于调用者自己的会话，`admin.session.*` 需要
+//! [`rbac::SESSIONS_ADMIN`] 权限，且只能吊销权限不超过调用者的用户的会话。
+
+use axum::{ extract::Json, response::Json as JsonResponse };
+use async_trait::async_trait;
+use serde_json::{ json, Value };
+
+use super::{ authenticate, require_permission, respond, JsonRequest, JsonResponseWrapper, JsonRpcHandle, RpcError };
+use crate::account::admin;
+use crate::audit;
+use crate::rbac;
+use crate::token::session::{ self, SessionRecord };
+
+/// 会话的展示形式，`current` 表示是否为发起请求的令牌所属的会话。
+fn session_view(session: &SessionRecord, current_sid: &str) -> Value {
+    json!({
+        "id": session.id,
+        "device_name": session.device_name,
+        "user_agent": session.user_agent,
+        "ip": session.ip,
+        "created_at": session.created_at,
+        "last_seen": session.last_seen,
+        "current": session.id == current_sid,
+    })
+}
+
+/// 列出用户的所有会话。
+async fn list_sessions(user_id: u64, current_sid: &str) -> Result<Value, RpcError> {
+    let sessions = session::list_sessions(user_id).await.map_err(RpcError::internal)?;
+    Ok(Value::Array(sessions.iter().map(|session| session_view(session, current_sid)).collect()))
+}
+
+/// `session.list`：列出调用者的所有会话，最近活跃的在前。
+pub struct SessionListJsonRpcHandler;
+
+#[async_trait]
+impl JsonRpcHandle for SessionListJsonRpcHandler {
+    /// 返回会话数组，每项包含 `id`、`device_name`、`user_agent`、`ip`、`created_at`、`last_seen` 和 `current`。
+    async fn json_rpc_handle(&self, req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper> {
+        let result = match authenticate(&req).await {
+            Ok(claims) => list_sessions(claims.user_id().unwrap_or_default(), &claims.sid).await,
+            Err(err) => Err(err),
+        };
+        respond(&req, result)
+    }
+}
+
+/// `session.revoke`：吊销调用者的一个会话。
+pub struct SessionRevokeJsonRpcHandler;
+
+#[async_trait]
+impl JsonRpcHandle for SessionRevokeJsonRpcHandler {
+    /// 参数 `id` 为会话 ID。返回 `{revoked}`，会话不存在或不属于调用者时为 `false`。
+    async fn json_rpc_handle(&self, req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper> {
+        let result = async {
+            let claims = authenticate(&req).await?;
+            let id = req.param_str("id")?;
+            let revoked = session::revoke_user_session(claims.user_id().unwrap_or_default(), id)
+                .await
+                .map_err(RpcError::internal)?;
+            Ok(json!({ "revoked": revoked }))
+        }.await;
+        respond(&req, result)
+    }
+}
+
+/// `admin.session.list`：列出任意用户的所有会话。
+pub struct AdminSessionListJsonRpcHandler;
+
+#[async_trait]
+impl JsonRpcHandle for AdminSessionListJsonRpcHandler {
+    /// 参数 `user_id` 为用户 ID，返回格式与 `session.list` 相同。
+    async fn json_rpc_handle(&self, req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper> {
+        let result = async {
+            let claims = require_permission(&req, rbac::SESSIONS_ADMIN).await?;
+            list_sessions(req.param_u64("user_id")?, &claims.sid).await
+        }.await;
+        respond(&req, result)
+    }
+}
+
+/// `admin.session.revoke`：吊销任意用户的一个会话。
+pub struct AdminSessionRevokeJsonRpcHandler;
+
+#[async_trait]
+impl JsonRpcHandle for AdminSessionRevokeJsonRpcHandler {
+    /// 参数 `id` 为会话 ID。返回 `{revoked}`，会话不存在时为 `false`。
+    async fn json_rpc_handle(&self, req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper> {
+        let result = async {
+            let claims = require_permission(&req, rbac::SESSIONS_ADMIN).await?;
+            let Some(session) = session::get_session(req.param_str("id")?).await.map_err(RpcError::internal)? else {
+                return Ok(json!({ "revoked": false }));
+            };
+            if let Ok(user) = admin::get_user(session.user_id).await {
+                rbac::can_manage(&claims, &user)?;
+            }
+            session::revoke_session(&session).await.map_err(RpcError::internal)?;
+            audit::record(&claims, "admin.session.revoke", &session.id, json!({ "user_id": session.user_id })).await;
+            Ok(json!({ "revoked": true }))
+        }.await;
+        respond(&req, result)
+    }
+}
@@ -0,0 +1,277 @@
+//! 用户资料的读取和修改（见 [`crate::account::profile`]），用户管理（见 [`crate::account::admin`]），
+//! 以及角色的查看。用户管理的各个方法需要对应的权限（见 [`crate::rbac`]），且只能管理权限不超过
+//! 调用者的用户；管理员的每次修改都写入审计日志，见 [`crate::audit`]。
+
+use axum::{ extract::Json, response::Json as JsonResponse };
+use async_trait::async_trait;
+use serde_json::{ json, Value };
+
+use super::{ require_permission, respond, JsonRequest, JsonResponseWrapper, JsonRpcHandle, RpcError };
+use crate::account::admin::{ self, AdminUserView, ListQuery, NewUser };
+use crate::account::profile::{ self, ProfileUpdate };
+use crate::audit;
+use crate::rbac;
+use crate::token::Claims;
+use crate::userstore::UserRecord;
+
+/// `user.profile.get`：读取调用者自己的资料。
+pub struct ProfileGetJsonRpcHandler;
+
+#[async_trait]
+impl JsonRpcHandle for ProfileGetJsonRpcHandler {
+    /// 返回完整资料，包括邮箱、语言、时区和 `version`。
+    async fn json_rpc_handle(&self, req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper> {
+        let result = async {
+            let claims = require_permission(&req, rbac::PROFILE_READ).await?;
+            let own = profile::get_own(claims.user_id().unwrap_or_default()).await?;
+            serde_json::to_value(own).map_err(RpcError::internal)
+        }.await;
+        respond(&req, result)
+    }
+}
+
+/// `user.profile.update`：部分修改调用者自己的资料。
+pub struct ProfileUpdateJsonRpcHandler;
+
+#[async_trait]
+impl JsonRpcHandle for ProfileUpdateJsonRpcHandler {
+    /// 参数为要修改的字段，`null` 或空串清空该字段；可选参数 `version` 为读取到的资料版本，
+    /// 与当前版本不一致时返回 `errorid` 24。返回修改后的完整资料。
+    async fn json_rpc_handle(&self, req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper> {
+        let result = async {
+            let claims = require_permission(&req, rbac::PROFILE_WRITE).await?;
+            let update: ProfileUpdate = req.params_as()?;
+            let own = profile::update(claims.user_id().unwrap_or_default(), &update).await?;
+            serde_json::to_value(own).map_err(RpcError::internal)
+        }.await;
+        respond(&req, result)
+    }
+}
+
+/// `user.profile.public`：读取其他用户的公开资料。
+pub struct PublicProfileJsonRpcHandler;
+
+#[async_trait]
+impl JsonRpcHandle for PublicProfileJsonRpcHandler {
+    /// 参数 `user_id` 或 `username` 指定用户。返回不含邮箱、语言、时区的公开资料。
+    async fn json_rpc_handle(&self, req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper> {
+        let result = async {
+            require_permission(&req, rbac::PROFILE_READ).await?;
+            let public = match req.param_u64("user_id") {
+                Ok(user_id) => profile::get_public(user_id).await?,
+                Err(_) => profile::get_public_by_username(req.param_str("username")?).await?,
+            };
+            serde_json::to_value(public).map_err(RpcError::internal)
+        }.await;
+        respond(&req, result)
+    }
+}
+
+pub(super) fn user_value(user: &UserRecord) -> Result<Value, RpcError> {
+    serde_json::to_value(AdminUserView::from(user)).map_err(RpcError::internal)
+}
+
+/// 读取要管理的用户，并检查调用者能否管理该用户，见 [`rbac::can_manage`]。
+pub(super) async fn manageable(claims: &Claims, user_id: u64) -> Result<UserRecord, RpcError> {
+    let user = admin::get_user(user_id).await?;
+    rbac::can_manage(claims, &user)?;
+    Ok(user)
+}
+
+/// 管理员不能停用或删除自己的账户，避免把自己锁在管理后台之外。
+fn not_self(claims: &Claims, user_id: u64, action: &str) -> Result<(), RpcError> {
+    if claims.user_id() == Some(user_id) {
+        return Err(RpcError::invalid_params(format!("cannot {} your own account", action)));
+    }
+    Ok(())
+}
+
+/// `admin.user.list`：按条件列出用户。
+pub struct AdminUserListJsonRpcHandler;
+
+#[async_trait]
+impl JsonRpcHandle for AdminUserListJsonRpcHandler {
+    /// 参数均可省略：`status`（`active`、`unverified`、`disabled`）、`role`、`created_after`、
+    /// `created_before`、`sort`（`id`、`username`、`created_at`、`updated_at`）、`order`（`asc`、`desc`）、
+    /// `offset`、`limit`。返回 `{total, users}`。
+    async fn json_rpc_handle(&self, req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper> {
+        let result = async {
+            require_permission(&req, rbac::USERS_READ).await?;
+            let query: ListQuery = req.params_as()?;
+            let page = admin::list_users(&query).await?;
+            serde_json::to_value(page).map_err(RpcError::internal)
+        }.await;
+        respond(&req, result)
+    }
+}
+
+/// `admin.user.get`：查看一个用户。
+pub struct AdminUserGetJsonRpcHandler;
+
+#[async_trait]
+impl JsonRpcHandle for AdminUserGetJsonRpcHandler {
+    /// 参数 `user_id`。返回用户信息，格式与 `admin.user.list` 中的每一项相同。
+    async fn json_rpc_handle(&self, req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper> {
+        let result = async {
+            require_permission(&req, rbac::USERS_READ).await?;
+            user_value(&admin::get_user(req.param_u64("user_id")?).await?)
+        }.await;
+        respond(&req, result)
+    }
+}
+
+/// `admin.user.create`：创建用户。
+pub struct AdminUserCreateJsonRpcHandler;
+
+#[async_trait]
+impl JsonRpcHandle for AdminUserCreateJsonRpcHandler {
+    /// 参数 `username`、`password`，可选 `email`、`email_verified`、`roles`。返回新用户的信息。
+    /// 指定 `roles` 时还需要 `users.roles` 权限，且只能分配调用者权限范围内的角色。
+    async fn json_rpc_handle(&self, req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper> {
+        let result = async {
+            let claims = require_permission(&req, rbac::USERS_WRITE).await?;
+            let new_user: NewUser = req.params_as()?;
+            if !new_user.roles.is_empty() {
+                rbac::authorize(&claims, rbac::USERS_ROLES)?;
+                rbac::can_assign(&claims, &new_user.roles)?;
+            }
+            let user = admin::create(&new_user).await?;
+            audit::record(&claims, "admin.user.create", user.id, json!({
+                "username": user.username,
+                "email": user.email,
+                "email_verified": user.email_verified,
+                "roles": user.roles,
+            })).await;
+            user_value(&user)
+        }.await;
+        respond(&req, result)
+    }
+}
+
+/// `admin.user.disable`：停用用户，并使其所有登录失效。
+pub struct AdminUserDisableJsonRpcHandler;
+
+#[async_trait]
+impl JsonRpcHandle for AdminUserDisableJsonRpcHandler {
+    /// 参数 `user_id`。返回用户信息。
+    async fn json_rpc_handle(&self, req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper> {
+        let result = async {
+            let claims = require_permission(&req, rbac::USERS_DISABLE).await?;
+            let user_id = req.param_u64("user_id")?;
+            not_self(&claims, user_id, "disable")?;
+            manageable(&claims, user_id).await?;
+            let user = admin::set_disabled(user_id, true).await?;
+            audit::record(&claims, "admin.user.disable", user_id, Value::Null).await;
+            user_value(&user)
+        }.await;
+        respond(&req, result)
+    }
+}
+
+/// `admin.user.enable`：重新启用被停用的用户。
+pub struct AdminUserEnableJsonRpcHandler;
+
+#[async_trait]
+impl JsonRpcHandle for AdminUserEnableJsonRpcHandler {
+    /// 参数 `user_id`。返回用户信息。
+    async fn json_rpc_handle(&self, req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper> {
+        let result = async {
+            let claims = require_permission(&req, rbac::USERS_DISABLE).await?;
+            let user_id = req.param_u64("user_id")?;
+            manageable(&claims, user_id).await?;
+            let user = admin::set_disabled(user_id, false).await?;
+            audit::record(&claims, "admin.user.enable", user_id, Value::Null).await;
+            user_value(&user)
+        }.await;
+        respond(&req, result)
+    }
+}
+
+/// `admin.user.delete`：删除用户，并使其所有登录失效。
+pub struct AdminUserDeleteJsonRpcHandler;
+
+#[async_trait]
+impl JsonRpcHandle for AdminUserDeleteJsonRpcHandler {
+    /// 参数 `user_id`。返回 `{deleted: true}`。
+    async fn json_rpc_handle(&self, req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper> {
+        let result = async {
+            let claims = require_permission(&req, rbac::USERS_WRITE).await?;
+            let user_id = req.param_u64("user_id")?;
+            not_self(&claims, user_id, "delete")?;
+            manageable(&claims, user_id).await?;
+            let user = admin::delete(user_id).await?;
+            audit::record(&claims, "admin.user.delete", user_id, json!({ "username": user.username })).await;
+            Ok(json!({ "deleted": true }))
+        }.await;
+        respond(&req, result)
+    }
+}
+
+/// `admin.user.resetPassword`：重置用户的密码。
+pub struct AdminUserResetPasswordJsonRpcHandler;
+
+#[async_trait]
+impl JsonRpcHandle for AdminUserResetPasswordJsonRpcHandler {
+    /// 参数 `user_id`，可选 `password`。给出 `password` 时直接设置新密码并使该用户所有登录失效，
+    /// 否则向用户的邮箱发送密码重置码。返回 `{password_set}`，表示是否直接设置了密码。
+    async fn json_rpc_handle(&self, req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper> {
+        let result = async {
+            let claims = require_permission(&req, rbac::USERS_WRITE).await?;
+            let user_id = req.param_u64("user_id")?;
+            manageable(&claims, user_id).await?;
+            let password = req.param_str("password").ok();
+            let password_set = admin::reset_password(user_id, password).await?;
+            audit::record(&claims, "admin.user.resetPassword", user_id, json!({ "password_set": password_set })).await;
+            Ok(json!({ "password_set": password_set }))
+        }.await;
+        respond(&req, result)
+    }
+}
+
+/// `admin.user.setRoles`：设置用户的角色。
+pub struct AdminUserSetRolesJsonRpcHandler;
+
+#[async_trait]
+impl JsonRpcHandle for AdminUserSetRolesJsonRpcHandler {
+    /// 参数 `user_id` 和 `roles`（字符串数组），替换用户原有的全部角色。调用者只能增减
+    /// 自己权限范围内的角色。角色有变化时该用户需要重新登录。返回用户信息。
+    async fn json_rpc_handle(&self, req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper> {
+        let result = async {
+            let claims = require_permission(&req, rbac::USERS_ROLES).await?;
+            let user_id = req.param_u64("user_id")?;
+            let roles: Vec<String> = req.params
+                .as_ref()
+                .and_then(|params| params.get("roles"))
+                .and_then(|roles| serde_json::from_value(roles.clone()).ok())
+                .ok_or_else(|| RpcError::invalid_params("missing string array parameter 'roles'"))?;
+            let before = manageable(&claims, user_id).await?.roles;
+            let changed: Vec<String> = roles
+                .iter()
+                .filter(|role| !before.contains(role))
+                .chain(before.iter().filter(|role| !roles.contains(role)))
+                .cloned()
+                .collect();
+            rbac::can_assign(&claims, &changed)?;
+            let user = admin::set_roles(user_id, &roles).await?;
+            audit::record(&claims, "admin.user.setRoles", user_id, json!({ "before": before, "after": user.roles })).await;
+            user_value(&user)
+        }.await;
+        respond(&req, result)
+    }
+}
+
+/// `admin.role.list`：列出所有角色及其权限。
+pub struct AdminRoleListJsonRpcHandler;
+
+#[async_trait]
+impl JsonRpcHandle for AdminRoleListJsonRpcHandler {
+    /// 返回 `{roles, permissions}`：`roles` 为内置和自定义角色，每项包含 `name`、`builtin`
+    /// 和 `permissions`；`permissions` 为服务器检查的全部权限名。
+    async fn json_rpc_handle(&self, req: Json<JsonRequest>) -> JsonResponse<JsonResponseWrapper> {
+        let result = async {
+            require_permission(&req, rbac::ROLES_READ).await?;
+            Ok(json!({ "roles": rbac::roles(), "permissions": rbac::PERMISSIONS }))
+        }.await;
+        respond(&req, result)
+    }
+}
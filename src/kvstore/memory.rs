@@ -0,0 +1,148 @@
+use std::collections::{ HashMap, HashSet };
+use std::sync::Mutex;
+use std::time::{ Duration, Instant };
+
+use async_trait::async_trait;
+
+use super::{ KvStore, KvStoreError, KvStoreResult };
+
+/// 每写入多少次清理一次过期的键。
+const PURGE_INTERVAL: u64 = 1024;
+
+/// 进程内的键值存储，过期的键在读取时或定期清理时移除。
+#[derive(Default)]
+pub struct MemoryKvStore {
+    inner: Mutex<MemoryInner>,
+}
+
+#[derive(Default)]
+struct MemoryInner {
+    entries: HashMap<String, Entry>,
+    sets: HashMap<String, HashSet<String>>,
+    writes: u64,
+}
+
+struct Entry {
+    value: String,
+    expires_at: Option<Instant>,
+}
+
+impl Entry {
+    fn new(value: &str, ttl_secs: Option<u64>) -> Self {
+        Entry {
+            value: value.to_string(),
+            expires_at: ttl_secs.map(|ttl| Instant::now() + Duration::from_secs(ttl)),
+        }
+    }
+
+    fn is_live(&self, now: Instant) -> bool {
+        self.expires_at.is_none_or(|at| at > now)
+    }
+}
+
+impl MemoryKvStore {
+    /// 创建一个空的内存存储。
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl MemoryInner {
+    /// 返回未过期的条目，已过期的条目会被移除。
+    fn live(&mut self, key: &str) -> Option<&mut Entry> {
+        let now = Instant::now();
+        if self.entries.get(key).is_some_and(|entry| !entry.is_live(now)) {
+            self.entries.remove(key);
+        }
+        self.entries.get_mut(key)
+    }
+
+    fn insert(&mut self, key: &str, entry: Entry) {
+        self.writes += 1;
+        if self.writes.is_multiple_of(PURGE_INTERVAL) {
+            let now = Instant::now();
+            self.entries.retain(|_, entry| entry.is_live(now));
+        }
+        self.entries.insert(key.to_string(), entry);
+    }
+}
+
+#[async_trait]
+impl KvStore for MemoryKvStore {
+    async fn get(&self, key: &str) -> KvStoreResult<Option<String>> {
+        Ok(self.inner.lock().unwrap().live(key).map(|entry| entry.value.clone()))
+    }
+
+    async fn set(&self, key: &str, value: &str, ttl_secs: Option<u64>) -> KvStoreResult<()> {
+        self.inner.lock().unwrap().insert(key, Entry::new(value, ttl_secs));
+        Ok(())
+    }
+
+    async fn set_nx(&self, key: &str, value: &str, ttl_secs: Option<u64>) -> KvStoreResult<bool> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.live(key).is_some() {
+            return Ok(false);
+        }
+        inner.insert(key, Entry::new(value, ttl_secs));
+        Ok(true)
+    }
+
+    async fn set_existing(&self, key: &str, value: &str, ttl_secs: Option<u64>) -> KvStoreResult<bool> {
+        let mut inner = self.inner.lock().unwrap();
+        let Some(entry) = inner.live(key) else {
+            return Ok(false);
+        };
+        entry.value = value.to_string();
+        if let Some(ttl) = ttl_secs {
+            entry.expires_at = Some(Instant::now() + Duration::from_secs(ttl));
+        }
+        Ok(true)
+    }
+
+    async fn take(&self, key: &str) -> KvStoreResult<Option<String>> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.live(key);
+        Ok(inner.entries.remove(key).map(|entry| entry.value))
+    }
+
+    async fn delete(&self, key: &str) -> KvStoreResult<()> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.entries.remove(key);
+        inner.sets.remove(key);
+        Ok(())
+    }
+
+    async fn incr(&self, key: &str, ttl_secs: Option<u64>) -> KvStoreResult<i64> {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(entry) = inner.live(key) {
+            let value = entry.value
+                .parse::<i64>()
+                .map_err(|_| KvStoreError::Backend(format!("value of {} is not an integer", key)))? + 1;
+            entry.value = value.to_string();
+            return Ok(value);
+        }
+        inner.insert(key, Entry::new("1", ttl_secs));
+        Ok(1)
+    }
+
+    async fn set_add(&self, key: &str, member: &str) -> KvStoreResult<()> {
+        self.inner.lock().unwrap().sets.entry(key.to_string()).or_default().insert(member.to_string());
+        Ok(())
+    }
+
+    async fn set_remove(&self, key: &str, member: &str) -> KvStoreResult<()> {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(set) = inner.sets.get_mut(key) {
+            set.remove(member);
+            if set.is_empty() {
+                inner.sets.remove(key);
+            }
+        }
+        Ok(())
+    }
+
+    async fn set_members(&self, key: &str) -> KvStoreResult<Vec<String>> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner.sets.get(key).map(|set| set.iter().cloned().collect()).unwrap_or_default())
+    }
+}
@@ -0,0 +1,125 @@
+//! # 临时数据存储
+//!
+//! 刷新令牌、令牌吊销列表、登录会话等带有效期的临时数据保存在键值存储 [`KvStore`] 中，后端实现：
+//!
+//! - [`memory::MemoryKvStore`]：进程内存储，始终可用，重启后数据丢失，且不能在多个实例之间共享；
+//! - `redis::RedisKvStore`：基于 Redis，需要开启 `redis-store` feature。
+//!
+//! 所有键都以 `btcm:` 开头，值为字符串（通常是 JSON）或字符串集合。
+//!
+//! ## 选择后端
+//!
+//! 服务器启动时调用 [`init_kv_store_from_env`]，根据环境变量 `BTCMWEB_KV_STORE`
+//! 选择后端：`redis://` 或 `memory`，未设置时使用内存存储。
+
+pub mod memory;
+#[cfg(feature = "redis-store")]
+pub mod redis;
+
+use std::fmt;
+use std::sync::{ Arc, RwLock };
+
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+
+/// 选择临时数据存储后端时读取的环境变量名。
+pub static KV_STORE_ENV_VAR: &str = "BTCMWEB_KV_STORE";
+
+/// 键值存储操作的错误。
+#[derive(Debug)]
+pub enum KvStoreError {
+    /// 后端错误（连接失败等）。
+    Backend(String),
+}
+
+impl fmt::Display for KvStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KvStoreError::Backend(message) => write!(f, "kv store backend error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for KvStoreError {}
+
+/// 键值存储操作的结果类型。
+pub type KvStoreResult<T> = Result<T, KvStoreError>;
+
+/// 带有效期的异步键值存储接口。
+///
+/// `ttl_secs` 为 `None` 时键不会过期。
+#[async_trait]
+pub trait KvStore: Send + Sync {
+    /// 读取键的值。
+    async fn get(&self, key: &str) -> KvStoreResult<Option<String>>;
+
+    /// 写入键的值，覆盖原值和原有效期。
+    async fn set(&self, key: &str, value: &str, ttl_secs: Option<u64>) -> KvStoreResult<()>;
+
+    /// 仅当键不存在时写入，返回是否写入成功。
+    async fn set_nx(&self, key: &str, value: &str, ttl_secs: Option<u64>) -> KvStoreResult<bool>;
+
+    /// 仅当键存在时写入，返回是否写入成功。
+    ///
+    /// 与 [`KvStore::set`] 不同，`ttl_secs` 为 `None` 时保留原有效期，否则重新计算有效期。
+    async fn set_existing(&self, key: &str, value: &str, ttl_secs: Option<u64>) -> KvStoreResult<bool>;
+
+    /// 读取并删除键，保证同一个值只被取走一次。
+    async fn take(&self, key: &str) -> KvStoreResult<Option<String>>;
+
+    /// 删除键。
+    async fn delete(&self, key: &str) -> KvStoreResult<()>;
+
+    /// 将键的整数值加一并返回新值，键不存在时视为 0。
+    ///
+    /// 键不存在时以 `ttl_secs` 作为新键的有效期；键已存在时不改变原有效期，
+    /// 因此可以用作固定窗口计数器。加一和设置有效期是一个原子操作，不会留下永不过期的计数。
+    async fn incr(&self, key: &str, ttl_secs: Option<u64>) -> KvStoreResult<i64>;
+
+    /// 向集合中添加成员，集合不存在时创建。集合不会过期。
+    async fn set_add(&self, key: &str, member: &str) -> KvStoreResult<()>;
+
+    /// 从集合中移除成员，集合为空时删除。
+    async fn set_remove(&self, key: &str, member: &str) -> KvStoreResult<()>;
+
+    /// 列出集合中的所有成员，顺序不确定。
+    async fn set_members(&self, key: &str) -> KvStoreResult<Vec<String>>;
+}
+
+lazy_static! {
+    /// 服务器使用的临时数据存储，默认为内存存储。
+    static ref KV_STORE: RwLock<Arc<dyn KvStore>> = RwLock::new(Arc::new(memory::MemoryKvStore::new()));
+}
+
+/// 获取当前使用的临时数据存储。
+pub fn kv_store() -> Arc<dyn KvStore> {
+    KV_STORE.read().unwrap().clone()
+}
+
+/// 替换当前使用的临时数据存储。
+pub fn set_kv_store(store: Arc<dyn KvStore>) {
+    *KV_STORE.write().unwrap() = store;
+}
+
+/// 根据环境变量 `BTCMWEB_KV_STORE` 初始化临时数据存储。
+///
+/// 未设置时保留内存存储；URL 对应的 feature 未开启时返回错误。
+pub async fn init_kv_store_from_env() -> KvStoreResult<()> {
+    let Ok(url) = std::env::var(KV_STORE_ENV_VAR) else {
+        return Ok(());
+    };
+    set_kv_store(connect(&url).await?);
+    Ok(())
+}
+
+/// 根据 URL 前缀连接对应的键值存储后端。
+pub async fn connect(url: &str) -> KvStoreResult<Arc<dyn KvStore>> {
+    if url == "memory" {
+        return Ok(Arc::new(memory::MemoryKvStore::new()));
+    }
+    #[cfg(feature = "redis-store")]
+    if url.starts_with("redis://") || url.starts_with("rediss://") {
+        return Ok(Arc::new(redis::RedisKvStore::connect(url).await?));
+    }
+    Err(KvStoreError::Backend(format!("unsupported kv store url: {}", url)))
+}
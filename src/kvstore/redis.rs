@@ -0,0 +1,115 @@
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use redis::{ aio::MultiplexedConnection, AsyncCommands, RedisError };
+
+use super::{ KvStore, KvStoreError, KvStoreResult };
+
+impl From<RedisError> for KvStoreError {
+    fn from(err: RedisError) -> Self {
+        KvStoreError::Backend(err.to_string())
+    }
+}
+
+/// 基于 Redis 的键值存储，有效期由 Redis 的 `EX` 处理。
+pub struct RedisKvStore {
+    con: MultiplexedConnection,
+}
+
+impl RedisKvStore {
+    /// 连接到指定的 Redis 服务器。
+    pub async fn connect(url: &str) -> KvStoreResult<Self> {
+        let client = redis::Client::open(url)?;
+        let con = client.get_multiplexed_tokio_connection().await?;
+        Ok(RedisKvStore { con })
+    }
+}
+
+lazy_static! {
+    /// 加一，并为没有有效期的计数设置有效期。ARGV[1] 为有效期（秒），空字符串表示不过期。
+    static ref INCR_SCRIPT: redis::Script = redis::Script::new(r"
+        local value = redis.call('INCR', KEYS[1])
+        if ARGV[1] ~= '' and redis.call('TTL', KEYS[1]) == -1 then
+            redis.call('EXPIRE', KEYS[1], ARGV[1])
+        end
+        return value
+    ");
+}
+
+/// 构造带可选 `EX` 和 `NX` 参数的 `SET` 命令。
+fn set_cmd(key: &str, value: &str, ttl_secs: Option<u64>, nx: bool) -> redis::Cmd {
+    let mut cmd = redis::cmd("SET");
+    cmd.arg(key).arg(value);
+    if let Some(ttl) = ttl_secs {
+        cmd.arg("EX").arg(ttl.max(1));
+    }
+    if nx {
+        cmd.arg("NX");
+    }
+    cmd
+}
+
+#[async_trait]
+impl KvStore for RedisKvStore {
+    async fn get(&self, key: &str) -> KvStoreResult<Option<String>> {
+        let mut con = self.con.clone();
+        Ok(con.get(key).await?)
+    }
+
+    async fn set(&self, key: &str, value: &str, ttl_secs: Option<u64>) -> KvStoreResult<()> {
+        let mut con = self.con.clone();
+        let _: () = set_cmd(key, value, ttl_secs, false).query_async(&mut con).await?;
+        Ok(())
+    }
+
+    async fn set_nx(&self, key: &str, value: &str, ttl_secs: Option<u64>) -> KvStoreResult<bool> {
+        let mut con = self.con.clone();
+        let reply: Option<String> = set_cmd(key, value, ttl_secs, true).query_async(&mut con).await?;
+        Ok(reply.is_some())
+    }
+
+    async fn set_existing(&self, key: &str, value: &str, ttl_secs: Option<u64>) -> KvStoreResult<bool> {
+        let mut con = self.con.clone();
+        let mut cmd = redis::cmd("SET");
+        cmd.arg(key).arg(value).arg("XX");
+        match ttl_secs {
+            Some(ttl) => cmd.arg("EX").arg(ttl.max(1)),
+            None => cmd.arg("KEEPTTL"),
+        };
+        let reply: Option<String> = cmd.query_async(&mut con).await?;
+        Ok(reply.is_some())
+    }
+
+    async fn take(&self, key: &str) -> KvStoreResult<Option<String>> {
+        let mut con = self.con.clone();
+        Ok(redis::cmd("GETDEL").arg(key).query_async(&mut con).await?)
+    }
+
+    async fn delete(&self, key: &str) -> KvStoreResult<()> {
+        let mut con = self.con.clone();
+        let _: () = con.del(key).await?;
+        Ok(())
+    }
+
+    async fn incr(&self, key: &str, ttl_secs: Option<u64>) -> KvStoreResult<i64> {
+        let mut con = self.con.clone();
+        let ttl = ttl_secs.map(|ttl| ttl.max(1).to_string()).unwrap_or_default();
+        Ok(INCR_SCRIPT.key(key).arg(ttl).invoke_async(&mut con).await?)
+    }
+
+    async fn set_add(&self, key: &str, member: &str) -> KvStoreResult<()> {
+        let mut con = self.con.clone();
+        let _: () = con.sadd(key, member).await?;
+        Ok(())
+    }
+
+    async fn set_remove(&self, key: &str, member: &str) -> KvStoreResult<()> {
+        let mut con = self.con.clone();
+        let _: () = con.srem(key, member).await?;
+        Ok(())
+    }
+
+    async fn set_members(&self, key: &str) -> KvStoreResult<Vec<String>> {
+        let mut con = self.con.clone();
+        Ok(con.smembers(key).await?)
+    }
+}
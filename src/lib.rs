@@ -1,3 +1,11 @@
 pub mod webserver;
 pub mod jsonrpc;
-
+pub mod userstore;
+pub mod account;
+pub mod token;
+pub mod config;
+pub mod kvstore;
+pub mod captcha;
+pub mod mailer;
+pub mod audit;
+pub mod rbac;
@@ -0,0 +1,39 @@
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+
+use super::{ MailMessage, Mailer, MailerError };
+use crate::config::config;
+
+/// 将每封邮件写入目录中一个 `.eml` 文件的邮件后端，用于开发环境和集成测试。
+pub struct FileMailer {
+    dir: PathBuf,
+}
+
+impl FileMailer {
+    /// 使用指定的目录，目录不存在时创建。
+    pub fn new(dir: &str) -> Result<Self, MailerError> {
+        std::fs::create_dir_all(dir).map_err(|err| MailerError::Config(format!("{}: {}", dir, err)))?;
+        Ok(FileMailer { dir: PathBuf::from(dir) })
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, message: &MailMessage) -> Result<(), MailerError> {
+        let name = format!(
+            "{}-{}.eml",
+            chrono::Utc::now().format("%Y%m%d%H%M%S%.6f"),
+            &crate::token::new_token_id()[..8]
+        );
+        let content = format!(
+            "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n{}",
+            config().email.from,
+            message.to,
+            message.subject,
+            chrono::Utc::now().to_rfc2822(),
+            message.body
+        );
+        tokio::fs::write(self.dir.join(name), content).await.map_err(|err| MailerError::Send(err.to_string()))
+    }
+}
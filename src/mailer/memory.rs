@@ -0,0 +1,37 @@
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+
+use super::{ MailMessage, Mailer, MailerError };
+
+/// 保存在内存中的邮件后端，邮件不会真正发出。
+#[derive(Default)]
+pub struct MemoryMailer {
+    outbox: Mutex<Vec<MailMessage>>,
+}
+
+impl MemoryMailer {
+    /// 创建一个空的内存后端。
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 已发送的所有邮件。
+    pub fn messages(&self) -> Vec<MailMessage> {
+        self.outbox.lock().unwrap().clone()
+    }
+
+    /// 取出并清空已发送的邮件。
+    pub fn take(&self) -> Vec<MailMessage> {
+        std::mem::take(&mut *self.outbox.lock().unwrap())
+    }
+}
+
+#[async_trait]
+impl Mailer for MemoryMailer {
+    async fn send(&self, message: &MailMessage) -> Result<(), MailerError> {
+        tracing::debug!("queued mail to {} in memory: {}", message.to, message.subject);
+        self.outbox.lock().unwrap().push(message.clone());
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+//! # 邮件发送
+//!
+//! 账户相关的邮件（邮箱验证、密码重置）通过统一的异步接口 [`Mailer`] 发送，后端实现：
+//!
+//! - [`memory::MemoryMailer`]：保存在进程内存中，始终可用，适合开发和测试；
+//! - [`file::FileMailer`]：每封邮件写入目录中的一个 `.eml` 文件；
+//! - `smtp::SmtpMailer`：通过 SMTP 服务器发送，需要开启 `smtp-mailer` feature。
+//!
+//! ## 选择后端
+//!
+//! 服务器启动时调用 [`init_mailer_from_env`]，根据环境变量 `BTCMWEB_MAILER` 选择后端：
+//! `memory`、`file:<目录>`、`smtp://` 或 `smtps://`（URL 格式见 lettre 的 `from_url`），
+//! 未设置时使用内存后端。
+
+pub mod file;
+pub mod memory;
+#[cfg(feature = "smtp-mailer")]
+pub mod smtp;
+
+use std::fmt;
+use std::sync::{ Arc, RwLock };
+
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+
+/// 选择邮件后端时读取的环境变量名。
+pub static MAILER_ENV_VAR: &str = "BTCMWEB_MAILER";
+
+/// 一封纯文本邮件。发件人取自配置文件的 `email.from`。
+#[derive(Debug, Clone, PartialEq)]
+pub struct MailMessage {
+    /// 收件人地址。
+    pub to: String,
+    /// 主题。
+    pub subject: String,
+    /// 纯文本正文。
+    pub body: String,
+}
+
+/// 邮件发送的错误。
+#[derive(Debug)]
+pub enum MailerError {
+    /// 后端配置错误。
+    Config(String),
+    /// 发送失败。
+    Send(String),
+}
+
+impl fmt::Display for MailerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MailerError::Config(message) => write!(f, "mailer config error: {}", message),
+            MailerError::Send(message) => write!(f, "failed to send mail: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for MailerError {}
+
+/// 统一的异步邮件发送接口。
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    /// 发送一封邮件。
+    async fn send(&self, message: &MailMessage) -> Result<(), MailerError>;
+}
+
+lazy_static! {
+    /// 服务器使用的邮件后端，默认为内存后端。
+    static ref MAILER: RwLock<Arc<dyn Mailer>> = RwLock::new(Arc::new(memory::MemoryMailer::new()));
+}
+
+/// 获取当前使用的邮件后端。
+pub fn mailer() -> Arc<dyn Mailer> {
+    MAILER.read().unwrap().clone()
+}
+
+/// 替换当前使用的邮件后端。
+pub fn set_mailer(mailer: Arc<dyn Mailer>) {
+    *MAILER.write().unwrap() = mailer;
+}
+
+/// 根据环境变量 `BTCMWEB_MAILER` 初始化邮件后端。
+///
+/// 未设置时保留内存后端；URL 对应的 feature 未开启时返回错误。
+pub fn init_mailer_from_env() -> Result<(), MailerError> {
+    let Ok(url) = std::env::var(MAILER_ENV_VAR) else {
+        return Ok(());
+    };
+    set_mailer(connect(&url)?);
+    Ok(())
+}
+
+/// 根据 URL 前缀创建对应的邮件后端。
+pub fn connect(url: &str) -> Result<Arc<dyn Mailer>, MailerError> {
+    if url == "memory" {
+        return Ok(Arc::new(memory::MemoryMailer::new()));
+    }
+    if let Some(dir) = url.strip_prefix("file:") {
+        return Ok(Arc::new(file::FileMailer::new(dir)?));
+    }
+    #[cfg(feature = "smtp-mailer")]
+    if url.starts_with("smtp://") || url.starts_with("smtps://") {
+        return Ok(Arc::new(smtp::SmtpMailer::from_url(url)?));
+    }
+    Err(MailerError::Config(format!("unsupported mailer url: {}", url)))
+}
@@ -0,0 +1,233 @@
+//! # 角色和权限
+//!
+//! 权限是一个点分隔的名字，例如 `users.read`，授权时可以用 `users.*` 表示某一类的全部权限，
+//! 用 `*` 表示全部权限。角色是一组权限的集合：
+//!
+//! - 内置角色 [`BUILTIN_ROLES`]：`superadmin`、`admin`、`moderator` 和 `user`；
+//! - 自定义角色来自配置 `roles.custom`，见 [`crate::config::RolesConfig`]。
+//!
+//! 每个用户都隐含 `user` 角色，另外拥有 [`UserRecord::roles`] 中分配的角色。
+//! 权限只来自存储的角色，与用户名无关；配置的管理员账户在启动时被分配 `superadmin` 角色，
+//! 见 [`crate::account::admin::seed_admins`]。
+//! 签发访问令牌时，用户的角色和展开后的全部权限写入声明 [`Claims::roles`] 和 [`Claims::perms`]，
+//! 因此修改角色时吊销该用户已签发的所有令牌和会话（见 [`crate::account::admin::set_roles`]），
+//! 用户重新登录后以新的角色签发令牌。
+//!
+//! 需要权限的接口使用 [`authorize`] 检查令牌，JSON-RPC 方法使用
+//! [`crate::jsonrpc::require_permission`]。管理员只能分配、撤销自己权限范围内的角色，
+//! 也只能管理权限不超过自己的用户，见 [`can_assign`] 和 [`can_manage`]。
+
+use std::collections::BTreeSet;
+
+use lazy_static::lazy_static;
+use serde::Serialize;
+
+use crate::account::AccountError;
+use crate::config::config;
+use crate::token::Claims;
+use crate::userstore::UserRecord;
+
+/// 读取自己和其他用户的资料。
+pub const PROFILE_READ: &str = "profile.read";
+/// 修改自己的资料。
+pub const PROFILE_WRITE: &str = "profile.write";
+/// 列出和查看用户。
+pub const USERS_READ: &str = "users.read";
+/// 创建、删除用户和重置密码。
+pub const USERS_WRITE: &str = "users.write";
+/// 停用和启用用户。
+pub const USERS_DISABLE: &str = "users.disable";
+/// 分配角色。
+pub const USERS_ROLES: &str = "users.roles";
+/// 查看和吊销其他用户的会话。
+pub const SESSIONS_ADMIN: &str = "sessions.admin";
+/// 查看和解除登录锁定。
+pub const LOCKOUT_ADMIN: &str = "lockout.admin";
+/// 查看和轮换 JWT 签名密钥。
+pub const KEYS_ADMIN: &str = "keys.admin";
+/// 查看角色定义。
+pub const ROLES_READ: &str = "roles.read";
+/// 管理服务账户的 API 密钥。
+pub const API_KEYS_ADMIN: &str = "apikeys.admin";
+/// 创建和吊销邀请码。
+pub const INVITES_ADMIN: &str = "invites.admin";
+/// 查看、批准和拒绝等待批准的注册。
+pub const REGISTRATIONS_APPROVE: &str = "registrations.approve";
+
+/// 服务器检查的全部权限。自定义角色也可以包含其他名字的权限，供 `btcmnetwork` 等服务按令牌判断。
+pub const PERMISSIONS: &[&str] = &[
+    PROFILE_READ, PROFILE_WRITE, USERS_READ, USERS_WRITE, USERS_DISABLE, USERS_ROLES,
+    SESSIONS_ADMIN, LOCKOUT_ADMIN, KEYS_ADMIN, ROLES_READ, API_KEYS_ADMIN, INVITES_ADMIN,
+    REGISTRATIONS_APPROVE,
+];
+
+/// 所有用户都隐含的角色。
+pub const USER_ROLE: &str = "user";
+/// 拥有全部权限的角色。
+pub const SUPERADMIN_ROLE: &str = "superadmin";
+
+/// 内置角色。
+pub const BUILTIN_ROLES: &[(&str, &[&str])] = &[
+    (SUPERADMIN_ROLE, &["*"]),
+    ("admin", &[
+        PROFILE_READ, PROFILE_WRITE, USERS_READ, USERS_WRITE, USERS_DISABLE, USERS_ROLES,
+        SESSIONS_ADMIN, LOCKOUT_ADMIN, ROLES_READ, API_KEYS_ADMIN, INVITES_ADMIN, REGISTRATIONS_APPROVE,
+    ]),
+    ("moderator", &[
+        PROFILE_READ, PROFILE_WRITE, USERS_READ, USERS_DISABLE, SESSIONS_ADMIN, LOCKOUT_ADMIN, REGISTRATIONS_APPROVE,
+    ]),
+    (USER_ROLE, &[PROFILE_READ, PROFILE_WRITE]),
+];
+
+/// 一个角色及其权限。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Role {
+    /// 角色名。
+    pub name: String,
+    /// 是否为内置角色。
+    pub builtin: bool,
+    /// 角色拥有的权限。
+    pub permissions: Vec<String>,
+}
+
+lazy_static! {
+    /// 内置角色和配置中有效的自定义角色。
+    static ref ROLES: Vec<Role> = load_roles();
+}
+
+fn load_roles() -> Vec<Role> {
+    let mut roles: Vec<Role> = BUILTIN_ROLES
+        .iter()
+        .map(|(name, permissions)| Role {
+            name: name.to_string(),
+            builtin: true,
+            permissions: permissions.iter().map(ToString::to_string).collect(),
+        })
+        .collect();
+    for (name, permissions) in &config().roles.custom {
+        if roles.iter().any(|role| &role.name == name) {
+            tracing::warn!("custom role {} ignored: it has the same name as a built-in role", name);
+            continue;
+        }
+        if !valid_role_name(name) {
+            tracing::warn!("custom role {} ignored: invalid role name", name);
+            continue;
+        }
+        if let Some(invalid) = permissions.iter().find(|permission| !valid_permission(permission)) {
+            tracing::warn!("custom role {} ignored: invalid permission {}", name, invalid);
+            continue;
+        }
+        let permissions: BTreeSet<String> = permissions.iter().cloned().collect();
+        roles.push(Role { name: name.clone(), builtin: false, permissions: permissions.into_iter().collect() });
+    }
+    roles
+}
+
+/// 角色名是否符合规则：小写字母开头，只含小写字母、数字、`_` 和 `-`，最长 32 个字符。
+pub fn valid_role_name(name: &str) -> bool {
+    (1..=32).contains(&name.len())
+        && name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '_' | '-'))
+}
+
+/// 权限名是否符合规则：`*`，或由点分隔的小写名字组成，最后一段可以是 `*`。
+pub fn valid_permission(permission: &str) -> bool {
+    let segments: Vec<&str> = permission.split('.').collect();
+    segments.iter().enumerate().all(|(i, segment)| {
+        (*segment == "*" && i == segments.len() - 1) || valid_role_name(segment)
+    })
+}
+
+/// 全部角色：内置角色在前，自定义角色按名字排序在后。
+pub fn roles() -> &'static [Role] {
+    &ROLES
+}
+
+/// 按名字查找角色。
+pub fn role(name: &str) -> Option<&'static Role> {
+    ROLES.iter().find(|role| role.name == name)
+}
+
+/// 用户实际拥有的角色：隐含的 `user` 和分配的角色，按名字排序。
+///
+/// 分配后又从配置中删除的自定义角色不再生效。
+pub fn effective_roles(user: &UserRecord) -> Vec<String> {
+    let mut roles = BTreeSet::from([USER_ROLE.to_string()]);
+    roles.extend(user.roles.iter().filter(|name| role(name).is_some()).cloned());
+    roles.into_iter().collect()
+}
+
+/// 一组角色展开后的全部权限，按名字排序。包含 `*` 时只返回 `*`。
+pub fn permissions_of(roles: &[String]) -> Vec<String> {
+    let permissions: BTreeSet<String> = roles
+        .iter()
+        .filter_map(|name| role(name))
+        .flat_map(|role| role.permissions.iter().cloned())
+        .collect();
+    if permissions.contains("*") {
+        return vec!["*".to_string()];
+    }
+    permissions.into_iter().collect()
+}
+
+/// `granted` 中的权限是否包含 `permission`。`permission` 本身带 `*` 时，
+/// 只有同样或更大范围的授权才算包含。
+pub fn allows(granted: &[String], permission: &str) -> bool {
+    granted.iter().any(|granted| {
+        granted == "*"
+            || granted == permission
+            || granted.strip_suffix('*').is_some_and(|prefix| prefix.ends_with('.') && permission.starts_with(prefix))
+    })
+}
+
+/// 两组权限的交集：一组中被另一组包含的权限，按名字排序。
+pub fn intersect(a: &[String], b: &[String]) -> Vec<String> {
+    let permissions: BTreeSet<String> = a
+        .iter()
+        .filter(|permission| allows(b, permission))
+        .chain(b.iter().filter(|permission| allows(a, permission)))
+        .cloned()
+        .collect();
+    permissions.into_iter().collect()
+}
+
+/// 令牌是否拥有 `permission`。`user` 角色的权限总是拥有，不依赖令牌中的声明；
+/// API 密钥（见 [`crate::account::apikey`]）例外，只拥有其权限范围内的权限。
+pub fn has_permission(claims: &Claims, permission: &str) -> bool {
+    allows(&claims.perms, permission)
+        || (claims.api_key.is_none() && role(USER_ROLE).is_some_and(|role| allows(&role.permissions, permission)))
+}
+
+/// `permission` 是否超出 `user` 角色，即只有被分配了其他角色的用户才拥有。
+pub fn is_privileged(permission: &str) -> bool {
+    !role(USER_ROLE).is_some_and(|role| allows(&role.permissions, permission))
+}
+
+/// 检查令牌是否拥有 `permission`，没有时返回 [`AccountError::Forbidden`]。
+pub fn authorize(claims: &Claims, permission: &str) -> Result<(), AccountError> {
+    if !has_permission(claims, permission) {
+        return Err(AccountError::Forbidden);
+    }
+    Ok(())
+}
+
+/// 检查令牌能否分配或撤销这些角色：角色的每个权限都必须在令牌的权限范围内。
+/// 角色不存在时返回 [`AccountError::InvalidRole`]。
+pub fn can_assign(claims: &Claims, roles: &[String]) -> Result<(), AccountError> {
+    for name in roles {
+        let role = role(name).ok_or_else(|| AccountError::InvalidRole(format!("unknown role {}", name)))?;
+        if !role.permissions.iter().all(|permission| has_permission(claims, permission)) {
+            return Err(AccountError::Forbidden);
+        }
+    }
+    Ok(())
+}
+
+/// 检查令牌能否管理该用户：用户的每个权限都必须在令牌的权限范围内。
+pub fn can_manage(claims: &Claims, user: &UserRecord) -> Result<(), AccountError> {
+    let permissions = permissions_of(&effective_roles(user));
+    if !permissions.iter().all(|permission| has_permission(claims, permission)) {
+        return Err(AccountError::Forbidden);
+    }
+    Ok(())
+}
@@ -0,0 +1,15 @@
+use axum::{ http::header, response::{ IntoResponse, Json } };
+use jsonwebtoken::jwk::JwkSet;
+
+use super::token_service;
+
+/// JWKS 响应允许被缓存的秒数。
+const JWKS_MAX_AGE: u32 = 300;
+
+/// `GET /.well-known/jwks.json`：返回所有非对称签名密钥的公钥。
+pub async fn jwks_handler() -> impl IntoResponse {
+    (
+        [(header::CACHE_CONTROL, format!("public, max-age={}", JWKS_MAX_AGE))],
+        Json(JwkSet { keys: token_service().public_jwks() }),
+    )
+}
@@ -0,0 +1,412 @@
+//! # 签名密钥环
+//!
+//! 密钥环包含配置文件中的密钥和轮换生成的密钥，校验时按 JWT 头部的 `kid` 选择。
+//! 没有生效的生成密钥时，配置中的第一个密钥用于签名；否则最近生效的生成密钥用于签名。
+//!
+//! 轮换生成的密钥连同私钥保存在用户存储的附属记录中（见 [`crate::userstore::UserStore::swap_record`]），
+//! 私钥材料用配置 `jwt.keyring_secret`（或环境变量 `BTCMWEB_KEYRING_SECRET`）派生的密钥加密，见 [`KeyringCipher`]。
+//! 每个实例每隔 [`SYNC_INTERVAL_SECS`] 秒从中同步，因此多实例部署时所有实例使用同一组密钥，重启后密钥也不会丢失。
+//! 新密钥在 [`ACTIVATION_DELAY_SECS`] 秒后才开始用于签名，此前所有实例都已同步，能够校验它签发的令牌；
+//! 被取代的密钥在它签发的所有令牌都过期后（访问令牌有效期加上时钟偏差）从记录中移除。
+//! 管理接口只能生成新密钥，不能加载任意路径的密钥文件。
+//!
+//! 配置文件中列出的密钥不会被自动移除，由运维人员从配置中删除。
+
+use std::sync::Arc;
+
+use base64::{ engine::general_purpose::URL_SAFE_NO_PAD, Engine };
+use jsonwebtoken::{
+    jwk::{ AlgorithmParameters, EllipticCurve, Jwk, KeyAlgorithm, OctetKeyPairParameters, OctetKeyPairType },
+    Algorithm,
+    DecodingKey,
+    EncodingKey,
+};
+use rand::RngCore;
+use ring::{
+    aead::{ Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN },
+    digest::{ digest, SHA256 },
+    rand::SystemRandom,
+    signature::{ Ed25519KeyPair, KeyPair },
+};
+use serde::{ Deserialize, Serialize };
+
+use super::{ keys::{ common_parameters, SigningKey }, TokenError };
+use crate::config::JwtConfig;
+
+/// 旧密钥在退役时间之外额外保留的秒数，容忍各节点之间的时钟偏差。
+pub const RETIRE_LEEWAY_SECS: i64 = 60;
+/// 各实例从用户存储同步生成的密钥的间隔（秒）。
+pub const SYNC_INTERVAL_SECS: u64 = 60;
+/// 生成的密钥在轮换后多久开始用于签名（秒），大于同步间隔。
+pub const ACTIVATION_DELAY_SECS: i64 = 2 * SYNC_INTERVAL_SECS as i64 + RETIRE_LEEWAY_SECS;
+/// 未配置 `jwt.keyring_secret` 时读取私钥加密密钥的环境变量名。
+pub static KEYRING_SECRET_ENV_VAR: &str = "BTCMWEB_KEYRING_SECRET";
+/// 加密后的私钥材料的前缀，不在 base64url 字母表中，与未加密的旧记录区分。
+const SEALED_PREFIX: &str = "enc:";
+
+/// 保存生成的密钥的附属记录类型。
+///
+/// 记录中包含私钥材料。两者都未配置时私钥以 base64url 明文保存，能读取用户存储的人
+/// 即可伪造任意用户的令牌，生产环境必须配置 `jwt.keyring_secret` 或 `BTCMWEB_KEYRING_SECRET`。
+pub const RECORD_KIND: &str = "jwt";
+/// 保存生成的密钥的附属记录的键。
+pub const RECORD_KEY: &str = "keyring";
+
+/// 保存在用户存储中的一个生成的密钥。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredKey {
+    /// 密钥 ID。
+    pub kid: String,
+    /// 签名算法：`EdDSA` 或 `HS256`。
+    pub algorithm: String,
+    /// 私钥材料：HS256 为共享密钥，EdDSA 为 PKCS#8 DER。配置了 [`KeyringCipher`] 时为
+    /// `enc:` 加 base64url 的 nonce 和密文，否则为 base64url 明文。
+    secret: String,
+    /// 开始用于签名的时间（Unix 时间戳，秒）。
+    pub activate_at: i64,
+    /// 移除时间（Unix 时间戳，秒）；`None` 表示还没有被取代。
+    pub retire_at: Option<i64>,
+}
+
+impl StoredKey {
+    /// 在 `now` 时是否仍可用于校验。
+    fn active(&self, now: i64) -> bool {
+        self.retire_at.is_none_or(|at| at > now)
+    }
+
+    /// 由保存的私钥材料构造签名密钥，加密的私钥用 `cipher` 解密。未加密的旧记录仍可读取。
+    fn signing_key(&self, cipher: Option<&KeyringCipher>) -> Result<SigningKey, TokenError> {
+        let secret = match (self.secret.strip_prefix(SEALED_PREFIX), cipher) {
+            (Some(sealed), Some(cipher)) => cipher.open(&self.aad(), sealed)
+                .ok_or_else(|| TokenError::Key(format!("jwt key {}: cannot decrypt stored secret", self.kid)))?,
+            (Some(_), None) => return Err(TokenError::Key(format!(
+                "jwt key {}: stored secret is encrypted but jwt.keyring_secret and {} are not set", self.kid, KEYRING_SECRET_ENV_VAR
+            ))),
+            (None, _) => URL_SAFE_NO_PAD
+                .decode(&self.secret)
+                .map_err(|_| TokenError::Key(format!("jwt key {}: invalid stored secret", self.kid)))?,
+        };
+        match self.algorithm.as_str() {
+            "HS256" => Ok(SigningKey::hs256(&self.kid, &secret)),
+            "EdDSA" => ed25519_key(&self.kid, &secret),
+            other => Err(TokenError::Key(format!("jwt key {}: unsupported algorithm {}", self.kid, other))),
+        }
+    }
+
+    /// 加密时的附加数据，使密文只能用于同一个 `kid` 和算法。
+    fn aad(&self) -> String {
+        format!("{}:{}", self.kid, self.algorithm)
+    }
+}
+
+/// 加密保存在用户存储中的私钥材料的密钥（AES-256-GCM），由配置的口令经 SHA-256 派生。
+///
+/// 所有实例必须使用相同的口令，否则无法读取其他实例生成的密钥。
+pub struct KeyringCipher {
+    key: LessSafeKey,
+}
+
+impl KeyringCipher {
+    /// 由口令派生密钥。
+    pub fn new(secret: &[u8]) -> Self {
+        let unbound = UnboundKey::new(&AES_256_GCM, digest(&SHA256, secret).as_ref()).expect("sha-256 output is a valid aes-256 key");
+        KeyringCipher { key: LessSafeKey::new(unbound) }
+    }
+
+    /// 读取配置的 `keyring_secret`，未配置时读取 `BTCMWEB_KEYRING_SECRET`，都未设置时为 `None`。
+    pub fn from_config(config: &JwtConfig) -> Option<Self> {
+        config.keyring_secret.clone()
+            .or_else(|| std::env::var(KEYRING_SECRET_ENV_VAR).ok())
+            .filter(|secret| !secret.is_empty())
+            .map(|secret| Self::new(secret.as_bytes()))
+    }
+
+    /// 加密 `plaintext`，返回 base64url 的 nonce 和密文。
+    fn seal(&self, aad: &str, plaintext: &[u8]) -> String {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::rngs::OsRng.fill_bytes(&mut nonce);
+        let mut sealed = plaintext.to_vec();
+        self.key
+            .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(aad.as_bytes()), &mut sealed)
+            .expect("aes-gcm seal");
+        URL_SAFE_NO_PAD.encode([nonce.as_slice(), &sealed].concat())
+    }
+
+    /// 解密 [`Self::seal`] 的结果，密钥、附加数据不符或密文被篡改时为 `None`。
+    fn open(&self, aad: &str, sealed: &str) -> Option<Vec<u8>> {
+        let mut sealed = URL_SAFE_NO_PAD.decode(sealed).ok()?;
+        if sealed.len() < NONCE_LEN {
+            return None;
+        }
+        let mut ciphertext = sealed.split_off(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(&sealed).ok()?;
+        let plaintext = self.key.open_in_place(nonce, Aad::from(aad.as_bytes()), &mut ciphertext).ok()?;
+        Some(plaintext.to_vec())
+    }
+}
+
+/// 用户存储中保存的全部生成的密钥。
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StoredKeys {
+    /// 按生成顺序排列的密钥。
+    pub keys: Vec<StoredKey>,
+}
+
+impl StoredKeys {
+    /// 解析附属记录，记录不存在时为空。
+    pub fn parse(raw: Option<&str>) -> Result<Self, TokenError> {
+        raw.map_or_else(
+            || Ok(StoredKeys::default()),
+            |raw| serde_json::from_str(raw).map_err(|err| TokenError::Store(format!("invalid jwt keyring record: {}", err))),
+        )
+    }
+
+    /// 序列化为附属记录。
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("stored jwt keys serialize")
+    }
+
+    /// 最近生效（或将要生效）的密钥。
+    pub fn latest(&self) -> Option<&StoredKey> {
+        self.keys.iter().max_by_key(|key| key.activate_at)
+    }
+
+    /// 加入新生成的密钥：移除已退役的密钥，尚未被取代的密钥在新密钥签发的令牌
+    /// 可能出现之后、它们自己签发的令牌全部过期时退役。
+    pub fn push(&mut self, key: StoredKey, now: i64, access_token_ttl_secs: i64) {
+        self.keys.retain(|stored| stored.active(now));
+        let retire_at = key.activate_at + access_token_ttl_secs + RETIRE_LEEWAY_SECS;
+        for stored in self.keys.iter_mut().filter(|stored| stored.retire_at.is_none()) {
+            stored.retire_at = Some(retire_at);
+        }
+        self.keys.push(key);
+    }
+}
+
+/// 密钥环中一个生成的密钥。
+struct GeneratedKey {
+    key: Arc<SigningKey>,
+    activate_at: i64,
+    retire_at: Option<i64>,
+}
+
+/// 签名密钥环。
+pub struct KeyRing {
+    /// 配置文件中的密钥，第一个在没有生效的生成密钥时用于签名。
+    configured: Vec<Arc<SigningKey>>,
+    /// 从用户存储同步的生成的密钥。
+    generated: Vec<GeneratedKey>,
+    /// 加密生成的密钥的私钥材料，未配置时以明文保存。
+    cipher: Option<KeyringCipher>,
+}
+
+/// 密钥环中一个密钥的概要，用于管理接口展示。
+#[derive(Debug, Clone, Serialize)]
+pub struct KeySummary {
+    /// 密钥 ID。
+    pub kid: String,
+    /// 签名算法。
+    pub algorithm: String,
+    /// 是否为当前签名密钥。
+    pub current: bool,
+    /// 是否为轮换生成的密钥。
+    pub generated: bool,
+    /// 开始用于签名的时间（Unix 时间戳，秒），只有生成的密钥有。
+    pub activate_at: Option<i64>,
+    /// 移除时间（Unix 时间戳，秒）。
+    pub retire_at: Option<i64>,
+}
+
+impl KeyRing {
+    /// 以配置中的密钥创建密钥环：第一个为当前密钥，其余为不自动移除的校验密钥。
+    /// `cipher` 用于加密和解密保存在用户存储中的生成的密钥。
+    pub fn new(keys: Vec<SigningKey>, cipher: Option<KeyringCipher>) -> Self {
+        KeyRing { configured: keys.into_iter().map(Arc::new).collect(), generated: Vec::new(), cipher }
+    }
+
+    /// 加密生成的密钥的私钥材料的密钥。
+    pub fn cipher(&self) -> Option<&KeyringCipher> {
+        self.cipher.as_ref()
+    }
+
+    /// 在 `now` 时用于签名的密钥。
+    pub fn current(&self, now: i64) -> Arc<SigningKey> {
+        self.generated
+            .iter()
+            .filter(|entry| entry.activate_at <= now)
+            .max_by_key(|entry| entry.activate_at)
+            .map_or_else(|| self.configured[0].clone(), |entry| entry.key.clone())
+    }
+
+    /// 按 `kid` 查找可用于校验的密钥，已到退役时间的密钥不再使用。
+    ///
+    /// 尚未生效的生成密钥同样可用于校验，其他实例可能已经开始用它签名。
+    pub fn find(&self, kid: &str, now: i64) -> Option<Arc<SigningKey>> {
+        self.configured
+            .iter()
+            .find(|key| key.kid == kid)
+            .or_else(|| {
+                self.generated
+                    .iter()
+                    .find(|entry| entry.key.kid == kid && entry.retire_at.is_none_or(|at| at > now))
+                    .map(|entry| &entry.key)
+            })
+            .cloned()
+    }
+
+    /// 以用户存储中保存的密钥替换生成的密钥。与配置中的密钥 `kid` 相同的密钥被忽略。
+    pub fn set_generated(&mut self, stored: &StoredKeys) -> Result<(), TokenError> {
+        let mut generated = Vec::with_capacity(stored.keys.len());
+        for key in &stored.keys {
+            if self.configured.iter().any(|configured| configured.kid == key.kid) {
+                tracing::warn!("ignoring generated jwt key {}, the id is used by a configured key", key.kid);
+                continue;
+            }
+            let existing = self.generated.iter().find(|entry| entry.key.kid == key.kid).map(|entry| entry.key.clone());
+            let signing_key = match existing {
+                Some(existing) => existing,
+                None => Arc::new(key.signing_key(self.cipher.as_ref())?),
+            };
+            generated.push(GeneratedKey { key: signing_key, activate_at: key.activate_at, retire_at: key.retire_at });
+        }
+        self.generated = generated;
+        Ok(())
+    }
+
+    /// 所有仍在使用的密钥的公开 JWK，包括尚未生效的生成密钥。
+    pub fn public_jwks(&self, now: i64) -> Vec<Jwk> {
+        self.configured
+            .iter()
+            .chain(
+                self.generated
+                    .iter()
+                    .filter(|entry| entry.retire_at.is_none_or(|at| at > now))
+                    .map(|entry| &entry.key),
+            )
+            .filter_map(|key| key.jwk.clone())
+            .collect()
+    }
+
+    /// 所有密钥的概要。
+    pub fn summaries(&self, now: i64) -> Vec<KeySummary> {
+        let current = self.current(now);
+        let summary = |key: &Arc<SigningKey>, generated: bool, activate_at: Option<i64>, retire_at: Option<i64>| KeySummary {
+            kid: key.kid.clone(),
+            algorithm: format!("{:?}", key.algorithm),
+            current: Arc::ptr_eq(key, &current),
+            generated,
+            activate_at,
+            retire_at,
+        };
+        self.configured
+            .iter()
+            .map(|key| summary(key, false, None, None))
+            .chain(self.generated.iter().map(|entry| summary(&entry.key, true, Some(entry.activate_at), entry.retire_at)))
+            .collect()
+    }
+}
+
+/// 生成一个新的签名密钥，`kid` 由生成时间和随机后缀组成，在 `activate_at` 开始用于签名。
+///
+/// 支持 `EdDSA`（公钥通过 JWKS 公开）和 `HS256`。私钥材料用 `cipher` 加密，`cipher` 为 `None` 时以明文保存。
+pub fn generate_key(algorithm: &str, activate_at: i64, cipher: Option<&KeyringCipher>) -> Result<StoredKey, TokenError> {
+    let mut suffix = [0u8; 4];
+    rand::rngs::OsRng.fill_bytes(&mut suffix);
+    let kid = format!(
+        "{}-{}",
+        chrono::Utc::now().format("%Y%m%d%H%M%S"),
+        suffix.iter().map(|b| format!("{:02x}", b)).collect::<String>()
+    );
+    let secret = match algorithm {
+        "HS256" => {
+            let mut secret = [0u8; 32];
+            rand::rngs::OsRng.fill_bytes(&mut secret);
+            secret.to_vec()
+        }
+        "EdDSA" => Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+            .map_err(|_| TokenError::Key("failed to generate Ed25519 key".into()))?
+            .as_ref()
+            .to_vec(),
+        other => return Err(TokenError::Key(format!("cannot generate keys for algorithm {}", other))),
+    };
+    let mut key = StoredKey {
+        kid,
+        algorithm: algorithm.to_string(),
+        secret: URL_SAFE_NO_PAD.encode(&secret),
+        activate_at,
+        retire_at: None,
+    };
+    match cipher {
+        Some(cipher) => key.secret = format!("{}{}", SEALED_PREFIX, cipher.seal(&key.aad(), &secret)),
+        None => tracing::warn!(
+            "jwt.keyring_secret and {} are not set, generated jwt key {} is stored unencrypted", KEYRING_SECRET_ENV_VAR, key.kid
+        ),
+    }
+    // 保存之前确认能够构造出签名密钥。
+    key.signing_key(cipher)?;
+    Ok(key)
+}
+
+/// 由 PKCS#8 DER 构造 Ed25519 签名密钥。
+fn ed25519_key(kid: &str, pkcs8: &[u8]) -> Result<SigningKey, TokenError> {
+    let pair = Ed25519KeyPair::from_pkcs8(pkcs8)
+        .map_err(|_| TokenError::Key(format!("jwt key {}: invalid Ed25519 key", kid)))?;
+    let x = URL_SAFE_NO_PAD.encode(pair.public_key().as_ref());
+    let decoding = DecodingKey::from_ed_components(&x).map_err(TokenError::Jwt)?;
+    let jwk = Jwk {
+        common: common_parameters(kid, KeyAlgorithm::EdDSA),
+        algorithm: AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+            key_type: OctetKeyPairType::OctetKeyPair,
+            curve: EllipticCurve::Ed25519,
+            x,
+        }),
+    };
+    Ok(SigningKey {
+        kid: kid.to_string(),
+        algorithm: Algorithm::EdDSA,
+        encoding: EncodingKey::from_ed_der(pkcs8),
+        decoding,
+        jwk: Some(jwk),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_secrets_are_encrypted() {
+        let cipher = KeyringCipher::new(b"keyring-secret-for-tests-only");
+        for algorithm in ["HS256", "EdDSA"] {
+            let key = generate_key(algorithm, 0, Some(&cipher)).unwrap();
+            assert!(key.secret.starts_with(SEALED_PREFIX), "{}", algorithm);
+            assert_eq!(key.signing_key(Some(&cipher)).unwrap().kid, key.kid);
+            let stored = StoredKeys { keys: vec![key.clone()] }.to_json();
+            let parsed = StoredKeys::parse(Some(&stored)).unwrap();
+            assert!(parsed.keys[0].signing_key(Some(&cipher)).is_ok(), "the record round-trips");
+        }
+    }
+
+    #[test]
+    fn encrypted_secrets_need_the_same_key() {
+        let cipher = KeyringCipher::new(b"keyring-secret-for-tests-only");
+        let key = generate_key("HS256", 0, Some(&cipher)).unwrap();
+        assert!(matches!(key.signing_key(None), Err(TokenError::Key(_))));
+        assert!(matches!(key.signing_key(Some(&KeyringCipher::new(b"another-secret"))), Err(TokenError::Key(_))));
+
+        let moved = StoredKey { kid: "other".to_string(), ..key.clone() };
+        assert!(moved.signing_key(Some(&cipher)).is_err(), "ciphertext is bound to the kid");
+        let mut tampered = key.clone();
+        tampered.secret.pop();
+        tampered.secret.push(if key.secret.ends_with('A') { 'B' } else { 'A' });
+        assert!(tampered.signing_key(Some(&cipher)).is_err());
+    }
+
+    #[test]
+    fn unencrypted_records_are_still_readable() {
+        let key = generate_key("EdDSA", 0, None).unwrap();
+        assert!(URL_SAFE_NO_PAD.decode(&key.secret).is_ok());
+        assert!(key.signing_key(None).is_ok());
+        assert!(key.signing_key(Some(&KeyringCipher::new(b"keyring-secret-for-tests-only"))).is_ok());
+    }
+}
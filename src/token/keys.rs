@@ -0,0 +1,311 @@
+//! 签名密钥的加载，以及从公钥 PEM 中提取 JWK 参数。
+
+use base64::{ engine::general_purpose::{ STANDARD, URL_SAFE_NO_PAD }, Engine };
+use jsonwebtoken::{
+    jwk::{
+        AlgorithmParameters,
+        CommonParameters,
+        EllipticCurve,
+        Jwk,
+        KeyAlgorithm,
+        OctetKeyPairParameters,
+        OctetKeyPairType,
+        PublicKeyUse,
+        RSAKeyParameters,
+        RSAKeyType,
+    },
+    Algorithm,
+    DecodingKey,
+    EncodingKey,
+};
+use rand::RngCore;
+
+use super::TokenError;
+use crate::config::JwtKeyConfig;
+
+/// 未配置任何密钥时读取 HS256 密钥的环境变量名。
+pub static JWT_SECRET_ENV_VAR: &str = "BTCMWEB_JWT_SECRET";
+
+/// 一个可用于签名和校验的密钥。
+pub struct SigningKey {
+    /// 密钥 ID，写入 JWT 头部的 `kid`。
+    pub kid: String,
+    /// 签名算法。
+    pub algorithm: Algorithm,
+    /// 签名用的私钥（HS256 为共享密钥）。
+    pub encoding: EncodingKey,
+    /// 校验用的公钥（HS256 为共享密钥）。
+    pub decoding: DecodingKey,
+    /// 公开的 JWK；对称密钥不公开，为 `None`。
+    pub jwk: Option<Jwk>,
+}
+
+impl SigningKey {
+    /// 根据配置加载密钥。
+    pub fn from_config(config: &JwtKeyConfig) -> Result<Self, TokenError> {
+        match config.algorithm.as_str() {
+            "HS256" => {
+                let secret = config.secret
+                    .as_ref()
+                    .ok_or_else(|| key_error(&config.kid, "HS256 key requires `secret`"))?;
+                Ok(Self::hs256(&config.kid, secret.as_bytes()))
+            }
+            "RS256" | "EdDSA" => {
+                let private_pem = read_key_file(&config.kid, config.private_key_file.as_deref(), "private_key_file")?;
+                let public_pem = read_key_file(&config.kid, config.public_key_file.as_deref(), "public_key_file")?;
+                if config.algorithm == "RS256" {
+                    Self::rs256(&config.kid, &private_pem, &public_pem)
+                } else {
+                    Self::eddsa(&config.kid, &private_pem, &public_pem)
+                }
+            }
+            other => Err(key_error(&config.kid, &format!("unsupported algorithm {}", other))),
+        }
+    }
+
+    /// 构造 HS256 密钥。
+    pub fn hs256(kid: &str, secret: &[u8]) -> Self {
+        SigningKey {
+            kid: kid.to_string(),
+            algorithm: Algorithm::HS256,
+            encoding: EncodingKey::from_secret(secret),
+            decoding: DecodingKey::from_secret(secret),
+            jwk: None,
+        }
+    }
+
+    /// 未配置密钥时使用的 HS256 密钥：读取 `BTCMWEB_JWT_SECRET`，未设置时随机生成。
+    pub fn fallback() -> Self {
+        match std::env::var(JWT_SECRET_ENV_VAR) {
+            Ok(secret) if !secret.is_empty() => Self::hs256("default", secret.as_bytes()),
+            _ => {
+                tracing::warn!("no jwt keys configured and {} is not set, using a random secret", JWT_SECRET_ENV_VAR);
+                let mut secret = [0u8; 32];
+                rand::rngs::OsRng.fill_bytes(&mut secret);
+                Self::hs256("default", &secret)
+            }
+        }
+    }
+
+    fn rs256(kid: &str, private_pem: &str, public_pem: &str) -> Result<Self, TokenError> {
+        let encoding = EncodingKey::from_rsa_pem(private_pem.as_bytes()).map_err(|err| key_error(kid, &err.to_string()))?;
+        let decoding = DecodingKey::from_rsa_pem(public_pem.as_bytes()).map_err(|err| key_error(kid, &err.to_string()))?;
+        let (n, e) = rsa_public_components(public_pem).ok_or_else(|| key_error(kid, "invalid RSA public key"))?;
+        let jwk = Jwk {
+            common: common_parameters(kid, KeyAlgorithm::RS256),
+            algorithm: AlgorithmParameters::RSA(RSAKeyParameters {
+                key_type: RSAKeyType::RSA,
+                n: URL_SAFE_NO_PAD.encode(n),
+                e: URL_SAFE_NO_PAD.encode(e),
+            }),
+        };
+        Ok(SigningKey { kid: kid.to_string(), algorithm: Algorithm::RS256, encoding, decoding, jwk: Some(jwk) })
+    }
+
+    fn eddsa(kid: &str, private_pem: &str, public_pem: &str) -> Result<Self, TokenError> {
+        let encoding = EncodingKey::from_ed_pem(private_pem.as_bytes()).map_err(|err| key_error(kid, &err.to_string()))?;
+        let decoding = DecodingKey::from_ed_pem(public_pem.as_bytes()).map_err(|err| key_error(kid, &err.to_string()))?;
+        let x = ed25519_public_key(public_pem).ok_or_else(|| key_error(kid, "invalid Ed25519 public key"))?;
+        let jwk = Jwk {
+            common: common_parameters(kid, KeyAlgorithm::EdDSA),
+            algorithm: AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                key_type: OctetKeyPairType::OctetKeyPair,
+                curve: EllipticCurve::Ed25519,
+                x: URL_SAFE_NO_PAD.encode(x),
+            }),
+        };
+        Ok(SigningKey { kid: kid.to_string(), algorithm: Algorithm::EdDSA, encoding, decoding, jwk: Some(jwk) })
+    }
+}
+
+pub(crate) fn common_parameters(kid: &str, algorithm: KeyAlgorithm) -> CommonParameters {
+    CommonParameters {
+        public_key_use: Some(PublicKeyUse::Signature),
+        key_algorithm: Some(algorithm),
+        key_id: Some(kid.to_string()),
+        ..Default::default()
+    }
+}
+
+fn key_error(kid: &str, message: &str) -> TokenError {
+    TokenError::Key(format!("jwt key {}: {}", kid, message))
+}
+
+fn read_key_file(kid: &str, path: Option<&str>, field: &str) -> Result<String, TokenError> {
+    let path = path.ok_or_else(|| key_error(kid, &format!("missing `{}`", field)))?;
+    std::fs::read_to_string(path).map_err(|err| key_error(kid, &format!("failed to read {}: {}", path, err)))
+}
+
+/// 解码 PEM，返回标签（如 `PUBLIC KEY`）和 DER 内容。
+fn pem_to_der(pem: &str) -> Option<(String, Vec<u8>)> {
+    let mut label = None;
+    let mut body = String::new();
+    for line in pem.lines().map(str::trim) {
+        if let Some(rest) = line.strip_prefix("-----BEGIN ") {
+            label = Some(rest.trim_end_matches('-').to_string());
+        } else if line.starts_with("-----END ") {
+            break;
+        } else if label.is_some() {
+            body.push_str(line);
+        }
+    }
+    Some((label?, STANDARD.decode(body).ok()?))
+}
+
+/// 读取一个 DER TLV，返回标签、内容和剩余字节。
+fn read_tlv(der: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = der.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = if first < 0x80 {
+        (first as usize, rest)
+    } else {
+        let count = (first & 0x7f) as usize;
+        if count == 0 || count > 4 || rest.len() < count {
+            return None;
+        }
+        let len = rest[..count].iter().fold(0usize, |acc, b| (acc << 8) | *b as usize);
+        (len, &rest[count..])
+    };
+    (rest.len() >= len).then(|| (tag, &rest[..len], &rest[len..]))
+}
+
+const DER_SEQUENCE: u8 = 0x30;
+const DER_INTEGER: u8 = 0x02;
+const DER_BIT_STRING: u8 = 0x03;
+const DER_OBJECT_ID: u8 = 0x06;
+
+/// rsaEncryption（1.2.840.113549.1.1.1）的 DER 编码。
+const OID_RSA_ENCRYPTION: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+/// Ed25519（1.3.101.112）的 DER 编码。
+const OID_ED25519: &[u8] = &[0x2b, 0x65, 0x70];
+
+/// 从 SubjectPublicKeyInfo 中取出公钥位串（去掉未使用位数字节），算法标识必须是 `algorithm`。
+fn spki_public_key<'a>(der: &'a [u8], algorithm: &[u8]) -> Option<&'a [u8]> {
+    let (DER_SEQUENCE, spki, _) = read_tlv(der)? else { return None };
+    let (DER_SEQUENCE, identifier, rest) = read_tlv(spki)? else { return None };
+    let (DER_OBJECT_ID, oid, _) = read_tlv(identifier)? else { return None };
+    if oid != algorithm {
+        return None;
+    }
+    let (DER_BIT_STRING, bits, _) = read_tlv(rest)? else { return None };
+    bits.split_first().filter(|(unused, _)| **unused == 0).map(|(_, key)| key)
+}
+
+/// 从 RSA 公钥 PEM（`PUBLIC KEY` 或 `RSA PUBLIC KEY`）中提取模数和指数。
+fn rsa_public_components(pem: &str) -> Option<(Vec<u8>, Vec<u8>)> {
+    let (label, der) = pem_to_der(pem)?;
+    let rsa_key = if label == "RSA PUBLIC KEY" { der.as_slice() } else { spki_public_key(&der, OID_RSA_ENCRYPTION)? };
+    let (DER_SEQUENCE, seq, _) = read_tlv(rsa_key)? else { return None };
+    let (DER_INTEGER, n, rest) = read_tlv(seq)? else { return None };
+    let (DER_INTEGER, e, _) = read_tlv(rest)? else { return None };
+    let strip = |bytes: &[u8]| bytes.iter().skip_while(|b| **b == 0).copied().collect::<Vec<u8>>();
+    Some((strip(n), strip(e)))
+}
+
+/// 从 Ed25519 公钥 PEM 中提取 32 字节的公钥。
+fn ed25519_public_key(pem: &str) -> Option<Vec<u8>> {
+    let (_, der) = pem_to_der(pem)?;
+    spki_public_key(&der, OID_ED25519).filter(|key| key.len() == 32).map(<[u8]>::to_vec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RSA_SPKI_PEM: &str = "-----BEGIN PUBLIC KEY-----
+MIGfMA0GCSqGSIb3DQEBAQUAA4GNADCBiQKBgQCtJkIfKzcR9pD80K7gom9TEFu1
+r+OLEfg8nhNF18YZqWVTx/JPaVNzWTeGxjaG1IwVx/9mH3QiGAORve99MpSaAzwV
+EiKKg5qsxGVRnPd3tVf7fk962gfiG3N/6WR+QDZygIm7+dcgG1qDkifkG8a0N+CV
+jaUTjhLquNF6/Ppa5QIDAQAB
+-----END PUBLIC KEY-----";
+
+    const RSA_PKCS1_PEM: &str = "-----BEGIN RSA PUBLIC KEY-----
+MIGJAoGBAK0mQh8rNxH2kPzQruCib1MQW7Wv44sR+DyeE0XXxhmpZVPH8k9pU3NZ
+N4bGNobUjBXH/2YfdCIYA5G9730ylJoDPBUSIoqDmqzEZVGc93e1V/t+T3raB+Ib
+c3/pZH5ANnKAibv51yAbWoOSJ+QbxrQ34JWNpROOEuq40Xr8+lrlAgMBAAE=
+-----END RSA PUBLIC KEY-----";
+
+    const ED25519_SPKI_PEM: &str = "-----BEGIN PUBLIC KEY-----
+MCowBQYDK2VwAyEApl1QKMYrROOCCT1kDVGu4Elr9tRYUtcduofyPF8AWVA=
+-----END PUBLIC KEY-----";
+
+    fn pem(label: &str, der: &[u8]) -> String {
+        format!("-----BEGIN {0}-----\n{1}\n-----END {0}-----", label, STANDARD.encode(der))
+    }
+
+    #[test]
+    fn read_tlv_short_and_long_lengths() {
+        assert_eq!(read_tlv(&[0x02, 0x01, 0x05, 0xff]), Some((0x02, &[0x05][..], &[0xff][..])));
+        let mut long = vec![0x04, 0x81, 0x80];
+        long.extend([0xaa; 0x80]);
+        let (tag, value, rest) = read_tlv(&long).unwrap();
+        assert_eq!((tag, value.len(), rest.len()), (0x04, 0x80, 0));
+    }
+
+    #[test]
+    fn read_tlv_rejects_malformed_input() {
+        assert_eq!(read_tlv(&[]), None);
+        assert_eq!(read_tlv(&[0x30]), None, "missing length");
+        assert_eq!(read_tlv(&[0x30, 0x03, 0x01, 0x02]), None, "value shorter than its length");
+        assert_eq!(read_tlv(&[0x30, 0x82, 0x01]), None, "truncated length bytes");
+        assert_eq!(read_tlv(&[0x30, 0x80, 0x00, 0x00]), None, "indefinite length");
+        assert_eq!(read_tlv(&[0x30, 0x85, 0x01, 0x00, 0x00, 0x00, 0x00]), None, "more than four length bytes");
+        assert_eq!(read_tlv(&[0x30, 0x84, 0xff, 0xff, 0xff, 0xff, 0x00]), None, "length beyond the input");
+    }
+
+    #[test]
+    fn rsa_components_from_both_encodings() {
+        let (n, e) = rsa_public_components(RSA_SPKI_PEM).unwrap();
+        assert_eq!(n.len(), 128);
+        assert_eq!(n[0], 0xad, "the sign byte is stripped");
+        assert_eq!(e, vec![0x01, 0x00, 0x01]);
+        assert_eq!(rsa_public_components(RSA_PKCS1_PEM), Some((n, e)));
+    }
+
+    #[test]
+    fn truncated_keys_are_rejected() {
+        let (_, der) = pem_to_der(RSA_SPKI_PEM).unwrap();
+        for len in 0..der.len() {
+            assert_eq!(spki_public_key(&der[..len], OID_RSA_ENCRYPTION), None, "truncated to {} bytes", len);
+            assert_eq!(rsa_public_components(&pem("PUBLIC KEY", &der[..len])), None, "truncated to {} bytes", len);
+        }
+        let (_, der) = pem_to_der(RSA_PKCS1_PEM).unwrap();
+        for len in 0..der.len() {
+            assert_eq!(rsa_public_components(&pem("RSA PUBLIC KEY", &der[..len])), None, "truncated to {} bytes", len);
+        }
+        assert_eq!(rsa_public_components("-----BEGIN PUBLIC KEY-----\nnot base64!\n-----END PUBLIC KEY-----"), None);
+        assert_eq!(rsa_public_components(""), None);
+    }
+
+    #[test]
+    fn oversized_lengths_are_rejected() {
+        let (_, mut der) = pem_to_der(RSA_SPKI_PEM).unwrap();
+        // 外层 SEQUENCE 的长度为 0x81 0x9f，改为比实际内容多一个字节。
+        assert_eq!(&der[..3], &[0x30, 0x81, 0x9f]);
+        der[2] = 0xa0;
+        assert_eq!(spki_public_key(&der, OID_RSA_ENCRYPTION), None);
+        assert_eq!(rsa_public_components(&pem("PUBLIC KEY", &der)), None);
+
+        let (_, mut der) = pem_to_der(RSA_PKCS1_PEM).unwrap();
+        // 模数 INTEGER 的长度（0x81 0x81）超出外层 SEQUENCE。
+        assert_eq!(&der[3..6], &[0x02, 0x81, 0x81]);
+        der[5] = 0xff;
+        assert_eq!(rsa_public_components(&pem("RSA PUBLIC KEY", &der)), None);
+    }
+
+    #[test]
+    fn non_rsa_keys_are_rejected() {
+        assert_eq!(rsa_public_components(ED25519_SPKI_PEM), None);
+        let (_, der) = pem_to_der(ED25519_SPKI_PEM).unwrap();
+        assert_eq!(spki_public_key(&der, OID_RSA_ENCRYPTION), None);
+        assert_eq!(spki_public_key(&der, OID_ED25519).map(<[u8]>::len), Some(32));
+        assert_eq!(ed25519_public_key(ED25519_SPKI_PEM).map(|key| key.len()), Some(32));
+        assert_eq!(ed25519_public_key(RSA_SPKI_PEM), None);
+
+        // 算法标识不是 OBJECT IDENTIFIER。
+        let (_, mut der) = pem_to_der(RSA_SPKI_PEM).unwrap();
+        assert_eq!(der[5], DER_OBJECT_ID);
+        der[5] = DER_INTEGER;
+        assert_eq!(spki_public_key(&der, OID_RSA_ENCRYPTION), None);
+    }
+}
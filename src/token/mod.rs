@@ -0,0 +1,388 @@
+//! # 访问令牌
+//!
+//! 登录成功后签发 JWT 访问令牌，客户端在后续请求（例如 `JsonRequest.token`）中携带。
+//!
+//! 签名密钥、签发者、受众和有效期来自配置文件的 `jwt` 部分（见 [`crate::config::JwtConfig`]），
+//! 支持 HS256、RS256 和 EdDSA。`keys` 中的第一个密钥用于签名，其余密钥只用于按 `kid` 校验。
+//! 密钥可以按计划或通过管理接口轮换而不使已签发的令牌失效，生成的密钥保存在用户存储中，见 [`keyring`]。
+//! 未配置任何密钥时退回到 HS256，密钥读取自环境变量 `BTCMWEB_JWT_SECRET`，
+//! 未设置时随机生成，此时重启服务器会使所有已签发的令牌失效。
+//!
+//! 非对称密钥的公钥通过 `/.well-known/jwks.json` 公开，`btcmnetwork` 节点无需共享密钥即可校验令牌。
+//!
+//! 访问令牌有效期较短，登录时同时签发一个刷新令牌，客户端通过 `/token/refresh` 换取新的令牌对，
+//! 见 [`refresh`]。已签发的令牌可以在过期前被吊销，见 [`revoke`]；
+//! 需要认证的请求应使用 [`authenticate_access_token`]，它在校验签名之外还检查吊销状态。
+//! 每次登录对应一个会话，见 [`session`]。
+//! 访问令牌中带有用户的角色和权限，见 [`crate::rbac`]。
+
+pub mod jwks;
+pub mod keyring;
+pub mod keys;
+pub mod refresh;
+pub mod revoke;
+pub mod session;
+
+use std::fmt;
+use std::sync::RwLock;
+use std::time::Duration;
+
+use jsonwebtoken::{ decode, decode_header, encode, Header, Validation };
+use lazy_static::lazy_static;
+use rand::RngCore;
+use serde::{ Deserialize, Serialize };
+
+use crate::config::{ config, JwtConfig };
+use crate::kvstore::KvStoreError;
+use crate::userstore::{ self, UserRecord };
+use keyring::{ KeyRing, KeyringCipher, KeySummary, StoredKey, StoredKeys };
+use keys::SigningKey;
+use session::{ DeviceInfo, SessionRecord };
+
+/// 令牌操作的错误。
+#[derive(Debug)]
+pub enum TokenError {
+    /// 密钥配置错误。
+    Key(String),
+    /// 令牌头部的 `kid` 不对应任何已知密钥。
+    UnknownKey(String),
+    /// 签名、解析或声明校验失败。
+    Jwt(jsonwebtoken::errors::Error),
+    /// 刷新令牌未知、过期或已被吊销。
+    InvalidRefreshToken,
+    /// 已使用过的刷新令牌被再次使用，所在令牌族已被吊销。
+    RefreshTokenReused,
+    /// 访问令牌已被吊销（退出登录）。
+    Revoked,
+    /// 令牌存储错误。
+    Store(String),
+}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenError::Key(message) => write!(f, "{}", message),
+            TokenError::UnknownKey(kid) => write!(f, "unknown jwt key id {}", kid),
+            TokenError::Jwt(err) => write!(f, "{}", err),
+            TokenError::InvalidRefreshToken => write!(f, "invalid refresh token"),
+            TokenError::RefreshTokenReused => write!(f, "refresh token reused"),
+            TokenError::Revoked => write!(f, "token has been revoked"),
+            TokenError::Store(message) => write!(f, "token store error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for TokenError {}
+
+impl From<jsonwebtoken::errors::Error> for TokenError {
+    fn from(err: jsonwebtoken::errors::Error) -> Self {
+        TokenError::Jwt(err)
+    }
+}
+
+impl From<KvStoreError> for TokenError {
+    fn from(err: KvStoreError) -> Self {
+        TokenError::Store(err.to_string())
+    }
+}
+
+impl From<serde_json::Error> for TokenError {
+    fn from(err: serde_json::Error) -> Self {
+        TokenError::Store(format!("invalid token record: {}", err))
+    }
+}
+
+/// 登录或刷新时签发的令牌对。
+#[derive(Debug, Clone)]
+pub struct TokenPair {
+    /// JWT 访问令牌。
+    pub access_token: String,
+    /// 不透明的刷新令牌。
+    pub refresh_token: String,
+}
+
+/// 访问令牌中的声明。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    /// 用户 ID（client id）的十进制字符串。
+    pub sub: String,
+    /// 用户名。
+    pub username: String,
+    /// 签发者。
+    pub iss: String,
+    /// 受众。
+    pub aud: Vec<String>,
+    /// 签发时间（Unix 时间戳，秒）。
+    pub iat: i64,
+    /// 生效时间（Unix 时间戳，秒）。
+    pub nbf: i64,
+    /// 过期时间（Unix 时间戳，秒）。
+    pub exp: i64,
+    /// 令牌唯一 ID。
+    pub jti: String,
+    /// 签发时用户的令牌代数，低于当前代数的令牌已失效，见 [`revoke`]。
+    #[serde(default)]
+    pub gen: u64,
+    /// 令牌所属的会话 ID，见 [`session`]。
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub sid: String,
+    /// 签发时用户填写了邮箱但尚未验证，见 [`crate::config::UnverifiedConfig`]。
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub unverified: bool,
+    /// 签发令牌的登录是否通过了两步验证。
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub mfa: bool,
+    /// 签发时用户拥有的角色，见 [`crate::rbac::effective_roles`]。
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>,
+    /// 签发时用户拥有的全部权限，见 [`crate::rbac::permissions_of`]。
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub perms: Vec<String>,
+    /// 通过 API 密钥认证时为密钥 ID，见 [`crate::account::apikey`]。JWT 访问令牌中没有该声明。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+}
+
+impl Claims {
+    /// 返回令牌所属的用户 ID。
+    pub fn user_id(&self) -> Option<u64> {
+        self.sub.parse().ok()
+    }
+
+    /// 令牌是否拥有 `permission`，见 [`crate::rbac::has_permission`]。
+    pub fn has_permission(&self, permission: &str) -> bool {
+        crate::rbac::has_permission(self, permission)
+    }
+}
+
+/// 签发和校验访问令牌的服务。
+pub struct TokenService {
+    issuer: String,
+    audience: Vec<String>,
+    access_token_ttl_secs: i64,
+    rotation_algorithm: String,
+    keys: RwLock<KeyRing>,
+}
+
+impl TokenService {
+    /// 根据配置创建服务，加载所有密钥。
+    pub fn from_config(config: &JwtConfig) -> Result<Self, TokenError> {
+        let mut keys = config.keys.iter().map(SigningKey::from_config).collect::<Result<Vec<_>, _>>()?;
+        if keys.is_empty() {
+            keys.push(SigningKey::fallback());
+        }
+        Ok(TokenService {
+            issuer: config.issuer.clone(),
+            audience: config.audience.clone(),
+            access_token_ttl_secs: config.access_token_ttl_secs,
+            rotation_algorithm: config.rotation_algorithm.clone(),
+            keys: RwLock::new(KeyRing::new(keys, KeyringCipher::from_config(config))),
+        })
+    }
+
+    /// 为用户签发访问令牌，`generation` 为用户当前的令牌代数，`session_id` 为所属会话，
+    /// `mfa` 表示登录时是否通过了两步验证。
+    pub fn issue_access_token(&self, user: &UserRecord, generation: u64, session_id: &str, mfa: bool) -> Result<String, TokenError> {
+        let now = chrono::Utc::now().timestamp();
+        let roles = crate::rbac::effective_roles(user);
+        let claims = Claims {
+            sub: user.id.to_string(),
+            username: user.username.clone(),
+            iss: self.issuer.clone(),
+            aud: self.audience.clone(),
+            iat: now,
+            nbf: now,
+            exp: now + self.access_token_ttl_secs,
+            jti: new_token_id(),
+            gen: generation,
+            sid: session_id.to_string(),
+            unverified: crate::account::verification::is_unverified(user),
+            mfa,
+            perms: crate::rbac::permissions_of(&roles),
+            roles,
+            api_key: None,
+        };
+        let key = self.keys.read().unwrap().current(now);
+        let mut header = Header::new(key.algorithm);
+        header.kid = Some(key.kid.clone());
+        Ok(encode(&header, &claims, &key.encoding)?)
+    }
+
+    /// 按 `kid` 选择密钥，校验签名、签发者、受众和有效期，返回其中的声明。
+    ///
+    /// 没有 `kid` 的令牌使用当前签名密钥校验。
+    pub fn verify_access_token(&self, token: &str) -> Result<Claims, TokenError> {
+        let header = decode_header(token)?;
+        let key = {
+            let ring = self.keys.read().unwrap();
+            let now = chrono::Utc::now().timestamp();
+            match &header.kid {
+                Some(kid) => ring.find(kid, now).ok_or_else(|| TokenError::UnknownKey(kid.clone()))?,
+                None => ring.current(now),
+            }
+        };
+        let mut validation = Validation::new(key.algorithm);
+        validation.validate_nbf = true;
+        validation.set_issuer(&[&self.issuer]);
+        validation.set_audience(&self.audience);
+        validation.set_required_spec_claims(&["exp", "nbf", "iss", "aud", "sub"]);
+        Ok(decode::<Claims>(token, &key.decoding, &validation)?.claims)
+    }
+
+    /// 所有仍在使用的公开密钥的 JWK。
+    pub fn public_jwks(&self) -> Vec<jsonwebtoken::jwk::Jwk> {
+        self.keys.read().unwrap().public_jwks(chrono::Utc::now().timestamp())
+    }
+
+    /// 密钥环中所有密钥的概要。
+    pub fn key_summaries(&self) -> Vec<KeySummary> {
+        self.keys.read().unwrap().summaries(chrono::Utc::now().timestamp())
+    }
+
+    /// 从用户存储同步生成的密钥，见 [`keyring`]。
+    pub async fn sync_keys(&self) -> Result<(), TokenError> {
+        let raw = userstore::user_store()
+            .get_record(keyring::RECORD_KIND, keyring::RECORD_KEY)
+            .await
+            .map_err(|err| TokenError::Store(err.to_string()))?;
+        let stored = StoredKeys::parse(raw.as_deref())?;
+        self.keys.write().unwrap().set_generated(&stored)
+    }
+
+    /// 按配置的 `rotation_algorithm` 生成新的签名密钥并保存到用户存储，返回新密钥。
+    ///
+    /// 新密钥在 [`keyring::ACTIVATION_DELAY_SECS`] 秒后开始用于签名，
+    /// 原签名密钥在它签发的令牌全部过期之前继续用于校验。
+    pub async fn rotate(&self) -> Result<StoredKey, TokenError> {
+        self.rotate_if(|_| true).await.map(|key| key.expect("unconditional rotation"))
+    }
+
+    /// 最近一次轮换（生效时间）已超过 `interval_secs` 秒，或还没有生成过密钥时轮换签名密钥。
+    ///
+    /// 多个实例同时调用时只有一个实例轮换。
+    pub async fn rotate_if_due(&self, interval_secs: u64) -> Result<Option<StoredKey>, TokenError> {
+        let now = chrono::Utc::now().timestamp();
+        self.rotate_if(|stored| stored.latest().is_none_or(|latest| latest.activate_at + interval_secs as i64 <= now)).await
+    }
+
+    /// `due` 对用户存储中的密钥返回 `true` 时轮换，按记录内容比较并交换。
+    async fn rotate_if(&self, due: impl Fn(&StoredKeys) -> bool) -> Result<Option<StoredKey>, TokenError> {
+        let store = userstore::user_store();
+        let store_error = |err: userstore::UserStoreError| TokenError::Store(err.to_string());
+        loop {
+            let raw = store.get_record(keyring::RECORD_KIND, keyring::RECORD_KEY).await.map_err(store_error)?;
+            let mut stored = StoredKeys::parse(raw.as_deref())?;
+            if !due(&stored) {
+                self.keys.write().unwrap().set_generated(&stored)?;
+                return Ok(None);
+            }
+            let now = chrono::Utc::now().timestamp();
+            let key = keyring::generate_key(&self.rotation_algorithm, now + keyring::ACTIVATION_DELAY_SECS, self.keys.read().unwrap().cipher())?;
+            stored.push(key.clone(), now, self.access_token_ttl_secs);
+            let swapped = store
+                .swap_record(keyring::RECORD_KIND, keyring::RECORD_KEY, raw.as_deref(), Some(&stored.to_json()))
+                .await
+                .map_err(store_error)?;
+            if swapped {
+                self.keys.write().unwrap().set_generated(&stored)?;
+                tracing::info!("rotated jwt signing key, new kid {} active from {}", key.kid, key.activate_at);
+                return Ok(Some(key));
+            }
+        }
+    }
+}
+
+lazy_static! {
+    /// 服务器使用的令牌服务。密钥配置错误时启动失败。
+    static ref TOKEN_SERVICE: TokenService = TokenService::from_config(&config().jwt)
+        .unwrap_or_else(|err| panic!("failed to load jwt keys: {}", err));
+}
+
+/// 获取服务器使用的令牌服务。
+pub fn token_service() -> &'static TokenService {
+    &TOKEN_SERVICE
+}
+
+/// 启动密钥维护任务：每隔 [`keyring::SYNC_INTERVAL_SECS`] 秒从用户存储同步生成的密钥，
+/// 配置的 `rotation_interval_secs` 不为 0 时同时按该间隔轮换签名密钥。
+///
+/// 启动前应先调用 [`TokenService::sync_keys`]。
+pub fn spawn_key_rotation() {
+    let interval_secs = config().jwt.rotation_interval_secs;
+    tokio::spawn(async move {
+        let service = token_service();
+        let mut ticker = tokio::time::interval(Duration::from_secs(keyring::SYNC_INTERVAL_SECS));
+        loop {
+            ticker.tick().await;
+            let result = if interval_secs == 0 {
+                service.sync_keys().await
+            } else {
+                service.rotate_if_due(interval_secs).await.map(|_| ())
+            };
+            if let Err(err) = result {
+                tracing::error!("jwt key maintenance failed: {}", err);
+            }
+        }
+    });
+}
+
+/// 在会话中为用户签发访问令牌。
+pub fn issue_access_token(user: &UserRecord, session: &SessionRecord) -> Result<String, TokenError> {
+    token_service().issue_access_token(user, session.generation, &session.id, session.mfa)
+}
+
+/// 用户登录时创建会话，签发访问令牌和会话中的第一个刷新令牌。`mfa` 表示登录时是否通过了两步验证。
+pub async fn issue_tokens(user: &UserRecord, device: DeviceInfo, mfa: bool) -> Result<TokenPair, TokenError> {
+    let session = session::create_session(user.id, device, mfa).await?;
+    Ok(TokenPair {
+        access_token: issue_access_token(user, &session)?,
+        refresh_token: refresh::issue_refresh_token(&session).await?,
+    })
+}
+
+/// 使用刷新令牌换取新的令牌对，返回令牌所属的用户。
+///
+/// 刷新令牌的错误见 [`refresh::rotate_refresh_token`]；用户已被删除、停用或等待批准时返回
+/// [`TokenError::InvalidRefreshToken`]。
+pub async fn refresh_tokens(refresh_token: &str) -> Result<(UserRecord, TokenPair), TokenError> {
+    let (session, refresh_token) = refresh::rotate_refresh_token(refresh_token).await?;
+    let user = userstore::user_store()
+        .get_by_id(session.user_id)
+        .await
+        .map_err(|err| TokenError::Store(err.to_string()))?
+        .filter(|user| !user.disabled && !user.pending_approval)
+        .ok_or(TokenError::InvalidRefreshToken)?;
+    let access_token = issue_access_token(&user, &session)?;
+    Ok((user, TokenPair { access_token, refresh_token }))
+}
+
+/// 校验访问令牌的签名和声明，返回其中的声明。不检查吊销状态。
+pub fn verify_access_token(token: &str) -> Result<Claims, TokenError> {
+    token_service().verify_access_token(token)
+}
+
+/// 校验访问令牌并检查它是否已被吊销（包括所属会话是否仍然有效），返回其中的声明。
+///
+/// 校验通过时更新会话的最后活跃时间。
+pub async fn authenticate_access_token(token: &str) -> Result<Claims, TokenError> {
+    let claims = verify_access_token(token)?;
+    if revoke::is_revoked(&claims).await? {
+        return Err(TokenError::Revoked);
+    }
+    if !claims.sid.is_empty() {
+        let Some(mut session) = session::get_session(&claims.sid).await? else {
+            return Err(TokenError::Revoked);
+        };
+        if !session::touch_session(&mut session, false).await? {
+            return Err(TokenError::Revoked);
+        }
+    }
+    Ok(claims)
+}
+
+/// 生成 128 位随机的令牌 ID（十六进制）。
+pub fn new_token_id() -> String {
+    let mut bytes = [0u8; 16];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
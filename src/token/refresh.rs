@@ -0,0 +1,105 @@
+//! # 刷新令牌
+//!
+//! 刷新令牌是不透明的随机字符串，服务器只保存它的 SHA-256 摘要（见 [`crate::kvstore`]）。
+//! 每次登录创建一个新的会话（见 [`super::session`]），会话同时是刷新令牌的令牌族：
+//! 每次使用刷新令牌都会换发同一会话中的新刷新令牌，旧令牌随即失效。
+//!
+//! 已经使用过的刷新令牌再次出现，说明令牌可能被盗用（攻击者和合法客户端各持有一份），
+//! 此时整个会话被吊销，双方都必须重新登录。
+//!
+//! 会话被吊销、过期，或用户"退出所有设备"后（见 [`super::revoke`]），其中的刷新令牌全部失效。
+
+use ring::digest::{ digest, SHA256 };
+use serde::{ Deserialize, Serialize };
+
+use super::session::{ self, SessionRecord };
+use super::TokenError;
+use crate::config::config;
+use crate::kvstore::kv_store;
+
+/// 刷新令牌记录的键前缀，完整键为 `btcm:refresh:token:{sha256}`。
+const TOKEN_KEY_PREFIX: &str = "btcm:refresh:token:";
+/// 已使用标记的键前缀，完整键为 `btcm:refresh:used:{sha256}`。
+const USED_KEY_PREFIX: &str = "btcm:refresh:used:";
+
+/// 服务器保存的刷新令牌记录。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RefreshRecord {
+    /// 令牌所属的用户 ID。
+    user_id: u64,
+    /// 令牌所属的会话（令牌族）ID。
+    session_id: String,
+    /// 签发时间（Unix 时间戳，秒）。
+    issued_at: i64,
+}
+
+fn digest_hex(token: &str) -> String {
+    hex(digest(&SHA256, token.as_bytes()).as_ref())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn ttl_secs() -> u64 {
+    config().jwt.refresh_token_ttl_secs.max(1) as u64
+}
+
+/// 在会话中签发一个新的刷新令牌。
+pub async fn issue_refresh_token(session: &SessionRecord) -> Result<String, TokenError> {
+    let token = format!("{}{}", super::new_token_id(), super::new_token_id());
+    let record = RefreshRecord {
+        user_id: session.user_id,
+        session_id: session.id.clone(),
+        issued_at: chrono::Utc::now().timestamp(),
+    };
+    let key = format!("{}{}", TOKEN_KEY_PREFIX, digest_hex(&token));
+    kv_store().set(&key, &serde_json::to_string(&record)?, Some(ttl_secs())).await?;
+    Ok(token)
+}
+
+/// 查找刷新令牌所属的会话，令牌未知时返回 `None`。
+async fn find_record(token: &str) -> Result<Option<RefreshRecord>, TokenError> {
+    let data = kv_store().get(&format!("{}{}", TOKEN_KEY_PREFIX, digest_hex(token))).await?;
+    Ok(data.map(|data| serde_json::from_str(&data)).transpose()?)
+}
+
+/// 使用刷新令牌：校验后使其失效，并换发同一会话中的新刷新令牌。
+///
+/// 返回令牌所属的会话（最后活跃时间已更新）和新的刷新令牌。令牌未知、过期或所属会话已失效时返回
+/// [`TokenError::InvalidRefreshToken`]；令牌已被使用过时吊销整个会话并返回
+/// [`TokenError::RefreshTokenReused`]。
+pub async fn rotate_refresh_token(token: &str) -> Result<(SessionRecord, String), TokenError> {
+    let Some(record) = find_record(token).await? else {
+        return Err(TokenError::InvalidRefreshToken);
+    };
+    let Some(mut session) = session::get_session(&record.session_id).await? else {
+        return Err(TokenError::InvalidRefreshToken);
+    };
+    // 用 SET NX 标记已使用，并发的两次使用中只有一次能成功。
+    let used_key = format!("{}{}", USED_KEY_PREFIX, digest_hex(token));
+    if !kv_store().set_nx(&used_key, "1", Some(ttl_secs())).await? {
+        session::revoke_session(&session).await?;
+        tracing::warn!("refresh token reuse detected for user {}, revoked session {}", record.user_id, session.id);
+        return Err(TokenError::RefreshTokenReused);
+    }
+    // 滑动延长会话的有效期；会话同时被吊销时不再签发。
+    if !session::touch_session(&mut session, true).await? {
+        return Err(TokenError::InvalidRefreshToken);
+    }
+    let token = issue_refresh_token(&session).await?;
+    Ok((session, token))
+}
+
+/// 吊销刷新令牌所在的整个会话。令牌未知时不做任何操作。
+///
+/// `user_id` 用于确认令牌属于调用者，不属于时同样不做任何操作。
+pub async fn revoke_refresh_token(token: &str, user_id: u64) -> Result<(), TokenError> {
+    let Some(record) = find_record(token).await?.filter(|record| record.user_id == user_id) else {
+        return Ok(());
+    };
+    if let Some(session) = session::get_session(&record.session_id).await? {
+        session::revoke_session(&session).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,57 @@
+//! # 令牌吊销
+//!
+//! 访问令牌是自包含的 JWT，签名有效即可通过校验，因此服务器另外维护两种吊销状态：
+//!
+//! - 吊销列表：退出登录时按 `jti` 记录被吊销的令牌，记录在令牌过期后自动删除；
+//! - 令牌代数：每个用户有一个递增的代数，签发时写入令牌的 `gen` 声明。
+//!   "退出所有设备"时代数加一，此前签发的所有访问令牌和刷新令牌全部失效。
+//!
+//! 两种状态都保存在 [`crate::kvstore`] 中，多实例部署时应使用 Redis 后端共享。
+
+use super::{ Claims, TokenError };
+use crate::kvstore::kv_store;
+
+/// 吊销列表的键前缀，完整键为 `btcm:revoked:jti:{jti}`。
+const REVOKED_KEY_PREFIX: &str = "btcm:revoked:jti:";
+/// 令牌代数的键前缀，完整键为 `btcm:tokengen:{user_id}`。
+const GENERATION_KEY_PREFIX: &str = "btcm:tokengen:";
+
+fn generation_key(user_id: u64) -> String {
+    format!("{}{}", GENERATION_KEY_PREFIX, user_id)
+}
+
+/// 用户当前的令牌代数，从未退出所有设备时为 0。
+pub async fn token_generation(user_id: u64) -> Result<u64, TokenError> {
+    let value = kv_store().get(&generation_key(user_id)).await?;
+    Ok(value.and_then(|value| value.parse().ok()).unwrap_or(0))
+}
+
+/// 吊销一个访问令牌，直到它过期。
+pub async fn revoke_access_token(claims: &Claims) -> Result<(), TokenError> {
+    let ttl = claims.exp - chrono::Utc::now().timestamp();
+    if ttl <= 0 {
+        return Ok(());
+    }
+    let key = format!("{}{}", REVOKED_KEY_PREFIX, claims.jti);
+    kv_store().set(&key, &claims.sub, Some(ttl as u64)).await?;
+    Ok(())
+}
+
+/// 使用户此前签发的所有访问令牌和刷新令牌失效，返回新的令牌代数。
+pub async fn revoke_all_tokens(user_id: u64) -> Result<u64, TokenError> {
+    let generation = kv_store().incr(&generation_key(user_id), None).await?;
+    tracing::info!("revoked all tokens of user {}, generation {}", user_id, generation);
+    Ok(generation as u64)
+}
+
+/// 检查访问令牌是否已被吊销：`jti` 在吊销列表中，或代数低于用户当前的令牌代数。
+pub async fn is_revoked(claims: &Claims) -> Result<bool, TokenError> {
+    let store = kv_store();
+    if store.get(&format!("{}{}", REVOKED_KEY_PREFIX, claims.jti)).await?.is_some() {
+        return Ok(true);
+    }
+    match claims.user_id() {
+        Some(user_id) => Ok(claims.gen < token_generation(user_id).await?),
+        None => Ok(true),
+    }
+}
@@ -0,0 +1,167 @@
+//! # 登录会话
+//!
+//! 每次登录创建一个会话，记录设备名、User-Agent、IP 以及创建和最后活跃时间。
+//! 会话 ID 写入该次登录签发的所有访问令牌（`sid` 声明），同时作为刷新令牌的令牌族 ID
+//! （见 [`super::refresh`]），因此吊销一个会话会使它的访问令牌和刷新令牌同时失效。
+//!
+//! 会话记录保存在 [`crate::kvstore`] 中，有效期与刷新令牌相同，每次刷新时重新计时。
+//! 更新会话时只覆盖仍然存在的记录，不会恢复同时被吊销的会话。
+
+use serde::{ Deserialize, Serialize };
+
+use super::TokenError;
+use crate::config::config;
+use crate::kvstore::kv_store;
+
+/// 会话记录的键前缀，完整键为 `btcm:session:{id}`；键不存在表示会话已吊销或过期。
+const SESSION_KEY_PREFIX: &str = "btcm:session:";
+/// 用户会话集合的键前缀，完整键为 `btcm:sessions:{user_id}`。
+const USER_SESSIONS_KEY_PREFIX: &str = "btcm:sessions:";
+/// 两次更新最后活跃时间之间的最短间隔（秒），避免每个请求都写存储。
+const TOUCH_INTERVAL_SECS: i64 = 60;
+/// 设备名最长长度（字符数）。
+const DEVICE_NAME_MAX_LEN: usize = 64;
+/// User-Agent 最长长度（字符数）。
+const USER_AGENT_MAX_LEN: usize = 256;
+
+/// 登录时客户端的设备信息。
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeviceInfo {
+    /// 客户端提供的设备名。
+    pub device_name: Option<String>,
+    /// 请求头中的 User-Agent。
+    pub user_agent: Option<String>,
+    /// 客户端 IP。
+    pub ip: Option<String>,
+}
+
+/// 一个登录会话。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionRecord {
+    /// 会话 ID。
+    pub id: String,
+    /// 会话所属的用户 ID。
+    pub user_id: u64,
+    /// 创建时用户的令牌代数，低于当前代数的会话已失效。
+    #[serde(default)]
+    pub generation: u64,
+    /// 设备名。
+    #[serde(default)]
+    pub device_name: Option<String>,
+    /// User-Agent。
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    /// 登录时的客户端 IP。
+    #[serde(default)]
+    pub ip: Option<String>,
+    /// 创建时间，即登录时间（Unix 时间戳，秒）。
+    pub created_at: i64,
+    /// 最后活跃时间（Unix 时间戳，秒）。
+    pub last_seen: i64,
+    /// 登录时是否通过了两步验证，见 [`crate::account::mfa`]。
+    #[serde(default)]
+    pub mfa: bool,
+}
+
+fn session_key(id: &str) -> String {
+    format!("{}{}", SESSION_KEY_PREFIX, id)
+}
+
+fn user_sessions_key(user_id: u64) -> String {
+    format!("{}{}", USER_SESSIONS_KEY_PREFIX, user_id)
+}
+
+fn ttl_secs() -> u64 {
+    config().jwt.refresh_token_ttl_secs.max(1) as u64
+}
+
+fn truncate(value: Option<String>, max_len: usize) -> Option<String> {
+    value.map(|value| value.trim().chars().take(max_len).collect::<String>()).filter(|value| !value.is_empty())
+}
+
+/// 为用户创建一个新会话。`mfa` 表示登录时是否通过了两步验证。
+pub async fn create_session(user_id: u64, device: DeviceInfo, mfa: bool) -> Result<SessionRecord, TokenError> {
+    let now = chrono::Utc::now().timestamp();
+    let session = SessionRecord {
+        id: super::new_token_id(),
+        user_id,
+        generation: super::revoke::token_generation(user_id).await?,
+        device_name: truncate(device.device_name, DEVICE_NAME_MAX_LEN),
+        user_agent: truncate(device.user_agent, USER_AGENT_MAX_LEN),
+        ip: device.ip,
+        created_at: now,
+        last_seen: now,
+        mfa,
+    };
+    save_session(&session).await?;
+    kv_store().set_add(&user_sessions_key(user_id), &session.id).await?;
+    Ok(session)
+}
+
+/// 保存新会话。
+async fn save_session(session: &SessionRecord) -> Result<(), TokenError> {
+    kv_store().set(&session_key(&session.id), &serde_json::to_string(session)?, Some(ttl_secs())).await?;
+    Ok(())
+}
+
+/// 获取仍然有效的会话。会话不存在、已过期或早于用户当前的令牌代数时返回 `None`。
+pub async fn get_session(id: &str) -> Result<Option<SessionRecord>, TokenError> {
+    let Some(data) = kv_store().get(&session_key(id)).await? else {
+        return Ok(None);
+    };
+    let session: SessionRecord = serde_json::from_str(&data)?;
+    if session.generation < super::revoke::token_generation(session.user_id).await? {
+        revoke_session(&session).await?;
+        return Ok(None);
+    }
+    Ok(Some(session))
+}
+
+/// 更新会话的最后活跃时间，返回会话是否仍然存在。距上次更新不足一分钟时不写存储。
+///
+/// 只在会话记录仍然存在时写入，同时被吊销的会话返回 `false`。`extend` 为 `true` 时（刷新令牌时）
+/// 总是写入，并重新计算会话的有效期；否则保留原有效期。
+pub async fn touch_session(session: &mut SessionRecord, extend: bool) -> Result<bool, TokenError> {
+    let now = chrono::Utc::now().timestamp();
+    if !extend && now - session.last_seen < TOUCH_INTERVAL_SECS {
+        return Ok(true);
+    }
+    session.last_seen = now;
+    let ttl = extend.then(ttl_secs);
+    Ok(kv_store().set_existing(&session_key(&session.id), &serde_json::to_string(session)?, ttl).await?)
+}
+
+/// 列出用户所有仍然有效的会话，最近活跃的在前。已失效的会话会从用户的会话集合中移除。
+pub async fn list_sessions(user_id: u64) -> Result<Vec<SessionRecord>, TokenError> {
+    let store = kv_store();
+    let key = user_sessions_key(user_id);
+    let mut sessions = Vec::new();
+    for id in store.set_members(&key).await? {
+        match get_session(&id).await? {
+            Some(session) => sessions.push(session),
+            None => store.set_remove(&key, &id).await?,
+        }
+    }
+    sessions.sort_by_key(|session| std::cmp::Reverse(session.last_seen));
+    Ok(sessions)
+}
+
+/// 吊销会话，该会话的访问令牌和刷新令牌随即失效。
+pub async fn revoke_session(session: &SessionRecord) -> Result<(), TokenError> {
+    let store = kv_store();
+    store.delete(&session_key(&session.id)).await?;
+    store.set_remove(&user_sessions_key(session.user_id), &session.id).await?;
+    tracing::info!("revoked session {} of user {}", session.id, session.user_id);
+    Ok(())
+}
+
+/// 吊销用户的一个会话。会话不存在、已失效或不属于该用户时返回 `false`。
+pub async fn revoke_user_session(user_id: u64, id: &str) -> Result<bool, TokenError> {
+    match get_session(id).await? {
+        Some(session) if session.user_id == user_id => {
+            revoke_session(&session).await?;
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
@@ -0,0 +1,107 @@
+//! # 用户名和邮箱的规范形式
+//!
+//! 用户存储不直接用原始字符串做唯一索引，而是使用这里计算的键：
+//!
+//! - 用户名：去掉首尾空白，NFKC 规范化，再做大小写折叠，得到 [`canonical_username`]；
+//!   唯一索引使用它的 Unicode 混淆骨架 [`username_key`]（UTS #39），
+//!   因此 `Alice` 与 `alice`、`bob1` 与 `bobl`、拉丁 `alice` 与西里尔 `аlice` 都不能同时注册；
+//! - 邮箱：去掉首尾空白，NFKC 规范化，整体转为小写，得到 [`canonical_email`]。
+//!   不去掉本地部分的 `.` 和 `+` 标签，它们在多数邮件服务中是不同的地址。
+//!
+//! 用户记录中仍然保存用户输入的显示形式。按用户名查找时先用骨架命中索引，
+//! 再用 [`same_username`] 确认规范形式相同，避免用一个易混淆的名字登录另一个账户。
+//!
+//! 修改键的计算规则后必须增加 [`KEY_VERSION`]，各存储后端启动时发现版本不同会按新规则重建索引。
+
+use unicode_normalization::UnicodeNormalization;
+use unicode_security::skeleton;
+
+/// 键的计算规则的版本，保存在用户存储中，见 [`super::reindex_needed`]。
+pub const KEY_VERSION: &str = "1";
+
+/// 用户名的显示形式：去掉首尾空白并做 NFKC 规范化，保留大小写。注册时保存这个形式。
+pub fn display_username(username: &str) -> String {
+    username.trim().nfkc().collect()
+}
+
+/// 用户名的规范形式：显示形式再做大小写折叠。
+pub fn canonical_username(username: &str) -> String {
+    fold_case(&display_username(username))
+}
+
+/// 用户名的唯一索引键：规范形式的混淆骨架。
+pub fn username_key(username: &str) -> String {
+    let skeleton: String = skeleton(&canonical_username(username)).collect();
+    // 骨架映射的目标字符不一定是小写，再折叠一次保证大小写变体得到相同的键。
+    fold_case(&skeleton)
+}
+
+/// 两个用户名的规范形式是否相同。
+pub fn same_username(a: &str, b: &str) -> bool {
+    canonical_username(a) == canonical_username(b)
+}
+
+/// 邮箱的规范形式：NFKC 规范化并转为小写。
+pub fn canonical_email(email: &str) -> String {
+    email.trim().nfkc().collect::<String>().to_lowercase()
+}
+
+/// 大小写折叠：在 `to_lowercase` 的基础上补充常见的完整折叠（`ß` → `ss`、`ς` → `σ`）。
+fn fold_case(s: &str) -> String {
+    let mut folded = String::with_capacity(s.len());
+    for c in s.chars().flat_map(char::to_lowercase) {
+        match c {
+            'ß' | 'ẞ' => folded.push_str("ss"),
+            'ς' => folded.push('σ'),
+            c => folded.push(c),
+        }
+    }
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn usernames_fold_case() {
+        assert_eq!(canonical_username("Alice"), "alice");
+        assert_eq!(canonical_username("  ALICE \t"), "alice");
+        assert_eq!(username_key("Alice"), username_key("alice"));
+        assert!(same_username("Alice", "aLICE"));
+        assert_eq!(canonical_username("Straße"), canonical_username("STRASSE"));
+        assert_eq!(canonical_username("ΣΟΦΟΣ"), canonical_username("σοφος"));
+        assert_eq!(display_username(" Alice "), "Alice", "the display form keeps the case");
+    }
+
+    #[test]
+    fn usernames_are_nfkc_normalized() {
+        // 全角字母和数字。
+        assert_eq!(canonical_username("Ａｌｉｃｅ１"), "alice1");
+        assert_eq!(username_key("Ａｌｉｃｅ１"), username_key("alice1"));
+        assert_eq!(display_username("Ａｌｉｃｅ"), "Alice");
+        // 合字和组合字符。
+        assert_eq!(canonical_username("ﬁsh"), "fish");
+        assert_eq!(canonical_username("e\u{301}clair"), canonical_username("\u{e9}clair"));
+    }
+
+    #[test]
+    fn confusable_usernames_share_a_key() {
+        let cyrillic = "\u{430}lice";
+        assert_ne!(canonical_username(cyrillic), canonical_username("alice"));
+        assert!(!same_username(cyrillic, "alice"), "a confusable name does not match another account");
+        assert_eq!(username_key(cyrillic), username_key("alice"));
+        assert_eq!(username_key("\u{410}LICE"), username_key("alice"));
+        assert_eq!(username_key("bob1"), username_key("bobl"));
+        assert_eq!(username_key("paypal"), username_key("paypa1"));
+        assert_ne!(username_key("alice"), username_key("alicia"));
+    }
+
+    #[test]
+    fn emails_are_lowercased_only() {
+        assert_eq!(canonical_email(" Alice@Example.COM "), "alice@example.com");
+        assert_eq!(canonical_email("ａｌｉｃｅ@example.com"), "alice@example.com");
+        assert_ne!(canonical_email("a.lice@example.com"), canonical_email("alice@example.com"));
+        assert_ne!(canonical_email("alice+tag@example.com"), canonical_email("alice@example.com"));
+    }
+}
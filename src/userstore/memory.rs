@@ -0,0 +1,158 @@
+use std::collections::{ BTreeMap, HashMap };
+use std::sync::atomic::{ AtomicU64, Ordering };
+use std::sync::RwLock;
+
+use async_trait::async_trait;
+
+use super::canonical::{ canonical_email, same_username, username_key };
+use super::{ UserRecord, UserStore, UserStoreError, UserStoreResult };
+
+/// 进程内的用户存储，重启后数据丢失。
+#[derive(Default)]
+pub struct MemoryUserStore {
+    next_id: AtomicU64,
+    inner: RwLock<MemoryInner>,
+    /// 附属记录，以 `(kind, key)` 为键。
+    records: RwLock<BTreeMap<(String, String), String>>,
+}
+
+#[derive(Default)]
+struct MemoryInner {
+    users: BTreeMap<u64, UserRecord>,
+    /// 以 [`username_key`] 为键。
+    by_username: HashMap<String, u64>,
+    /// 以 [`canonical_email`] 为键。
+    by_email: HashMap<String, u64>,
+}
+
+impl MemoryUserStore {
+    /// 创建一个空的内存存储。
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl MemoryInner {
+    /// 检查用户名和邮箱是否被 `id` 以外的用户占用。
+    fn check_unique(&self, user: &UserRecord) -> UserStoreResult<()> {
+        if self.by_username.get(&username_key(&user.username)).is_some_and(|id| *id != user.id) {
+            return Err(UserStoreError::DuplicateUsername);
+        }
+        if let Some(email) = &user.email {
+            if self.by_email.get(&canonical_email(email)).is_some_and(|id| *id != user.id) {
+                return Err(UserStoreError::DuplicateEmail);
+            }
+        }
+        Ok(())
+    }
+
+    fn insert(&mut self, user: &UserRecord) {
+        self.by_username.insert(username_key(&user.username), user.id);
+        if let Some(email) = &user.email {
+            self.by_email.insert(canonical_email(email), user.id);
+        }
+        self.users.insert(user.id, user.clone());
+    }
+
+    fn remove(&mut self, id: u64) -> Option<UserRecord> {
+        let user = self.users.remove(&id)?;
+        self.by_username.remove(&username_key(&user.username));
+        if let Some(email) = &user.email {
+            self.by_email.remove(&canonical_email(email));
+        }
+        Some(user)
+    }
+}
+
+#[async_trait]
+impl UserStore for MemoryUserStore {
+    async fn next_id(&self) -> UserStoreResult<u64> {
+        Ok(self.next_id.fetch_add(1, Ordering::SeqCst) + 1)
+    }
+
+    async fn create(&self, user: &UserRecord) -> UserStoreResult<()> {
+        let mut inner = self.inner.write().unwrap();
+        inner.check_unique(user)?;
+        if inner.users.contains_key(&user.id) {
+            return Err(UserStoreError::Backend(format!("user id {} already exists", user.id)));
+        }
+        inner.insert(user);
+        Ok(())
+    }
+
+    async fn get_by_id(&self, id: u64) -> UserStoreResult<Option<UserRecord>> {
+        Ok(self.inner.read().unwrap().users.get(&id).cloned())
+    }
+
+    async fn get_by_username(&self, username: &str) -> UserStoreResult<Option<UserRecord>> {
+        let inner = self.inner.read().unwrap();
+        Ok(inner.by_username
+            .get(&username_key(username))
+            .and_then(|id| inner.users.get(id))
+            .filter(|user| same_username(&user.username, username))
+            .cloned())
+    }
+
+    async fn get_by_email(&self, email: &str) -> UserStoreResult<Option<UserRecord>> {
+        let inner = self.inner.read().unwrap();
+        Ok(inner.by_email.get(&canonical_email(email)).and_then(|id| inner.users.get(id)).cloned())
+    }
+
+    async fn update(&self, expected_version: u64, user: &UserRecord) -> UserStoreResult<()> {
+        let mut inner = self.inner.write().unwrap();
+        let current = inner.users.get(&user.id).ok_or(UserStoreError::NotFound)?;
+        if current.version != expected_version {
+            return Err(UserStoreError::Conflict);
+        }
+        inner.check_unique(user)?;
+        inner.remove(user.id);
+        inner.insert(&UserRecord { version: expected_version + 1, ..user.clone() });
+        Ok(())
+    }
+
+    async fn delete(&self, id: u64) -> UserStoreResult<()> {
+        self.inner.write().unwrap().remove(id).map(|_| ()).ok_or(UserStoreError::NotFound)
+    }
+
+    async fn delete_if(&self, id: u64, expected_version: u64) -> UserStoreResult<()> {
+        let mut inner = self.inner.write().unwrap();
+        let current = inner.users.get(&id).ok_or(UserStoreError::NotFound)?;
+        if current.version != expected_version {
+            return Err(UserStoreError::Conflict);
+        }
+        inner.remove(id);
+        Ok(())
+    }
+
+    async fn list(&self, offset: usize, limit: usize) -> UserStoreResult<Vec<UserRecord>> {
+        let inner = self.inner.read().unwrap();
+        Ok(inner.users.values().skip(offset).take(limit).cloned().collect())
+    }
+
+    async fn get_record(&self, kind: &str, key: &str) -> UserStoreResult<Option<String>> {
+        Ok(self.records.read().unwrap().get(&(kind.to_string(), key.to_string())).cloned())
+    }
+
+    async fn list_records(&self, kind: &str) -> UserStoreResult<Vec<(String, String)>> {
+        Ok(self.records
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|((record_kind, _), _)| record_kind == kind)
+            .map(|((_, key), value)| (key.clone(), value.clone()))
+            .collect())
+    }
+
+    async fn swap_record(&self, kind: &str, key: &str, expected: Option<&str>, value: Option<&str>) -> UserStoreResult<bool> {
+        let mut records = self.records.write().unwrap();
+        let id = (kind.to_string(), key.to_string());
+        if records.get(&id).map(String::as_str) != expected {
+            return Ok(false);
+        }
+        match value {
+            Some(value) => records.insert(id, value.to_string()),
+            None => records.remove(&id),
+        };
+        Ok(true)
+    }
+}
@@ -0,0 +1,372 @@
+//! # 用户存储
+//!
+//! 该模块定义了统一的异步用户存储接口 [`UserStore`]，以及以下后端实现：
+//!
+//! - [`memory::MemoryUserStore`]：进程内存储，始终可用，适合开发和测试；
+//! - `redis::RedisUserStore`：基于 Redis，需要开启 `redis-store` feature；
+//! - `postgres::PostgresUserStore`：基于 Postgres，需要开启 `postgres-store` feature；
+//! - `sqlite::SqliteUserStore`：基于 SQLite，需要开启 `sqlite-store` feature。
+//!
+//! 所有后端都以 JSON 保存完整的 [`UserRecord`]，并在用户名和邮箱上保证唯一性。
+//! 更新按记录版本比较并写入，见 [`modify`]。API 密钥等需要持久保存的附属记录也保存在用户存储中，
+//! 通过 [`UserStore::swap_record`] 原子地修改。
+//! 唯一性按 [`canonical`] 计算的规范形式判断：用户名不区分大小写和易混淆字符，邮箱不区分大小写。
+//! `tests/common/conformance.rs` 提供了所有后端都必须通过的一组行为检查。
+//!
+//! ## 选择后端
+//!
+//! 服务器启动时调用 [`init_user_store_from_env`]，根据环境变量 `BTCMWEB_USER_STORE`
+//! 的 URL 前缀选择后端：`redis://`、`postgres://`、`sqlite:`，未设置时使用内存存储。
+//!
+//! ## 分配用户 ID
+//!
+//! 新用户的 ID 由 [`next_user_id`] 分配：默认由后端的 [`UserStore::next_id`] 分配连续 ID；
+//! 配置 `ids.generator` 为 `snowflake` 时由 [`snowflake`] 在本机生成，多个实例不需要共享计数器。
+
+pub mod canonical;
+pub mod memory;
+#[cfg(feature = "postgres-store")]
+pub mod postgres;
+#[cfg(feature = "redis-store")]
+pub mod redis;
+pub mod snowflake;
+#[cfg(feature = "sqlite-store")]
+pub mod sqlite;
+
+use std::fmt;
+use std::sync::{ Arc, RwLock };
+
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use serde::{ Deserialize, Serialize };
+
+use crate::config::{ config, IdGenerator };
+
+/// 选择用户存储后端时读取的环境变量名。
+pub static USER_STORE_ENV_VAR: &str = "BTCMWEB_USER_STORE";
+
+/// 一个已注册的用户。
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UserRecord {
+    /// 用户 ID，同时作为 IM 网络层使用的 client id。
+    pub id: u64,
+    /// 用户名。
+    pub username: String,
+    /// 邮箱，可为空。
+    #[serde(default)]
+    pub email: Option<String>,
+    /// 邮箱是否已通过验证链接确认。修改邮箱后重置为 `false`。
+    #[serde(default)]
+    pub email_verified: bool,
+    /// 密码哈希（PHC 字符串），从不保存明文密码。
+    pub password_hash: String,
+    /// 创建时间（Unix 时间戳，秒）。
+    pub created_at: i64,
+    /// 最后修改时间（Unix 时间戳，秒）。
+    pub updated_at: i64,
+    /// 已启用的 TOTP 两步验证，未启用时为空。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub totp: Option<TotpRecord>,
+    /// 用户资料，见 [`crate::account::profile`]。
+    #[serde(default)]
+    pub profile: Profile,
+    /// 是否被管理员停用。停用的账户不能登录，也不能刷新令牌。
+    #[serde(default)]
+    pub disabled: bool,
+    /// 管理员分配的角色，见 [`crate::rbac`]。
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>,
+    /// 是否为服务账户。服务账户没有密码，不能登录，只能使用 API 密钥调用 JSON-RPC，
+    /// 见 [`crate::account::apikey`]。
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub service: bool,
+    /// 是否在等待管理员批准注册。等待批准的账户不能登录，见 [`crate::account::invite`]。
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pending_approval: bool,
+    /// 注册时使用的邀请码的创建者 ID。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invited_by: Option<u64>,
+    /// 注册时使用的邀请码。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invite_code: Option<String>,
+    /// 记录版本，每次写入加一，用于 [`UserStore::update`] 的比较并写入。
+    #[serde(default)]
+    pub version: u64,
+}
+
+/// 用户资料。各字段的规则见 [`crate::account::profile`]。
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    /// 显示名称，为空时客户端显示用户名。
+    pub display_name: Option<String>,
+    /// 头像地址（`https://`）。
+    pub avatar_url: Option<String>,
+    /// 个人简介。
+    pub bio: Option<String>,
+    /// 语言（BCP 47 语言标签，如 `zh-CN`），不公开。
+    pub locale: Option<String>,
+    /// 时区（IANA 时区名，如 `Asia/Shanghai`），不公开。
+    pub time_zone: Option<String>,
+    /// 状态文字。
+    pub status_text: Option<String>,
+    /// 资料版本，每次修改加一，用于乐观并发控制。
+    pub version: u64,
+}
+
+/// 用户已启用的 TOTP 两步验证。
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TotpRecord {
+    /// Base32 编码的 TOTP 密钥。
+    pub secret: String,
+    /// 最近一次成功使用的时间步，不接受不大于它的时间步，防止同一个密码被重放。
+    #[serde(default)]
+    pub last_step: u64,
+    /// 未使用的恢复码的 SHA-256 摘要（十六进制）。
+    #[serde(default)]
+    pub recovery_codes: Vec<String>,
+    /// 启用时间（Unix 时间戳，秒）。
+    pub enabled_at: i64,
+}
+
+/// 用户存储操作的错误。
+#[derive(Debug)]
+pub enum UserStoreError {
+    /// 用户名已被占用。
+    DuplicateUsername,
+    /// 邮箱已被占用。
+    DuplicateEmail,
+    /// 用户不存在。
+    NotFound,
+    /// 记录已被其他请求修改，版本与预期不符。
+    Conflict,
+    /// 后端错误（连接失败、数据损坏等）。
+    Backend(String),
+}
+
+impl fmt::Display for UserStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UserStoreError::DuplicateUsername => write!(f, "username already exists"),
+            UserStoreError::DuplicateEmail => write!(f, "email already exists"),
+            UserStoreError::NotFound => write!(f, "user not found"),
+            UserStoreError::Conflict => write!(f, "user record was modified concurrently"),
+            UserStoreError::Backend(message) => write!(f, "user store backend error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for UserStoreError {}
+
+impl From<serde_json::Error> for UserStoreError {
+    fn from(err: serde_json::Error) -> Self {
+        UserStoreError::Backend(format!("invalid user record: {}", err))
+    }
+}
+
+#[cfg(any(feature = "postgres-store", feature = "sqlite-store"))]
+impl From<sqlx::Error> for UserStoreError {
+    fn from(err: sqlx::Error) -> Self {
+        match &err {
+            sqlx::Error::Database(db) if db.is_unique_violation() => {
+                let constraint = db.constraint().unwrap_or_else(|| db.message());
+                if constraint.contains("email") {
+                    UserStoreError::DuplicateEmail
+                } else {
+                    UserStoreError::DuplicateUsername
+                }
+            }
+            _ => UserStoreError::Backend(err.to_string()),
+        }
+    }
+}
+
+/// 用户存储操作的结果类型。
+pub type UserStoreResult<T> = Result<T, UserStoreError>;
+
+/// 统一的异步用户存储接口。
+#[async_trait]
+pub trait UserStore: Send + Sync {
+    /// 分配一个新的用户 ID。
+    async fn next_id(&self) -> UserStoreResult<u64>;
+
+    /// 创建用户。用户名（按混淆骨架）或邮箱（按规范形式）已存在时返回对应的重复错误。
+    async fn create(&self, user: &UserRecord) -> UserStoreResult<()>;
+
+    /// 按 ID 获取用户。
+    async fn get_by_id(&self, id: u64) -> UserStoreResult<Option<UserRecord>>;
+
+    /// 按用户名获取用户，按规范形式匹配，见 [`canonical::canonical_username`]。
+    async fn get_by_username(&self, username: &str) -> UserStoreResult<Option<UserRecord>>;
+
+    /// 按邮箱获取用户，按规范形式匹配，见 [`canonical::canonical_email`]。
+    async fn get_by_email(&self, email: &str) -> UserStoreResult<Option<UserRecord>>;
+
+    /// 比较并写入用户：仅当已保存记录的版本等于 `expected_version` 时写入 `user`，
+    /// 写入的记录版本为 `expected_version + 1`（忽略 `user.version`），否则返回 [`UserStoreError::Conflict`]。
+    /// 修改后的用户名或邮箱与其他用户冲突时返回对应的重复错误。一般通过 [`modify`] 调用。
+    async fn update(&self, expected_version: u64, user: &UserRecord) -> UserStoreResult<()>;
+
+    /// 删除用户。
+    async fn delete(&self, id: u64) -> UserStoreResult<()>;
+
+    /// 比较并删除用户：仅当已保存记录的版本等于 `expected_version` 时删除，否则返回
+    /// [`UserStoreError::Conflict`]。用于先检查记录再删除的操作，避免删除检查之后被修改的记录。
+    async fn delete_if(&self, id: u64, expected_version: u64) -> UserStoreResult<()>;
+
+    /// 按 ID 升序分页列出用户。
+    async fn list(&self, offset: usize, limit: usize) -> UserStoreResult<Vec<UserRecord>>;
+
+    /// 读取一条附属记录，见 [`UserStore::swap_record`]。
+    async fn get_record(&self, kind: &str, key: &str) -> UserStoreResult<Option<String>>;
+
+    /// 按键升序列出某一类的全部附属记录，返回 `(键, 值)`。
+    async fn list_records(&self, kind: &str) -> UserStoreResult<Vec<(String, String)>>;
+
+    /// 比较并交换一条附属记录：当前值等于 `expected`（`None` 表示不存在）时替换为 `value`
+    /// （`None` 表示删除），返回是否替换。
+    ///
+    /// 附属记录是与用户一起持久保存的小型 JSON 数据，如 API 密钥和邀请码，按 `kind` 分组、以 `key` 区分。
+    async fn swap_record(&self, kind: &str, key: &str, expected: Option<&str>, value: Option<&str>) -> UserStoreResult<bool>;
+}
+
+lazy_static! {
+    /// 服务器使用的用户存储，默认为内存存储。
+    static ref USER_STORE: RwLock<Arc<dyn UserStore>> = RwLock::new(Arc::new(memory::MemoryUserStore::new()));
+}
+
+/// 获取当前使用的用户存储。
+pub fn user_store() -> Arc<dyn UserStore> {
+    USER_STORE.read().unwrap().clone()
+}
+
+/// 替换当前使用的用户存储。
+pub fn set_user_store(store: Arc<dyn UserStore>) {
+    *USER_STORE.write().unwrap() = store;
+}
+
+/// 根据环境变量 `BTCMWEB_USER_STORE` 初始化用户存储。
+///
+/// 未设置时保留内存存储；URL 对应的 feature 未开启时返回错误。
+pub async fn init_user_store_from_env() -> UserStoreResult<()> {
+    let Ok(url) = std::env::var(USER_STORE_ENV_VAR) else {
+        return Ok(());
+    };
+    set_user_store(connect(&url).await?);
+    Ok(())
+}
+
+/// 存储元数据的附属记录类型。
+const META_KIND: &str = "meta";
+/// 保存索引键版本（[`canonical::KEY_VERSION`]）的附属记录的键。
+const KEY_VERSION_RECORD: &str = "key_version";
+
+/// 存储中的索引是否按旧的键规则建立（包括从未记录过版本的旧数据），需要重建。
+pub(crate) async fn reindex_needed(store: &dyn UserStore) -> UserStoreResult<bool> {
+    Ok(store.get_record(META_KIND, KEY_VERSION_RECORD).await?.as_deref() != Some(canonical::KEY_VERSION))
+}
+
+/// 记录索引已按当前的键规则重建。
+pub(crate) async fn mark_reindexed(store: &dyn UserStore) -> UserStoreResult<()> {
+    let current = store.get_record(META_KIND, KEY_VERSION_RECORD).await?;
+    store.swap_record(META_KIND, KEY_VERSION_RECORD, current.as_deref(), Some(canonical::KEY_VERSION)).await?;
+    Ok(())
+}
+
+/// 一个用户的索引键：`(ID, 用户名键, 邮箱键)`。
+pub(crate) type IndexKeys = (u64, String, Option<String>);
+
+/// 按当前规则计算所有用户的索引键。两个用户得到相同的键时返回错误并指出是哪两个用户，
+/// 需要先手动改名或修改邮箱再启动。
+pub(crate) fn index_keys(users: &[UserRecord]) -> UserStoreResult<Vec<IndexKeys>> {
+    let mut usernames = std::collections::HashMap::new();
+    let mut emails = std::collections::HashMap::new();
+    let mut keys = Vec::with_capacity(users.len());
+    for user in users {
+        let username = canonical::username_key(&user.username);
+        let email = user.email.as_deref().map(canonical::canonical_email);
+        if let Some(other) = usernames.insert(username.clone(), user.id) {
+            return Err(UserStoreError::Backend(format!(
+                "users {} and {} have the same canonical username, rename one of them before upgrading", other, user.id
+            )));
+        }
+        if let Some(email) = &email {
+            if let Some(other) = emails.insert(email.clone(), user.id) {
+                return Err(UserStoreError::Backend(format!(
+                    "users {} and {} have the same canonical email, change one of them before upgrading", other, user.id
+                )));
+            }
+        }
+        keys.push((user.id, username, email));
+    }
+    Ok(keys)
+}
+
+/// [`modify`] 因版本冲突重新读取的最多次数。
+pub(crate) const MAX_MODIFY_RETRIES: usize = 16;
+
+/// 读取用户，用 `change` 修改后按版本比较并写回，返回写入后的记录。
+///
+/// 写回前记录被其他请求修改时重新读取并再次调用 `change`，因此 `change` 只应修改自己负责的字段，
+/// 并在每次调用时重新检查前置条件。`change` 返回 `Ok(false)` 表示无需修改，此时不写入，返回读取到的记录。
+/// 用户不存在时返回 [`UserStoreError::NotFound`] 转换成的错误。
+pub async fn modify<E, F>(store: &dyn UserStore, id: u64, mut change: F) -> Result<UserRecord, E>
+where
+    E: From<UserStoreError>,
+    F: FnMut(&mut UserRecord) -> Result<bool, E>,
+{
+    for _ in 0..MAX_MODIFY_RETRIES {
+        let mut user = store.get_by_id(id).await?.ok_or(UserStoreError::NotFound)?;
+        if !change(&mut user)? {
+            return Ok(user);
+        }
+        user.updated_at = chrono::Utc::now().timestamp();
+        let expected_version = user.version;
+        match store.update(expected_version, &user).await {
+            Ok(()) => {
+                user.version = expected_version + 1;
+                return Ok(user);
+            }
+            Err(UserStoreError::Conflict) => continue,
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Err(UserStoreError::Conflict.into())
+}
+
+/// 为新用户分配 ID，分配方式见配置 `ids.generator`。
+pub async fn next_user_id(store: &dyn UserStore) -> UserStoreResult<u64> {
+    match config().ids.generator {
+        IdGenerator::Store => store.next_id().await,
+        IdGenerator::Snowflake => Ok(snowflake::generator()?.next_id()),
+    }
+}
+
+/// 检查 ID 分配配置，使用 snowflake 时节点 ID 无效则返回错误。服务器启动时调用。
+pub fn init_id_generator() -> UserStoreResult<()> {
+    if config().ids.generator == IdGenerator::Snowflake {
+        let generator = snowflake::generator()?;
+        tracing::info!("allocating snowflake user ids as node {}", generator.node_id());
+    }
+    Ok(())
+}
+
+/// 根据 URL 前缀连接对应的用户存储后端。
+pub async fn connect(url: &str) -> UserStoreResult<Arc<dyn UserStore>> {
+    if url == "memory" {
+        return Ok(Arc::new(memory::MemoryUserStore::new()));
+    }
+    #[cfg(feature = "redis-store")]
+    if url.starts_with("redis://") || url.starts_with("rediss://") {
+        return Ok(Arc::new(redis::RedisUserStore::connect(url).await?));
+    }
+    #[cfg(feature = "postgres-store")]
+    if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+        return Ok(Arc::new(postgres::PostgresUserStore::connect(url).await?));
+    }
+    #[cfg(feature = "sqlite-store")]
+    if url.starts_with("sqlite:") {
+        return Ok(Arc::new(sqlite::SqliteUserStore::connect(url).await?));
+    }
+    Err(UserStoreError::Backend(format!("unsupported user store url: {}", url)))
+}
@@ -0,0 +1,213 @@
+use async_trait::async_trait;
+use sqlx::{ postgres::PgPoolOptions, PgPool };
+
+use super::canonical::{ canonical_email, same_username, username_key };
+use super::{ index_keys, mark_reindexed, reindex_needed, UserRecord, UserStore, UserStoreError, UserStoreResult };
+
+/// 建表语句。完整记录以 JSON 保存在 `data` 列中，`username` 和 `email` 列保存显示形式，
+/// 唯一性由 `username_key` 和 `email_key` 列上的唯一索引保证，它们保存 [`super::canonical`] 计算的键。
+/// `version` 列与记录的版本相同，用于更新时比较并写入。附属记录保存在 `btcm_records` 表中。
+const SCHEMA: [&str; 10] = [
+    "CREATE TABLE IF NOT EXISTS btcm_users (
+        id           BIGINT PRIMARY KEY,
+        username     TEXT NOT NULL,
+        email        TEXT,
+        username_key TEXT,
+        email_key    TEXT,
+        data         TEXT NOT NULL,
+        version      BIGINT NOT NULL DEFAULT 0
+    )",
+    "CREATE SEQUENCE IF NOT EXISTS btcm_users_id_seq",
+    // 早期版本创建的表没有 version 列和规范键列，用户名和邮箱列上有唯一约束。
+    "ALTER TABLE btcm_users ADD COLUMN IF NOT EXISTS version BIGINT NOT NULL DEFAULT 0",
+    "ALTER TABLE btcm_users ADD COLUMN IF NOT EXISTS username_key TEXT",
+    "ALTER TABLE btcm_users ADD COLUMN IF NOT EXISTS email_key TEXT",
+    "ALTER TABLE btcm_users DROP CONSTRAINT IF EXISTS btcm_users_username_key",
+    "ALTER TABLE btcm_users DROP CONSTRAINT IF EXISTS btcm_users_email_key",
+    "CREATE UNIQUE INDEX IF NOT EXISTS btcm_users_username_canonical ON btcm_users (username_key)",
+    "CREATE UNIQUE INDEX IF NOT EXISTS btcm_users_email_canonical ON btcm_users (email_key)",
+    "CREATE TABLE IF NOT EXISTS btcm_records (
+        kind TEXT NOT NULL,
+        key  TEXT NOT NULL,
+        data TEXT NOT NULL,
+        PRIMARY KEY (kind, key)
+    )",
+];
+
+/// 基于 Postgres 的用户存储。
+pub struct PostgresUserStore {
+    pool: PgPool,
+}
+
+impl PostgresUserStore {
+    /// 连接到指定的数据库，并在需要时创建表。
+    pub async fn connect(url: &str) -> UserStoreResult<Self> {
+        let pool = PgPoolOptions::new().connect(url).await?;
+        for statement in SCHEMA {
+            sqlx::query(statement).execute(&pool).await?;
+        }
+        let store = PostgresUserStore { pool };
+        let unindexed: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM btcm_users WHERE username_key IS NULL)")
+            .fetch_one(&store.pool).await?;
+        if unindexed || reindex_needed(&store).await? {
+            store.reindex().await?;
+        }
+        Ok(store)
+    }
+
+    /// 按当前规则重新计算所有用户的键列，并把 `username` 和 `email` 列改为显示形式。
+    /// 在一个锁住用户表的事务中完成，两个用户的键相同时返回错误，不做任何修改。
+    async fn reindex(&self) -> UserStoreResult<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("LOCK TABLE btcm_users IN EXCLUSIVE MODE").execute(&mut *tx).await?;
+        let data: Vec<String> = sqlx::query_scalar("SELECT data FROM btcm_users").fetch_all(&mut *tx).await?;
+        let users = data.iter().map(|data| serde_json::from_str(data)).collect::<Result<Vec<UserRecord>, _>>()?;
+        let keys = index_keys(&users)?;
+        // 先清空，避免按新规则写入时与尚未更新的行冲突。
+        sqlx::query("UPDATE btcm_users SET username_key = NULL, email_key = NULL").execute(&mut *tx).await?;
+        for (user, (id, username_key, email_key)) in users.iter().zip(keys) {
+            sqlx::query("UPDATE btcm_users SET username = $2, email = $3, username_key = $4, email_key = $5 WHERE id = $1")
+                .bind(id as i64)
+                .bind(&user.username)
+                .bind(&user.email)
+                .bind(username_key)
+                .bind(email_key)
+                .execute(&mut *tx).await?;
+        }
+        tx.commit().await?;
+        mark_reindexed(self).await?;
+        tracing::info!("rebuilt canonical username and email keys of {} users", users.len());
+        Ok(())
+    }
+
+    async fn get_where(&self, column: &str, value: &str) -> UserStoreResult<Option<UserRecord>> {
+        let sql = format!("SELECT data FROM btcm_users WHERE {} = $1", column);
+        let data: Option<String> = sqlx::query_scalar(&sql).bind(value).fetch_optional(&self.pool).await?;
+        Ok(data.map(|data| serde_json::from_str(&data)).transpose()?)
+    }
+}
+
+#[async_trait]
+impl UserStore for PostgresUserStore {
+    async fn next_id(&self) -> UserStoreResult<u64> {
+        let id: i64 = sqlx::query_scalar("SELECT nextval('btcm_users_id_seq')").fetch_one(&self.pool).await?;
+        Ok(id as u64)
+    }
+
+    async fn create(&self, user: &UserRecord) -> UserStoreResult<()> {
+        sqlx::query(
+            "INSERT INTO btcm_users (id, username, email, username_key, email_key, data, version)
+             VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        )
+            .bind(user.id as i64)
+            .bind(&user.username)
+            .bind(&user.email)
+            .bind(username_key(&user.username))
+            .bind(user.email.as_deref().map(canonical_email))
+            .bind(serde_json::to_string(user)?)
+            .bind(user.version as i64)
+            .execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn get_by_id(&self, id: u64) -> UserStoreResult<Option<UserRecord>> {
+        let data: Option<String> = sqlx::query_scalar("SELECT data FROM btcm_users WHERE id = $1")
+            .bind(id as i64)
+            .fetch_optional(&self.pool).await?;
+        Ok(data.map(|data| serde_json::from_str(&data)).transpose()?)
+    }
+
+    async fn get_by_username(&self, username: &str) -> UserStoreResult<Option<UserRecord>> {
+        let user = self.get_where("username_key", &username_key(username)).await?;
+        Ok(user.filter(|user| same_username(&user.username, username)))
+    }
+
+    async fn get_by_email(&self, email: &str) -> UserStoreResult<Option<UserRecord>> {
+        self.get_where("email_key", &canonical_email(email)).await
+    }
+
+    async fn update(&self, expected_version: u64, user: &UserRecord) -> UserStoreResult<()> {
+        let version = expected_version + 1;
+        let result = sqlx::query(
+            "UPDATE btcm_users SET username = $2, email = $3, username_key = $4, email_key = $5, data = $6, version = $7
+             WHERE id = $1 AND version = $8",
+        )
+            .bind(user.id as i64)
+            .bind(&user.username)
+            .bind(&user.email)
+            .bind(username_key(&user.username))
+            .bind(user.email.as_deref().map(canonical_email))
+            .bind(serde_json::to_string(&UserRecord { version, ..user.clone() })?)
+            .bind(version as i64)
+            .bind(expected_version as i64)
+            .execute(&self.pool).await?;
+        if result.rows_affected() == 0 {
+            return match self.get_by_id(user.id).await? {
+                Some(_) => Err(UserStoreError::Conflict),
+                None => Err(UserStoreError::NotFound),
+            };
+        }
+        Ok(())
+    }
+
+    async fn delete(&self, id: u64) -> UserStoreResult<()> {
+        let result = sqlx::query("DELETE FROM btcm_users WHERE id = $1")
+            .bind(id as i64)
+            .execute(&self.pool).await?;
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn delete_if(&self, id: u64, expected_version: u64) -> UserStoreResult<()> {
+        let result = sqlx::query("DELETE FROM btcm_users WHERE id = $1 AND version = $2")
+            .bind(id as i64)
+            .bind(expected_version as i64)
+            .execute(&self.pool).await?;
+        if result.rows_affected() == 0 {
+            return match self.get_by_id(id).await? {
+                Some(_) => Err(UserStoreError::Conflict),
+                None => Err(UserStoreError::NotFound),
+            };
+        }
+        Ok(())
+    }
+
+    async fn list(&self, offset: usize, limit: usize) -> UserStoreResult<Vec<UserRecord>> {
+        let data: Vec<String> = sqlx::query_scalar("SELECT data FROM btcm_users ORDER BY id LIMIT $1 OFFSET $2")
+            .bind(limit as i64)
+            .bind(offset as i64)
+            .fetch_all(&self.pool).await?;
+        data.iter().map(|data| serde_json::from_str(data).map_err(UserStoreError::from)).collect()
+    }
+
+    async fn get_record(&self, kind: &str, key: &str) -> UserStoreResult<Option<String>> {
+        Ok(sqlx::query_scalar("SELECT data FROM btcm_records WHERE kind = $1 AND key = $2")
+            .bind(kind)
+            .bind(key)
+            .fetch_optional(&self.pool).await?)
+    }
+
+    async fn list_records(&self, kind: &str) -> UserStoreResult<Vec<(String, String)>> {
+        Ok(sqlx::query_as("SELECT key, data FROM btcm_records WHERE kind = $1 ORDER BY key")
+            .bind(kind)
+            .fetch_all(&self.pool).await?)
+    }
+
+    async fn swap_record(&self, kind: &str, key: &str, expected: Option<&str>, value: Option<&str>) -> UserStoreResult<bool> {
+        let query = match (expected, value) {
+            (None, None) => return Ok(self.get_record(kind, key).await?.is_none()),
+            (None, Some(value)) => sqlx::query(
+                "INSERT INTO btcm_records (kind, key, data) VALUES ($1, $2, $3) ON CONFLICT (kind, key) DO NOTHING",
+            ).bind(kind).bind(key).bind(value),
+            (Some(expected), Some(value)) => sqlx::query(
+                "UPDATE btcm_records SET data = $1 WHERE kind = $2 AND key = $3 AND data = $4",
+            ).bind(value).bind(kind).bind(key).bind(expected),
+            (Some(expected), None) => sqlx::query(
+                "DELETE FROM btcm_records WHERE kind = $1 AND key = $2 AND data = $3",
+            ).bind(kind).bind(key).bind(expected),
+        };
+        Ok(query.execute(&self.pool).await?.rows_affected() == 1)
+    }
+}
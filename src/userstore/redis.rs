@@ -0,0 +1,368 @@
+//! 基于 Redis 的用户存储。
+//!
+//! 创建、更新和删除都由一个 Lua 脚本完成，检查唯一性索引和写入记录在 Redis 服务器上原子执行，
+//! 并发注册同一个用户名时只有一个会成功，也不会留下只写了一半的索引。
+//! 更新和删除在脚本中比较记录的当前值。更新只在读取到的记录版本等于预期版本时写入，
+//! 记录在读取之后被其他请求修改、版本已变化时返回 [`UserStoreError::Conflict`]，不会用旧记录覆盖新记录；
+//! 删除在记录被并发修改时重新读取并重试。
+//!
+//! 连接时如果索引是按旧的键规则（见 [`super::canonical::KEY_VERSION`]）建立的，
+//! 会读取全部用户、按当前规则在临时键中重建用户名和邮箱索引，再原子地替换旧索引。
+//! 重建期间其他实例的写入可能丢失索引，应在升级时先单独启动一个实例完成重建。
+//!
+//! 所有键都不带 hash tag，不支持 Redis Cluster。
+
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use redis::{ aio::MultiplexedConnection, AsyncCommands, RedisError, Script };
+
+use super::canonical::{ canonical_email, same_username, username_key };
+use super::{ index_keys, mark_reindexed, reindex_needed, UserRecord, UserStore, UserStoreError, UserStoreResult };
+
+/// 用户记录键前缀，完整键为 `btcm:user:{id}`，值为 JSON。
+const USER_KEY_PREFIX: &str = "btcm:user:";
+/// 用户名到用户 ID 的哈希表，字段为 [`username_key`]。
+const USERNAME_INDEX: &str = "btcm:users:username";
+/// 邮箱到用户 ID 的哈希表，字段为 [`canonical_email`]。
+const EMAIL_INDEX: &str = "btcm:users:email";
+/// 重建索引时使用的临时键的后缀。
+const REBUILD_SUFFIX: &str = ":rebuild";
+/// 按 ID 排序的用户集合，用于分页。
+const ID_INDEX: &str = "btcm:users:ids";
+/// 用户 ID 计数器。
+const ID_COUNTER: &str = "btcm:users:next_id";
+/// 附属记录哈希表的键前缀，完整键为 `btcm:records:{kind}`，字段为记录的键。
+const RECORDS_KEY_PREFIX: &str = "btcm:records:";
+/// 更新和删除因记录被并发修改而重试的最多次数。
+const MAX_RETRIES: usize = 16;
+
+lazy_static! {
+    /// 创建用户。
+    ///
+    /// KEYS：用户记录、用户名索引、邮箱索引、ID 集合；
+    /// ARGV：ID、用户名键、邮箱键（无邮箱时为空串）、记录 JSON。
+    static ref CREATE_SCRIPT: Script = Script::new(r"
+        if redis.call('EXISTS', KEYS[1]) == 1 then return 'exists' end
+        if redis.call('HEXISTS', KEYS[2], ARGV[2]) == 1 then return 'username' end
+        if ARGV[3] ~= '' and redis.call('HEXISTS', KEYS[3], ARGV[3]) == 1 then return 'email' end
+        redis.call('HSET', KEYS[2], ARGV[2], ARGV[1])
+        if ARGV[3] ~= '' then redis.call('HSET', KEYS[3], ARGV[3], ARGV[1]) end
+        redis.call('SET', KEYS[1], ARGV[4])
+        redis.call('ZADD', KEYS[4], ARGV[1], ARGV[1])
+        return 'ok'
+    ");
+
+    /// 更新用户，记录的当前值必须仍是读取时的值。
+    ///
+    /// KEYS：用户记录、用户名索引、邮箱索引；
+    /// ARGV：ID、旧记录 JSON、新记录 JSON、旧用户名键、新用户名键、旧邮箱键、新邮箱键。
+    static ref UPDATE_SCRIPT: Script = Script::new(r"
+        local current = redis.call('GET', KEYS[1])
+        if not current then return 'missing' end
+        if current ~= ARGV[2] then return 'retry' end
+        if ARGV[5] ~= ARGV[4] then
+            local owner = redis.call('HGET', KEYS[2], ARGV[5])
+            if owner and owner ~= ARGV[1] then return 'username' end
+        end
+        if ARGV[7] ~= ARGV[6] and ARGV[7] ~= '' then
+            local owner = redis.call('HGET', KEYS[3], ARGV[7])
+            if owner and owner ~= ARGV[1] then return 'email' end
+        end
+        if ARGV[5] ~= ARGV[4] then
+            redis.call('HDEL', KEYS[2], ARGV[4])
+            redis.call('HSET', KEYS[2], ARGV[5], ARGV[1])
+        end
+        if ARGV[7] ~= ARGV[6] then
+            if ARGV[6] ~= '' then redis.call('HDEL', KEYS[3], ARGV[6]) end
+            if ARGV[7] ~= '' then redis.call('HSET', KEYS[3], ARGV[7], ARGV[1]) end
+        end
+        redis.call('SET', KEYS[1], ARGV[3])
+        return 'ok'
+    ");
+
+    /// 删除用户，记录的当前值必须仍是读取时的值。只释放仍指向该用户的索引。
+    ///
+    /// KEYS：用户记录、用户名索引、邮箱索引、ID 集合；
+    /// ARGV：ID、记录 JSON、用户名键、邮箱键。
+    static ref DELETE_SCRIPT: Script = Script::new(r"
+        local current = redis.call('GET', KEYS[1])
+        if not current then return 'missing' end
+        if current ~= ARGV[2] then return 'retry' end
+        redis.call('DEL', KEYS[1])
+        redis.call('ZREM', KEYS[4], ARGV[1])
+        if redis.call('HGET', KEYS[2], ARGV[3]) == ARGV[1] then redis.call('HDEL', KEYS[2], ARGV[3]) end
+        if ARGV[4] ~= '' and redis.call('HGET', KEYS[3], ARGV[4]) == ARGV[1] then
+            redis.call('HDEL', KEYS[3], ARGV[4])
+        end
+        return 'ok'
+    ");
+}
+
+lazy_static! {
+    /// 比较并交换一条附属记录。
+    ///
+    /// KEYS：附属记录哈希表；
+    /// ARGV：记录的键、是否期望存在（`1`/`0`）、期望值、是否写入（`1`/`0`）、新值。
+    static ref SWAP_RECORD_SCRIPT: Script = Script::new(r"
+        local current = redis.call('HGET', KEYS[1], ARGV[1])
+        if ARGV[2] == '1' then
+            if current ~= ARGV[3] then return 0 end
+        elseif current then
+            return 0
+        end
+        if ARGV[4] == '1' then
+            redis.call('HSET', KEYS[1], ARGV[1], ARGV[5])
+        else
+            redis.call('HDEL', KEYS[1], ARGV[1])
+        end
+        return 1
+    ");
+}
+
+/// 用户记录的邮箱索引键，没有邮箱时为空串。
+fn email_key(user: &UserRecord) -> String {
+    user.email.as_deref().map(canonical_email).unwrap_or_default()
+}
+
+impl From<RedisError> for UserStoreError {
+    fn from(err: RedisError) -> Self {
+        UserStoreError::Backend(err.to_string())
+    }
+}
+
+/// 把脚本返回的状态转换为结果，`retry` 返回 `Ok(false)`。
+fn script_outcome(status: &str) -> UserStoreResult<bool> {
+    match status {
+        "ok" => Ok(true),
+        "retry" => Ok(false),
+        "username" => Err(UserStoreError::DuplicateUsername),
+        "email" => Err(UserStoreError::DuplicateEmail),
+        "missing" => Err(UserStoreError::NotFound),
+        status => Err(UserStoreError::Backend(format!("unexpected script result: {}", status))),
+    }
+}
+
+/// 基于 Redis 的用户存储。
+pub struct RedisUserStore {
+    con: MultiplexedConnection,
+}
+
+impl RedisUserStore {
+    /// 连接到指定的 Redis 服务器。
+    pub async fn connect(url: &str) -> UserStoreResult<Self> {
+        let client = redis::Client::open(url)?;
+        let con = client.get_multiplexed_tokio_connection().await?;
+        let store = RedisUserStore { con };
+        if reindex_needed(&store).await? {
+            store.reindex().await?;
+        }
+        Ok(store)
+    }
+
+    /// 按当前规则重建用户名和邮箱索引。两个用户的键相同时返回错误，不修改旧索引。
+    async fn reindex(&self) -> UserStoreResult<()> {
+        let mut con = self.con.clone();
+        let ids: Vec<u64> = con.zrange(ID_INDEX, 0, -1).await?;
+        let mut users = Vec::with_capacity(ids.len());
+        for id in ids {
+            if let Some(user) = self.get_by_id(id).await? {
+                users.push(user);
+            }
+        }
+        let keys = index_keys(&users)?;
+
+        let usernames = format!("{}{}", USERNAME_INDEX, REBUILD_SUFFIX);
+        let emails = format!("{}{}", EMAIL_INDEX, REBUILD_SUFFIX);
+        let mut fill = redis::pipe();
+        fill.del(&usernames).ignore().del(&emails).ignore();
+        for (id, username_key, email_key) in &keys {
+            fill.hset(&usernames, username_key, id).ignore();
+            if let Some(email_key) = email_key {
+                fill.hset(&emails, email_key, id).ignore();
+            }
+        }
+        fill.query_async::<_, ()>(&mut con).await?;
+
+        // RENAME 不接受不存在的源键，没有用户或没有邮箱时直接删除旧索引。
+        let mut swap = redis::pipe();
+        swap.atomic();
+        for (index, rebuilt, empty) in [
+            (USERNAME_INDEX, &usernames, keys.is_empty()),
+            (EMAIL_INDEX, &emails, keys.iter().all(|(_, _, email)| email.is_none())),
+        ] {
+            if empty {
+                swap.del(index).ignore();
+            } else {
+                swap.rename(rebuilt, index).ignore();
+            }
+        }
+        swap.query_async::<_, ()>(&mut con).await?;
+        mark_reindexed(self).await?;
+        tracing::info!("rebuilt username and email indexes of {} users", users.len());
+        Ok(())
+    }
+
+    fn user_key(id: u64) -> String {
+        format!("{}{}", USER_KEY_PREFIX, id)
+    }
+
+    /// 通过索引哈希表查找用户。
+    async fn get_by_index(&self, index: &str, field: &str) -> UserStoreResult<Option<UserRecord>> {
+        let mut con = self.con.clone();
+        let id: Option<u64> = con.hget(index, field).await?;
+        match id {
+            Some(id) => self.get_by_id(id).await,
+            None => Ok(None),
+        }
+    }
+
+    /// 读取记录的原始 JSON，用于脚本中比较当前值。
+    async fn get_raw(&self, id: u64) -> UserStoreResult<Option<String>> {
+        let mut con = self.con.clone();
+        Ok(con.get(Self::user_key(id)).await?)
+    }
+
+    /// 删除用户及其索引；给出 `expected_version` 时仅在版本一致时删除。
+    async fn delete_checked(&self, id: u64, expected_version: Option<u64>) -> UserStoreResult<()> {
+        for _ in 0..MAX_RETRIES {
+            let raw = self.get_raw(id).await?.ok_or(UserStoreError::NotFound)?;
+            let user: UserRecord = serde_json::from_str(&raw)?;
+            if expected_version.is_some_and(|version| version != user.version) {
+                return Err(UserStoreError::Conflict);
+            }
+            let mut con = self.con.clone();
+            let status: String = DELETE_SCRIPT
+                .key(Self::user_key(id))
+                .key(USERNAME_INDEX)
+                .key(EMAIL_INDEX)
+                .key(ID_INDEX)
+                .arg(id)
+                .arg(&raw)
+                .arg(username_key(&user.username))
+                .arg(email_key(&user))
+                .invoke_async(&mut con).await?;
+            if script_outcome(&status)? {
+                return Ok(());
+            }
+        }
+        Err(UserStoreError::Backend(format!("user {} is being modified concurrently", id)))
+    }
+}
+
+#[async_trait]
+impl UserStore for RedisUserStore {
+    async fn next_id(&self) -> UserStoreResult<u64> {
+        let mut con = self.con.clone();
+        Ok(con.incr(ID_COUNTER, 1).await?)
+    }
+
+    async fn create(&self, user: &UserRecord) -> UserStoreResult<()> {
+        let mut con = self.con.clone();
+        let status: String = CREATE_SCRIPT
+            .key(Self::user_key(user.id))
+            .key(USERNAME_INDEX)
+            .key(EMAIL_INDEX)
+            .key(ID_INDEX)
+            .arg(user.id)
+            .arg(username_key(&user.username))
+            .arg(email_key(user))
+            .arg(serde_json::to_string(user)?)
+            .invoke_async(&mut con).await?;
+        if status == "exists" {
+            return Err(UserStoreError::Backend(format!("user id {} already exists", user.id)));
+        }
+        script_outcome(&status).map(|_| ())
+    }
+
+    async fn get_by_id(&self, id: u64) -> UserStoreResult<Option<UserRecord>> {
+        let data = self.get_raw(id).await?;
+        Ok(data.map(|data| serde_json::from_str(&data)).transpose()?)
+    }
+
+    async fn get_by_username(&self, username: &str) -> UserStoreResult<Option<UserRecord>> {
+        let user = self.get_by_index(USERNAME_INDEX, &username_key(username)).await?;
+        Ok(user.filter(|user| same_username(&user.username, username)))
+    }
+
+    async fn get_by_email(&self, email: &str) -> UserStoreResult<Option<UserRecord>> {
+        self.get_by_index(EMAIL_INDEX, &canonical_email(email)).await
+    }
+
+    async fn update(&self, expected_version: u64, user: &UserRecord) -> UserStoreResult<()> {
+        let data = serde_json::to_string(&UserRecord { version: expected_version + 1, ..user.clone() })?;
+        for _ in 0..MAX_RETRIES {
+            let raw = self.get_raw(user.id).await?.ok_or(UserStoreError::NotFound)?;
+            let old: UserRecord = serde_json::from_str(&raw)?;
+            if old.version != expected_version {
+                return Err(UserStoreError::Conflict);
+            }
+            let mut con = self.con.clone();
+            let status: String = UPDATE_SCRIPT
+                .key(Self::user_key(user.id))
+                .key(USERNAME_INDEX)
+                .key(EMAIL_INDEX)
+                .arg(user.id)
+                .arg(&raw)
+                .arg(&data)
+                .arg(username_key(&old.username))
+                .arg(username_key(&user.username))
+                .arg(email_key(&old))
+                .arg(email_key(user))
+                .invoke_async(&mut con).await?;
+            if script_outcome(&status)? {
+                return Ok(());
+            }
+        }
+        Err(UserStoreError::Conflict)
+    }
+
+    async fn delete(&self, id: u64) -> UserStoreResult<()> {
+        self.delete_checked(id, None).await
+    }
+
+    async fn delete_if(&self, id: u64, expected_version: u64) -> UserStoreResult<()> {
+        self.delete_checked(id, Some(expected_version)).await
+    }
+
+    async fn list(&self, offset: usize, limit: usize) -> UserStoreResult<Vec<UserRecord>> {
+        if limit == 0 {
+            return Ok(Vec::new());
+        }
+        let mut con = self.con.clone();
+        let ids: Vec<u64> = con.zrange(ID_INDEX, offset as isize, (offset + limit - 1) as isize).await?;
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let keys: Vec<String> = ids.into_iter().map(Self::user_key).collect();
+        let data: Vec<Option<String>> = redis::cmd("MGET").arg(&keys).query_async(&mut con).await?;
+        data.into_iter()
+            .flatten()
+            .map(|data| serde_json::from_str(&data).map_err(UserStoreError::from))
+            .collect()
+    }
+
+    async fn get_record(&self, kind: &str, key: &str) -> UserStoreResult<Option<String>> {
+        let mut con = self.con.clone();
+        Ok(con.hget(format!("{}{}", RECORDS_KEY_PREFIX, kind), key).await?)
+    }
+
+    async fn list_records(&self, kind: &str) -> UserStoreResult<Vec<(String, String)>> {
+        let mut con = self.con.clone();
+        let mut records: Vec<(String, String)> = con.hgetall(format!("{}{}", RECORDS_KEY_PREFIX, kind)).await?;
+        records.sort();
+        Ok(records)
+    }
+
+    async fn swap_record(&self, kind: &str, key: &str, expected: Option<&str>, value: Option<&str>) -> UserStoreResult<bool> {
+        let mut con = self.con.clone();
+        let swapped: i64 = SWAP_RECORD_SCRIPT
+            .key(format!("{}{}", RECORDS_KEY_PREFIX, kind))
+            .arg(key)
+            .arg(if expected.is_some() { "1" } else { "0" })
+            .arg(expected.unwrap_or_default())
+            .arg(if value.is_some() { "1" } else { "0" })
+            .arg(value.unwrap_or_default())
+            .invoke_async(&mut con).await?;
+        Ok(swapped == 1)
+    }
+}
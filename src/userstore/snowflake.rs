@@ -0,0 +1,246 @@
+//! # Snowflake 用户 ID
+//!
+//! 配置 `ids.generator` 为 `snowflake` 时，新用户的 ID 由本机生成，不访问用户存储。
+//! 一个 ID 是 53 位的正整数，不超过 JavaScript 的 `Number.MAX_SAFE_INTEGER`，
+//! 客户端可以直接按 JSON 数字解析而不丢失精度。从高到低依次为：
+//!
+//! - 41 位时间戳：距配置 `ids.epoch_ms` 的毫秒数，可用约 69 年；
+//! - 6 位节点 ID：配置 `ids.node_id` 或环境变量 `BTCMWEB_NODE_ID`，0 到 63；
+//! - 6 位序号：同一毫秒内的第几个 ID，每个节点每毫秒最多 64 个。
+//!
+//! 只要同时运行的实例节点 ID 各不相同，ID 就全局唯一。同一节点上的 ID 严格递增：
+//! 同一毫秒内的序号用完时借用下一毫秒；系统时钟回拨时继续沿用上一个 ID 的时间戳，
+//! 直到时钟追上为止，因此回拨不会产生重复或更小的 ID。
+//! 生成器的状态只在内存中，重启前后的时钟回拨超过停机时长时仍可能重复，部署时应避免。
+
+use std::fmt;
+use std::sync::atomic::{ AtomicU64, Ordering };
+
+use lazy_static::lazy_static;
+
+use super::{ UserStoreError, UserStoreResult };
+use crate::config::config;
+
+/// 覆盖配置 `ids.node_id` 的环境变量名。
+pub static NODE_ID_ENV_VAR: &str = "BTCMWEB_NODE_ID";
+/// 默认的时间戳起点：2024-01-01T00:00:00Z。
+pub const DEFAULT_EPOCH_MS: u64 = 1_704_067_200_000;
+/// ID 的总位数，使 ID 不超过 JavaScript 的安全整数范围（2^53 - 1）。
+pub const ID_BITS: u32 = 53;
+/// 节点 ID 的位数。
+pub const NODE_BITS: u32 = 6;
+/// 序号的位数。
+pub const SEQUENCE_BITS: u32 = 6;
+/// 最大的节点 ID。
+pub const MAX_NODE_ID: u16 = (1 << NODE_BITS) - 1;
+/// 时间戳的位数。
+const TIMESTAMP_BITS: u32 = ID_BITS - NODE_BITS - SEQUENCE_BITS;
+const SEQUENCE_MASK: u64 = (1 << SEQUENCE_BITS) - 1;
+
+/// 返回当前 Unix 时间戳（毫秒）的时钟。
+pub type Clock = Box<dyn Fn() -> u64 + Send + Sync>;
+
+/// 一个 ID 的组成部分，见 [`SnowflakeGenerator::decompose`]。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdParts {
+    /// 生成时的 Unix 时间戳（毫秒）。时钟回拨或序号借用时可能略晚于实际时间。
+    pub timestamp_ms: u64,
+    /// 节点 ID。
+    pub node_id: u16,
+    /// 同一毫秒内的序号。
+    pub sequence: u16,
+}
+
+/// Snowflake ID 生成器，可以在多个线程中共享。
+pub struct SnowflakeGenerator {
+    node_id: u16,
+    epoch_ms: u64,
+    clock: Clock,
+    /// 上一个 ID 的时间戳和序号：`(毫秒 << SEQUENCE_BITS) | 序号`。
+    last: AtomicU64,
+}
+
+impl fmt::Debug for SnowflakeGenerator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SnowflakeGenerator")
+            .field("node_id", &self.node_id)
+            .field("epoch_ms", &self.epoch_ms)
+            .field("last", &self.last)
+            .finish()
+    }
+}
+
+fn system_clock() -> u64 {
+    chrono::Utc::now().timestamp_millis().max(0) as u64
+}
+
+impl SnowflakeGenerator {
+    /// 创建使用系统时钟的生成器。节点 ID 超过 [`MAX_NODE_ID`] 时返回错误。
+    pub fn new(node_id: u16, epoch_ms: u64) -> UserStoreResult<Self> {
+        Self::with_clock(node_id, epoch_ms, Box::new(system_clock))
+    }
+
+    /// 创建使用指定时钟的生成器，用于测试时钟回拨等情况。
+    pub fn with_clock(node_id: u16, epoch_ms: u64, clock: Clock) -> UserStoreResult<Self> {
+        if node_id > MAX_NODE_ID {
+            return Err(UserStoreError::Backend(format!("node id {} out of range 0..={}", node_id, MAX_NODE_ID)));
+        }
+        Ok(SnowflakeGenerator { node_id, epoch_ms, clock, last: AtomicU64::new(0) })
+    }
+
+    /// 节点 ID。
+    pub fn node_id(&self) -> u16 {
+        self.node_id
+    }
+
+    /// 按时钟的当前时间生成一个 ID。时钟可以比上一次调用时更早，见模块文档。
+    pub fn next_id(&self) -> u64 {
+        let elapsed = (self.clock)().saturating_sub(self.epoch_ms).min((1 << TIMESTAMP_BITS) - 1);
+        let now = elapsed << SEQUENCE_BITS;
+        let mut last = self.last.load(Ordering::Relaxed);
+        loop {
+            let next = now.max(last + 1);
+            match self.last.compare_exchange_weak(last, next, Ordering::AcqRel, Ordering::Relaxed) {
+                Ok(_) => return self.compose(next),
+                Err(actual) => last = actual,
+            }
+        }
+    }
+
+    fn compose(&self, tick: u64) -> u64 {
+        (tick >> SEQUENCE_BITS) << (NODE_BITS + SEQUENCE_BITS)
+            | u64::from(self.node_id) << SEQUENCE_BITS
+            | (tick & SEQUENCE_MASK)
+    }
+
+    /// 拆分由该生成器（或时间戳起点相同的生成器）生成的 ID。
+    pub fn decompose(&self, id: u64) -> IdParts {
+        IdParts {
+            timestamp_ms: (id >> (NODE_BITS + SEQUENCE_BITS)) + self.epoch_ms,
+            node_id: ((id >> SEQUENCE_BITS) & u64::from(MAX_NODE_ID)) as u16,
+            sequence: (id & SEQUENCE_MASK) as u16,
+        }
+    }
+}
+
+lazy_static! {
+    /// 按配置创建的生成器，配置无效时为错误信息。
+    static ref GENERATOR: Result<SnowflakeGenerator, String> = from_config();
+}
+
+fn from_config() -> Result<SnowflakeGenerator, String> {
+    let ids = &config().ids;
+    let node_id = match std::env::var(NODE_ID_ENV_VAR) {
+        Ok(value) => value.trim().parse().map_err(|_| format!("invalid {}: {}", NODE_ID_ENV_VAR, value))?,
+        Err(_) => ids.node_id.ok_or_else(|| format!("ids.node_id or {} must be set to use snowflake ids", NODE_ID_ENV_VAR))?,
+    };
+    SnowflakeGenerator::new(node_id, ids.epoch_ms).map_err(|err| err.to_string())
+}
+
+/// 按配置创建的全局生成器。未设置节点 ID 或节点 ID 无效时返回错误。
+pub fn generator() -> UserStoreResult<&'static SnowflakeGenerator> {
+    GENERATOR.as_ref().map_err(|err| UserStoreError::Backend(err.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::sync::Arc;
+
+    use super::*;
+
+    /// 可以手动设置时间的时钟，返回生成器和用于设置时间的句柄。
+    fn manual_clock(node_id: u16, start_ms: u64) -> (SnowflakeGenerator, Arc<AtomicU64>) {
+        let now = Arc::new(AtomicU64::new(start_ms));
+        let clock = now.clone();
+        let generator = SnowflakeGenerator::with_clock(node_id, DEFAULT_EPOCH_MS, Box::new(move || {
+            clock.load(Ordering::SeqCst)
+        })).unwrap();
+        (generator, now)
+    }
+
+    #[test]
+    fn rejects_invalid_node() {
+        assert!(SnowflakeGenerator::new(MAX_NODE_ID, DEFAULT_EPOCH_MS).is_ok());
+        assert!(SnowflakeGenerator::new(MAX_NODE_ID + 1, DEFAULT_EPOCH_MS).is_err());
+    }
+
+    #[test]
+    fn concurrent_ids_are_unique() {
+        const THREADS: usize = 8;
+        const PER_THREAD: usize = 20_000;
+        let generator = Arc::new(SnowflakeGenerator::new(7, DEFAULT_EPOCH_MS).unwrap());
+        let handles: Vec<_> = (0..THREADS)
+            .map(|_| {
+                let generator = generator.clone();
+                std::thread::spawn(move || (0..PER_THREAD).map(|_| generator.next_id()).collect::<Vec<u64>>())
+            })
+            .collect();
+        let mut unique = HashSet::new();
+        for handle in handles {
+            let ids = handle.join().unwrap();
+            assert!(ids.windows(2).all(|pair| pair[0] < pair[1]), "ids must increase within a thread");
+            for id in ids {
+                assert_eq!(generator.decompose(id).node_id, 7);
+                assert!(unique.insert(id), "duplicate snowflake id {}", id);
+            }
+        }
+        assert_eq!(unique.len(), THREADS * PER_THREAD);
+    }
+
+    #[test]
+    fn nodes_do_not_collide() {
+        let now_ms = DEFAULT_EPOCH_MS + 1_000_000;
+        let mut unique = HashSet::new();
+        for node_id in [0, 1, 32, MAX_NODE_ID] {
+            let (generator, _) = manual_clock(node_id, now_ms);
+            for sequence in 0..=SEQUENCE_MASK as u16 {
+                let id = generator.next_id();
+                let parts = generator.decompose(id);
+                assert_eq!((parts.timestamp_ms, parts.node_id, parts.sequence), (now_ms, node_id, sequence));
+                assert!(unique.insert(id), "ids of different nodes must not collide");
+            }
+        }
+    }
+
+    #[test]
+    fn survives_clock_rollback() {
+        let start = DEFAULT_EPOCH_MS + 5_000_000;
+        let (generator, now) = manual_clock(3, start);
+        let mut last = generator.next_id();
+        for now_ms in [start - 1, start - 10_000, start - 1, start, start + 1] {
+            now.store(now_ms, Ordering::SeqCst);
+            for _ in 0..10 {
+                let id = generator.next_id();
+                assert!(id > last, "ids must keep increasing when the clock goes back");
+                last = id;
+            }
+        }
+        now.store(start + 60_000, Ordering::SeqCst);
+        let parts = generator.decompose(generator.next_id());
+        assert_eq!((parts.timestamp_ms, parts.sequence), (start + 60_000, 0));
+    }
+
+    #[test]
+    fn sequence_overflow_borrows_next_ms() {
+        let now_ms = DEFAULT_EPOCH_MS + 42;
+        let (generator, _) = manual_clock(1, now_ms);
+        let per_ms = 1usize << SEQUENCE_BITS;
+        let ids: Vec<u64> = (0..per_ms + 10).map(|_| generator.next_id()).collect();
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+        let overflow = generator.decompose(ids[per_ms]);
+        assert_eq!((overflow.timestamp_ms, overflow.node_id, overflow.sequence), (now_ms + 1, 1, 0));
+    }
+
+    #[test]
+    fn ids_are_javascript_safe_integers() {
+        const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+        let (generator, now) = manual_clock(MAX_NODE_ID, u64::MAX);
+        let id = generator.next_id();
+        assert!(id <= MAX_SAFE_INTEGER);
+        assert_eq!(id, MAX_SAFE_INTEGER - SEQUENCE_MASK);
+        // 时间戳用完后仍然递增，直到序号也用完。
+        now.store(DEFAULT_EPOCH_MS, Ordering::SeqCst);
+        assert!(generator.next_id() <= MAX_SAFE_INTEGER);
+    }
+}
@@ -0,0 +1,269 @@
+use async_trait::async_trait;
+use sqlx::{ sqlite::SqlitePoolOptions, SqlitePool };
+
+use super::canonical::{ canonical_email, same_username, username_key };
+use super::{ index_keys, mark_reindexed, reindex_needed, UserRecord, UserStore, UserStoreError, UserStoreResult };
+
+/// 建表语句。完整记录以 JSON 保存在 `data` 列中，`username` 和 `email` 列保存显示形式，
+/// 唯一性由 `username_key` 和 `email_key` 列上的唯一索引保证，它们保存 [`super::canonical`] 计算的键。
+/// `version` 列与记录的版本相同，用于更新时比较并写入。附属记录保存在 `btcm_records` 表中。
+const SCHEMA: [&str; 3] = [
+    "CREATE TABLE IF NOT EXISTS btcm_users (
+        id           INTEGER PRIMARY KEY,
+        username     TEXT NOT NULL,
+        email        TEXT,
+        username_key TEXT,
+        email_key    TEXT,
+        data         TEXT NOT NULL,
+        version      INTEGER NOT NULL DEFAULT 0
+    )",
+    "CREATE TABLE IF NOT EXISTS btcm_users_id_seq (id INTEGER PRIMARY KEY AUTOINCREMENT)",
+    "CREATE TABLE IF NOT EXISTS btcm_records (
+        kind TEXT NOT NULL,
+        key  TEXT NOT NULL,
+        data TEXT NOT NULL,
+        PRIMARY KEY (kind, key)
+    )",
+];
+
+/// 添加列之后才能创建的唯一索引。
+const INDEXES: [&str; 2] = [
+    "CREATE UNIQUE INDEX IF NOT EXISTS btcm_users_username_canonical ON btcm_users (username_key)",
+    "CREATE UNIQUE INDEX IF NOT EXISTS btcm_users_email_canonical ON btcm_users (email_key)",
+];
+
+/// 列不存在时添加。SQLite 的 `ALTER TABLE ... ADD COLUMN` 不支持 `IF NOT EXISTS`。
+async fn add_column(pool: &SqlitePool, name: &str, definition: &str) -> UserStoreResult<()> {
+    let exists: bool = sqlx::query_scalar("SELECT COUNT(*) > 0 FROM pragma_table_info('btcm_users') WHERE name = ?")
+        .bind(name)
+        .fetch_one(pool).await?;
+    if !exists {
+        sqlx::query(&format!("ALTER TABLE btcm_users ADD COLUMN {} {}", name, definition)).execute(pool).await?;
+    }
+    Ok(())
+}
+
+/// 基于 SQLite 的用户存储。
+///
+/// 创建和更新使用 `OR IGNORE`，违反唯一约束时不报错而是影响零行，再查询是哪个约束冲突。
+/// sqlx 0.7 的 SQLite 驱动在语句执行出错后会继续执行同一条语句，
+/// 失败的插入可能在调用方收到错误之后又写入成功，因此不能依赖约束错误。
+pub struct SqliteUserStore {
+    pool: SqlitePool,
+}
+
+impl SqliteUserStore {
+    /// 连接到指定的数据库（如 `sqlite:users.db?mode=rwc`），并在需要时创建表。
+    pub async fn connect(url: &str) -> UserStoreResult<Self> {
+        let pool = SqlitePoolOptions::new().connect(url).await?;
+        for statement in SCHEMA {
+            sqlx::query(statement).execute(&pool).await?;
+        }
+        // 早期版本创建的表没有 version 列和规范键列，用户名和邮箱列上有唯一约束。
+        add_column(&pool, "version", "INTEGER NOT NULL DEFAULT 0").await?;
+        add_column(&pool, "username_key", "TEXT").await?;
+        add_column(&pool, "email_key", "TEXT").await?;
+        for statement in INDEXES {
+            sqlx::query(statement).execute(&pool).await?;
+        }
+        let store = SqliteUserStore { pool };
+        let unindexed: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM btcm_users WHERE username_key IS NULL)")
+            .fetch_one(&store.pool).await?;
+        if unindexed || reindex_needed(&store).await? {
+            store.reindex().await?;
+        }
+        Ok(store)
+    }
+
+    /// 按当前规则重新计算所有用户的键列，并把 `username` 和 `email` 列改为显示形式。
+    /// 在一个事务中完成，两个用户的键相同时返回错误，不做任何修改。
+    async fn reindex(&self) -> UserStoreResult<()> {
+        let mut tx = self.pool.begin().await?;
+        let data: Vec<String> = sqlx::query_scalar("SELECT data FROM btcm_users").fetch_all(&mut *tx).await?;
+        let users = data.iter().map(|data| serde_json::from_str(data)).collect::<Result<Vec<UserRecord>, _>>()?;
+        let keys = index_keys(&users)?;
+        // 先清空，避免按新规则写入时与尚未更新的行冲突；旧表的用户名列仍有唯一约束，先换成不会重复的占位值。
+        sqlx::query("UPDATE btcm_users SET username = '#' || id, email = NULL, username_key = NULL, email_key = NULL")
+            .execute(&mut *tx).await?;
+        for (user, (id, username_key, email_key)) in users.iter().zip(keys) {
+            sqlx::query("UPDATE btcm_users SET username = ?, email = ?, username_key = ?, email_key = ? WHERE id = ?")
+                .bind(&user.username)
+                .bind(&user.email)
+                .bind(username_key)
+                .bind(email_key)
+                .bind(id as i64)
+                .execute(&mut *tx).await?;
+        }
+        tx.commit().await?;
+        mark_reindexed(self).await?;
+        tracing::info!("rebuilt canonical username and email keys of {} users", users.len());
+        Ok(())
+    }
+
+    async fn get_where(&self, column: &str, value: &str) -> UserStoreResult<Option<UserRecord>> {
+        let sql = format!("SELECT data FROM btcm_users WHERE {} = ?", column);
+        let data: Option<String> = sqlx::query_scalar(&sql).bind(value).fetch_optional(&self.pool).await?;
+        Ok(data.map(|data| serde_json::from_str(&data)).transpose()?)
+    }
+
+    /// 其他用户已占用 `user` 的用户名或邮箱时，返回对应的重复错误。
+    async fn taken_by_other(&self, user: &UserRecord) -> UserStoreResult<Option<UserStoreError>> {
+        let username: Option<bool> = sqlx::query_scalar(
+            "SELECT username_key = ? FROM btcm_users WHERE id != ? AND (username_key = ? OR email_key = ?) LIMIT 1",
+        )
+            .bind(username_key(&user.username))
+            .bind(user.id as i64)
+            .bind(username_key(&user.username))
+            .bind(user.email.as_deref().map(canonical_email))
+            .fetch_optional(&self.pool).await?;
+        Ok(username.map(|username| if username {
+            UserStoreError::DuplicateUsername
+        } else {
+            UserStoreError::DuplicateEmail
+        }))
+    }
+}
+
+#[async_trait]
+impl UserStore for SqliteUserStore {
+    async fn next_id(&self) -> UserStoreResult<u64> {
+        let result = sqlx::query("INSERT INTO btcm_users_id_seq DEFAULT VALUES").execute(&self.pool).await?;
+        Ok(result.last_insert_rowid() as u64)
+    }
+
+    async fn create(&self, user: &UserRecord) -> UserStoreResult<()> {
+        loop {
+            let result = sqlx::query(
+                "INSERT OR IGNORE INTO btcm_users (id, username, email, username_key, email_key, data, version)
+                 VALUES (?, ?, ?, ?, ?, ?, ?)",
+            )
+                .bind(user.id as i64)
+                .bind(&user.username)
+                .bind(&user.email)
+                .bind(username_key(&user.username))
+                .bind(user.email.as_deref().map(canonical_email))
+                .bind(serde_json::to_string(user)?)
+                .bind(user.version as i64)
+                .execute(&self.pool).await?;
+            if result.rows_affected() == 1 {
+                return Ok(());
+            }
+            if let Some(err) = self.taken_by_other(user).await? {
+                return Err(err);
+            }
+            if self.get_by_id(user.id).await?.is_some() {
+                return Err(UserStoreError::Backend(format!("user id {} already exists", user.id)));
+            }
+            // 冲突的用户已被删除，重试。
+        }
+    }
+
+    async fn get_by_id(&self, id: u64) -> UserStoreResult<Option<UserRecord>> {
+        let data: Option<String> = sqlx::query_scalar("SELECT data FROM btcm_users WHERE id = ?")
+            .bind(id as i64)
+            .fetch_optional(&self.pool).await?;
+        Ok(data.map(|data| serde_json::from_str(&data)).transpose()?)
+    }
+
+    async fn get_by_username(&self, username: &str) -> UserStoreResult<Option<UserRecord>> {
+        let user = self.get_where("username_key", &username_key(username)).await?;
+        Ok(user.filter(|user| same_username(&user.username, username)))
+    }
+
+    async fn get_by_email(&self, email: &str) -> UserStoreResult<Option<UserRecord>> {
+        self.get_where("email_key", &canonical_email(email)).await
+    }
+
+    async fn update(&self, expected_version: u64, user: &UserRecord) -> UserStoreResult<()> {
+        let version = expected_version + 1;
+        let data = serde_json::to_string(&UserRecord { version, ..user.clone() })?;
+        loop {
+            let result = sqlx::query(
+                "UPDATE OR IGNORE btcm_users SET username = ?, email = ?, username_key = ?, email_key = ?, data = ?, version = ?
+                 WHERE id = ? AND version = ?",
+            )
+                .bind(&user.username)
+                .bind(&user.email)
+                .bind(username_key(&user.username))
+                .bind(user.email.as_deref().map(canonical_email))
+                .bind(&data)
+                .bind(version as i64)
+                .bind(user.id as i64)
+                .bind(expected_version as i64)
+                .execute(&self.pool).await?;
+            if result.rows_affected() == 1 {
+                return Ok(());
+            }
+            match self.get_by_id(user.id).await? {
+                None => return Err(UserStoreError::NotFound),
+                Some(current) if current.version != expected_version => return Err(UserStoreError::Conflict),
+                Some(_) => {}
+            }
+            if let Some(err) = self.taken_by_other(user).await? {
+                return Err(err);
+            }
+            // 冲突的用户已被删除或改名，重试。
+        }
+    }
+
+    async fn delete(&self, id: u64) -> UserStoreResult<()> {
+        let result = sqlx::query("DELETE FROM btcm_users WHERE id = ?")
+            .bind(id as i64)
+            .execute(&self.pool).await?;
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn delete_if(&self, id: u64, expected_version: u64) -> UserStoreResult<()> {
+        let result = sqlx::query("DELETE FROM btcm_users WHERE id = ? AND version = ?")
+            .bind(id as i64)
+            .bind(expected_version as i64)
+            .execute(&self.pool).await?;
+        if result.rows_affected() == 0 {
+            return match self.get_by_id(id).await? {
+                Some(_) => Err(UserStoreError::Conflict),
+                None => Err(UserStoreError::NotFound),
+            };
+        }
+        Ok(())
+    }
+
+    async fn list(&self, offset: usize, limit: usize) -> UserStoreResult<Vec<UserRecord>> {
+        let data: Vec<String> = sqlx::query_scalar("SELECT data FROM btcm_users ORDER BY id LIMIT ? OFFSET ?")
+            .bind(limit as i64)
+            .bind(offset as i64)
+            .fetch_all(&self.pool).await?;
+        data.iter().map(|data| serde_json::from_str(data).map_err(UserStoreError::from)).collect()
+    }
+
+    async fn get_record(&self, kind: &str, key: &str) -> UserStoreResult<Option<String>> {
+        Ok(sqlx::query_scalar("SELECT data FROM btcm_records WHERE kind = ? AND key = ?")
+            .bind(kind)
+            .bind(key)
+            .fetch_optional(&self.pool).await?)
+    }
+
+    async fn list_records(&self, kind: &str) -> UserStoreResult<Vec<(String, String)>> {
+        Ok(sqlx::query_as("SELECT key, data FROM btcm_records WHERE kind = ? ORDER BY key")
+            .bind(kind)
+            .fetch_all(&self.pool).await?)
+    }
+
+    async fn swap_record(&self, kind: &str, key: &str, expected: Option<&str>, value: Option<&str>) -> UserStoreResult<bool> {
+        let query = match (expected, value) {
+            (None, None) => return Ok(self.get_record(kind, key).await?.is_none()),
+            (None, Some(value)) => sqlx::query(
+                "INSERT INTO btcm_records (kind, key, data) VALUES (?, ?, ?) ON CONFLICT (kind, key) DO NOTHING",
+            ).bind(kind).bind(key).bind(value),
+            (Some(expected), Some(value)) => sqlx::query(
+                "UPDATE btcm_records SET data = ? WHERE kind = ? AND key = ? AND data = ?",
+            ).bind(value).bind(kind).bind(key).bind(expected),
+            (Some(expected), None) => sqlx::query(
+                "DELETE FROM btcm_records WHERE kind = ? AND key = ? AND data = ?",
+            ).bind(kind).bind(key).bind(expected),
+        };
+        Ok(query.execute(&self.pool).await?.rows_affected() == 1)
+    }
+}
@@ -30,9 +30,40 @@
 //! Web 服务器具有以下路由：
 //!
 //! - `/welcome`：对于 GET 请求，返回 "欢迎来到 Bitcomm！"。
-//! - `/jsonrpc`：处理 GET 和 POST 请求。对于 GET，它回复 "Hi Json-RPC from /jsonrpc"。对于 POST，它委托处理给 `jsonrpc::json_rpc_handler` 函数。
+//! - `/jsonrpc`：处理 GET 和 POST 请求。对于 GET，它通过查询参数 `method`、`id`、`params`（base64url 编码的 JSON）调用注册为只读安全的方法（`admin.*` 方法都不安全），令牌只能放在 `Authorization: Bearer` 请求头中，查询参数 `token` 会被拒绝，响应可被代理缓存。对于 POST，它委托处理给 `jsonrpc::call_json_rpc_handler` 函数；`token` 可以是访问令牌，也可以是服务账户的 API 密钥（见 [`crate::account::apikey`]）；请求体无法解析时返回 JSON-RPC 解析错误（-32700）或无效请求（-32600）。
+//! - `/captcha`：GET 获取一道验证码，返回 `{codekey, image, expires_in, required}`，见 [`crate::captcha`]。
+//! - `/reguser`：POST 注册用户，校验用户名、密码和邮箱后写入 [`crate::userstore`] 中配置的用户存储，返回分配的 client id。
+//!   是否允许注册、是否需要邀请码（`invite`）和管理员批准由注册模式决定，见 [`crate::account::invite`]；
+//!   需要批准的账户在批准前登录返回 `errorid` 为 [`crate::account::AccountError::PendingApproval`] 的响应。
+//! - `/login`：POST 登录，校验用户名和密码，成功时创建一个会话（可带设备名 `device`），
+//!   返回 client id、JWT 访问令牌和刷新令牌。会话可通过 `session.*` JSON-RPC 方法查看和吊销。
+//!   账户启用了两步验证时不签发令牌，而是返回 `errorid` 为 [`crate::account::AccountError::MfaRequired`]
+//!   的响应和一个 `mfa_token`。
+//! - `/login/mfa`：POST `{mfa_token, code}`，提交 TOTP 密码或恢复码完成登录，返回内容与 `/login` 成功时相同。
+//!   两步验证通过 `mfa.*` JSON-RPC 方法启用和停用，见 [`crate::account::mfa`]。
+//! - `/token/refresh`：POST `{refresh_token}`，换取新的访问令牌和刷新令牌，旧刷新令牌随即失效；
+//!   重复使用已失效的刷新令牌会吊销该次登录签发的所有刷新令牌。
+//! - `/logout`：POST 退出登录，吊销请求头 `Authorization: Bearer <token>` 中的访问令牌及其会话；
+//!   请求体可带 `{refresh_token}`，同时吊销该次登录签发的刷新令牌。
+//! - `/logout/all`：POST 退出所有设备，使该用户此前签发的所有访问令牌和刷新令牌失效。
+//! - `/email/verify`：GET `?token=...` 显示验证邮件中链接的确认页面，页面以表单 POST `token` 完成验证，
+//!   之后该用户需要重新登录，见 [`crate::account::verification`]。
+//! - `/email/resend`：POST 重新向当前用户（`Authorization: Bearer <token>`）的邮箱发送验证邮件，每分钟最多一次。
+//! - `/password/forgot`：POST `{username}` 或 `{email}`，向账户的邮箱发送一次性的密码重置码，
+//!   无论账户是否存在都返回成功，见 [`crate::account::reset`]。
+//! - `/password/reset`：POST `{token, password}`，使用重置码设置新密码，并使该用户所有已登录的会话失效。
+//! - `/profile`：GET 读取当前用户的资料，PATCH 部分修改资料，见 [`crate::account::profile`]。
+//!   响应头 `ETag` 为完整资料的摘要，PATCH 带 `If-Match` 时与当前资料不一致返回 412。
+//! - `/users/{id}/profile`：GET 读取其他用户的公开资料。
+//! - `/.well-known/jwks.json`：GET 返回 JWT 非对称签名密钥的公钥（JWKS），供 `btcmnetwork` 节点校验令牌。
 //! - `/admin`：从 "admin" 目录提供静态文件服务。如果找不到所请求的资源，它将回退到使用 `ServeFile` 回退机制提供 "index.html"。
 //!
+//! 需要登录的接口还按令牌中的权限（见 [`crate::rbac`]）检查，没有权限时返回 403，
+//! `errorid` 为 [`crate::account::AccountError::Forbidden`]。
+//!
+//! `/reguser` 到 `/users/{id}/profile` 的账户接口总是返回 `{clientid, errorid, message}`，`errorid` 为 0 表示成功，
+//! 其余取值由 [`crate::account::AccountError::errorid`] 定义。
+//!
 //! ## 函数
 //!
 //! - `get_adminserver_port`：返回格式化后的 Web 服务器的 IP 地址和端口。
@@ -40,15 +71,30 @@
 //! - `star_webserver`：启动 Web 服务器，绑定到指定的地址并提供配置的路由。使用 Bitcomm 记录器记录服务器地址。
 //!
 
-use tracing::info;
+use tracing::{ error, info };
 // use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-use axum::{ routing::{ get, post }, Router };
+use std::net::SocketAddr;
+
+use axum::{
+    extract::{ ConnectInfo, FromRequestParts, Path },
+    http::{ header, request::Parts, HeaderMap, StatusCode },
+    response::IntoResponse,
+    routing::{ get, post },
+    Router,
+};
 // use btcmtools::LOGGER;
 use serde::{Deserialize, Serialize};
 // use slog::info;
 use tower_http::{services::{ ServeDir, ServeFile }, trace::TraceLayer};
+use crate::account::{ self, profile::{ self, ProfileUpdate }, AccountError };
+use crate::captcha;
 use crate::jsonrpc;
+use crate::kvstore;
+use crate::mailer;
+use crate::rbac;
+use crate::token::{ self, session::DeviceInfo };
+use crate::userstore;
 
 /// Bitcomm 管理服务器的 IP 地址。
 pub static BITCOMM_ADMINSERVER: &str = "0.0.0.0";
@@ -61,6 +107,11 @@ pub fn get_adminserver_port() -> String {
     format!("{}:{}", BITCOMM_ADMINSERVER, BITCOMM_ADMINSERVER_PORT)
 }
 
+/// 返回完整的应用路由（不含日志中间件），用于进程内调用，例如 JSON-RPC 回放。
+pub fn app_router() -> Router {
+    using_serve_dir_with_assets_fallback()
+}
+
 /// 配置带有路由和静态文件服务的 Router，包括对丢失的资源的回退。
 fn using_serve_dir_with_assets_fallback() -> Router {
     // `ServeDir` 允许设置资源未找到时的回退，因此对于 `GET /assets/doesnt-exist.jpg`，它将返回 `index.html` 而不是 404
@@ -72,48 +123,490 @@ fn using_serve_dir_with_assets_fallback() -> Router {
             get(|| async { "欢迎来到 Bitcomm！" })
         )
         .route("/jsonrpc", post(jsonrpc::call_json_rpc_handler))//json_rpc_handler))
-        .route(
-            "/jsonrpc",
-            get(|| async { "你好，来自 /jsonrpc 的 Json-RPC" })
-        )
+        .route("/jsonrpc", get(jsonrpc::call_json_rpc_get_handler))
+        .route("/captcha", get(captcha_challenge))
         .route("/reguser", post(register))
         .route("/login", post(login))
+        .route("/login/mfa", post(login_mfa))
+        .route("/token/refresh", post(refresh))
+        .route("/logout", post(logout))
+        .route("/logout/all", post(logout_all))
+        .route("/email/verify", get(verify_email_page).post(verify_email))
+        .route("/email/resend", post(resend_verification_email))
+        .route("/password/forgot", post(forgot_password))
+        .route("/password/reset", post(reset_password))
+        .route("/profile", get(get_profile).patch(update_profile))
+        .route("/users/:id/profile", get(get_public_profile))
+        .route("/.well-known/jwks.json", get(token::jwks::jwks_handler))
         .nest_service("/admin", serve_dir.clone())
         .fallback_service(serve_dir)
 }
-#[allow(dead_code)]
+/// `/reguser` 和 `/login` 的请求体。`codekey` 和 `codevalue` 为验证码及其答案。
 #[derive(Debug, Deserialize)]
 struct User {
     username    :  String,
     password    :  String,
+    #[serde(default)]
+    email       :  Option<String>,
+    #[serde(default)]
     codekey     :  String,
+    #[serde(default)]
     codevalue   :  String,
+    /// 登录时客户端提供的设备名，显示在会话列表中。
+    #[serde(default)]
+    device      :  Option<String>,
+    /// 注册时的邀请码，见 [`account::invite`]。
+    #[serde(default)]
+    invite      :  Option<String>,
+}
+
+/// `/login/mfa` 的请求体。`code` 为 TOTP 密码或恢复码。
+#[derive(Debug, Deserialize)]
+struct MfaLoginRequest {
+    mfa_token: String,
+    code: String,
+}
+
+/// `/token/refresh` 的请求体。
+#[derive(Debug, Deserialize)]
+struct RefreshRequest {
+    refresh_token: String,
+}
+
+/// `/logout` 的请求体，可省略。
+#[derive(Debug, Default, Deserialize)]
+struct LogoutRequest {
+    #[serde(default)]
+    refresh_token: Option<String>,
+}
+
+/// `/email/verify` 的查询参数和表单。
+#[derive(Debug, Deserialize)]
+struct VerifyEmailQuery {
+    token: String,
 }
 
+/// `/password/forgot` 的请求体，`username` 和 `email` 二选一。
+#[derive(Debug, Deserialize)]
+struct ForgotPasswordRequest {
+    #[serde(default)]
+    username: Option<String>,
+    #[serde(default)]
+    email: Option<String>,
+}
+
+/// `/password/reset` 的请求体。
+#[derive(Debug, Deserialize)]
+struct ResetPasswordRequest {
+    token: String,
+    password: String,
+}
+
+/// 从 `Authorization: Bearer <token>` 请求头中提取并校验访问令牌，包括吊销状态。
+///
+/// 令牌缺失或无效时直接返回 `errorid` 为 [`AccountError::Unauthorized`] 的响应。
+struct AuthUser(token::Claims);
+
+#[axum::async_trait]
+impl<S> FromRequestParts<S> for AuthUser where S: Send + Sync {
+    type Rejection = (StatusCode, axum::response::Json<ApiResponse>);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let unauthorized = |err: AccountError| (StatusCode::UNAUTHORIZED, ApiResponse::error(err));
+        let bearer = parts.headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| unauthorized(AccountError::Unauthorized))?;
+        match token::authenticate_access_token(bearer.trim()).await {
+            Ok(claims) => Ok(AuthUser(claims)),
+            Err(token::TokenError::Store(detail)) => Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ApiResponse::error(AccountError::Internal(detail)),
+            )),
+            Err(_) => Err(unauthorized(AccountError::Unauthorized)),
+        }
+    }
+}
+
+/// 检查令牌是否拥有 `permission`（见 [`rbac`]），没有时返回 403 和 `errorid` 为
+/// [`AccountError::Forbidden`] 的响应。
+fn forbidden(claims: &token::Claims, permission: &str) -> Option<axum::response::Response> {
+    rbac::authorize(claims, permission).err().map(|err| (StatusCode::FORBIDDEN, ApiResponse::error(err)).into_response())
+}
+
+/// `/reguser`、`/login` 等账户接口的响应体。`errorid` 为 0 表示成功，其余取值见 [`AccountError::errorid`]。
 #[derive(Serialize)]
 struct ApiResponse {
     clientid:u64,
     errorid:u32,
     message: String,
+    /// 登录或刷新成功时签发的访问令牌。
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
+    /// 登录或刷新成功时签发的刷新令牌。
+    #[serde(skip_serializing_if = "Option::is_none")]
+    refresh_token: Option<String>,
+    /// 需要两步验证时，提交给 `/login/mfa` 的令牌。
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mfa_token: Option<String>,
+    /// 密码不符合规则时逐条列出的原因，见 [`account::policy::PolicyViolation`]。
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    reasons: Vec<account::policy::PolicyViolation>,
+    /// 用户资料，见 [`profile`]。
+    #[serde(skip_serializing_if = "Option::is_none")]
+    profile: Option<serde_json::Value>,
+    /// 资料字段不符合规则时逐个列出的错误。
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    fields: Vec<profile::FieldError>,
+}
+
+impl ApiResponse {
+    /// 只有 `clientid`、`errorid` 和 `message` 的响应，其余字段由各构造函数填写。
+    fn new(clientid: u64, errorid: u32, message: String) -> ApiResponse {
+        ApiResponse {
+            clientid,
+            errorid,
+            message,
+            token: None,
+            refresh_token: None,
+            mfa_token: None,
+            reasons: Vec::new(),
+            profile: None,
+            fields: Vec::new(),
+        }
+    }
+
+    fn ok(clientid: u64, message: &str, tokens: Option<token::TokenPair>) -> axum::response::Json<ApiResponse> {
+        let mut response = ApiResponse::new(clientid, 0, message.into());
+        if let Some(tokens) = tokens {
+            response.token = Some(tokens.access_token);
+            response.refresh_token = Some(tokens.refresh_token);
+        }
+        axum::response::Json(response)
+    }
+
+    fn profile(clientid: u64, message: &str, profile: impl Serialize) -> axum::response::Json<ApiResponse> {
+        let mut response = ApiResponse::ok(clientid, message, None);
+        match serde_json::to_value(profile) {
+            Ok(profile) => response.0.profile = Some(profile),
+            Err(err) => return ApiResponse::error(AccountError::Internal(err.to_string())),
+        }
+        response
+    }
+
+    fn error(err: AccountError) -> axum::response::Json<ApiResponse> {
+        if let AccountError::Internal(detail) = &err {
+            error!("account operation failed: {}", detail);
+        }
+        let mut response = ApiResponse::new(0, err.errorid(), err.to_string());
+        match err {
+            AccountError::InvalidPassword(violations) => response.reasons = violations,
+            AccountError::InvalidProfile(fields) => response.fields = fields,
+            _ => {}
+        }
+        axum::response::Json(response)
+    }
+
+    fn mfa_required(mfa_token: String) -> axum::response::Json<ApiResponse> {
+        let err = AccountError::MfaRequired;
+        let mut response = ApiResponse::new(0, err.errorid(), err.to_string());
+        response.mfa_token = Some(mfa_token);
+        axum::response::Json(response)
+    }
 }
-async fn register(user: axum::extract::Json<User>) -> axum::response::Json<ApiResponse> {
-    // TODO: Implement user registration logic, e.g., store in a database
-    println!("Registering user: {:?}", user);
-    axum::response::Json(ApiResponse {
-        clientid    :   1001,
-        errorid     :   0,
-        message     :   "User registered successfully".into(),
-    })
+
+/// 注册用户：校验输入，哈希密码并写入用户存储，返回分配的 client id。
+///
+/// 需要验证码时（见 [`captcha`]）先校验 `codekey` 和 `codevalue`。
+async fn register(
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    user: axum::extract::Json<User>
+) -> axum::response::Json<ApiResponse> {
+    info!("registering user: {}", user.username);
+    let ip = client_ip(&connect_info);
+    if let Err(err) = captcha::check(&ip, &user.codekey, &user.codevalue).await {
+        return ApiResponse::error(err.into());
+    }
+    let email = user.email.as_deref().filter(|email| !email.is_empty());
+    match account::register(&user.username, &user.password, email, user.invite.as_deref()).await {
+        Ok(record) if record.pending_approval => ApiResponse::ok(record.id, "User registered, awaiting approval", None),
+        Ok(record) => ApiResponse::ok(record.id, "User registered successfully", None),
+        Err(err) => ApiResponse::error(err),
+    }
+}
+
+/// 登录：校验用户名和密码，成功时签发访问令牌和刷新令牌。
+///
+/// 用户名或 IP 被锁定时直接拒绝（见 [`account::lockout`]）；需要验证码时先校验 `codekey` 和 `codevalue`；
+/// 密码错误计入该用户名和该 IP 的失败次数。所有失败的响应都延迟到相同的最短耗时。
+/// 每次登录创建一个会话，记录请求体中的 `device`、请求头中的 User-Agent 和客户端 IP。
+/// 账户启用了两步验证时只返回 `mfa_token`，由 [`login_mfa`] 完成登录。
+async fn login(
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    user: axum::extract::Json<User>
+) -> axum::response::Json<ApiResponse> {
+    info!("logging in user: {}", user.username);
+    let started = std::time::Instant::now();
+    let ip = client_ip(&connect_info);
+    let record = match check_login(&ip, &user).await {
+        Ok(record) => record,
+        Err(err) => {
+            account::lockout::pad_failure(started).await;
+            return ApiResponse::error(err);
+        }
+    };
+    let device = DeviceInfo {
+        device_name: user.device.clone(),
+        user_agent: headers.get(header::USER_AGENT).and_then(|value| value.to_str().ok()).map(str::to_string),
+        ip: connect_info.map(|ConnectInfo(addr)| addr.ip().to_string()),
+    };
+    if account::mfa::is_enabled(&record) {
+        return match account::mfa::start_challenge(&record, &user.username, device).await {
+            Ok(mfa_token) => ApiResponse::mfa_required(mfa_token),
+            Err(err) => ApiResponse::error(err),
+        };
+    }
+    match token::issue_tokens(&record, device, false).await {
+        Ok(tokens) => ApiResponse::ok(record.id, "User logged in successfully", Some(tokens)),
+        Err(err) => ApiResponse::error(err.into()),
+    }
+}
+
+/// 登录第二步：校验 TOTP 密码或恢复码，成功时签发访问令牌和刷新令牌。
+///
+/// 错误与密码错误一样计入用户名和 IP 的失败次数并受锁定限制，失败的响应延迟到相同的最短耗时。
+async fn login_mfa(
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    body: axum::extract::Json<MfaLoginRequest>
+) -> axum::response::Json<ApiResponse> {
+    let started = std::time::Instant::now();
+    let ip = client_ip(&connect_info);
+    let (record, device) = match account::mfa::complete_challenge(&body.mfa_token, &body.code, &ip).await {
+        Ok(result) => result,
+        Err(err) => {
+            account::lockout::pad_failure(started).await;
+            return ApiResponse::error(err);
+        }
+    };
+    match token::issue_tokens(&record, device, true).await {
+        Ok(tokens) => ApiResponse::ok(record.id, "User logged in successfully", Some(tokens)),
+        Err(err) => ApiResponse::error(err.into()),
+    }
+}
+
+/// 登录的锁定、验证码和密码校验，成功时返回用户记录，并记录或清除失败次数。
+///
+/// 启用了两步验证的账户在密码正确时不清除失败次数，由第二步完成后清除。
+async fn check_login(ip: &str, user: &User) -> Result<userstore::UserRecord, AccountError> {
+    account::lockout::check(&user.username, ip).await?;
+    captcha::check(ip, &user.codekey, &user.codevalue).await?;
+    if user.codekey.is_empty() && account::lockout::captcha_required(&user.username).await? {
+        return Err(AccountError::CaptchaRequired);
+    }
+    match account::authenticate(&user.username, &user.password).await {
+        Ok(record) => {
+            if !account::mfa::is_enabled(&record) {
+                if let Err(err) = account::lockout::record_success(&user.username).await {
+                    error!("failed to clear login failures: {}", err);
+                }
+            }
+            Ok(record)
+        }
+        Err(AccountError::InvalidCredentials) => {
+            if let Err(err) = captcha::record_failure(ip).await {
+                error!("failed to record login failure: {}", err);
+            }
+            account::lockout::record_failure(&user.username, ip).await?;
+            Err(AccountError::InvalidCredentials)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// 客户端 IP，无法获取时（例如进程内调用）为 `unknown`。
+fn client_ip(connect_info: &Option<ConnectInfo<SocketAddr>>) -> String {
+    connect_info.as_ref().map(|ConnectInfo(addr)| addr.ip().to_string()).unwrap_or_else(|| "unknown".into())
+}
+
+/// 获取验证码：返回 `{codekey, image, expires_in, required}`，`image` 为 PNG 图片的 data URL（`data:image/png;base64,...`）。
+async fn captcha_challenge(connect_info: Option<ConnectInfo<SocketAddr>>) -> axum::response::Response {
+    match captcha::issue_challenge(&client_ip(&connect_info)).await {
+        Ok(challenge) => ([(header::CACHE_CONTROL, "no-store")], axum::response::Json(challenge)).into_response(),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, ApiResponse::error(err.into())).into_response(),
+    }
+}
+
+/// 刷新令牌：使用刷新令牌换取新的令牌对。
+async fn refresh(body: axum::extract::Json<RefreshRequest>) -> axum::response::Json<ApiResponse> {
+    match token::refresh_tokens(&body.refresh_token).await {
+        Ok((record, tokens)) => ApiResponse::ok(record.id, "Token refreshed successfully", Some(tokens)),
+        Err(err) => ApiResponse::error(err.into()),
+    }
+}
+
+/// 退出登录：吊销当前访问令牌和它所属的会话，以及请求体中的刷新令牌（如果有）。
+async fn logout(
+    AuthUser(claims): AuthUser,
+    body: Option<axum::extract::Json<LogoutRequest>>
+) -> axum::response::Json<ApiResponse> {
+    let clientid = claims.user_id().unwrap_or_default();
+    if let Err(err) = token::revoke::revoke_access_token(&claims).await {
+        return ApiResponse::error(err.into());
+    }
+    if let Err(err) = token::session::revoke_user_session(clientid, &claims.sid).await {
+        return ApiResponse::error(err.into());
+    }
+    if let Some(refresh_token) = body.and_then(|body| body.0.refresh_token) {
+        if let Err(err) = token::refresh::revoke_refresh_token(&refresh_token, clientid).await {
+            return ApiResponse::error(err.into());
+        }
+    }
+    info!("user {} logged out", claims.username);
+    ApiResponse::ok(clientid, "User logged out successfully", None)
+}
+
+/// 退出所有设备：使该用户此前签发的所有访问令牌和刷新令牌失效。
+async fn logout_all(AuthUser(claims): AuthUser) -> axum::response::Json<ApiResponse> {
+    let clientid = claims.user_id().unwrap_or_default();
+    match token::revoke::revoke_all_tokens(clientid).await {
+        Ok(_) => ApiResponse::ok(clientid, "User logged out from all devices", None),
+        Err(err) => ApiResponse::error(err.into()),
+    }
+}
+
+/// 邮箱验证的 HTML 页面。
+fn verification_page(message: &str, form_token: Option<&str>) -> axum::response::Html<String> {
+    let form = form_token
+        .map(|token| format!(
+            "<form method=\"post\" action=\"/email/verify\"><input type=\"hidden\" name=\"token\" value=\"{}\"><button type=\"submit\">Confirm</button></form>",
+            html_escape(token)
+        ))
+        .unwrap_or_default();
+    axum::response::Html(format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>Bitcomm email verification</title></head><body><p>{}</p>{}</body></html>",
+        html_escape(message),
+        form
+    ))
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;").replace('\'', "&#39;")
+}
+
+/// 打开验证链接：只显示确认页面，不修改任何状态。
+async fn verify_email_page(query: axum::extract::Query<VerifyEmailQuery>) -> axum::response::Html<String> {
+    if account::verification::is_valid_token(&query.token) {
+        verification_page("Confirm your Bitcomm email address.", Some(&query.token))
+    } else {
+        verification_page(&AccountError::InvalidVerificationToken.to_string(), None)
+    }
+}
+
+/// 提交确认页面：校验令牌，将用户的邮箱标记为已验证。
+async fn verify_email(form: axum::extract::Form<VerifyEmailQuery>) -> axum::response::Html<String> {
+    match account::verification::confirm_email(&form.token).await {
+        Ok(_) => verification_page("Email verified successfully. Please sign in again.", None),
+        Err(err) => {
+            if let AccountError::Internal(detail) = &err {
+                error!("email verification failed: {}", detail);
+            }
+            verification_page(&err.to_string(), None)
+        }
+    }
+}
+
+/// 重新发送验证邮件。
+async fn resend_verification_email(AuthUser(claims): AuthUser) -> axum::response::Json<ApiResponse> {
+    let clientid = claims.user_id().unwrap_or_default();
+    match account::verification::resend_verification_email(clientid).await {
+        Ok(()) => ApiResponse::ok(clientid, "Verification email sent", None),
+        Err(err) => ApiResponse::error(err),
+    }
+}
+
+/// 申请重置密码：在后台向账户的邮箱发送重置码。为了不泄露账户是否存在，总是立即返回相同的结果。
+async fn forgot_password(body: axum::extract::Json<ForgotPasswordRequest>) -> axum::response::Json<ApiResponse> {
+    let body = body.0;
+    let Some(identifier) = body.email.or(body.username).filter(|identifier| !identifier.trim().is_empty()) else {
+        return ApiResponse::error(AccountError::InvalidUsername("username or email is required".into()));
+    };
+    account::reset::request_password_reset(&identifier);
+    ApiResponse::ok(0, "If the account exists, a password reset email has been sent", None)
+}
+
+/// 重置密码：校验重置码，设置新密码并吊销该用户的所有令牌。
+async fn reset_password(body: axum::extract::Json<ResetPasswordRequest>) -> axum::response::Json<ApiResponse> {
+    match account::reset::reset_password(&body.token, &body.password).await {
+        Ok(record) => ApiResponse::ok(record.id, "Password reset successfully", None),
+        Err(err) => ApiResponse::error(err),
+    }
+}
+
+/// 读取自己的资料。响应头 `ETag` 为完整资料的摘要，请求头 `If-None-Match` 与之相同时返回 304。
+async fn get_profile(AuthUser(claims): AuthUser, headers: HeaderMap) -> axum::response::Response {
+    if let Some(response) = forbidden(&claims, rbac::PROFILE_READ) {
+        return response;
+    }
+    let clientid = claims.user_id().unwrap_or_default();
+    match profile::get_own(clientid).await {
+        Ok(own) => {
+            let etag = profile::etag(&own);
+            let not_modified = headers
+                .get(header::IF_NONE_MATCH)
+                .and_then(|value| value.to_str().ok())
+                .is_some_and(|value| profile::etag_matches(value, &etag));
+            if not_modified {
+                return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response();
+            }
+            ([(header::ETAG, etag)], ApiResponse::profile(clientid, "Profile loaded", own)).into_response()
+        }
+        Err(err) => ApiResponse::error(err).into_response(),
+    }
+}
+
+/// 部分修改自己的资料，见 [`ProfileUpdate`]。
+///
+/// 请求头 `If-Match` 为读取时的 `ETag`（或请求体中的 `version` 为读取时的资料版本），
+/// 与当前资料不一致时返回 412，`errorid` 为 [`AccountError::ProfileConflict`]。`If-Match` 按强比较，
+/// 弱校验形式 `W/"..."` 总是不匹配；`If-Match: *` 表示不检查。
+async fn update_profile(
+    AuthUser(claims): AuthUser,
+    headers: HeaderMap,
+    body: axum::extract::Json<ProfileUpdate>
+) -> axum::response::Response {
+    if let Some(response) = forbidden(&claims, rbac::PROFILE_WRITE) {
+        return response;
+    }
+    let clientid = claims.user_id().unwrap_or_default();
+    let conflict = || (StatusCode::PRECONDITION_FAILED, ApiResponse::error(AccountError::ProfileConflict)).into_response();
+    let mut update = body.0;
+    if let Some(value) = headers.get(header::IF_MATCH) {
+        match value.to_str() {
+            Ok(value) => update.etag = Some(value.to_string()),
+            Err(_) => return conflict(),
+        }
+    }
+    match profile::update(clientid, &update).await {
+        Ok(own) => (
+            [(header::ETAG, profile::etag(&own))],
+            ApiResponse::profile(clientid, "Profile updated successfully", own),
+        ).into_response(),
+        Err(AccountError::ProfileConflict) => conflict(),
+        Err(err) => ApiResponse::error(err).into_response(),
+    }
 }
 
-async fn login(user: axum::extract::Json<User>) -> axum::response::Json<ApiResponse> {
-    // TODO: Implement user login logic, e.g., check credentials in a database
-    println!("Logging in user: {:?}", user);
-    axum::response::Json(ApiResponse {
-        clientid    :   1001,
-        errorid     :   0,
-        message     :   "User logged in successfully".into(),
-    })
+/// 读取其他用户的公开资料，不含邮箱、语言和时区。
+async fn get_public_profile(AuthUser(claims): AuthUser, Path(id): Path<u64>) -> axum::response::Response {
+    if let Some(response) = forbidden(&claims, rbac::PROFILE_READ) {
+        return response;
+    }
+    match profile::get_public(id).await {
+        Ok(public) => ApiResponse::profile(claims.user_id().unwrap_or_default(), "Profile loaded", public).into_response(),
+        Err(err) => ApiResponse::error(err).into_response(),
+    }
 }
 
 /// 启动 Bitcomm Web 服务器。绑定到指定的地址并提供配置的路由。使用 Bitcomm 记录器记录服务器地址。
@@ -121,11 +614,18 @@ async fn login(user: axum::extract::Json<User>) -> axum::response::Json<ApiRespo
 pub async fn star_webserver() {
 
     let server_address = get_adminserver_port();
+    userstore::init_user_store_from_env().await.unwrap();
+    userstore::init_id_generator().unwrap();
+    kvstore::init_kv_store_from_env().await.unwrap();
+    account::admin::seed_admins().await.unwrap();
+    mailer::init_mailer_from_env().unwrap();
+    token::token_service().sync_keys().await.unwrap();
+    token::spawn_key_rotation();
     // 使用路由构建我们的应用程序
     let app = using_serve_dir_with_assets_fallback().layer(TraceLayer::new_for_http());
         // app.layer(TraceLayer::new_for_http());
     // 运行服务器
     let listener = tokio::net::TcpListener::bind(server_address.as_str()).await.unwrap();
     info!("http listening {}", listener.local_addr().unwrap());
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap();
 }
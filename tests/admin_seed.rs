@@ -0,0 +1,46 @@
+//! 配置的管理员用户名是保留名，不能注册，账户由启动时的 `seed_admins` 创建；
+//! 管理员权限只来自存储的角色，与用户名无关。
+
+use btcmweb::account::{ self, admin, AccountError };
+use btcmweb::config::CONFIG_ENV_VAR;
+use btcmweb::rbac;
+use btcmweb::userstore::{ user_store, UserRecord };
+
+const PASSWORD: &str = "xK9#mQ2$vL7!zz";
+/// 配置 `admin.user_ids` 中的已有用户。
+const PROMOTED_ID: u64 = 9_000_000_001;
+/// 升级前已存在、与配置的管理员同名的用户。
+const LEGACY_ID: u64 = 9_000_000_002;
+
+/// 本测试程序只有这一个测试，配置和环境变量在读取配置前设置一次。
+#[tokio::test]
+async fn configured_admins_are_seeded_not_registered() {
+    let path = std::env::temp_dir().join(format!("btcm_admin_seed_{}.json", std::process::id()));
+    std::fs::write(&path, r#"{ "admin": { "usernames": ["rootadmin", "legacyroot"], "user_ids": [9000000001] } }"#).unwrap();
+    std::env::set_var(CONFIG_ENV_VAR, &path);
+    let store = user_store();
+    store.create(&UserRecord { id: PROMOTED_ID, username: "promoted".into(), ..Default::default() }).await.unwrap();
+    store.create(&UserRecord { id: LEGACY_ID, username: "LegacyRoot".into(), ..Default::default() }).await.unwrap();
+
+    assert!(account::is_reserved_username("RootAdmin"));
+    let err = account::register("rootadmin", PASSWORD, None, None).await.unwrap_err();
+    assert!(matches!(err, AccountError::InvalidUsername(_)), "{}", err);
+
+    assert!(admin::seed_admins().await.unwrap().is_empty(), "nothing is created without a password");
+    assert_eq!(store.get_by_username("rootadmin").await.unwrap(), None);
+    let promoted = store.get_by_id(PROMOTED_ID).await.unwrap().unwrap();
+    assert_eq!(rbac::effective_roles(&promoted), vec![rbac::SUPERADMIN_ROLE.to_string(), rbac::USER_ROLE.to_string()]);
+    let legacy = store.get_by_id(LEGACY_ID).await.unwrap().unwrap();
+    assert!(legacy.roles.is_empty(), "an existing account is not promoted by its name");
+    assert_eq!(rbac::effective_roles(&legacy), vec![rbac::USER_ROLE.to_string()]);
+
+    std::env::set_var(admin::ADMIN_PASSWORD_ENV_VAR, PASSWORD);
+    let created = admin::seed_admins().await.unwrap();
+    assert_eq!(created.len(), 1);
+    assert_eq!(created[0].username, "rootadmin");
+    assert_eq!(created[0].roles, vec![rbac::SUPERADMIN_ROLE.to_string()]);
+    assert!(admin::seed_admins().await.unwrap().is_empty(), "seeding is idempotent");
+    let stored = store.get_by_username("rootadmin").await.unwrap().unwrap();
+    assert_eq!(stored.id, created[0].id);
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,80 @@
+//! API 密钥保存在用户存储中，轮换和吊销按记录内容比较并交换。
+
+use btcmweb::account::apikey::{ self, NewApiKey };
+use btcmweb::account::AccountError;
+use btcmweb::kvstore::kv_store;
+use btcmweb::userstore::{ user_store, UserRecord };
+
+/// 同时发出的轮换请求数。
+const TASKS: usize = 8;
+
+async fn service_account(name: &str) -> u64 {
+    let store = user_store();
+    let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos();
+    let user = UserRecord {
+        id: store.next_id().await.unwrap(),
+        username: format!("{}{:x}", name, nanos),
+        roles: vec!["moderator".to_string()],
+        service: true,
+        ..Default::default()
+    };
+    store.create(&user).await.unwrap();
+    user.id
+}
+
+fn new_key(user_id: u64) -> NewApiKey {
+    NewApiKey { user_id, name: "ci".to_string(), scopes: vec!["users.read".to_string()], expires_in_secs: None }
+}
+
+#[tokio::test]
+async fn keys_are_persisted_in_the_user_store() {
+    let user_id = service_account("keybot").await;
+    let (full_key, key) = apikey::create(&new_key(user_id), 1).await.unwrap();
+    assert!(user_store().get_record("apikey", &key.id).await.unwrap().is_some());
+    assert_eq!(kv_store().get(&format!("btcm:apikey:{}", key.id)).await.unwrap(), None);
+
+    let claims = apikey::authenticate(&full_key).await.unwrap();
+    assert_eq!(claims.sub, user_id.to_string());
+    assert_eq!(claims.api_key.as_deref(), Some(key.id.as_str()));
+    let mut tampered = full_key.clone();
+    tampered.pop();
+    tampered.push(if full_key.ends_with('0') { '1' } else { '0' });
+    assert!(matches!(apikey::authenticate(&tampered).await, Err(AccountError::Unauthorized)));
+
+    let (rotated_key, rotated) = apikey::rotate(&key.id, 60, None, 1).await.unwrap();
+    assert!(apikey::authenticate(&full_key).await.is_ok(), "the old key works during the grace period");
+    assert!(apikey::authenticate(&rotated_key).await.is_ok());
+    assert_eq!(apikey::list(user_id).await.unwrap().len(), 2);
+
+    assert!(apikey::revoke(&key.id).await.unwrap());
+    assert!(!apikey::revoke(&key.id).await.unwrap());
+    assert!(matches!(apikey::authenticate(&full_key).await, Err(AccountError::Unauthorized)));
+    assert_eq!(user_store().get_record("apikey", &key.id).await.unwrap(), None);
+
+    assert_eq!(apikey::revoke_all(user_id).await.unwrap(), 1);
+    assert!(apikey::list(user_id).await.unwrap().is_empty());
+    assert!(matches!(apikey::authenticate(&rotated_key).await, Err(AccountError::Unauthorized)));
+    assert_eq!(user_store().get_record("apikey", &rotated.id).await.unwrap(), None);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_rotations_leave_one_successor() {
+    let user_id = service_account("rotbot").await;
+    let (_, key) = apikey::create(&new_key(user_id), 1).await.unwrap();
+    let mut tasks = Vec::new();
+    for _ in 0..TASKS {
+        let id = key.id.clone();
+        tasks.push(tokio::spawn(async move { apikey::rotate(&id, 0, None, 1).await }));
+    }
+    let mut winners = Vec::new();
+    for task in tasks {
+        match task.await.unwrap() {
+            Ok((_, rotated)) => winners.push(rotated),
+            Err(err) => assert!(matches!(err, AccountError::ApiKeyNotFound), "{}", err),
+        }
+    }
+    assert_eq!(winners.len(), 1, "exactly one rotation of the same key may win");
+    let keys = apikey::list(user_id).await.unwrap();
+    assert_eq!(keys.iter().map(|key| key.id.as_str()).collect::<Vec<_>>(), vec![winners[0].id.as_str()]);
+    apikey::revoke_all(user_id).await.unwrap();
+}
@@ -0,0 +1,45 @@
+//! 同一 IP 的失败次数达到阈值后要求验证码，登录成功不会清除计数。
+
+use axum::body::Body;
+use axum::http::{ header, Request };
+use btcmweb::{ account, captcha };
+use btcmweb::kvstore::kv_store;
+use serde_json::{ json, Value };
+use tower::ServiceExt;
+
+const PASSWORD: &str = "xK9#mQ2$vL7!zz";
+/// 测试中的请求没有连接信息，客户端 IP 为 `unknown`。
+const IP: &str = "unknown";
+/// 默认配置的失败次数阈值。
+const FAILURE_THRESHOLD: usize = 3;
+
+async fn login(body: Value) -> Value {
+    let request = Request::post("/login")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let response = btcmweb::webserver::app_router().oneshot(request).await.unwrap();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+#[tokio::test]
+async fn successful_login_keeps_the_ip_failure_count() {
+    let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos();
+    let username = format!("cap{:x}", nanos);
+    account::register(&username, PASSWORD, None, None).await.unwrap();
+    for _ in 0..FAILURE_THRESHOLD {
+        captcha::record_failure(IP).await.unwrap();
+    }
+    assert!(captcha::is_required(IP).await.unwrap());
+
+    let challenge = captcha::issue_challenge(IP).await.unwrap();
+    assert!(challenge.image.starts_with("data:image/png;base64,"));
+    let answer = kv_store().get(&format!("btcm:captcha:{}", challenge.codekey)).await.unwrap().unwrap();
+    let body = login(json!({ "username": username, "password": PASSWORD, "codekey": challenge.codekey, "codevalue": answer })).await;
+    assert_eq!(body["errorid"], 0, "{}", body);
+
+    assert!(captcha::is_required(IP).await.unwrap(), "logging in to an own account does not reset the count");
+    let body = login(json!({ "username": username, "password": PASSWORD })).await;
+    assert_ne!(body["errorid"], 0, "{}", body);
+}
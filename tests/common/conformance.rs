@@ -0,0 +1,436 @@
+//! # 用户存储一致性检查
+//!
+//! 所有 [`UserStore`] 后端都必须通过的一组行为检查。检查失败时会 panic，
+//! `tests/userstore_conformance.rs` 对每个后端调用 [`run_all`]。只在测试中编译，不属于库的公开接口。
+//!
+//! 每次运行都会使用带随机前缀的用户名和邮箱，并在结束时删除创建的用户，
+//! 因此也可以对已有数据的 Redis/Postgres 实例运行（设置 `REDIS_URL`/`POSTGRES_URL`）。
+
+use std::collections::HashSet;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::Poll;
+
+use btcmweb::userstore::{ Profile, UserRecord, UserStore, UserStoreError };
+
+/// 并发检查中同时发出的请求数。
+const CONCURRENCY: usize = 32;
+
+/// 依次运行所有检查。
+pub async fn run_all(store: &dyn UserStore) {
+    create_and_get(store).await;
+    duplicate_username_rejected(store).await;
+    duplicate_email_rejected(store).await;
+    update_changes_indexes(store).await;
+    update_conflicts_rejected(store).await;
+    update_checks_version(store).await;
+    delete_removes_indexes(store).await;
+    delete_if_checks_version(store).await;
+    lookups_are_canonical(store).await;
+    confusable_usernames_rejected(store).await;
+    concurrent_creates_are_atomic(store).await;
+    concurrent_renames_are_atomic(store).await;
+    list_is_ordered_and_paginated(store).await;
+    next_id_is_unique(store).await;
+    concurrent_next_id_is_unique(store).await;
+    records_swap_atomically(store).await;
+}
+
+/// 每次运行唯一的名称前缀，避免与已有数据或并行运行冲突。
+fn unique_prefix() -> String {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    format!("conf{:x}", nanos)
+}
+
+/// 在当前任务中并发执行所有 future，按原顺序返回结果。
+async fn join_all<F: Future>(futures: Vec<F>) -> Vec<F::Output> {
+    let mut futures: Vec<Pin<Box<F>>> = futures.into_iter().map(Box::pin).collect();
+    let mut results: Vec<Option<F::Output>> = futures.iter().map(|_| None).collect();
+    std::future::poll_fn(|cx| {
+        let mut pending = false;
+        for (future, result) in futures.iter_mut().zip(results.iter_mut()) {
+            if result.is_none() {
+                match future.as_mut().poll(cx) {
+                    Poll::Ready(output) => *result = Some(output),
+                    Poll::Pending => pending = true,
+                }
+            }
+        }
+        if pending { Poll::Pending } else { Poll::Ready(()) }
+    })
+    .await;
+    results.into_iter().map(Option::unwrap).collect()
+}
+
+async fn new_user(store: &dyn UserStore, username: &str, email: Option<&str>) -> UserRecord {
+    let now = chrono::Utc::now().timestamp();
+    UserRecord {
+        id: store.next_id().await.expect("next_id"),
+        username: username.to_string(),
+        email: email.map(str::to_string),
+        password_hash: "$argon2id$v=19$m=19456,t=2,p=1$c2FsdHNhbHQ$aGFzaGhhc2g".to_string(),
+        created_at: now,
+        updated_at: now,
+        ..Default::default()
+    }
+}
+
+/// 创建后能通过 ID、用户名和邮箱读回相同的记录。
+pub async fn create_and_get(store: &dyn UserStore) {
+    let prefix = unique_prefix();
+    let email = format!("{}@example.com", prefix);
+    let user = new_user(store, &prefix, Some(&email)).await;
+    store.create(&user).await.expect("create");
+
+    assert_eq!(store.get_by_id(user.id).await.unwrap().as_ref(), Some(&user));
+    assert_eq!(store.get_by_username(&user.username).await.unwrap().as_ref(), Some(&user));
+    assert_eq!(store.get_by_email(&email).await.unwrap().as_ref(), Some(&user));
+    assert_eq!(store.get_by_username(&format!("{}-missing", prefix)).await.unwrap(), None);
+
+    store.delete(user.id).await.unwrap();
+}
+
+/// 用户名重复时拒绝创建。
+pub async fn duplicate_username_rejected(store: &dyn UserStore) {
+    let prefix = unique_prefix();
+    let first = new_user(store, &prefix, None).await;
+    store.create(&first).await.unwrap();
+
+    let second = new_user(store, &prefix, Some(&format!("{}@example.com", prefix))).await;
+    assert!(matches!(store.create(&second).await, Err(UserStoreError::DuplicateUsername)));
+    // 失败的创建不能留下邮箱占用。
+    assert_eq!(store.get_by_email(&format!("{}@example.com", prefix)).await.unwrap(), None);
+
+    store.delete(first.id).await.unwrap();
+}
+
+/// 邮箱重复时拒绝创建，且不留下用户名占用。
+pub async fn duplicate_email_rejected(store: &dyn UserStore) {
+    let prefix = unique_prefix();
+    let email = format!("{}@example.com", prefix);
+    let first = new_user(store, &format!("{}a", prefix), Some(&email)).await;
+    store.create(&first).await.unwrap();
+
+    let second = new_user(store, &format!("{}b", prefix), Some(&email)).await;
+    assert!(matches!(store.create(&second).await, Err(UserStoreError::DuplicateEmail)));
+    assert_eq!(store.get_by_username(&second.username).await.unwrap(), None);
+
+    store.delete(first.id).await.unwrap();
+}
+
+/// 修改用户名和邮箱后，旧值释放，新值可查。
+pub async fn update_changes_indexes(store: &dyn UserStore) {
+    let prefix = unique_prefix();
+    let mut user = new_user(store, &format!("{}old", prefix), Some(&format!("{}old@example.com", prefix))).await;
+    store.create(&user).await.unwrap();
+
+    user.username = format!("{}new", prefix);
+    user.email = Some(format!("{}new@example.com", prefix));
+    store.update(user.version, &user).await.expect("update");
+    user.version += 1;
+
+    assert_eq!(store.get_by_username(&format!("{}old", prefix)).await.unwrap(), None);
+    assert_eq!(store.get_by_email(&format!("{}old@example.com", prefix)).await.unwrap(), None);
+    assert_eq!(store.get_by_username(&user.username).await.unwrap().as_ref(), Some(&user));
+    assert_eq!(store.get_by_email(&format!("{}new@example.com", prefix)).await.unwrap().as_ref(), Some(&user));
+
+    let mut missing = user.clone();
+    missing.id = store.next_id().await.unwrap();
+    missing.username = format!("{}ghost", prefix);
+    missing.email = None;
+    assert!(matches!(store.update(0, &missing).await, Err(UserStoreError::NotFound)));
+
+    store.delete(user.id).await.unwrap();
+}
+
+/// 修改为其他用户的用户名或邮箱时被拒绝，原记录保持不变。
+pub async fn update_conflicts_rejected(store: &dyn UserStore) {
+    let prefix = unique_prefix();
+    let a = new_user(store, &format!("{}a", prefix), Some(&format!("{}a@example.com", prefix))).await;
+    let b = new_user(store, &format!("{}b", prefix), Some(&format!("{}b@example.com", prefix))).await;
+    store.create(&a).await.unwrap();
+    store.create(&b).await.unwrap();
+
+    let mut renamed = b.clone();
+    renamed.username = a.username.clone();
+    assert!(matches!(store.update(b.version, &renamed).await, Err(UserStoreError::DuplicateUsername)));
+
+    let mut reemailed = b.clone();
+    reemailed.email = a.email.clone();
+    assert!(matches!(store.update(b.version, &reemailed).await, Err(UserStoreError::DuplicateEmail)));
+
+    assert_eq!(store.get_by_id(b.id).await.unwrap().as_ref(), Some(&b));
+    assert_eq!(store.get_by_username(&b.username).await.unwrap().as_ref(), Some(&b));
+
+    store.delete(a.id).await.unwrap();
+    store.delete(b.id).await.unwrap();
+}
+
+/// 更新只在版本与预期相同时写入，写入后版本加一；并发更新同一版本时恰好一个成功。
+pub async fn update_checks_version(store: &dyn UserStore) {
+    let prefix = unique_prefix();
+    let user = new_user(store, &prefix, None).await;
+    store.create(&user).await.unwrap();
+
+    let mut first = user.clone();
+    first.profile.bio = Some("first".to_string());
+    store.update(user.version, &first).await.expect("update with current version");
+    let stored = store.get_by_id(user.id).await.unwrap().unwrap();
+    assert_eq!(stored.version, user.version + 1);
+    assert_eq!(stored.profile.bio.as_deref(), Some("first"));
+
+    let mut stale = user.clone();
+    stale.profile.bio = Some("stale".to_string());
+    assert!(matches!(store.update(user.version, &stale).await, Err(UserStoreError::Conflict)));
+    assert_eq!(store.get_by_id(user.id).await.unwrap().as_ref(), Some(&stored));
+
+    let writers: Vec<UserRecord> = (0..CONCURRENCY)
+        .map(|i| UserRecord { profile: Profile { bio: Some(i.to_string()), ..Default::default() }, ..stored.clone() })
+        .collect();
+    let results = join_all(writers.iter().map(|user| store.update(stored.version, user)).collect()).await;
+    assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 1, "exactly one concurrent update may win");
+    assert!(results.iter().filter(|r| r.is_err()).all(|r| matches!(r, Err(UserStoreError::Conflict))));
+    let winner = results.iter().position(Result::is_ok).unwrap();
+    let current = store.get_by_id(user.id).await.unwrap().unwrap();
+    assert_eq!(current.version, stored.version + 1);
+    assert_eq!(current.profile.bio, Some(winner.to_string()));
+
+    store.delete(user.id).await.unwrap();
+}
+
+/// 删除后记录和索引都被移除，名称可以重新使用。
+pub async fn delete_removes_indexes(store: &dyn UserStore) {
+    let prefix = unique_prefix();
+    let email = format!("{}@example.com", prefix);
+    let user = new_user(store, &prefix, Some(&email)).await;
+    store.create(&user).await.unwrap();
+    store.delete(user.id).await.expect("delete");
+
+    assert_eq!(store.get_by_id(user.id).await.unwrap(), None);
+    assert_eq!(store.get_by_username(&prefix).await.unwrap(), None);
+    assert_eq!(store.get_by_email(&email).await.unwrap(), None);
+    assert!(matches!(store.delete(user.id).await, Err(UserStoreError::NotFound)));
+
+    let again = new_user(store, &prefix, Some(&email)).await;
+    store.create(&again).await.expect("username reusable after delete");
+    store.delete(again.id).await.unwrap();
+}
+
+/// 比较并删除只在版本与预期相同时删除，版本过期时记录保持不变。
+pub async fn delete_if_checks_version(store: &dyn UserStore) {
+    let prefix = unique_prefix();
+    let email = format!("{}@example.com", prefix);
+    let user = new_user(store, &prefix, Some(&email)).await;
+    store.create(&user).await.unwrap();
+
+    let mut changed = user.clone();
+    changed.profile.bio = Some("changed".to_string());
+    store.update(user.version, &changed).await.unwrap();
+    assert!(matches!(store.delete_if(user.id, user.version).await, Err(UserStoreError::Conflict)));
+    let stored = store.get_by_id(user.id).await.unwrap().expect("stale delete keeps the record");
+    assert_eq!(store.get_by_email(&email).await.unwrap().map(|user| user.id), Some(user.id));
+
+    store.delete_if(user.id, stored.version).await.expect("delete with current version");
+    assert_eq!(store.get_by_id(user.id).await.unwrap(), None);
+    assert_eq!(store.get_by_username(&prefix).await.unwrap(), None);
+    assert!(matches!(store.delete_if(user.id, stored.version).await, Err(UserStoreError::NotFound)));
+}
+
+/// 用户名和邮箱按规范形式查找和判重，记录保留原始写法。
+pub async fn lookups_are_canonical(store: &dyn UserStore) {
+    let prefix = unique_prefix();
+    let username = format!("{}Mixed", prefix);
+    let email = format!("{}.Mixed@Example.COM", prefix);
+    let user = new_user(store, &username, Some(&email)).await;
+    store.create(&user).await.unwrap();
+
+    let upper = username.to_uppercase();
+    assert_eq!(store.get_by_username(&upper).await.unwrap().as_ref(), Some(&user));
+    assert_eq!(store.get_by_username(&format!(" {} ", username)).await.unwrap().as_ref(), Some(&user));
+    assert_eq!(store.get_by_email(&email.to_lowercase()).await.unwrap().as_ref(), Some(&user));
+
+    let other = new_user(store, &upper, None).await;
+    assert!(matches!(store.create(&other).await, Err(UserStoreError::DuplicateUsername)));
+    let other = new_user(store, &format!("{}other", prefix), Some(&email.to_uppercase())).await;
+    assert!(matches!(store.create(&other).await, Err(UserStoreError::DuplicateEmail)));
+
+    // 只修改大小写不算冲突。
+    let mut renamed = user.clone();
+    renamed.username = username.to_lowercase();
+    renamed.email = Some(email.to_lowercase());
+    store.update(renamed.version, &renamed).await.expect("case-only rename");
+    renamed.version += 1;
+    assert_eq!(store.get_by_username(&username).await.unwrap().as_ref(), Some(&renamed));
+
+    store.delete(user.id).await.unwrap();
+    assert_eq!(store.get_by_username(&upper).await.unwrap(), None);
+}
+
+/// 易混淆的用户名不能同时注册，也不能互相用来查找。
+pub async fn confusable_usernames_rejected(store: &dyn UserStore) {
+    let prefix = unique_prefix();
+    let user = new_user(store, &format!("{}bob1", prefix), None).await;
+    store.create(&user).await.unwrap();
+
+    let lookalike = format!("{}bobl", prefix);
+    let other = new_user(store, &lookalike, None).await;
+    assert!(matches!(store.create(&other).await, Err(UserStoreError::DuplicateUsername)));
+    assert_eq!(store.get_by_username(&lookalike).await.unwrap(), None);
+
+    // 西里尔字母 `о`（U+043E）与拉丁字母 `o` 混淆。
+    let cyrillic = new_user(store, &format!("{}b\u{43e}b1", prefix), None).await;
+    assert!(matches!(store.create(&cyrillic).await, Err(UserStoreError::DuplicateUsername)));
+
+    store.delete(user.id).await.unwrap();
+}
+
+/// 并发注册同一个用户名或同一个邮箱时恰好一个成功，失败的注册不留下任何占用。
+pub async fn concurrent_creates_are_atomic(store: &dyn UserStore) {
+    let prefix = unique_prefix();
+    let mut same_name = Vec::new();
+    for i in 0..CONCURRENCY {
+        same_name.push(new_user(store, &prefix, Some(&format!("{}-{}@example.com", prefix, i))).await);
+    }
+    let results = join_all(same_name.iter().map(|user| store.create(user)).collect()).await;
+    let winners: Vec<&UserRecord> = same_name.iter().zip(&results).filter(|(_, r)| r.is_ok()).map(|(u, _)| u).collect();
+    assert_eq!(winners.len(), 1, "exactly one concurrent create may win the username");
+    assert!(results.iter().filter(|r| r.is_err()).all(|r| matches!(r, Err(UserStoreError::DuplicateUsername))));
+    let winner = winners[0].clone();
+    assert_eq!(store.get_by_username(&prefix).await.unwrap().as_ref(), Some(&winner));
+    for user in same_name.iter().filter(|u| u.id != winner.id) {
+        assert_eq!(store.get_by_email(user.email.as_deref().unwrap()).await.unwrap(), None);
+        assert_eq!(store.get_by_id(user.id).await.unwrap(), None);
+    }
+
+    let email = format!("{}shared@example.com", prefix);
+    let mut same_email = Vec::new();
+    for i in 0..CONCURRENCY {
+        same_email.push(new_user(store, &format!("{}e{}", prefix, i), Some(&email)).await);
+    }
+    let results = join_all(same_email.iter().map(|user| store.create(user)).collect()).await;
+    assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 1, "exactly one concurrent create may win the email");
+    assert!(results.iter().filter(|r| r.is_err()).all(|r| matches!(r, Err(UserStoreError::DuplicateEmail))));
+    for (user, result) in same_email.iter().zip(&results) {
+        let found = store.get_by_username(&user.username).await.unwrap();
+        assert_eq!(found.is_some(), result.is_ok(), "a failed create must not keep its username");
+        if result.is_ok() {
+            store.delete(user.id).await.unwrap();
+        }
+    }
+
+    store.delete(winner.id).await.unwrap();
+}
+
+/// 多个用户并发改成同一个用户名时恰好一个成功，其余用户保持原名。
+pub async fn concurrent_renames_are_atomic(store: &dyn UserStore) {
+    let prefix = unique_prefix();
+    let mut users = Vec::new();
+    for i in 0..CONCURRENCY {
+        let user = new_user(store, &format!("{}r{}", prefix, i), None).await;
+        store.create(&user).await.unwrap();
+        users.push(user);
+    }
+    let target = format!("{}target", prefix);
+    let renamed: Vec<UserRecord> = users
+        .iter()
+        .map(|user| UserRecord { username: target.clone(), ..user.clone() })
+        .collect();
+    let results = join_all(renamed.iter().map(|user| store.update(user.version, user)).collect()).await;
+    assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 1, "exactly one concurrent rename may win");
+    assert!(results.iter().filter(|r| r.is_err()).all(|r| matches!(r, Err(UserStoreError::DuplicateUsername))));
+
+    for ((user, renamed), result) in users.iter().zip(&renamed).zip(&results) {
+        let expected = match result {
+            Ok(()) => UserRecord { version: renamed.version + 1, ..renamed.clone() },
+            Err(_) => user.clone(),
+        };
+        assert_eq!(store.get_by_id(user.id).await.unwrap(), Some(expected));
+        assert_eq!(store.get_by_username(&user.username).await.unwrap().is_some(), result.is_err());
+    }
+    // 删除与更新并发时，索引不能指向已删除的用户。
+    let (deleted, _) = tokio::join!(store.delete(users[0].id), store.update(renamed[0].version, &renamed[0]));
+    deleted.unwrap();
+    assert_eq!(store.get_by_id(users[0].id).await.unwrap(), None);
+    assert!(store.get_by_username(&target).await.unwrap().is_none_or(|user| user.id != users[0].id));
+
+    for user in &users[1..] {
+        store.delete(user.id).await.unwrap();
+    }
+}
+
+/// 列表按 ID 升序，并正确分页。
+pub async fn list_is_ordered_and_paginated(store: &dyn UserStore) {
+    let prefix = unique_prefix();
+    let mut created = Vec::new();
+    for i in 0..3 {
+        let user = new_user(store, &format!("{}{}", prefix, i), None).await;
+        store.create(&user).await.unwrap();
+        created.push(user);
+    }
+
+    let all = store.list(0, usize::MAX / 2).await.unwrap();
+    assert!(all.windows(2).all(|w| w[0].id < w[1].id), "list must be ordered by id");
+    let ours: Vec<&UserRecord> = all.iter().filter(|u| u.username.starts_with(&prefix)).collect();
+    assert_eq!(ours.len(), 3);
+
+    let position = all.iter().position(|u| u.id == created[0].id).unwrap();
+    let page = store.list(position + 1, 1).await.unwrap();
+    assert_eq!(page.len(), 1);
+    assert_eq!(page[0].id, created[1].id);
+    assert!(store.list(0, 0).await.unwrap().is_empty());
+
+    for user in created {
+        store.delete(user.id).await.unwrap();
+    }
+}
+
+/// `next_id` 每次返回不同的 ID。
+pub async fn next_id_is_unique(store: &dyn UserStore) {
+    let mut ids = Vec::new();
+    for _ in 0..16 {
+        ids.push(store.next_id().await.unwrap());
+    }
+    let mut deduped = ids.clone();
+    deduped.sort_unstable();
+    deduped.dedup();
+    assert_eq!(deduped.len(), ids.len());
+}
+
+/// 并发调用 `next_id` 也返回不同的 ID。
+pub async fn concurrent_next_id_is_unique(store: &dyn UserStore) {
+    let ids = join_all((0..CONCURRENCY).map(|_| store.next_id()).collect()).await;
+    let unique: HashSet<u64> = ids.iter().map(|id| *id.as_ref().unwrap()).collect();
+    assert_eq!(unique.len(), CONCURRENCY, "concurrent next_id calls must not collide");
+}
+
+/// 附属记录只在当前值与预期相同时交换，按键排序列出；并发交换同一个值时恰好一个成功。
+pub async fn records_swap_atomically(store: &dyn UserStore) {
+    let kind = unique_prefix();
+    assert_eq!(store.get_record(&kind, "a").await.unwrap(), None);
+    assert!(store.swap_record(&kind, "b", None, Some("1")).await.unwrap());
+    assert!(!store.swap_record(&kind, "b", None, Some("2")).await.unwrap(), "create must fail when the record exists");
+    assert!(store.swap_record(&kind, "a", None, Some("1")).await.unwrap());
+    assert!(!store.swap_record(&kind, "a", Some("0"), Some("2")).await.unwrap(), "stale swap must fail");
+    assert!(store.swap_record(&kind, "a", Some("1"), Some("2")).await.unwrap());
+    assert_eq!(store.get_record(&kind, "a").await.unwrap().as_deref(), Some("2"));
+    assert_eq!(
+        store.list_records(&kind).await.unwrap(),
+        vec![("a".to_string(), "2".to_string()), ("b".to_string(), "1".to_string())],
+    );
+    assert!(store.list_records(&format!("{}x", kind)).await.unwrap().is_empty(), "kinds must not share records");
+
+    let values: Vec<String> = (0..CONCURRENCY).map(|i| format!("v{}", i)).collect();
+    let results = join_all(values.iter().map(|value| store.swap_record(&kind, "a", Some("2"), Some(value))).collect()).await;
+    assert_eq!(results.iter().filter(|r| *r.as_ref().unwrap()).count(), 1, "exactly one concurrent swap may win");
+    let winner = results.iter().position(|r| *r.as_ref().unwrap()).unwrap();
+    assert_eq!(store.get_record(&kind, "a").await.unwrap(), Some(values[winner].clone()));
+
+    assert!(!store.swap_record(&kind, "a", Some("2"), None).await.unwrap(), "stale delete must fail");
+    assert!(store.swap_record(&kind, "a", Some(&values[winner]), None).await.unwrap());
+    assert!(store.swap_record(&kind, "b", Some("1"), None).await.unwrap());
+    assert!(store.swap_record(&kind, "a", None, None).await.unwrap());
+    assert!(store.list_records(&kind).await.unwrap().is_empty());
+}
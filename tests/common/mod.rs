@@ -0,0 +1,3 @@
+//! 测试共用的辅助代码。
+
+pub mod conformance;
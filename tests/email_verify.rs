@@ -0,0 +1,64 @@
+//! 打开验证链接只显示确认页面，提交后才完成验证，并吊销带有 `unverified` 声明的令牌。
+
+use axum::body::Body;
+use axum::http::{ header, Request, StatusCode };
+use btcmweb::account::{ self, verification };
+use btcmweb::token::{ self, session::DeviceInfo, TokenError };
+use btcmweb::userstore::user_store;
+use tower::ServiceExt;
+
+const PASSWORD: &str = "xK9#mQ2$vL7!zz";
+
+async fn send(request: Request<Body>) -> (StatusCode, String) {
+    let response = btcmweb::webserver::app_router().oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, String::from_utf8(bytes.to_vec()).unwrap())
+}
+
+fn confirm(token: &str) -> Request<Body> {
+    Request::post("/email/verify")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from(format!("token={}", token)))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn get_shows_a_form_and_post_verifies() {
+    let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos();
+    let username = format!("verify{:x}", nanos);
+    let user = account::register(&username, PASSWORD, Some(&format!("{}@example.com", username)), None).await.unwrap();
+    let token = verification::verification_token(&user).unwrap();
+    let tokens = token::issue_tokens(&user, DeviceInfo::default(), false).await.unwrap();
+    assert!(token::authenticate_access_token(&tokens.access_token).await.unwrap().unverified);
+
+    let (status, page) = send(Request::get(format!("/email/verify?token={}", token)).body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(page.contains("method=\"post\"") && page.contains(&token), "{}", page);
+    let stored = user_store().get_by_id(user.id).await.unwrap().unwrap();
+    assert!(!stored.email_verified, "opening the link does not verify the email");
+    assert!(token::authenticate_access_token(&tokens.access_token).await.is_ok());
+
+    let (status, page) = send(confirm(&token)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(page.contains("verified successfully"), "{}", page);
+    let stored = user_store().get_by_id(user.id).await.unwrap().unwrap();
+    assert!(stored.email_verified);
+    assert!(matches!(token::authenticate_access_token(&tokens.access_token).await, Err(TokenError::Revoked)));
+    assert!(matches!(token::refresh_tokens(&tokens.refresh_token).await, Err(TokenError::InvalidRefreshToken)));
+
+    let relogin = token::issue_tokens(&stored, DeviceInfo::default(), false).await.unwrap();
+    assert!(!token::authenticate_access_token(&relogin.access_token).await.unwrap().unverified);
+    let (_, page) = send(confirm(&token)).await;
+    assert!(page.contains("verified successfully"), "confirming again is not an error: {}", page);
+    assert!(token::authenticate_access_token(&relogin.access_token).await.is_ok(), "confirming again keeps the new tokens");
+}
+
+#[tokio::test]
+async fn invalid_links_show_no_form() {
+    let (status, page) = send(Request::get("/email/verify?token=bogus.token").body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(page.contains("Invalid or expired verification link") && !page.contains("<form"), "{}", page);
+    let (_, page) = send(confirm("bogus.token")).await;
+    assert!(page.contains("Invalid or expired verification link"), "{}", page);
+}
@@ -0,0 +1,62 @@
+//! 邀请码保存在用户存储中，注册前预留使用次数，注册失败时归还。
+
+use btcmweb::account::{ self, invite, AccountError };
+use btcmweb::userstore::user_store;
+
+const PASSWORD: &str = "xK9#mQ2$vL7!zz";
+/// 同时发出的注册请求数。
+const TASKS: usize = 10;
+
+fn unique_name(prefix: &str) -> String {
+    let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos();
+    format!("{}{:x}", prefix, nanos)
+}
+
+async fn new_invite(max_uses: u64) -> invite::Invite {
+    invite::create(&invite::NewInvite { max_uses: Some(max_uses), ..Default::default() }, 1).await.unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_registrations_respect_max_uses() {
+    let created = new_invite(3).await;
+    assert!(user_store().get_record("invite", &created.code).await.unwrap().is_some());
+
+    let prefix = unique_name("inv");
+    let mut tasks = Vec::new();
+    for i in 0..TASKS {
+        let username = format!("{}u{}", prefix, i);
+        let code = created.code.clone();
+        tasks.push(tokio::spawn(async move { account::register(&username, PASSWORD, None, Some(&code)).await }));
+    }
+    let mut registered = Vec::new();
+    for task in tasks {
+        match task.await.unwrap() {
+            Ok(user) => registered.push(user),
+            Err(err) => assert!(matches!(err, AccountError::InvalidInvite), "{}", err),
+        }
+    }
+    assert_eq!(registered.len(), 3, "an invite with 3 uses admits exactly 3 accounts");
+    assert!(registered.iter().all(|user| user.invite_code.as_deref() == Some(created.code.as_str())));
+    assert_eq!(invite::get(&created.code).await.unwrap().unwrap().uses, 3);
+    assert!(invite::revoke(&created.code).await.unwrap());
+}
+
+#[tokio::test]
+async fn failed_registration_releases_the_use() {
+    let created = new_invite(1).await;
+    let username = unique_name("rel");
+    let err = account::register(&username, "short", None, Some(&created.code)).await.unwrap_err();
+    assert!(matches!(err, AccountError::InvalidPassword(_)), "{}", err);
+    assert_eq!(invite::get(&created.code).await.unwrap().unwrap().uses, 0);
+
+    let user = account::register(&username, PASSWORD, None, Some(&created.code.to_uppercase())).await.unwrap();
+    assert_eq!(user.invite_code.as_deref(), Some(created.code.as_str()));
+    let err = account::register(&format!("{}b", username), PASSWORD, None, Some(&created.code)).await.unwrap_err();
+    assert!(matches!(err, AccountError::InvalidInvite), "{}", err);
+
+    let other = new_invite(1).await;
+    let err = account::register(&username, PASSWORD, None, Some(&other.code)).await.unwrap_err();
+    assert!(matches!(err, AccountError::UsernameTaken), "{}", err);
+    assert_eq!(invite::get(&other.code).await.unwrap().unwrap().uses, 0);
+    assert!(invite::list().await.unwrap().iter().any(|invite| invite.code == other.code));
+}
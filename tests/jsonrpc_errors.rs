@@ -0,0 +1,37 @@
+//! POST `/jsonrpc` 的错误响应：请求体无法解析、请求无效和方法不存在时都带有错误对象。
+
+use axum::body::Body;
+use axum::http::{ header, Request };
+use btcmweb::jsonrpc::{ INVALID_REQUEST, METHOD_NOT_FOUND, PARSE_ERROR };
+use serde_json::{ json, Value };
+use tower::ServiceExt;
+
+async fn post(body: &str) -> Value {
+    let request = Request::post("/jsonrpc")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let response = btcmweb::webserver::app_router().oneshot(request).await.unwrap();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+#[tokio::test]
+async fn unknown_methods_are_method_not_found() {
+    let body = post(&json!({ "jsonrpc": "2.0", "id": 42, "method": "no.such.method", "token": "" }).to_string()).await;
+    assert_eq!(body["id"], 42);
+    assert_eq!(body["error"]["code"], METHOD_NOT_FOUND, "{}", body);
+    assert!(body["error"]["message"].as_str().unwrap().contains("no.such.method"));
+    assert_eq!(body["result"], Value::Null);
+}
+
+#[tokio::test]
+async fn malformed_bodies_are_rejected_with_error_objects() {
+    let body = post("{\"jsonrpc\": \"2.0\", \"id\": 1, ").await;
+    assert_eq!(body["error"]["code"], PARSE_ERROR, "{}", body);
+    assert_eq!(body["id"], Value::Null);
+
+    let body = post(&json!({ "jsonrpc": "2.0", "id": "req-9", "method": ["add"], "token": "" }).to_string()).await;
+    assert_eq!(body["error"]["code"], INVALID_REQUEST, "{}", body);
+    assert_eq!(body["id"], "req-9");
+}
@@ -0,0 +1,79 @@
+//! 通过 HTTP GET 调用 JSON-RPC：只接受安全的方法，令牌只能放在 `Authorization` 请求头中。
+
+use axum::body::Body;
+use axum::http::{ header, Request, StatusCode };
+use base64::{ engine::general_purpose::URL_SAFE_NO_PAD, Engine };
+use btcmweb::jsonrpc::{ INVALID_REQUEST, METHOD_NOT_ALLOWED };
+use serde_json::{ json, Value };
+use tower::ServiceExt;
+
+async fn get(uri: &str, authorization: Option<&str>) -> (StatusCode, Option<String>, Value) {
+    let mut request = Request::get(uri);
+    if let Some(authorization) = authorization {
+        request = request.header(header::AUTHORIZATION, authorization);
+    }
+    let response = btcmweb::webserver::app_router().oneshot(request.body(Body::empty()).unwrap()).await.unwrap();
+    let status = response.status();
+    let cache_control = response
+        .headers()
+        .get(header::CACHE_CONTROL)
+        .map(|value| value.to_str().unwrap().to_string());
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, cache_control, serde_json::from_slice(&bytes).unwrap())
+}
+
+fn params(value: Value) -> String {
+    URL_SAFE_NO_PAD.encode(value.to_string())
+}
+
+#[tokio::test]
+async fn safe_method_is_callable_and_cacheable() {
+    let uri = format!("/jsonrpc?method=add&id=7&params={}", params(json!({ "a": 1, "b": 2 })));
+    let (status, cache_control, body) = get(&uri, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["id"], 7);
+    assert!(body["error"].is_null(), "{}", body);
+    assert_eq!(cache_control.as_deref(), Some("public, max-age=60"));
+}
+
+#[tokio::test]
+async fn token_in_query_is_rejected() {
+    let (_, cache_control, body) = get("/jsonrpc?method=add&id=1&token=secret", None).await;
+    assert_eq!(body["id"], 1);
+    assert_eq!(body["error"]["code"], INVALID_REQUEST);
+    assert_eq!(cache_control.as_deref(), Some("no-store"));
+
+    let (_, _, body) = get("/jsonrpc?method=add&id=1", Some("Basic c2VjcmV0")).await;
+    assert_eq!(body["error"]["code"], INVALID_REQUEST);
+}
+
+#[tokio::test]
+async fn admin_methods_are_not_allowed_over_get() {
+    for method in [
+        "admin.jwt.keys",
+        "admin.user.list",
+        "admin.user.get",
+        "admin.apiKey.list",
+        "admin.session.list",
+        "admin.lockout.list",
+        "admin.lockout.get",
+        "admin.invite.list",
+        "admin.registration.list",
+    ] {
+        let (_, cache_control, body) = get(&format!("/jsonrpc?method={}&id=1", method), Some("Bearer whatever")).await;
+        assert_eq!(body["error"]["code"], METHOD_NOT_ALLOWED, "{} must not be callable over GET", method);
+        assert_eq!(cache_control.as_deref(), Some("no-store"));
+    }
+}
+
+#[tokio::test]
+async fn malformed_query_is_a_json_rpc_error() {
+    let (_, cache_control, body) = get("/jsonrpc?id=1", None).await;
+    assert_eq!(body["jsonrpc"], "2.0");
+    assert_eq!(body["error"]["code"], INVALID_REQUEST);
+    assert_eq!(cache_control.as_deref(), Some("no-store"));
+
+    let (_, _, body) = get("/jsonrpc?method=add&id=2&params=not*base64", None).await;
+    assert_eq!(body["id"], 2);
+    assert!(body["error"]["message"].as_str().unwrap().contains("base64url"));
+}
@@ -0,0 +1,70 @@
+//! 轮换生成的签名密钥保存在用户存储中，其他实例同步后使用同一组密钥。
+
+use btcmweb::config::JwtConfig;
+use btcmweb::token::keyring::{ StoredKeys, RECORD_KEY, RECORD_KIND };
+use btcmweb::token::{ TokenService, TokenError };
+use btcmweb::userstore::{ user_store, UserRecord };
+
+/// 两个实例使用相同的配置密钥和密钥环口令，模拟多实例部署。
+fn instance() -> TokenService {
+    let config = JwtConfig {
+        keys: vec![serde_json::from_value(serde_json::json!({
+            "kid": "configured", "algorithm": "HS256", "secret": "configured-secret-for-tests-only"
+        })).unwrap()],
+        keyring_secret: Some("keyring-secret-for-tests-only".into()),
+        ..Default::default()
+    };
+    TokenService::from_config(&config).unwrap()
+}
+
+/// 把保存的密钥的生效时间提前，跳过激活等待。
+async fn activate_now() {
+    let store = user_store();
+    let raw = store.get_record(RECORD_KIND, RECORD_KEY).await.unwrap();
+    let mut stored = StoredKeys::parse(raw.as_deref()).unwrap();
+    let now = chrono::Utc::now().timestamp();
+    for key in stored.keys.iter_mut().filter(|key| key.activate_at > now) {
+        key.activate_at = now - 1;
+    }
+    assert!(store.swap_record(RECORD_KIND, RECORD_KEY, raw.as_deref(), Some(&stored.to_json())).await.unwrap());
+}
+
+#[tokio::test]
+async fn generated_keys_are_shared_through_the_user_store() {
+    let user = UserRecord { id: 42, username: "keyring".into(), ..Default::default() };
+    let first = instance();
+    let second = instance();
+
+    let key = first.rotate().await.unwrap();
+    let raw = user_store().get_record(RECORD_KIND, RECORD_KEY).await.unwrap().unwrap();
+    assert!(raw.contains("\"secret\":\"enc:"), "private keys are stored encrypted: {}", raw);
+    let stored = StoredKeys::parse(Some(&raw)).unwrap();
+    assert_eq!(stored.keys.iter().map(|key| key.kid.as_str()).collect::<Vec<_>>(), vec![key.kid.as_str()]);
+    assert!(key.activate_at > chrono::Utc::now().timestamp(), "a new key is not used before every instance has synced");
+    let current = |service: &TokenService| service.key_summaries().into_iter().find(|summary| summary.current).unwrap().kid;
+    assert_eq!(current(&first), "configured");
+
+    second.sync_keys().await.unwrap();
+    assert!(second.key_summaries().iter().any(|summary| summary.kid == key.kid && summary.generated));
+    assert!(second.public_jwks().iter().any(|jwk| jwk.common.key_id.as_deref() == Some(key.kid.as_str())));
+
+    activate_now().await;
+    first.sync_keys().await.unwrap();
+    second.sync_keys().await.unwrap();
+    assert_eq!(current(&first), key.kid);
+    let token = first.issue_access_token(&user, 0, "sid", false).unwrap();
+    assert_eq!(jsonwebtoken::decode_header(&token).unwrap().kid.as_deref(), Some(key.kid.as_str()));
+    assert_eq!(second.verify_access_token(&token).unwrap().sub, "42");
+    let restarted = instance();
+    assert!(matches!(restarted.verify_access_token(&token), Err(TokenError::UnknownKey(_))));
+    restarted.sync_keys().await.unwrap();
+    assert!(restarted.verify_access_token(&token).is_ok(), "generated keys survive a restart");
+
+    assert!(second.rotate_if_due(3600).await.unwrap().is_none(), "only one instance rotates per interval");
+    let next = second.rotate().await.unwrap();
+    let stored = StoredKeys::parse(user_store().get_record(RECORD_KIND, RECORD_KEY).await.unwrap().as_deref()).unwrap();
+    let previous = stored.keys.iter().find(|stored| stored.kid == key.kid).unwrap();
+    assert!(previous.retire_at.is_some_and(|at| at > next.activate_at), "the old key verifies until its tokens expire");
+    first.sync_keys().await.unwrap();
+    assert!(first.verify_access_token(&token).is_ok());
+}
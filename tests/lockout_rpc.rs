@@ -0,0 +1,68 @@
+//! 管理员通过 `admin.lockout.*` 查看锁定，解除后被锁定的用户名可以再次登录。
+
+use axum::body::Body;
+use axum::http::{ header, Request };
+use btcmweb::account::{ lockout, AccountError };
+use btcmweb::token::{ self, session::DeviceInfo };
+use btcmweb::userstore::{ user_store, UserRecord };
+use serde_json::{ json, Value };
+use tower::ServiceExt;
+
+async fn call(method: &str, token: &str, params: Value) -> Value {
+    let body = json!({ "jsonrpc": "2.0", "method": method, "id": 1, "token": token, "params": params });
+    let request = Request::post("/jsonrpc")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let response = btcmweb::webserver::app_router().oneshot(request).await.unwrap();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+#[tokio::test]
+async fn clearing_a_lockout_unlocks_the_account() {
+    let store = user_store();
+    let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos();
+    let admin = UserRecord {
+        id: store.next_id().await.unwrap(),
+        username: format!("lockadmin{:x}", nanos),
+        roles: vec!["admin".to_string()],
+        ..Default::default()
+    };
+    store.create(&admin).await.unwrap();
+    let tokens = token::issue_tokens(&admin, DeviceInfo::default(), true).await.unwrap();
+
+    // 每次失败使用不同的 IP，只锁定用户名。
+    let username = format!("LockedUser{:x}", nanos);
+    let subject = lockout::Subject::account(&username).to_string();
+    for i in 0..5 {
+        lockout::record_failure(&username, &format!("192.0.2.{}", i)).await.unwrap();
+    }
+    assert!(matches!(lockout::check(&username, "198.51.100.1").await, Err(AccountError::LoginLocked(_))));
+
+    let listed = call("admin.lockout.list", &tokens.access_token, json!({})).await;
+    let entry = listed["result"].as_array().unwrap_or_else(|| panic!("{}", listed))
+        .iter()
+        .find(|entry| entry["subject"] == subject)
+        .unwrap_or_else(|| panic!("{} not listed in {}", subject, listed))
+        .clone();
+    assert_eq!(entry["failures"], 5);
+    assert!(entry["locked_until"].is_i64());
+
+    let got = call("admin.lockout.get", &tokens.access_token, json!({ "username": username.to_lowercase() })).await;
+    assert_eq!(got["result"][0]["subject"], subject, "{}", got);
+    assert!(got["result"][0]["locked_until"].is_i64());
+
+    let cleared = call("admin.lockout.clear", &tokens.access_token, json!({ "username": username })).await;
+    assert_eq!(cleared["result"]["cleared"], json!([subject]), "{}", cleared);
+    lockout::check(&username, "198.51.100.1").await.expect("cleared account can log in again");
+
+    let got = call("admin.lockout.get", &tokens.access_token, json!({ "username": username })).await;
+    assert_eq!(got["result"][0]["failures"], 0);
+    assert!(got["result"][0]["locked_until"].is_null());
+    let listed = call("admin.lockout.list", &tokens.access_token, json!({})).await;
+    assert!(listed["result"].as_array().unwrap().iter().all(|entry| entry["subject"] != subject));
+
+    let denied = call("admin.lockout.clear", "", json!({ "username": username })).await;
+    assert!(!denied["error"].is_null(), "clearing requires an administrator");
+}
@@ -0,0 +1,67 @@
+//! `/logout` 把当前访问令牌的 `jti` 加入吊销列表；`/logout/all` 使令牌代数加一，此前签发的访问令牌全部失效。
+
+use axum::body::Body;
+use axum::http::{ header, Request };
+use btcmweb::token::{ self, revoke, session::DeviceInfo, TokenError };
+use btcmweb::userstore::{ user_store, UserRecord };
+use serde_json::Value;
+use tower::ServiceExt;
+
+async fn user(name: &str) -> UserRecord {
+    let store = user_store();
+    let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos();
+    let user = UserRecord { id: store.next_id().await.unwrap(), username: format!("{}{:x}", name, nanos), ..Default::default() };
+    store.create(&user).await.unwrap();
+    user
+}
+
+async fn post(uri: &str, access_token: &str) -> Value {
+    let request = Request::post(uri)
+        .header(header::AUTHORIZATION, format!("Bearer {}", access_token))
+        .body(Body::empty())
+        .unwrap();
+    let response = btcmweb::webserver::app_router().oneshot(request).await.unwrap();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+#[tokio::test]
+async fn logout_denylists_the_access_token() {
+    let user = user("logout").await;
+    let tokens = token::issue_tokens(&user, DeviceInfo::default(), false).await.unwrap();
+    let claims = token::verify_access_token(&tokens.access_token).unwrap();
+    assert!(!revoke::is_revoked(&claims).await.unwrap());
+
+    let response = post("/logout", &tokens.access_token).await;
+    assert_eq!(response["errorid"], 0, "{}", response);
+    assert_eq!(revoke::token_generation(user.id).await.unwrap(), claims.gen, "logout leaves the generation unchanged");
+    assert!(revoke::is_revoked(&claims).await.unwrap(), "the jti is on the denylist");
+    assert!(matches!(token::authenticate_access_token(&tokens.access_token).await, Err(TokenError::Revoked)));
+    assert!(matches!(token::refresh_tokens(&tokens.refresh_token).await, Err(TokenError::InvalidRefreshToken)));
+
+    let replay = post("/logout", &tokens.access_token).await;
+    assert_ne!(replay["errorid"], 0, "a logged out token is rejected: {}", replay);
+}
+
+#[tokio::test]
+async fn logout_all_bumps_the_generation() {
+    let user = user("logoutall").await;
+    let first = token::issue_tokens(&user, DeviceInfo::default(), false).await.unwrap();
+    let second = token::issue_tokens(&user, DeviceInfo::default(), false).await.unwrap();
+    let before = revoke::token_generation(user.id).await.unwrap();
+
+    let response = post("/logout/all", &first.access_token).await;
+    assert_eq!(response["errorid"], 0, "{}", response);
+    assert_eq!(revoke::token_generation(user.id).await.unwrap(), before + 1);
+    for tokens in [&first, &second] {
+        let claims = token::verify_access_token(&tokens.access_token).unwrap();
+        assert!(claims.gen < before + 1);
+        assert!(revoke::is_revoked(&claims).await.unwrap());
+        assert!(matches!(token::authenticate_access_token(&tokens.access_token).await, Err(TokenError::Revoked)));
+        assert!(token::refresh_tokens(&tokens.refresh_token).await.is_err());
+    }
+
+    let fresh = token::issue_tokens(&user, DeviceInfo::default(), false).await.unwrap();
+    let claims = token::authenticate_access_token(&fresh.access_token).await.expect("tokens issued afterwards are valid");
+    assert_eq!(claims.gen, before + 1);
+}
@@ -0,0 +1,43 @@
+//! 默认要求管理员通过两步验证才能使用管理权限；尚未启用两步验证的管理员仍可以启用。
+
+use axum::body::Body;
+use axum::http::{ header, Request };
+use btcmweb::jsonrpc::FORBIDDEN;
+use btcmweb::token::{ self, session::DeviceInfo };
+use btcmweb::userstore::{ user_store, UserRecord };
+use serde_json::{ json, Value };
+use tower::ServiceExt;
+
+async fn call(method: &str, token: &str) -> Value {
+    let body = json!({ "jsonrpc": "2.0", "method": method, "id": 1, "token": token, "params": {} });
+    let request = Request::post("/jsonrpc")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let response = btcmweb::webserver::app_router().oneshot(request).await.unwrap();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+#[tokio::test]
+async fn password_only_admins_can_only_enroll() {
+    let store = user_store();
+    let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos();
+    let admin = UserRecord {
+        id: store.next_id().await.unwrap(),
+        username: format!("mfaadmin{:x}", nanos),
+        roles: vec!["admin".to_string()],
+        ..Default::default()
+    };
+    store.create(&admin).await.unwrap();
+
+    let password_only = token::issue_tokens(&admin, DeviceInfo::default(), false).await.unwrap();
+    let body = call("admin.user.list", &password_only.access_token).await;
+    assert_eq!(body["error"]["code"], FORBIDDEN, "{}", body);
+    let body = call("mfa.totp.enroll", &password_only.access_token).await;
+    assert!(body["result"]["secret"].is_string(), "{}", body);
+
+    let with_mfa = token::issue_tokens(&admin, DeviceInfo::default(), true).await.unwrap();
+    let body = call("admin.user.list", &with_mfa.access_token).await;
+    assert!(body["error"].is_null(), "{}", body);
+}
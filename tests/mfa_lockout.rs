@@ -0,0 +1,75 @@
+//! 两步验证的错误按用户名和 IP 计入登录失败次数，重新取得 `mfa_token` 不会重置计数。
+
+use btcmweb::account::{ lockout, mfa, totp, AccountError };
+use btcmweb::token::session::DeviceInfo;
+use btcmweb::userstore::{ user_store, TotpRecord, UserRecord };
+
+/// 默认配置的用户名锁定阈值。
+const ACCOUNT_THRESHOLD: usize = 5;
+
+async fn mfa_user(name: &str) -> UserRecord {
+    let store = user_store();
+    let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos();
+    let user = UserRecord {
+        id: store.next_id().await.unwrap(),
+        username: format!("{}{:x}", name, nanos),
+        totp: Some(TotpRecord { secret: totp::generate_secret(), last_step: 0, recovery_codes: Vec::new(), enabled_at: 0 }),
+        ..Default::default()
+    };
+    store.create(&user).await.unwrap();
+    user
+}
+
+fn current_code(user: &UserRecord) -> String {
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+    totp::code_at(&user.totp.as_ref().unwrap().secret, totp::time_step(now)).unwrap()
+}
+
+/// 模拟每次都重新输入正确的密码，取得新的 `mfa_token` 后提交一次验证码。
+async fn attempt(user: &UserRecord, code: &str, ip: &str) -> Result<UserRecord, AccountError> {
+    let mfa_token = mfa::start_challenge(user, &user.username, DeviceInfo::default()).await.unwrap();
+    mfa::complete_challenge(&mfa_token, code, ip).await.map(|(user, _)| user)
+}
+
+#[tokio::test]
+async fn failures_across_challenges_lock_the_account() {
+    let user = mfa_user("mfalock").await;
+    for _ in 0..ACCOUNT_THRESHOLD {
+        let err = attempt(&user, "not-a-code", "192.0.2.10").await.unwrap_err();
+        assert!(matches!(err, AccountError::InvalidMfaCode), "{}", err);
+    }
+    let err = attempt(&user, &current_code(&user), "192.0.2.11").await.unwrap_err();
+    assert!(matches!(err, AccountError::LoginLocked(_)), "a new token and a new ip do not reset the account count: {}", err);
+    assert!(matches!(lockout::check(&user.username, "192.0.2.12").await, Err(AccountError::LoginLocked(_))));
+    assert_eq!(lockout::status(&lockout::Subject::ip("192.0.2.10")).await.unwrap().failures, ACCOUNT_THRESHOLD as u64);
+}
+
+#[tokio::test]
+async fn success_clears_the_account_failures() {
+    let user = mfa_user("mfaok").await;
+    for _ in 0..2 {
+        assert!(matches!(attempt(&user, "not-a-code", "192.0.2.20").await, Err(AccountError::InvalidMfaCode)));
+    }
+    let subject = lockout::Subject::account(&user.username);
+    assert_eq!(lockout::status(&subject).await.unwrap().failures, 2);
+    assert_eq!(attempt(&user, &current_code(&user), "192.0.2.20").await.unwrap().id, user.id);
+    assert_eq!(lockout::status(&subject).await.unwrap().failures, 0);
+}
+
+#[tokio::test]
+async fn recovery_codes_are_not_spent_without_a_live_challenge() {
+    let user = mfa_user("mfarecover").await;
+    let codes = mfa::regenerate_recovery_codes(user.id, &current_code(&user)).await.unwrap();
+    let remaining = || async { mfa::remaining_recovery_codes(&user_store().get_by_id(user.id).await.unwrap().unwrap()) };
+    assert_eq!(remaining().await, Some(codes.len()));
+
+    let mfa_token = mfa::start_challenge(&user, &user.username, DeviceInfo::default()).await.unwrap();
+    let err = mfa::complete_challenge(&mfa_token, "not-a-code", "192.0.2.30").await.unwrap_err();
+    assert!(matches!(err, AccountError::InvalidMfaCode), "{}", err);
+    mfa::complete_challenge(&mfa_token, &codes[0], "192.0.2.30").await.expect("a wrong code keeps the challenge");
+    assert_eq!(remaining().await, Some(codes.len() - 1));
+
+    let err = mfa::complete_challenge(&mfa_token, &codes[1], "192.0.2.30").await.unwrap_err();
+    assert!(matches!(err, AccountError::InvalidMfaToken), "{}", err);
+    assert_eq!(remaining().await, Some(codes.len() - 1), "a used token does not burn a recovery code");
+}
@@ -0,0 +1,95 @@
+//! 重置密码的申请总是返回相同的结果，邮件在后台发送；重置令牌与发送到的邮箱绑定。
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::http::{ header, Request };
+use btcmweb::account::{ self, reset, AccountError };
+use btcmweb::mailer::{ memory::MemoryMailer, set_mailer };
+use btcmweb::userstore::{ self, user_store, UserRecord };
+use serde_json::{ json, Value };
+use tokio::sync::OnceCell;
+use tower::ServiceExt;
+
+const PASSWORD: &str = "xK9#mQ2$vL7!zz";
+const NEW_PASSWORD: &str = "pR4%tW8&nB3*yy";
+
+static MAILER: OnceCell<Arc<MemoryMailer>> = OnceCell::const_new();
+
+async fn memory_mailer() -> Arc<MemoryMailer> {
+    MAILER
+        .get_or_init(|| async {
+            let mailer = Arc::new(MemoryMailer::new());
+            set_mailer(mailer.clone());
+            mailer
+        })
+        .await
+        .clone()
+}
+
+async fn user_with_email(name: &str) -> UserRecord {
+    let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos();
+    let username = format!("{}{:x}", name, nanos);
+    let email = format!("{}@example.com", username);
+    account::register(&username, PASSWORD, Some(&email), None).await.unwrap()
+}
+
+/// 等待后台任务发出邮件，返回其中的重置码。
+async fn reset_code(mailer: &MemoryMailer, email: &str) -> String {
+    for _ in 0..100 {
+        if let Some(message) = mailer.messages().into_iter().find(|message| message.to == email && message.subject.starts_with("Reset")) {
+            let mut lines = message.body.lines().skip_while(|line| !line.ends_with("new password:"));
+            return lines.nth(2).unwrap().trim().to_string();
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("no reset email sent to {}", email);
+}
+
+async fn forgot_password(identifier: &str) -> Value {
+    let request = Request::post("/password/forgot")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(json!({ "username": identifier }).to_string()))
+        .unwrap();
+    let response = btcmweb::webserver::app_router().oneshot(request).await.unwrap();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+#[tokio::test]
+async fn requests_are_answered_uniformly() {
+    let mailer = memory_mailer().await;
+    let user = user_with_email("pwuniform").await;
+    let known = forgot_password(&user.username).await;
+    let unknown = forgot_password("no-such-account-pwuniform").await;
+    assert_eq!(known, unknown);
+    assert_eq!(known["errorid"], 0, "{}", known);
+
+    let email = user.email.clone().unwrap();
+    let code = reset_code(&mailer, &email).await;
+    let reset = reset::reset_password(&code, NEW_PASSWORD).await.unwrap();
+    assert_eq!(reset.id, user.id);
+    assert!(reset.email_verified);
+    assert!(matches!(reset::reset_password(&code, NEW_PASSWORD).await, Err(AccountError::InvalidResetToken)));
+}
+
+#[tokio::test]
+async fn changing_the_email_invalidates_the_token() {
+    let mailer = memory_mailer().await;
+    let user = user_with_email("pwemail").await;
+    reset::request_password_reset(&user.username);
+    let code = reset_code(&mailer, user.email.as_deref().unwrap()).await;
+
+    let store = user_store();
+    userstore::modify(store.as_ref(), user.id, |user| {
+        user.email = Some(format!("moved-{}@example.com", user.username));
+        user.email_verified = false;
+        Ok::<_, AccountError>(true)
+    }).await.unwrap();
+
+    assert!(matches!(reset::reset_password(&code, NEW_PASSWORD).await, Err(AccountError::InvalidResetToken)));
+    let stored = store.get_by_id(user.id).await.unwrap().unwrap();
+    assert_eq!(stored.password_hash, user.password_hash, "the password is unchanged");
+    assert!(!stored.email_verified, "the new address is not marked as verified");
+}
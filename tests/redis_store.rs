@@ -0,0 +1,125 @@
+//! 对真实 Redis 运行的 [`RedisUserStore`] 并发测试。
+//!
+//! 需要开启 `redis-store` feature 并设置环境变量 `REDIS_URL`（如 `redis://127.0.0.1/`），未设置时跳过：
+//!
+//! ```text
+//! REDIS_URL=redis://127.0.0.1/ cargo test --features redis-store --test redis_store
+//! ```
+#![cfg(feature = "redis-store")]
+
+use std::sync::Arc;
+
+use btcmweb::userstore::redis::RedisUserStore;
+use btcmweb::userstore::{ UserRecord, UserStore, UserStoreError };
+
+/// 同时发出的请求数。
+const TASKS: usize = 32;
+
+async fn connect() -> Option<Arc<RedisUserStore>> {
+    let Ok(url) = std::env::var("REDIS_URL") else {
+        eprintln!("REDIS_URL is not set, skipping");
+        return None;
+    };
+    Some(Arc::new(RedisUserStore::connect(&url).await.expect("connect to REDIS_URL")))
+}
+
+fn unique_prefix() -> String {
+    let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos();
+    format!("redis{:x}", nanos)
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn concurrent_creates_of_same_name_and_email() {
+    let Some(store) = connect().await else {
+        return;
+    };
+    let prefix = unique_prefix();
+    let email = format!("{}@example.com", prefix);
+    let mut tasks = Vec::new();
+    for _ in 0..TASKS {
+        let store = store.clone();
+        let user = UserRecord {
+            id: store.next_id().await.unwrap(),
+            username: prefix.clone(),
+            email: Some(email.clone()),
+            password_hash: "$argon2id$v=19$m=19456,t=2,p=1$c2FsdHNhbHQ$aGFzaGhhc2g".to_string(),
+            ..Default::default()
+        };
+        tasks.push(tokio::spawn(async move {
+            let result = store.create(&user).await;
+            (user, result)
+        }));
+    }
+    let mut results = Vec::new();
+    for task in tasks {
+        results.push(task.await.unwrap());
+    }
+
+    let winners: Vec<&UserRecord> = results.iter().filter(|(_, result)| result.is_ok()).map(|(user, _)| user).collect();
+    assert_eq!(winners.len(), 1, "exactly one concurrent create may succeed");
+    assert!(results
+        .iter()
+        .filter_map(|(_, result)| result.as_ref().err())
+        .all(|err| matches!(err, UserStoreError::DuplicateUsername | UserStoreError::DuplicateEmail)));
+
+    // 两个索引都指向胜出的用户，失败的创建没有留下记录。
+    let winner = winners[0].clone();
+    assert_eq!(store.get_by_username(&prefix).await.unwrap().as_ref(), Some(&winner));
+    assert_eq!(store.get_by_email(&email).await.unwrap().as_ref(), Some(&winner));
+    for (user, _) in results.iter().filter(|(user, _)| user.id != winner.id) {
+        assert_eq!(store.get_by_id(user.id).await.unwrap(), None);
+    }
+
+    store.delete(winner.id).await.unwrap();
+    assert_eq!(store.get_by_username(&prefix).await.unwrap(), None);
+    assert_eq!(store.get_by_email(&email).await.unwrap(), None);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+async fn concurrent_updates_of_same_version() {
+    let Some(store) = connect().await else {
+        return;
+    };
+    let prefix = unique_prefix();
+    let user = UserRecord {
+        id: store.next_id().await.unwrap(),
+        username: prefix.clone(),
+        password_hash: "$argon2id$v=19$m=19456,t=2,p=1$c2FsdHNhbHQ$aGFzaGhhc2g".to_string(),
+        ..Default::default()
+    };
+    store.create(&user).await.unwrap();
+
+    let mut tasks = Vec::new();
+    for i in 0..TASKS {
+        let store = store.clone();
+        let mut renamed = user.clone();
+        renamed.username = format!("{}r{}", prefix, i);
+        tasks.push(tokio::spawn(async move {
+            let result = store.update(user.version, &renamed).await;
+            (renamed, result)
+        }));
+    }
+    let mut results = Vec::new();
+    for task in tasks {
+        results.push(task.await.unwrap());
+    }
+
+    let winners: Vec<&UserRecord> = results.iter().filter(|(_, result)| result.is_ok()).map(|(user, _)| user).collect();
+    assert_eq!(winners.len(), 1, "exactly one update of the same version may succeed");
+    assert!(results
+        .iter()
+        .filter_map(|(_, result)| result.as_ref().err())
+        .all(|err| matches!(err, UserStoreError::Conflict)));
+
+    // 只有胜出的用户名留在索引中。
+    let stored = store.get_by_id(user.id).await.unwrap().unwrap();
+    assert_eq!(stored.username, winners[0].username);
+    assert_eq!(stored.version, user.version + 1);
+    for (renamed, _) in &results {
+        let found = store.get_by_username(&renamed.username).await.unwrap();
+        assert_eq!(found.is_some(), renamed.username == stored.username);
+    }
+    assert_eq!(store.get_by_username(&prefix).await.unwrap(), None);
+
+    store.delete(user.id).await.unwrap();
+}
@@ -0,0 +1,26 @@
+//! 已经换发过的刷新令牌再次使用时吊销整个令牌族（会话），其他会话不受影响。
+
+use btcmweb::token::{ self, session::DeviceInfo, TokenError };
+use btcmweb::userstore::{ user_store, UserRecord };
+
+#[tokio::test]
+async fn reusing_a_rotated_refresh_token_revokes_the_family() {
+    let store = user_store();
+    let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos();
+    let user = UserRecord { id: store.next_id().await.unwrap(), username: format!("reuse{:x}", nanos), ..Default::default() };
+    store.create(&user).await.unwrap();
+    let stolen = token::issue_tokens(&user, DeviceInfo::default(), false).await.unwrap();
+    let other = token::issue_tokens(&user, DeviceInfo::default(), false).await.unwrap();
+
+    let (_, rotated) = token::refresh_tokens(&stolen.refresh_token).await.unwrap();
+    token::authenticate_access_token(&rotated.access_token).await.expect("rotated access token is valid");
+    let (_, rotated) = token::refresh_tokens(&rotated.refresh_token).await.unwrap();
+
+    assert!(matches!(token::refresh_tokens(&stolen.refresh_token).await, Err(TokenError::RefreshTokenReused)));
+    assert!(matches!(token::refresh_tokens(&rotated.refresh_token).await, Err(TokenError::InvalidRefreshToken)), "the newest token of the family is revoked too");
+    assert!(matches!(token::authenticate_access_token(&rotated.access_token).await, Err(TokenError::Revoked)));
+    assert!(matches!(token::authenticate_access_token(&stolen.access_token).await, Err(TokenError::Revoked)));
+
+    token::authenticate_access_token(&other.access_token).await.expect("other sessions are unaffected");
+    token::refresh_tokens(&other.refresh_token).await.expect("other sessions can still refresh");
+}
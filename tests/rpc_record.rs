@@ -0,0 +1,69 @@
+//! 开启录制后，录制文件中不包含令牌、API 密钥、密码和两步验证密钥。
+
+use axum::body::Body;
+use axum::http::{ header, Request };
+use btcmweb::account::admin;
+use btcmweb::jsonrpc::replay::{ self, REDACTED };
+use btcmweb::token::{ self, session::DeviceInfo };
+use btcmweb::userstore::{ user_store, UserRecord };
+use serde_json::{ json, Value };
+use tower::ServiceExt;
+
+const NEW_PASSWORD: &str = "pR4%tW8&nB3*yy";
+
+async fn call(method: &str, token: &str, params: Value) -> Value {
+    let body = json!({ "jsonrpc": "2.0", "method": method, "id": 1, "token": token, "params": params });
+    let request = Request::post("/jsonrpc")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let response = btcmweb::webserver::app_router().oneshot(request).await.unwrap();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+#[tokio::test]
+async fn recorded_calls_contain_no_secrets() {
+    // 录制路径在第一次使用时读取，必须在任何请求之前设置。
+    let path = std::env::temp_dir().join(format!("btcm-rpc-record-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    std::env::set_var(replay::RECORD_ENV_VAR, &path);
+
+    let store = user_store();
+    let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos();
+    let root = UserRecord {
+        id: store.next_id().await.unwrap(),
+        username: format!("recroot{:x}", nanos),
+        roles: vec!["superadmin".to_string()],
+        ..Default::default()
+    };
+    store.create(&root).await.unwrap();
+    let tokens = token::issue_tokens(&root, DeviceInfo::default(), true).await.unwrap();
+    let service = admin::create_service_account(&format!("recsvc{:x}", nanos), &["admin".to_string()]).await.unwrap();
+
+    let created = call("admin.apiKey.create", &tokens.access_token, json!({
+        "user_id": service.id, "name": "record", "scopes": ["users.read"],
+    })).await;
+    let api_key = created["result"]["key"].as_str().unwrap_or_else(|| panic!("{}", created)).to_string();
+    let listed = call("admin.user.list", &api_key, json!({})).await;
+    assert!(listed["error"].is_null(), "{}", listed);
+    let member = UserRecord { id: store.next_id().await.unwrap(), username: format!("recuser{:x}", nanos), ..Default::default() };
+    store.create(&member).await.unwrap();
+    let reset = call("admin.user.resetPassword", &tokens.access_token, json!({ "user_id": member.id, "password": NEW_PASSWORD })).await;
+    assert!(reset["error"].is_null(), "{}", reset);
+    let enrolled = call("mfa.totp.enroll", &tokens.access_token, json!({})).await;
+    let secret = enrolled["result"]["secret"].as_str().unwrap_or_else(|| panic!("{}", enrolled)).to_string();
+
+    let capture = std::fs::read_to_string(&path).unwrap();
+    for leaked in [tokens.access_token.as_str(), &api_key, &secret, NEW_PASSWORD] {
+        assert!(!capture.contains(leaked), "{} leaked into the capture", leaked);
+    }
+    let exchanges = replay::load_exchanges(&path).await.unwrap();
+    assert_eq!(exchanges.len(), 4);
+    let values: Vec<Value> = exchanges.iter().map(|exchange| serde_json::to_value(exchange).unwrap()).collect();
+    assert!(values.iter().all(|value| value["request"]["token"] == ""));
+    assert_eq!(values[0]["response"]["result"]["key"], REDACTED);
+    assert_eq!(values[2]["request"]["params"]["password"], REDACTED);
+    assert_eq!(values[3]["response"]["result"]["secret"], REDACTED);
+    std::fs::remove_file(&path).unwrap();
+}
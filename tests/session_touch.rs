@@ -0,0 +1,55 @@
+//! 更新会话只覆盖仍然存在的记录，只有刷新令牌时才延长有效期。
+
+use std::time::Duration;
+
+use btcmweb::kvstore::{ memory::MemoryKvStore, KvStore };
+use btcmweb::token::session::{ self, DeviceInfo };
+
+/// `set_existing` 不创建键；`ttl_secs` 为 `None` 时保留原有效期。
+async fn set_existing_semantics(store: &dyn KvStore, prefix: &str) {
+    let key = format!("{}:set_existing", prefix);
+    store.delete(&key).await.unwrap();
+    assert!(!store.set_existing(&key, "a", None).await.unwrap());
+    assert_eq!(store.get(&key).await.unwrap(), None, "a missing key is not created");
+
+    store.set(&key, "a", Some(1)).await.unwrap();
+    assert!(store.set_existing(&key, "b", None).await.unwrap());
+    assert_eq!(store.get(&key).await.unwrap().as_deref(), Some("b"));
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert_eq!(store.get(&key).await.unwrap(), None, "the original ttl is kept");
+
+    store.set(&key, "a", Some(1)).await.unwrap();
+    assert!(store.set_existing(&key, "c", Some(60)).await.unwrap());
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert_eq!(store.get(&key).await.unwrap().as_deref(), Some("c"), "an explicit ttl replaces the original");
+    store.delete(&key).await.unwrap();
+}
+
+#[tokio::test]
+async fn memory_set_existing() {
+    set_existing_semantics(&MemoryKvStore::new(), "btcm:test").await;
+}
+
+#[cfg(feature = "redis-store")]
+#[tokio::test]
+async fn redis_set_existing() {
+    let Ok(url) = std::env::var("REDIS_URL") else {
+        eprintln!("REDIS_URL not set, skipping");
+        return;
+    };
+    let store = btcmweb::kvstore::redis::RedisKvStore::connect(&url).await.unwrap();
+    set_existing_semantics(&store, "btcm:test").await;
+}
+
+#[tokio::test]
+async fn touching_a_revoked_session_does_not_restore_it() {
+    let mut created = session::create_session(7001, DeviceInfo::default(), false).await.unwrap();
+    assert!(session::touch_session(&mut created, true).await.unwrap());
+
+    let mut stale = session::get_session(&created.id).await.unwrap().unwrap();
+    session::revoke_session(&created).await.unwrap();
+    assert!(!session::touch_session(&mut stale, true).await.unwrap());
+    stale.last_seen -= 3600;
+    assert!(!session::touch_session(&mut stale, false).await.unwrap());
+    assert!(session::get_session(&created.id).await.unwrap().is_none());
+}
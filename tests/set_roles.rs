@@ -0,0 +1,39 @@
+//! 修改角色时吊销该用户已签发的令牌，令牌中的角色声明不会在修改后继续生效。
+
+use btcmweb::account::admin;
+use btcmweb::token::{ self, session::DeviceInfo, TokenError };
+use btcmweb::userstore::{ user_store, UserRecord };
+
+async fn user(name: &str) -> UserRecord {
+    let store = user_store();
+    let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos();
+    let user = UserRecord {
+        id: store.next_id().await.unwrap(),
+        username: format!("{}{:x}", name, nanos),
+        roles: vec!["admin".to_string()],
+        ..Default::default()
+    };
+    store.create(&user).await.unwrap();
+    user
+}
+
+#[tokio::test]
+async fn changing_roles_revokes_issued_tokens() {
+    let user = user("demoted").await;
+    let tokens = token::issue_tokens(&user, DeviceInfo::default(), false).await.unwrap();
+    let claims = token::authenticate_access_token(&tokens.access_token).await.unwrap();
+    assert!(claims.roles.contains(&"admin".to_string()));
+
+    let unchanged = admin::set_roles(user.id, &["admin".to_string()]).await.unwrap();
+    assert_eq!(unchanged.roles, vec!["admin".to_string()]);
+    assert!(token::authenticate_access_token(&tokens.access_token).await.is_ok(), "an unchanged role set keeps the tokens");
+
+    let demoted = admin::set_roles(user.id, &["moderator".to_string()]).await.unwrap();
+    assert_eq!(demoted.roles, vec!["moderator".to_string()]);
+    assert!(matches!(token::authenticate_access_token(&tokens.access_token).await, Err(TokenError::Revoked)));
+    assert!(matches!(token::refresh_tokens(&tokens.refresh_token).await, Err(TokenError::InvalidRefreshToken)));
+
+    let relogin = token::issue_tokens(&demoted, DeviceInfo::default(), false).await.unwrap();
+    let claims = token::authenticate_access_token(&relogin.access_token).await.unwrap();
+    assert!(claims.roles.contains(&"moderator".to_string()) && !claims.roles.contains(&"admin".to_string()));
+}
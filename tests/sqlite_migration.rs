@@ -0,0 +1,75 @@
+//! 打开早期版本创建的 SQLite 数据库时，按 [`canonical`] 的规则回填规范键列并建立唯一索引。
+//!
+//! [`canonical`]: btcmweb::userstore::canonical
+#![cfg(feature = "sqlite-store")]
+
+use btcmweb::userstore::sqlite::SqliteUserStore;
+use btcmweb::userstore::{ UserRecord, UserStore, UserStoreError };
+use sqlx::sqlite::SqlitePoolOptions;
+
+/// 引入规范键之前的表结构：用户名和邮箱列直接保存原始值并带唯一约束。
+const OLD_SCHEMA: &str = "CREATE TABLE btcm_users (
+    id       INTEGER PRIMARY KEY,
+    username TEXT NOT NULL UNIQUE,
+    email    TEXT UNIQUE,
+    data     TEXT NOT NULL
+)";
+
+/// 创建一个早期版本的数据库，写入给定的用户，返回连接 URL 和文件路径。
+async fn old_database(name: &str, users: &[UserRecord]) -> (String, std::path::PathBuf) {
+    let path = std::env::temp_dir().join(format!("btcm-migration-{}-{}.db", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let url = format!("sqlite:{}?mode=rwc", path.display());
+    let pool = SqlitePoolOptions::new().connect(&url).await.unwrap();
+    sqlx::query(OLD_SCHEMA).execute(&pool).await.unwrap();
+    for user in users {
+        sqlx::query("INSERT INTO btcm_users (id, username, email, data) VALUES (?, ?, ?, ?)")
+            .bind(user.id as i64)
+            .bind(&user.username)
+            .bind(&user.email)
+            .bind(serde_json::to_string(user).unwrap())
+            .execute(&pool).await.unwrap();
+    }
+    pool.close().await;
+    (url, path)
+}
+
+fn user(id: u64, username: &str, email: Option<&str>) -> UserRecord {
+    UserRecord { id, username: username.to_string(), email: email.map(str::to_string), ..Default::default() }
+}
+
+#[tokio::test]
+async fn backfills_canonical_keys() {
+    let users = [user(1, "Alice", Some("Alice@Example.COM")), user(2, "bob", None)];
+    let (url, path) = old_database("backfill", &users).await;
+    let store = SqliteUserStore::connect(&url).await.expect("migrate old database");
+
+    assert_eq!(store.get_by_username("ALICE").await.unwrap().map(|user| user.id), Some(1));
+    assert_eq!(store.get_by_email("alice@example.com").await.unwrap().map(|user| user.id), Some(1));
+    assert_eq!(store.get_by_username("Bob").await.unwrap().map(|user| user.id), Some(2));
+    assert!(matches!(store.create(&user(3, "alice", None)).await, Err(UserStoreError::DuplicateUsername)));
+    assert!(matches!(store.create(&user(3, "bobl", Some("ALICE@example.com"))).await, Err(UserStoreError::DuplicateEmail)));
+    store.create(&user(3, "carol", Some("carol@example.com"))).await.unwrap();
+    drop(store);
+
+    // 再次打开时不需要重建，已有的数据保持不变。
+    let store = SqliteUserStore::connect(&url).await.unwrap();
+    assert_eq!(store.list(0, 10).await.unwrap().len(), 3);
+    drop(store);
+    let _ = std::fs::remove_file(path);
+}
+
+#[tokio::test]
+async fn refuses_colliding_users() {
+    let users = [user(1, "Alice", None), user(2, "alice", None)];
+    let (url, path) = old_database("collision", &users).await;
+    let err = SqliteUserStore::connect(&url).await.err().expect("colliding usernames must be reported");
+    assert!(err.to_string().contains("users 1 and 2"), "{}", err);
+
+    // 失败的迁移不修改已有的数据。
+    let pool = SqlitePoolOptions::new().connect(&url).await.unwrap();
+    let usernames: Vec<String> = sqlx::query_scalar("SELECT username FROM btcm_users ORDER BY id").fetch_all(&pool).await.unwrap();
+    assert_eq!(usernames, ["Alice", "alice"]);
+    pool.close().await;
+    let _ = std::fs::remove_file(path);
+}
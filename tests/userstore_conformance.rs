@@ -0,0 +1,53 @@
+//! 对每个用户存储后端运行 [`conformance::run_all`]。
+//!
+//! 内存存储始终运行；SQLite 需要开启 `sqlite-store` feature，使用临时目录中的数据库文件。
+//! Postgres 和 Redis 需要开启对应的 feature 并设置环境变量，未设置时跳过：
+//!
+//! ```text
+//! POSTGRES_URL=postgres://localhost/btcm_test cargo test --features postgres-store --test userstore_conformance
+//! REDIS_URL=redis://127.0.0.1/ cargo test --features redis-store --test userstore_conformance
+//! ```
+
+mod common;
+
+use btcmweb::userstore::memory::MemoryUserStore;
+use common::conformance;
+
+#[tokio::test]
+async fn memory_store_conforms() {
+    conformance::run_all(&MemoryUserStore::new()).await;
+}
+
+#[cfg(feature = "sqlite-store")]
+#[tokio::test]
+async fn sqlite_store_conforms() {
+    let path = std::env::temp_dir().join(format!("btcm-conformance-{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let url = format!("sqlite:{}?mode=rwc", path.display());
+    let store = btcmweb::userstore::sqlite::SqliteUserStore::connect(&url).await.expect("open sqlite database");
+    conformance::run_all(&store).await;
+    drop(store);
+    let _ = std::fs::remove_file(path);
+}
+
+#[cfg(feature = "postgres-store")]
+#[tokio::test]
+async fn postgres_store_conforms() {
+    let Ok(url) = std::env::var("POSTGRES_URL") else {
+        eprintln!("POSTGRES_URL is not set, skipping");
+        return;
+    };
+    let store = btcmweb::userstore::postgres::PostgresUserStore::connect(&url).await.expect("connect to POSTGRES_URL");
+    conformance::run_all(&store).await;
+}
+
+#[cfg(feature = "redis-store")]
+#[tokio::test]
+async fn redis_store_conforms() {
+    let Ok(url) = std::env::var("REDIS_URL") else {
+        eprintln!("REDIS_URL is not set, skipping");
+        return;
+    };
+    let store = btcmweb::userstore::redis::RedisUserStore::connect(&url).await.expect("connect to REDIS_URL");
+    conformance::run_all(&store).await;
+}